@@ -120,6 +120,11 @@ import {
   youtubeIcon,
 } from "../packages/excalidraw/components/icons";
 import { appThemeAtom, useHandleAppTheme } from "./useHandleAppTheme";
+import { useHandleLaunchFiles } from "./useHandleLaunchFiles";
+import { autosaveScene, useHandleAutosave } from "./useHandleAutosave";
+import { listen } from "@tauri-apps/api/event";
+import { jotaiStore } from "../packages/excalidraw/jotai";
+import { activeConfirmDialogAtom } from "../packages/excalidraw/components/ActiveConfirmDialog";
 import { getPreferredLanguage } from "./app-language/language-detector";
 import { useAppLangCode } from "./app-language/language-state";
 import DebugCanvas, {
@@ -378,6 +383,26 @@ const ExcalidrawWrapper = () => {
     migrationAdapter: LibraryLocalStorageMigrationAdapter,
   });
 
+  useHandleLaunchFiles({
+    excalidrawAPI,
+    initialized: initialStatePromiseRef.current.promise,
+  });
+
+  useHandleAutosave({ excalidrawAPI });
+
+  // "Clear canvas" in the desktop app's tray asks first, like the menu item
+  useEffect(() => {
+    if (!excalidrawAPI || !("__TAURI__" in window)) {
+      return;
+    }
+    const unlisten = listen("clear-canvas", () => {
+      jotaiStore.set(activeConfirmDialogAtom, "clearCanvas");
+    });
+    return () => {
+      unlisten.then((fn) => fn());
+    };
+  }, [excalidrawAPI]);
+
   const [, forceRefresh] = useState(false);
 
   useEffect(() => {
@@ -656,6 +681,8 @@ const ExcalidrawWrapper = () => {
       });
     }
 
+    autosaveScene(elements, appState, files);
+
     // Render the debug scene if the debug canvas is available
     if (debugCanvasRef.current && excalidrawAPI) {
       debugRenderer(
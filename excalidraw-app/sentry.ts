@@ -1,5 +1,6 @@
 import * as Sentry from "@sentry/browser";
 import * as SentryIntegrations from "@sentry/integrations";
+import { invoke } from "@tauri-apps/api";
 
 const SentryEnvHostnameMap: { [key: string]: string } = {
   "excalidraw.com": "production",
@@ -36,3 +37,17 @@ Sentry.init({
     return event;
   },
 });
+
+// Sentry can't report from an offline desktop app, so keep errors in the
+// native log file as well
+if ("__TAURI__" in window) {
+  const forwardError = (message: string) =>
+    invoke("log_message", { level: "error", message }).catch(() => {});
+
+  window.addEventListener("error", (event) => {
+    forwardError(event.error?.stack ?? event.message);
+  });
+  window.addEventListener("unhandledrejection", (event) => {
+    forwardError(String(event.reason?.stack ?? event.reason));
+  });
+}
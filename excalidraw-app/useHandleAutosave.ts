@@ -0,0 +1,72 @@
+import { invoke } from "@tauri-apps/api";
+import { listen } from "@tauri-apps/api/event";
+import { useEffect } from "react";
+import { serializeAsJSON } from "../packages/excalidraw/data/json";
+import type { ExcalidrawElement } from "../packages/excalidraw/element/types";
+import type {
+  AppState,
+  BinaryFiles,
+  ExcalidrawImperativeAPI,
+} from "../packages/excalidraw/types";
+import { debounce } from "../packages/excalidraw/utils";
+import { SAVE_TO_LOCAL_STORAGE_TIMEOUT } from "./app_constants";
+import { loadScene } from "./useHandleLaunchFiles";
+
+/** `autosave::Snapshot`, sent with `restore-scene`. */
+type Snapshot = { path: string; savedAt: number; contents: string };
+
+const autosave = debounce(
+  (
+    elements: readonly ExcalidrawElement[],
+    appState: AppState,
+    files: BinaryFiles,
+  ) => {
+    invoke("autosave_scene", {
+      snapshot: serializeAsJSON(elements, appState, files, "local"),
+    }).catch((error) => console.error(error));
+  },
+  SAVE_TO_LOCAL_STORAGE_TIMEOUT,
+);
+
+/** Hands the scene to the desktop app's crash recovery snapshots. */
+export const autosaveScene = (
+  elements: readonly ExcalidrawElement[],
+  appState: AppState,
+  files: BinaryFiles,
+) => {
+  if ("__TAURI__" in window) {
+    autosave(elements, appState, files);
+  }
+};
+
+/**
+ * Loads the autosaved scene of a crashed session once the user agreed to
+ * restore it.
+ */
+export const useHandleAutosave = ({
+  excalidrawAPI,
+}: {
+  excalidrawAPI: ExcalidrawImperativeAPI | null;
+}) => {
+  useEffect(() => {
+    if (!excalidrawAPI || !("__TAURI__" in window)) {
+      return;
+    }
+
+    const unlisten = listen<Snapshot>("restore-scene", async (event) => {
+      try {
+        await loadScene(excalidrawAPI, event.payload);
+      } catch (error: any) {
+        console.error(error);
+        excalidrawAPI.updateScene({
+          appState: { errorMessage: "Couldn't restore the autosaved drawing" },
+        });
+      }
+    });
+
+    return () => {
+      autosave.flush();
+      unlisten.then((fn) => fn());
+    };
+  }, [excalidrawAPI]);
+};
@@ -0,0 +1,96 @@
+import { invoke } from "@tauri-apps/api";
+import { listen } from "@tauri-apps/api/event";
+import type { UnlistenFn } from "@tauri-apps/api/event";
+import { useEffect } from "react";
+import { StoreAction } from "../packages/excalidraw";
+import { MIME_TYPES } from "../packages/excalidraw/constants";
+import { loadFromBlob } from "../packages/excalidraw/data/blob";
+import type {
+  ExcalidrawImperativeAPI,
+  LibraryItems,
+} from "../packages/excalidraw/types";
+
+/** `files::SceneFile`, sent with `open-file`. */
+type SceneFile = { path: string; contents: string };
+
+/** `launch::LaunchFile`, sent with `launch-files`. */
+type LaunchFile =
+  | ({ kind: "scene" } & SceneFile)
+  | { kind: "library"; path: string; items: LibraryItems };
+
+export const loadScene = async (
+  excalidrawAPI: ExcalidrawImperativeAPI,
+  { contents }: SceneFile,
+) => {
+  const data = await loadFromBlob(
+    new Blob([contents], { type: MIME_TYPES.excalidraw }),
+    excalidrawAPI.getAppState(),
+    excalidrawAPI.getSceneElementsIncludingDeleted(),
+  );
+  excalidrawAPI.updateScene({
+    elements: data.elements,
+    appState: data.appState,
+    storeAction: StoreAction.CAPTURE,
+  });
+  excalidrawAPI.addFiles(Object.values(data.files));
+};
+
+const loadLaunchFiles = async (
+  excalidrawAPI: ExcalidrawImperativeAPI,
+  files: LaunchFile[],
+) => {
+  for (const file of files) {
+    try {
+      if (file.kind === "library") {
+        // the app already added it to the library folder
+        await excalidrawAPI.updateLibrary({
+          libraryItems: file.items,
+          merge: true,
+          openLibraryMenu: true,
+        });
+      } else {
+        await loadScene(excalidrawAPI, file);
+      }
+    } catch (error: any) {
+      console.error(error);
+      excalidrawAPI.updateScene({
+        appState: { errorMessage: `Couldn't open ${file.path}` },
+      });
+    }
+  }
+};
+
+/**
+ * Loads the files the desktop app was launched with, once the initial scene
+ * is in place so it doesn't replace them, then those of later launches
+ * forwarded to it and files opened from the tray.
+ */
+export const useHandleLaunchFiles = ({
+  excalidrawAPI,
+  initialized,
+}: {
+  excalidrawAPI: ExcalidrawImperativeAPI | null;
+  initialized: Promise<unknown>;
+}) => {
+  useEffect(() => {
+    if (!excalidrawAPI || !("__TAURI__" in window)) {
+      return;
+    }
+
+    const unlisteners: Promise<UnlistenFn>[] = [
+      listen<LaunchFile[]>("launch-files", (event) => {
+        loadLaunchFiles(excalidrawAPI, event.payload);
+      }),
+      listen<SceneFile>("open-file", (event) => {
+        loadLaunchFiles(excalidrawAPI, [{ kind: "scene", ...event.payload }]);
+      }),
+    ];
+    initialized
+      .then(() => invoke<LaunchFile[]>("take_launch_files"))
+      .then((files) => loadLaunchFiles(excalidrawAPI, files));
+
+    return () => {
+      unlisteners.forEach((unlisten) => unlisten.then((fn) => fn()));
+    };
+  }, [excalidrawAPI, initialized]);
+};
@@ -3,6 +3,7 @@ import { useEffect, useState, useMemo, useRef } from "react";
 import { ContinueDrawingIcon, PauseDrawingIcon } from "./icons";
 import { ToolButton } from "./ToolButton";
 import { capitalizeString } from "../utils";
+import { invoke } from "@tauri-apps/api";
 import { appWindow } from "@tauri-apps/api/window";
 import { type UnlistenFn } from "@tauri-apps/api/helpers/event";
 
@@ -95,8 +96,8 @@ function useTauriAppFocusState() {
 
   useEffect(() => {
     appWindow
-      .listen("tauri://focus  ", (event) => {
-        setFocused(true);
+      .listen<{ to: string }>("overlay-mode-changed", (event) => {
+        setFocused(event.payload.to === "drawing");
       })
       .then((fn) => (unSubFn.current = fn));
 
@@ -123,7 +124,7 @@ export function ExitDrawButton() {
       aria-label={capitalizeString("exit")}
       onClick={() => {
         setTimeout(() => {
-          appWindow.setIgnoreCursorEvents(true);
+          invoke("set_overlay_mode", { mode: "passThrough" });
         }, 100);
       }}
       // aria-keyshortcuts={shortcut}
@@ -283,13 +283,15 @@ const splitBuffers = (concatenatedBuffer: Uint8Array) => {
 const _encryptAndCompress = async (
   data: Uint8Array | string,
   encryptionKey: string,
+  iv?: Uint8Array,
 ) => {
-  const { encryptedBuffer, iv } = await encryptData(
+  const { encryptedBuffer, iv: usedIV } = await encryptData(
     encryptionKey,
     deflate(data),
+    iv,
   );
 
-  return { iv, buffer: new Uint8Array(encryptedBuffer) };
+  return { iv: usedIV, buffer: new Uint8Array(encryptedBuffer) };
 };
 
 /**
@@ -309,6 +311,8 @@ export const compressData = async <T extends Record<string, any> = never>(
   dataBuffer: Uint8Array,
   options: {
     encryptionKey: string;
+    /** only ever fixed to make test payloads reproducible */
+    iv?: Uint8Array;
   } & ([T] extends [never]
     ? {
         metadata?: T;
@@ -334,6 +338,7 @@ export const compressData = async <T extends Record<string, any> = never>(
   const { iv, buffer } = await _encryptAndCompress(
     concatBuffers(contentsMetadataBuffer, dataBuffer),
     options.encryptionKey,
+    options.iv,
   );
 
   return concatBuffers(encodingMetadataBuffer, iv, buffer);
@@ -49,10 +49,11 @@ export const getCryptoKey = (key: string, usage: KeyUsage) =>
 export const encryptData = async (
   key: string | CryptoKey,
   data: Uint8Array | ArrayBuffer | Blob | File | string,
+  /** only ever fixed to make test payloads reproducible */
+  iv: Uint8Array = createIV(),
 ): Promise<{ encryptedBuffer: ArrayBuffer; iv: Uint8Array }> => {
   const importedKey =
     typeof key === "string" ? await getCryptoKey(key, "encrypt") : key;
-  const iv = createIV();
   const buffer: ArrayBuffer | Uint8Array =
     typeof data === "string"
       ? new TextEncoder().encode(data)
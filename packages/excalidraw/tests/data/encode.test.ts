@@ -0,0 +1,51 @@
+import { readFileSync } from "node:fs";
+import { webcrypto } from "node:crypto";
+import { decompressData } from "../../data/encode";
+
+// the payloads the desktop app's codec (src-tauri/src/scene/encode.rs) is
+// tested against, regenerated by src-tauri/fixtures/compressed.test.ts
+const FIXTURES = new URL("../../../../src-tauri/fixtures/", import.meta.url);
+const KEY = "a4TG7upSM0JQhhp1bRVWCg";
+const FILE_DATA_URL = "data:image/png;base64,iVBORw0KGgo=";
+
+const fixture = (name: string) =>
+  new Uint8Array(readFileSync(new URL(name, FIXTURES)));
+
+describe("compressed payloads shared with the desktop app", () => {
+  beforeAll(() => {
+    // jsdom's crypto has no `subtle`
+    vi.stubGlobal("crypto", webcrypto);
+  });
+
+  afterAll(() => {
+    vi.unstubAllGlobals();
+  });
+
+  it("decodes the share link and file payloads", async () => {
+    const scene = await decompressData(fixture("scene.compressed"), {
+      decryptionKey: KEY,
+    });
+    expect(scene.metadata).toBe(null);
+    expect(scene.data).toEqual(fixture("scene.excalidraw"));
+
+    const file = await decompressData(fixture("file.compressed"), {
+      decryptionKey: KEY,
+    });
+    expect(file.metadata).toEqual({
+      id: "file-1",
+      mimeType: "image/png",
+      created: 1,
+      lastRetrieved: 2,
+    });
+    expect(new TextDecoder().decode(file.data)).toBe(FILE_DATA_URL);
+  });
+
+  it("decodes the payload written by the desktop app", async () => {
+    const { metadata, data } = await decompressData(
+      fixture("scene.rs.compressed"),
+      { decryptionKey: KEY },
+    );
+    expect(metadata).toBe(null);
+    expect(data).toEqual(fixture("scene.excalidraw"));
+  });
+});
@@ -8,6 +8,7 @@ import { syncInvalidIndices } from "../../fractionalIndex";
 import { randomInteger } from "../../random";
 import type { AppState } from "../../types";
 import { cloneJSON } from "../../utils";
+import fixtures from "../fixtures/reconcile.json";
 
 type Id = string;
 type ElementLike = {
@@ -139,158 +140,15 @@ describe("elements reconciliation", () => {
     //  :R means remote element was resolved
     //
     // if versions are missing, it defaults to version 0
+    //
+    // the cases are shared with the Rust port in src-tauri
     // -------------------------------------------------------------------------
 
-    test(["A:1", "B:1", "C:1"], ["B:2"], ["A:L", "B:R", "C:L"]);
-    test(["A:1", "B:1", "C"], ["B:2", "A:2"], ["B:R", "A:R", "C:L"]);
-    test(["A:2", "B:1", "C"], ["B:2", "A:1"], ["A:L", "B:R", "C:L"]);
-    test(["A:1", "C:1"], ["B:1"], ["A:L", "B:R", "C:L"]);
-    test(["A", "B"], ["A:1"], ["A:R", "B:L"]);
-    test(["A"], ["A", "B"], ["A:L", "B:R"]);
-    test(["A"], ["A:1", "B"], ["A:R", "B:R"]);
-    test(["A:2"], ["A:1", "B"], ["A:L", "B:R"]);
-    test(["A:2"], ["B", "A:1"], ["A:L", "B:R"]);
-    test(["A:1"], ["B", "A:2"], ["B:R", "A:R"]);
-    test(["A"], ["A:1"], ["A:R"]);
-    test(["A", "B:1", "D"], ["B", "C:2", "A"], ["C:R", "A:R", "B:L", "D:L"]);
-
-    // some of the following tests are kinda arbitrary and they're less
-    // likely to happen in real-world cases
-    test(["A", "B"], ["B:1", "A:1"], ["B:R", "A:R"]);
-    test(["A:2", "B:2"], ["B:1", "A:1"], ["A:L", "B:L"]);
-    test(["A", "B", "C"], ["A", "B:2", "G", "C"], ["A:L", "B:R", "G:R", "C:L"]);
-    test(["A", "B", "C"], ["A", "B:2", "G"], ["A:R", "B:R", "C:L", "G:R"]);
-    test(
-      ["A:2", "B:2", "C"],
-      ["D", "B:1", "A:3"],
-      ["D:R", "B:L", "A:R", "C:L"],
-    );
-    test(
-      ["A:2", "B:2", "C"],
-      ["D", "B:2", "A:3", "C"],
-      ["D:R", "B:L", "A:R", "C:L"],
-    );
-    test(
-      ["A", "B", "C", "D", "E", "F"],
-      ["A", "B:2", "X", "E:2", "F", "Y"],
-      ["A:L", "B:R", "X:R", "C:L", "E:R", "D:L", "F:L", "Y:R"],
-    );
-
-    // fractional elements (previously annotated)
-    test(
-      ["A", "B", "C"],
-      ["A", "B", "X", "Y", "Z"],
-      ["A:R", "B:R", "C:L", "X:R", "Y:R", "Z:R"],
-    );
-
-    test(["A"], ["X", "Y"], ["A:L", "X:R", "Y:R"]);
-    test(["A"], ["X", "Y", "Z"], ["A:L", "X:R", "Y:R", "Z:R"]);
-    test(["A", "B"], ["C", "D", "F"], ["A:L", "C:R", "B:L", "D:R", "F:R"]);
-
-    test(
-      ["A", "B", "C", "D"],
-      ["C:1", "B", "D:1"],
-      ["A:L", "C:R", "B:L", "D:R"],
-    );
-    test(
-      ["A", "B", "C"],
-      ["X", "A", "Y", "B", "Z"],
-      ["X:R", "A:R", "Y:R", "B:L", "C:L", "Z:R"],
-    );
-    test(
-      ["B", "A", "C"],
-      ["X", "A", "Y", "B", "Z"],
-      ["X:R", "A:R", "C:L", "Y:R", "B:R", "Z:R"],
-    );
-    test(["A", "B"], ["A", "X", "Y"], ["A:R", "B:L", "X:R", "Y:R"]);
-    test(
-      ["A", "B", "C", "D", "E"],
-      ["A", "X", "C", "Y", "D", "Z"],
-      ["A:R", "B:L", "X:R", "C:R", "Y:R", "D:R", "E:L", "Z:R"],
-    );
-    test(
-      ["X", "Y", "Z"],
-      ["A", "B", "C"],
-      ["A:R", "X:L", "B:R", "Y:L", "C:R", "Z:L"],
-    );
-    test(
-      ["X", "Y", "Z"],
-      ["A", "B", "C", "X", "D", "Y", "Z"],
-      ["A:R", "B:R", "C:R", "X:L", "D:R", "Y:L", "Z:L"],
-    );
-    test(
-      ["A", "B", "C", "D", "E"],
-      ["C", "X", "A", "Y", "D", "E:1"],
-      ["B:L", "C:L", "X:R", "A:R", "Y:R", "D:R", "E:R"],
-    );
-    test(
-      ["C:1", "B", "D:1"],
-      ["A", "B", "C:1", "D:1"],
-      ["A:R", "B:R", "C:R", "D:R"],
-    );
-
-    test(
-      ["C:1", "B", "D:1"],
-      ["A", "B", "C:2", "D:1"],
-      ["A:R", "B:L", "C:R", "D:L"],
-    );
-
-    test(
-      ["A", "B", "C", "D"],
-      ["A", "C:1", "B", "D:1"],
-      ["A:L", "C:R", "B:L", "D:R"],
-    );
-
-    test(
-      ["A", "B", "C", "D"],
-      ["C", "X", "B", "Y", "A", "Z"],
-      ["C:R", "D:L", "X:R", "B:R", "Y:R", "A:R", "Z:R"],
-    );
-
-    test(
-      ["A", "B", "C", "D"],
-      ["A", "B:1", "C:1"],
-      ["A:R", "B:R", "C:R", "D:L"],
-    );
-
-    test(
-      ["A", "B", "C", "D"],
-      ["A", "C:1", "B:1"],
-      ["A:R", "C:R", "B:R", "D:L"],
-    );
-
-    test(
-      ["A", "B", "C", "D"],
-      ["A", "C:1", "B", "D:1"],
-      ["A:R", "C:R", "B:R", "D:R"],
-    );
-
-    test(["A:1", "B:1", "C"], ["B:2"], ["A:L", "B:R", "C:L"]);
-    test(["A:1", "B:1", "C"], ["B:2", "C:2"], ["A:L", "B:R", "C:R"]);
-    test(["A", "B"], ["A", "C", "B", "D"], ["A:R", "C:R", "B:R", "D:R"]);
-    test(["A", "B"], ["B", "C", "D"], ["A:L", "B:R", "C:R", "D:R"]);
-    test(["A", "B"], ["C", "D"], ["A:L", "C:R", "B:L", "D:R"]);
-    test(["A", "B"], ["A", "B:1"], ["A:L", "B:R"]);
-    test(["A:2", "B"], ["A", "B:1"], ["A:L", "B:R"]);
-    test(["A:2", "B:2"], ["B:1"], ["A:L", "B:L"]);
-    test(["A:2", "B:2"], ["B:1", "C"], ["A:L", "B:L", "C:R"]);
-    test(["A:2", "B:2"], ["A", "C", "B:1"], ["A:L", "B:L", "C:R"]);
-
-    // concurrent convergency
-    test(["A", "B", "C"], ["A", "B", "D"], ["A:R", "B:R", "C:L", "D:R"]);
-    test(["A", "B", "E"], ["A", "B", "D"], ["A:R", "B:R", "D:R", "E:L"]);
-    test(
-      ["A", "B", "C"],
-      ["A", "B", "D", "E"],
-      ["A:R", "B:R", "C:L", "D:R", "E:R"],
-    );
-    test(
-      ["A", "B", "E"],
-      ["A", "B", "D", "C"],
-      ["A:R", "B:R", "D:R", "E:L", "C:R"],
-    );
-    test(["A", "B"], ["B", "D"], ["A:L", "B:R", "D:R"]);
-    test(["C", "A", "B"], ["C", "B", "D"], ["C:R", "A:L", "B:R", "D:R"]);
+    for (const group of fixtures.reconcile) {
+      for (const { local, remote, expected } of group.cases) {
+        test(local, remote, expected as `${string}:${"L" | "R"}`[]);
+      }
+    }
   });
 
   it("test identical elements reconciliation", () => {
@@ -315,22 +173,9 @@ describe("elements reconciliation", () => {
     // identical id/version/versionNonce/index
     // -------------------------------------------------------------------------
 
-    testIdentical(
-      [{ id: "A", version: 1, versionNonce: 1, index: "a0" }],
-      [{ id: "A", version: 1, versionNonce: 1, index: "a0" }],
-      ["A"],
-    );
-    testIdentical(
-      [
-        { id: "A", version: 1, versionNonce: 1, index: "a0" },
-        { id: "B", version: 1, versionNonce: 1, index: "a0" },
-      ],
-      [
-        { id: "B", version: 1, versionNonce: 1, index: "a0" },
-        { id: "A", version: 1, versionNonce: 1, index: "a0" },
-      ],
-      ["A", "B"],
-    );
+    for (const { local, remote, expected } of fixtures.identical) {
+      testIdentical(local, remote, expected);
+    }
 
     // actually identical (arrays and element objects)
     // -------------------------------------------------------------------------
@@ -0,0 +1,53 @@
+// Regenerates the compressData() payloads the desktop app's codec
+// (src-tauri/src/scene/encode.rs) is tested against, with a fixed IV so they
+// are reproducible:
+//
+//   UPDATE_FIXTURES=1 yarn test:app --watch=false src-tauri/fixtures
+//
+// Skipped otherwise. packages/excalidraw/tests/data/encode.test.ts checks
+// that the committed payloads decode.
+import { readFileSync, writeFileSync } from "node:fs";
+import { webcrypto } from "node:crypto";
+import { compressData } from "../../packages/excalidraw/data/encode";
+import { IV_LENGTH_BYTES } from "../../packages/excalidraw/data/encryption";
+
+const KEY = "a4TG7upSM0JQhhp1bRVWCg";
+const IV = Uint8Array.from({ length: IV_LENGTH_BYTES }, (_, i) => i);
+const FILE_DATA_URL = "data:image/png;base64,iVBORw0KGgo=";
+
+const fixture = (name: string) => new URL(name, import.meta.url);
+
+describe.runIf(process.env.UPDATE_FIXTURES)("compressed fixtures", () => {
+  beforeAll(() => {
+    // jsdom's crypto has no `subtle`
+    vi.stubGlobal("crypto", webcrypto);
+  });
+
+  afterAll(() => {
+    vi.unstubAllGlobals();
+  });
+
+  it("writes the share link and file payloads", async () => {
+    // a share link payload, as exportToBackend() uploads it
+    const scene = new Uint8Array(readFileSync(fixture("scene.excalidraw")));
+    writeFileSync(
+      fixture("scene.compressed"),
+      await compressData(scene, { encryptionKey: KEY, iv: IV }),
+    );
+
+    // an image, as encodeFilesForUpload() uploads it
+    writeFileSync(
+      fixture("file.compressed"),
+      await compressData(new TextEncoder().encode(FILE_DATA_URL), {
+        encryptionKey: KEY,
+        iv: IV,
+        metadata: {
+          id: "file-1",
+          mimeType: "image/png",
+          created: 1,
+          lastRetrieved: 2,
+        },
+      }),
+    );
+  });
+});
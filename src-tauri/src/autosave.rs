@@ -0,0 +1,339 @@
+use std::{
+    fs,
+    path::{Path, PathBuf},
+    sync::{
+        mpsc::{self, RecvTimeoutError, Sender},
+        Arc, Mutex,
+    },
+    thread::{self, JoinHandle},
+    time::{Duration, Instant},
+};
+
+use serde::Serialize;
+use tauri::{api::dialog, AppHandle, Manager, Runtime, State};
+
+use crate::{
+    error::{Error, Result},
+    files,
+    overlay::MAIN_WINDOW,
+    scene::{self, SCENE_TYPE},
+};
+
+/// Emitted to the webview with a [`Snapshot`] the user chose to restore.
+pub const RESTORE_SCENE: &str = "restore-scene";
+
+const AUTOSAVE_DIR: &str = "autosave";
+/// Present while the app runs; finding it on startup means the previous
+/// session didn't shut down cleanly.
+const SESSION_SENTINEL: &str = "session.lock";
+const SNAPSHOT_EXTENSION: &str = "excalidraw";
+const KEEP_SNAPSHOTS: usize = 10;
+
+/// Quiet period after the last pushed snapshot before it is written.
+const DEBOUNCE: Duration = Duration::from_secs(2);
+/// Upper bound on how long continuous editing can postpone a write.
+const MAX_DELAY: Duration = Duration::from_secs(15);
+
+#[derive(Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct Snapshot {
+    pub path: PathBuf,
+    /// Milliseconds since the unix epoch.
+    pub saved_at: i64,
+    pub contents: String,
+}
+
+/// Writes scene snapshots pushed by the webview to the app data dir on a
+/// background thread, debounced and atomically, keeping the latest few.
+pub struct Autosave {
+    dir: PathBuf,
+    sender: Mutex<Option<Sender<String>>>,
+    worker: Mutex<Option<JoinHandle<()>>>,
+    /// Latest snapshot of a session that crashed, until restored or
+    /// discarded. Shared with the writer, which leaves it out of pruning.
+    recovery: Arc<Mutex<Option<PathBuf>>>,
+}
+
+impl Autosave {
+    /// Starts the writer and marks the session as running. Any snapshot left
+    /// by a previous session that didn't exit cleanly becomes the recovery
+    /// candidate.
+    pub fn start(dir: PathBuf) -> Result<Self> {
+        fs::create_dir_all(&dir)?;
+        let sentinel = dir.join(SESSION_SENTINEL);
+        let recovery = if sentinel.exists() {
+            list_snapshots(&dir)?.pop()
+        } else {
+            None
+        };
+        fs::write(&sentinel, std::process::id().to_string())?;
+        let recovery = Arc::new(Mutex::new(recovery));
+
+        let (sender, receiver) = mpsc::channel::<String>();
+        let worker_dir = dir.clone();
+        let worker_recovery = Arc::clone(&recovery);
+        let worker = thread::spawn(move || {
+            while let Ok(mut snapshot) = receiver.recv() {
+                let first = Instant::now();
+                loop {
+                    match receiver.recv_timeout(DEBOUNCE) {
+                        Ok(newer) => snapshot = newer,
+                        Err(RecvTimeoutError::Timeout) => break,
+                        Err(RecvTimeoutError::Disconnected) => break,
+                    }
+                    if first.elapsed() >= MAX_DELAY {
+                        break;
+                    }
+                }
+                let keep = worker_recovery.lock().unwrap().clone();
+                if let Err(err) = write_snapshot(&worker_dir, &snapshot, keep.as_deref()) {
+                    log::error!("autosave failed: {}", err);
+                }
+            }
+        });
+
+        Ok(Self {
+            dir,
+            sender: Mutex::new(Some(sender)),
+            worker: Mutex::new(Some(worker)),
+            recovery,
+        })
+    }
+
+    pub fn push(&self, snapshot: String) -> Result<()> {
+        match &*self.sender.lock().unwrap() {
+            Some(sender) => sender
+                .send(snapshot)
+                .map_err(|_| Error::Other("autosave has stopped".into())),
+            None => Err(Error::Other("autosave has stopped".into())),
+        }
+    }
+
+    /// Flushes the pending snapshot and marks the session as cleanly closed.
+    pub fn shutdown(&self) {
+        self.sender.lock().unwrap().take();
+        if let Some(worker) = self.worker.lock().unwrap().take() {
+            let _ = worker.join();
+        }
+        let _ = fs::remove_file(self.dir.join(SESSION_SENTINEL));
+    }
+
+    pub fn recovery(&self) -> Option<PathBuf> {
+        self.recovery.lock().unwrap().clone()
+    }
+
+    pub fn take_recovery(&self) -> Option<PathBuf> {
+        self.recovery.lock().unwrap().take()
+    }
+}
+
+pub fn autosave_dir<R: Runtime>(app: &AppHandle<R>) -> Result<PathBuf> {
+    app.path_resolver()
+        .app_data_dir()
+        .map(|dir| dir.join(AUTOSAVE_DIR))
+        .ok_or_else(|| Error::Other("could not resolve the app data dir".into()))
+}
+
+/// Writes `snapshot` and prunes all but the latest few, sparing `keep`.
+fn write_snapshot(dir: &Path, snapshot: &str, keep: Option<&Path>) -> Result<()> {
+    // zero-padded so lexical order is chronological
+    let path = dir.join(format!(
+        "{:013}.{}",
+        scene::now_millis(),
+        SNAPSHOT_EXTENSION
+    ));
+    files::write_atomic(&path, snapshot.as_bytes())?;
+
+    let mut snapshots = list_snapshots(dir)?;
+    snapshots.retain(|path| Some(path.as_path()) != keep);
+    let stale = snapshots.len().saturating_sub(KEEP_SNAPSHOTS);
+    for path in &snapshots[..stale] {
+        fs::remove_file(path)?;
+    }
+    Ok(())
+}
+
+/// Snapshot files in `dir`, oldest first.
+fn list_snapshots(dir: &Path) -> Result<Vec<PathBuf>> {
+    let mut snapshots = Vec::new();
+    for entry in fs::read_dir(dir)? {
+        let path = entry?.path();
+        if path
+            .extension()
+            .map_or(false, |ext| ext == SNAPSHOT_EXTENSION)
+        {
+            snapshots.push(path);
+        }
+    }
+    snapshots.sort();
+    Ok(snapshots)
+}
+
+fn read_snapshot(path: &Path) -> Result<Snapshot> {
+    let saved_at = path
+        .file_stem()
+        .and_then(|stem| stem.to_str())
+        .and_then(|stem| stem.parse().ok())
+        .unwrap_or_default();
+    Ok(Snapshot {
+        path: path.to_path_buf(),
+        saved_at,
+        contents: fs::read_to_string(path)?,
+    })
+}
+
+/// Asks the user whether to restore the scene of a crashed session, sending
+/// it to the webview if they agree.
+pub fn offer_recovery<R: Runtime>(app: &AppHandle<R>) {
+    if app.state::<Autosave>().recovery().is_none() {
+        return;
+    }
+    let window = app.get_window(MAIN_WINDOW);
+    let handle = app.clone();
+    dialog::ask(
+        window.as_ref(),
+        "Restore drawing?",
+        "Excalidraw didn't shut down properly. Restore the last autosaved drawing?",
+        move |restore| {
+            let autosave = handle.state::<Autosave>();
+            let path = match autosave.take_recovery() {
+                Some(path) if restore => path,
+                _ => return,
+            };
+            let result =
+                read_snapshot(&path).and_then(|snapshot| match handle.get_window(MAIN_WINDOW) {
+                    Some(window) => Ok(window.emit(RESTORE_SCENE, snapshot)?),
+                    None => Ok(()),
+                });
+            if let Err(err) = result {
+                log::error!("failed to restore {}: {}", path.display(), err);
+            }
+        },
+    );
+}
+
+/// Rejects snapshots that aren't scene JSON at all. Anything else the editor
+/// produced is stored as it is, even what the scene model can't read.
+fn check_snapshot(snapshot: &str) -> Result<()> {
+    let snapshot: serde_json::Value = serde_json::from_str(snapshot)?;
+    if snapshot.is_object() && snapshot["type"] == SCENE_TYPE {
+        Ok(())
+    } else {
+        Err(Error::Other("not an Excalidraw scene".into()))
+    }
+}
+
+/// Queues a snapshot of the current scene, serialized as `.excalidraw` JSON.
+#[tauri::command]
+pub fn autosave_scene(autosave: State<Autosave>, snapshot: String) -> Result<()> {
+    check_snapshot(&snapshot)?;
+    autosave.push(snapshot)
+}
+
+/// The snapshot a crashed session left behind, if it hasn't been restored or
+/// discarded yet.
+#[tauri::command]
+pub fn get_recovery(autosave: State<Autosave>) -> Result<Option<Snapshot>> {
+    autosave
+        .recovery()
+        .as_deref()
+        .map(read_snapshot)
+        .transpose()
+}
+
+#[tauri::command]
+pub fn discard_recovery(autosave: State<Autosave>) {
+    autosave.take_recovery();
+}
+
+/// Autosaved snapshots of the current and previous sessions, newest first.
+#[tauri::command]
+pub fn list_autosaves<R: Runtime>(app: AppHandle<R>) -> Result<Vec<Snapshot>> {
+    let mut snapshots = list_snapshots(&autosave_dir(&app)?)?;
+    snapshots.reverse();
+    snapshots.iter().map(|path| read_snapshot(path)).collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::scene::test_util::temp_dir;
+
+    #[test]
+    fn offers_recovery_only_after_unclean_shutdown() {
+        let dir = temp_dir("autosave");
+
+        let scene =
+            r#"{"type":"excalidraw","version":2,"source":"test","elements":[],"appState":{}}"#;
+
+        let autosave = Autosave::start(dir.clone()).unwrap();
+        assert_eq!(autosave.recovery(), None);
+        autosave.push(scene.into()).unwrap();
+        autosave.shutdown();
+        assert_eq!(list_snapshots(&dir).unwrap().len(), 1);
+        assert!(!dir.join(SESSION_SENTINEL).exists());
+
+        // never shut down, as if the app crashed
+        let autosave = Autosave::start(dir.clone()).unwrap();
+        assert_eq!(autosave.recovery(), None);
+        drop(autosave);
+
+        let autosave = Autosave::start(dir.clone()).unwrap();
+        let recovery = autosave.recovery().unwrap();
+        assert_eq!(read_snapshot(&recovery).unwrap().contents, scene);
+        autosave.shutdown();
+
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn accepts_any_scene_json() {
+        // a scene from a newer version the model may not read
+        check_snapshot(r#"{"type":"excalidraw","elements":[{"type":"hologram","x":"left"}]}"#)
+            .unwrap();
+        assert!(check_snapshot(r#"{"type":"excalidrawlib","libraryItems":[]}"#).is_err());
+        assert!(check_snapshot("[]").is_err());
+        assert!(check_snapshot("{").is_err());
+    }
+
+    #[test]
+    fn keeps_latest_snapshots() {
+        let dir = temp_dir("autosave-prune");
+
+        for i in 0..KEEP_SNAPSHOTS + 3 {
+            write_snapshot(&dir, &i.to_string(), None).unwrap();
+            thread::sleep(Duration::from_millis(2));
+        }
+        let snapshots = list_snapshots(&dir).unwrap();
+        assert_eq!(snapshots.len(), KEEP_SNAPSHOTS);
+        assert_eq!(
+            read_snapshot(snapshots.last().unwrap()).unwrap().contents,
+            (KEEP_SNAPSHOTS + 2).to_string()
+        );
+
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn keeps_the_recovery_snapshot() {
+        let dir = temp_dir("autosave-recovery");
+        let scene =
+            r#"{"type":"excalidraw","version":2,"source":"test","elements":[],"appState":{}}"#;
+        write_snapshot(&dir, scene, None).unwrap();
+        // left behind by a crash
+        fs::write(dir.join(SESSION_SENTINEL), "").unwrap();
+
+        let autosave = Autosave::start(dir.clone()).unwrap();
+        let recovery = autosave.recovery().unwrap();
+        // the new session autosaves while the user makes up their mind
+        for i in 0..KEEP_SNAPSHOTS + 3 {
+            thread::sleep(Duration::from_millis(2));
+            write_snapshot(&dir, &i.to_string(), Some(&recovery)).unwrap();
+        }
+        assert_eq!(list_snapshots(&dir).unwrap().len(), KEEP_SNAPSHOTS + 1);
+        assert_eq!(read_snapshot(&recovery).unwrap().contents, scene);
+        autosave.shutdown();
+
+        fs::remove_dir_all(&dir).unwrap();
+    }
+}
@@ -0,0 +1,338 @@
+//! Subcommands that run without opening a window, for scripts and CI, e.g.
+//! `excalidraw-desktop export diagram.excalidraw -o diagram.png --scale 2`,
+//! or for servers, e.g. `excalidraw-desktop share-server /srv/share-links`.
+
+use std::{
+    ffi::OsString,
+    fs,
+    net::{Ipv4Addr, SocketAddr},
+    path::PathBuf,
+};
+
+use crate::{
+    error::{Error, Result},
+    export::{pdf, png, svg, ExportOptions},
+    files,
+    scene::{restore, Scene},
+    share::{self, ShareServer, ShareServerInfo},
+};
+
+const USAGE: &str =
+    "usage: excalidraw-desktop export <input> [-o <output>] [--format <svg|png|pdf>]
+    [--scale <n>] [--padding <px>] [--dark] [--background <color|transparent>]
+
+Renders a .excalidraw file, or a PNG/SVG with an embedded scene, to SVG, PNG
+or PDF. PDFs get a page per frame, plus one for anything outside the
+frames. The format defaults to the output's extension, and the output to
+the input with the format's extension.";
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Format {
+    Svg,
+    Png,
+    Pdf,
+}
+
+impl Format {
+    fn from_extension(extension: &str) -> Option<Self> {
+        match extension.to_ascii_lowercase().as_str() {
+            SVG_EXTENSION => Some(Self::Svg),
+            PNG_EXTENSION => Some(Self::Png),
+            PDF_EXTENSION => Some(Self::Pdf),
+            _ => None,
+        }
+    }
+
+    fn extension(self) -> &'static str {
+        match self {
+            Self::Svg => SVG_EXTENSION,
+            Self::Png => PNG_EXTENSION,
+            Self::Pdf => PDF_EXTENSION,
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct ExportArgs {
+    pub input: PathBuf,
+    pub output: PathBuf,
+    pub format: Format,
+    pub options: ExportOptions,
+}
+
+impl ExportArgs {
+    /// Parses the arguments following `export`.
+    pub fn parse<I, S>(args: I) -> Result<Self>
+    where
+        I: IntoIterator<Item = S>,
+        S: Into<OsString>,
+    {
+        let mut input = None;
+        let mut output: Option<PathBuf> = None;
+        let mut format = None;
+        let mut options = ExportOptions::default();
+        let mut args = args.into_iter().map(Into::into);
+        while let Some(arg) = args.next() {
+            let flag = match arg.to_str() {
+                Some(flag) if flag.starts_with('-') => flag.to_owned(),
+                _ if input.is_none() => {
+                    input = Some(PathBuf::from(arg));
+                    continue;
+                }
+                _ => return Err(usage_error("only one input file can be exported at a time")),
+            };
+            let mut value = || {
+                args.next()
+                    .ok_or_else(|| usage_error(&format!("{} needs a value", flag)))
+            };
+            match flag.as_str() {
+                "-o" | "--output" => output = Some(PathBuf::from(value()?)),
+                "--format" => {
+                    let value = value()?;
+                    format = Some(
+                        value
+                            .to_str()
+                            .and_then(Format::from_extension)
+                            .ok_or_else(|| usage_error("--format must be svg, png or pdf"))?,
+                    );
+                }
+                "--scale" => options.scale = number(&flag, value()?)?,
+                "--padding" => options.padding = number(&flag, value()?)?,
+                "--background" => {
+                    let color = value()?;
+                    options.background = Some(
+                        color
+                            .into_string()
+                            .map_err(|_| usage_error("--background is not a color"))?,
+                    );
+                }
+                "--dark" => options.dark = true,
+                _ => return Err(usage_error(&format!("unknown option {}", flag))),
+            }
+        }
+
+        let input = input.ok_or_else(|| usage_error("no input file given"))?;
+        if options.scale <= 0.0 {
+            return Err(usage_error("--scale must be positive"));
+        }
+        if options.padding < 0.0 {
+            return Err(usage_error("--padding can't be negative"));
+        }
+        let format = match (format, &output) {
+            (Some(format), _) => format,
+            (None, Some(output)) => output
+                .extension()
+                .and_then(|ext| ext.to_str())
+                .and_then(Format::from_extension)
+                .ok_or_else(|| {
+                    usage_error(&format!(
+                        "can't tell the format of {}, pass --format",
+                        output.display()
+                    ))
+                })?,
+            (None, None) => Format::Svg,
+        };
+        let output = output.unwrap_or_else(|| input.with_extension(format.extension()));
+        Ok(Self {
+            input,
+            output,
+            format,
+            options,
+        })
+    }
+}
+
+const SVG_EXTENSION: &str = "svg";
+const PNG_EXTENSION: &str = "png";
+const PDF_EXTENSION: &str = "pdf";
+
+fn usage_error(message: &str) -> Error {
+    Error::Other(format!("{}\n\n{}", message, USAGE))
+}
+
+const SHARE_SERVER_USAGE: &str = "usage: excalidraw-desktop share-server <dir> [--port <n>]
+
+Serves share links from <dir> on all interfaces, for when the app is built
+with VITE_APP_BACKEND_V2_GET_URL and VITE_APP_BACKEND_V2_POST_URL pointing
+at this machine.";
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct ShareServerArgs {
+    pub dir: PathBuf,
+    pub port: u16,
+}
+
+impl ShareServerArgs {
+    /// Parses the arguments following `share-server`.
+    pub fn parse<I, S>(args: I) -> Result<Self>
+    where
+        I: IntoIterator<Item = S>,
+        S: Into<OsString>,
+    {
+        let usage_error =
+            |message: &str| Error::Other(format!("{}\n\n{}", message, SHARE_SERVER_USAGE));
+        let mut dir = None;
+        let mut port = share::DEFAULT_PORT;
+        let mut args = args.into_iter().map(Into::into);
+        while let Some(arg) = args.next() {
+            match arg.to_str() {
+                Some("--port") => {
+                    port = args
+                        .next()
+                        .and_then(|value| value.to_str()?.parse().ok())
+                        .ok_or_else(|| usage_error("--port needs a port number"))?;
+                }
+                Some(flag) if flag.starts_with('-') => {
+                    return Err(usage_error(&format!("unknown option {}", flag)))
+                }
+                _ if dir.is_none() => dir = Some(PathBuf::from(arg)),
+                _ => return Err(usage_error("only one directory can be served")),
+            }
+        }
+        let dir = dir.ok_or_else(|| usage_error("no directory given"))?;
+        Ok(Self { dir, port })
+    }
+}
+
+/// Serves share links until the process is killed.
+pub fn share_server(args: &ShareServerArgs) -> Result<()> {
+    let addr = SocketAddr::from((Ipv4Addr::UNSPECIFIED, args.port));
+    let server = ShareServer::start(addr, args.dir.clone())?;
+    let info = ShareServerInfo::new(&server);
+    println!("VITE_APP_BACKEND_V2_GET_URL={}", info.get_url);
+    println!("VITE_APP_BACKEND_V2_POST_URL={}", info.post_url);
+    server.wait();
+    Ok(())
+}
+
+fn number(flag: &str, value: OsString) -> Result<f64> {
+    value
+        .to_str()
+        .and_then(|value| value.parse::<f64>().ok())
+        .filter(|value| value.is_finite())
+        .ok_or_else(|| usage_error(&format!("{} needs a number", flag)))
+}
+
+/// Renders the input scene and writes it to the output.
+pub fn export(args: &ExportArgs) -> Result<()> {
+    let json = restore::restore_json(&files::read_scene_json(&args.input)?)?;
+    let scene = Scene::parse(&json)?;
+    let contents = match args.format {
+        Format::Svg => svg::render(&scene, &args.options)?.into_bytes(),
+        Format::Png => png::render(&scene, &args.options)?,
+        Format::Pdf => pdf::render(&scene, &args.options)?,
+    };
+    if let Some(dir) = args
+        .output
+        .parent()
+        .filter(|dir| !dir.as_os_str().is_empty())
+    {
+        fs::create_dir_all(dir)?;
+    }
+    files::write_atomic(&args.output, &contents)
+}
+
+/// Runs the subcommand in `args`, the arguments following the executable
+/// name, returning the process exit code. `None` if there is no subcommand
+/// and the app should start as usual.
+pub fn run<I, S>(args: I) -> Option<i32>
+where
+    I: IntoIterator<Item = S>,
+    S: Into<OsString>,
+{
+    let mut args = args.into_iter().map(Into::into);
+    let command = args.next()?;
+    let result = if command == "export" {
+        ExportArgs::parse(args).and_then(|args| {
+            export(&args)?;
+            println!("{}", args.output.display());
+            Ok(())
+        })
+    } else if command == "share-server" {
+        ShareServerArgs::parse(args).and_then(|args| share_server(&args))
+    } else {
+        return None;
+    };
+    Some(match result {
+        Ok(()) => 0,
+        Err(err) => {
+            eprintln!("{} failed: {}", command.to_string_lossy(), err);
+            1
+        }
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parses_export_options() {
+        let args = ExportArgs::parse([
+            "docs/flow.excalidraw",
+            "--scale",
+            "2",
+            "--padding",
+            "0",
+            "--dark",
+            "--background",
+            "transparent",
+            "-o",
+            "out/flow.png",
+        ])
+        .unwrap();
+        assert_eq!(args.input, PathBuf::from("docs/flow.excalidraw"));
+        assert_eq!(args.output, PathBuf::from("out/flow.png"));
+        assert_eq!(args.format, Format::Png);
+        assert_eq!(
+            args.options,
+            ExportOptions {
+                scale: 2.0,
+                padding: 0.0,
+                dark: true,
+                background: Some("transparent".into()),
+            }
+        );
+
+        let args = ExportArgs::parse(["flow.excalidraw"]).unwrap();
+        assert_eq!(args.output, PathBuf::from("flow.svg"));
+        assert_eq!(args.format, Format::Svg);
+        assert_eq!(args.options, ExportOptions::default());
+
+        let args = ExportArgs::parse(["slides.excalidraw", "--format", "pdf"]).unwrap();
+        assert_eq!(args.output, PathBuf::from("slides.pdf"));
+        assert_eq!(args.format, Format::Pdf);
+    }
+
+    #[test]
+    fn rejects_bad_arguments() {
+        assert!(ExportArgs::parse(Vec::<String>::new()).is_err());
+        assert!(ExportArgs::parse(["a.excalidraw", "b.excalidraw"]).is_err());
+        assert!(ExportArgs::parse(["a.excalidraw", "--scale"]).is_err());
+        assert!(ExportArgs::parse(["a.excalidraw", "--scale", "0"]).is_err());
+        assert!(ExportArgs::parse(["a.excalidraw", "--padding", "x"]).is_err());
+        assert!(ExportArgs::parse(["a.excalidraw", "--frames"]).is_err());
+        assert!(ExportArgs::parse(["a.excalidraw", "--format", "gif"]).is_err());
+        assert!(ExportArgs::parse(["a.excalidraw", "-o", "a.gif"]).is_err());
+    }
+
+    #[test]
+    fn parses_share_server_options() {
+        let args = ShareServerArgs::parse(["/srv/share", "--port", "8080"]).unwrap();
+        assert_eq!(args.dir, PathBuf::from("/srv/share"));
+        assert_eq!(args.port, 8080);
+        let args = ShareServerArgs::parse(["share"]).unwrap();
+        assert_eq!(args.port, share::DEFAULT_PORT);
+
+        assert!(ShareServerArgs::parse(Vec::<String>::new()).is_err());
+        assert!(ShareServerArgs::parse(["a", "b"]).is_err());
+        assert!(ShareServerArgs::parse(["a", "--port", "http"]).is_err());
+        assert!(ShareServerArgs::parse(["a", "--dark"]).is_err());
+    }
+
+    #[test]
+    fn only_handles_subcommands() {
+        assert_eq!(run(["drawing.excalidraw"]), None);
+        assert_eq!(run(Vec::<String>::new()), None);
+    }
+}
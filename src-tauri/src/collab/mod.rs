@@ -0,0 +1,117 @@
+//! Hosting collaboration sessions from the desktop app. The relay speaks the
+//! socket.io protocol of the hosted room server, so teammates on the same
+//! network point their collab socket at the host instead, no internet
+//! needed.
+
+mod packet;
+mod relay;
+
+use std::{
+    net::{IpAddr, Ipv4Addr, SocketAddr, UdpSocket},
+    sync::Mutex,
+};
+
+use serde::Serialize;
+use tauri::State;
+
+use crate::{
+    error::{Error, Result},
+    scene::{reconcile, Element},
+};
+use relay::RelayServer;
+
+/// The port `VITE_APP_WS_SERVER_URL` uses in development.
+pub const DEFAULT_PORT: u16 = 3002;
+
+/// The relay this app hosts, if any.
+#[derive(Default)]
+pub struct CollabHost(Mutex<Option<RelayServer>>);
+
+impl CollabHost {
+    pub fn stop(&self) {
+        if let Some(server) = self.0.lock().unwrap().take() {
+            server.stop();
+        }
+    }
+}
+
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct HostInfo {
+    pub port: u16,
+    /// Server URLs to connect `socket.io-client` to, the LAN address first
+    /// when there is one.
+    pub urls: Vec<String>,
+    pub clients: usize,
+}
+
+impl HostInfo {
+    fn new(server: &RelayServer) -> Self {
+        let port = server.local_addr().port();
+        let urls = lan_address()
+            .into_iter()
+            .chain(Some(IpAddr::V4(Ipv4Addr::LOCALHOST)))
+            .map(|ip| format!("http://{}", SocketAddr::new(ip, port)))
+            .collect();
+        Self {
+            port,
+            urls,
+            clients: server.relay().client_count(),
+        }
+    }
+}
+
+/// The address other machines reach this one at, from the interface the OS
+/// would route outgoing traffic through. Connecting a UDP socket sends
+/// nothing.
+pub fn lan_address() -> Option<IpAddr> {
+    let socket = UdpSocket::bind((Ipv4Addr::UNSPECIFIED, 0)).ok()?;
+    socket.connect((Ipv4Addr::new(10, 254, 254, 254), 1)).ok()?;
+    let ip = socket.local_addr().ok()?.ip();
+    if ip.is_loopback() || ip.is_unspecified() {
+        None
+    } else {
+        Some(ip)
+    }
+}
+
+/// Starts hosting on all interfaces, on `port` or [`DEFAULT_PORT`].
+#[tauri::command]
+pub fn start_collab_host(port: Option<u16>, host: State<CollabHost>) -> Result<HostInfo> {
+    let mut server = host.0.lock().unwrap();
+    if server.is_some() {
+        return Err(Error::Other(
+            "already hosting a collaboration session".into(),
+        ));
+    }
+    let addr = SocketAddr::from((Ipv4Addr::UNSPECIFIED, port.unwrap_or(DEFAULT_PORT)));
+    let started = RelayServer::start(addr)?;
+    log::info!("hosting collaboration on {}", started.local_addr());
+    let info = HostInfo::new(&started);
+    *server = Some(started);
+    Ok(info)
+}
+
+/// Stops hosting, disconnecting everyone.
+#[tauri::command]
+pub fn stop_collab_host(host: State<CollabHost>) {
+    host.stop();
+}
+
+#[tauri::command]
+pub fn get_collab_host(host: State<CollabHost>) -> Option<HostInfo> {
+    host.0.lock().unwrap().as_ref().map(HostInfo::new)
+}
+
+/// `reconcileElements`: merges a collaborator's elements into the local
+/// ones. `editing` are the ids of elements the user is in the middle of
+/// editing, resizing or creating, which keep their local copy.
+#[tauri::command]
+pub fn reconcile_elements(
+    local: Vec<Element>,
+    remote: Vec<Element>,
+    editing: Vec<String>,
+) -> Result<Vec<Element>> {
+    let editing: Vec<&str> = editing.iter().map(String::as_str).collect();
+    reconcile::reconcile_elements(&local, &remote, &editing)
+}
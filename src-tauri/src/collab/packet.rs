@@ -0,0 +1,283 @@
+//! The parts of the engine.io v4 and socket.io v5 wire formats that
+//! `socket.io-client` uses over a WebSocket.
+//!
+//! Each engine.io text frame starts with a packet type digit. Messages (`4`)
+//! carry a socket.io packet, `<type>[<attachments>-][<nsp>,][<id>][<json>]`.
+//! Binary arguments are sent as separate binary frames after the text frame
+//! and are referenced from the JSON by `{"_placeholder":true,"num":<n>}`.
+
+use serde_json::{json, Value};
+use tungstenite::Message;
+
+use crate::error::{Error, Result};
+
+/// engine.io packet types, the first character of each text frame.
+pub const OPEN: char = '0';
+pub const CLOSE: char = '1';
+pub const PING: char = '2';
+pub const PONG: char = '3';
+pub const MESSAGE: char = '4';
+
+/// The namespace `io(url)` connects to.
+pub const DEFAULT_NAMESPACE: &str = "/";
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum PacketType {
+    Connect,
+    Disconnect,
+    Event,
+    Ack,
+    ConnectError,
+    BinaryEvent,
+    BinaryAck,
+}
+
+impl PacketType {
+    fn from_char(c: char) -> Option<Self> {
+        Some(match c {
+            '0' => Self::Connect,
+            '1' => Self::Disconnect,
+            '2' => Self::Event,
+            '3' => Self::Ack,
+            '4' => Self::ConnectError,
+            '5' => Self::BinaryEvent,
+            '6' => Self::BinaryAck,
+            _ => return None,
+        })
+    }
+
+    fn to_char(self) -> char {
+        match self {
+            Self::Connect => '0',
+            Self::Disconnect => '1',
+            Self::Event => '2',
+            Self::Ack => '3',
+            Self::ConnectError => '4',
+            Self::BinaryEvent => '5',
+            Self::BinaryAck => '6',
+        }
+    }
+
+    fn is_binary(self) -> bool {
+        matches!(self, Self::BinaryEvent | Self::BinaryAck)
+    }
+}
+
+/// A socket.io packet. Binary attachments stay separate from `data`,
+/// which keeps the placeholders, so they can be relayed without copying.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Packet {
+    pub kind: PacketType,
+    pub namespace: String,
+    pub id: Option<u64>,
+    pub data: Option<Value>,
+    pub attachments: Vec<Vec<u8>>,
+}
+
+impl Packet {
+    /// An event emitted to the default namespace. `attachments` are the
+    /// binaries the placeholders in `args` point at.
+    pub fn event(name: &str, args: &[Value], attachments: Vec<Vec<u8>>) -> Self {
+        let mut data = vec![Value::from(name)];
+        data.extend_from_slice(args);
+        Self {
+            kind: if attachments.is_empty() {
+                PacketType::Event
+            } else {
+                PacketType::BinaryEvent
+            },
+            namespace: DEFAULT_NAMESPACE.into(),
+            id: None,
+            data: Some(Value::Array(data)),
+            attachments,
+        }
+    }
+
+    pub fn connect(sid: &str) -> Self {
+        Self {
+            kind: PacketType::Connect,
+            namespace: DEFAULT_NAMESPACE.into(),
+            id: None,
+            data: Some(json!({ "sid": sid })),
+            attachments: Vec::new(),
+        }
+    }
+
+    pub fn connect_error(namespace: &str, message: &str) -> Self {
+        Self {
+            kind: PacketType::ConnectError,
+            namespace: namespace.into(),
+            id: None,
+            data: Some(json!({ "message": message })),
+            attachments: Vec::new(),
+        }
+    }
+
+    /// Parses the socket.io packet in an engine.io message, returning it
+    /// with the number of binary frames that complete it.
+    pub fn decode(text: &str) -> Result<(Self, usize)> {
+        let invalid = || Error::Other(format!("invalid socket.io packet {:?}", text));
+        let kind = text
+            .chars()
+            .next()
+            .and_then(PacketType::from_char)
+            .ok_or_else(invalid)?;
+
+        let mut rest = &text[1..];
+        let mut expected = 0;
+        if kind.is_binary() {
+            let dash = rest.find('-').ok_or_else(invalid)?;
+            expected = rest[..dash].parse().map_err(|_| invalid())?;
+            rest = &rest[dash + 1..];
+        }
+
+        let mut namespace = DEFAULT_NAMESPACE.to_string();
+        if rest.starts_with('/') {
+            let end = rest.find(',').unwrap_or(rest.len());
+            namespace = rest[..end].to_string();
+            rest = rest.get(end + 1..).unwrap_or("");
+        }
+
+        let digits = rest
+            .find(|c: char| !c.is_ascii_digit())
+            .unwrap_or(rest.len());
+        let id = if digits > 0 {
+            Some(rest[..digits].parse().map_err(|_| invalid())?)
+        } else {
+            None
+        };
+        rest = &rest[digits..];
+
+        let data = if rest.is_empty() {
+            None
+        } else {
+            Some(serde_json::from_str::<Value>(rest)?)
+        };
+        if matches!(kind, PacketType::Event | PacketType::BinaryEvent)
+            && !data
+                .as_ref()
+                .map_or(false, |data| data.get(0).map_or(false, Value::is_string))
+        {
+            return Err(invalid());
+        }
+
+        let packet = Self {
+            kind,
+            namespace,
+            id,
+            data,
+            attachments: Vec::with_capacity(expected),
+        };
+        Ok((packet, expected))
+    }
+
+    /// The frames to send for this packet: the engine.io message, then one
+    /// binary frame per attachment.
+    pub fn encode(&self) -> Vec<Message> {
+        let mut text = String::new();
+        text.push(MESSAGE);
+        text.push(self.kind.to_char());
+        if self.kind.is_binary() {
+            text.push_str(&format!("{}-", self.attachments.len()));
+        }
+        if self.namespace != DEFAULT_NAMESPACE {
+            text.push_str(&self.namespace);
+            text.push(',');
+        }
+        if let Some(id) = self.id {
+            text.push_str(&id.to_string());
+        }
+        if let Some(data) = &self.data {
+            text.push_str(&data.to_string());
+        }
+
+        let mut frames = vec![Message::Text(text)];
+        frames.extend(self.attachments.iter().cloned().map(Message::Binary));
+        frames
+    }
+
+    /// The event name and its arguments, for event packets.
+    pub fn as_event(&self) -> Option<(&str, &[Value])> {
+        if !matches!(self.kind, PacketType::Event | PacketType::BinaryEvent) {
+            return None;
+        }
+        let data = self.data.as_ref()?.as_array()?;
+        let (name, args) = data.split_first()?;
+        Some((name.as_str()?, args))
+    }
+}
+
+/// The engine.io handshake that opens a session.
+pub fn open(sid: &str, ping_interval_ms: u64, ping_timeout_ms: u64, max_payload: usize) -> Message {
+    let handshake = json!({
+        "sid": sid,
+        // already on the WebSocket, there's nothing to upgrade to
+        "upgrades": [],
+        "pingInterval": ping_interval_ms,
+        "pingTimeout": ping_timeout_ms,
+        "maxPayload": max_payload,
+    });
+    Message::Text(format!("{}{}", OPEN, handshake))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn decodes_client_packets() {
+        let (packet, expected) = Packet::decode("0").unwrap();
+        assert_eq!(packet.kind, PacketType::Connect);
+        assert_eq!(packet.namespace, DEFAULT_NAMESPACE);
+        assert_eq!(expected, 0);
+
+        let (packet, _) = Packet::decode(r#"2["join-room","abc"]"#).unwrap();
+        assert_eq!(packet.as_event(), Some(("join-room", &[json!("abc")][..])));
+
+        let (packet, expected) = Packet::decode(
+            r#"52-/admin,7["server-broadcast","abc",{"_placeholder":true,"num":0},{"_placeholder":true,"num":1}]"#,
+        )
+        .unwrap();
+        assert_eq!(packet.kind, PacketType::BinaryEvent);
+        assert_eq!(packet.namespace, "/admin");
+        assert_eq!(packet.id, Some(7));
+        assert_eq!(expected, 2);
+        assert_eq!(packet.as_event().unwrap().1.len(), 3);
+
+        assert!(Packet::decode("").is_err());
+        assert!(Packet::decode("9").is_err());
+        assert!(Packet::decode("5[\"x\"]").is_err());
+        assert!(Packet::decode("2[1]").is_err());
+        assert!(Packet::decode("2{").is_err());
+    }
+
+    #[test]
+    fn encodes_events_with_attachments() {
+        let placeholder = json!({ "_placeholder": true, "num": 0 });
+        let packet = Packet::event("client-broadcast", &[placeholder], vec![vec![1, 2, 3]]);
+        assert_eq!(
+            packet.encode(),
+            vec![
+                Message::Text(r#"451-["client-broadcast",{"_placeholder":true,"num":0}]"#.into()),
+                Message::Binary(vec![1, 2, 3]),
+            ]
+        );
+
+        assert_eq!(
+            Packet::event("init-room", &[], Vec::new()).encode(),
+            vec![Message::Text(r#"42["init-room"]"#.into())]
+        );
+        assert_eq!(
+            Packet::connect_error("/admin", "Invalid namespace").encode(),
+            vec![Message::Text(
+                r#"44/admin,{"message":"Invalid namespace"}"#.into()
+            )]
+        );
+
+        let text = match &Packet::connect("s1").encode()[0] {
+            Message::Text(text) => text[1..].to_string(),
+            _ => unreachable!(),
+        };
+        assert_eq!(Packet::decode(&text).unwrap().0, Packet::connect("s1"));
+    }
+}
@@ -0,0 +1,685 @@
+//! A socket.io room server with the same events as excalidraw-room, the
+//! server `Collab.tsx` talks to. It only forwards payloads, which clients
+//! encrypt with the room key, so it never sees scene contents.
+
+use std::{
+    collections::HashMap,
+    io,
+    net::{SocketAddr, TcpListener, TcpStream},
+    sync::{
+        atomic::{AtomicBool, AtomicUsize, Ordering},
+        mpsc::{self, Receiver, Sender},
+        Arc, Mutex,
+    },
+    thread::{self, JoinHandle},
+    time::{Duration, Instant},
+};
+
+use rand::{distributions::Alphanumeric, Rng};
+use serde_json::Value;
+use tungstenite::{
+    handshake::server::{ErrorResponse, Request, Response},
+    http::StatusCode,
+    protocol::WebSocketConfig,
+    Message, WebSocket,
+};
+
+use super::packet::{self, Packet, PacketType, DEFAULT_NAMESPACE};
+use crate::error::{Error, Result};
+
+/// Events from `WS_EVENTS` in `app_constants.ts`, and the ones
+/// `Portal.tsx` listens for.
+pub const SERVER_BROADCAST: &str = "server-broadcast";
+pub const SERVER_VOLATILE_BROADCAST: &str = "server-volatile-broadcast";
+pub const USER_FOLLOW_CHANGE: &str = "user-follow";
+pub const USER_FOLLOW_ROOM_CHANGE: &str = "user-follow-room-change";
+const INIT_ROOM: &str = "init-room";
+const JOIN_ROOM: &str = "join-room";
+const FIRST_IN_ROOM: &str = "first-in-room";
+const NEW_USER: &str = "new-user";
+const ROOM_USER_CHANGE: &str = "room-user-change";
+const CLIENT_BROADCAST: &str = "client-broadcast";
+
+/// Prefix of the rooms followers of a user join, `follow@<socket id>`.
+const FOLLOW_ROOM_PREFIX: &str = "follow@";
+
+const ENGINE_PATH: &str = "/socket.io/";
+const PING_INTERVAL: Duration = Duration::from_secs(25);
+const PING_TIMEOUT: Duration = Duration::from_secs(20);
+/// Matches excalidraw-room's `maxHttpBufferSize`, scenes with images get big.
+const MAX_PAYLOAD: usize = 20_000_000;
+/// How often connections check for frames to send while waiting to read.
+const POLL_INTERVAL: Duration = Duration::from_millis(10);
+/// Queued packets past which volatile ones (cursors, idle status) are
+/// dropped for a client that can't keep up.
+const VOLATILE_BACKLOG: usize = 32;
+
+type Frames = Vec<Message>;
+
+struct Client {
+    outbox: Sender<Frames>,
+    backlog: Arc<AtomicUsize>,
+    /// Rooms in the order they were joined.
+    rooms: Vec<String>,
+}
+
+/// Connected clients and the rooms they are in.
+#[derive(Default)]
+struct Rooms {
+    clients: HashMap<String, Client>,
+    /// Socket ids per room, in join order like socket.io's adapter.
+    members: HashMap<String, Vec<String>>,
+}
+
+impl Rooms {
+    fn members(&self, room: &str) -> Vec<String> {
+        self.members.get(room).cloned().unwrap_or_default()
+    }
+
+    fn join(&mut self, id: &str, room: &str) {
+        let members = self.members.entry(room.to_string()).or_default();
+        if !members.iter().any(|member| member == id) {
+            members.push(id.to_string());
+        }
+        if let Some(client) = self.clients.get_mut(id) {
+            if !client.rooms.iter().any(|joined| joined == room) {
+                client.rooms.push(room.to_string());
+            }
+        }
+    }
+
+    fn leave(&mut self, id: &str, room: &str) {
+        if let Some(members) = self.members.get_mut(room) {
+            members.retain(|member| member != id);
+            if members.is_empty() {
+                self.members.remove(room);
+            }
+        }
+        if let Some(client) = self.clients.get_mut(id) {
+            client.rooms.retain(|joined| joined != room);
+        }
+    }
+
+    fn send(&self, to: &str, frames: &[Message], volatile: bool) {
+        if let Some(client) = self.clients.get(to) {
+            if volatile && client.backlog.load(Ordering::Relaxed) >= VOLATILE_BACKLOG {
+                return;
+            }
+            client.backlog.fetch_add(1, Ordering::Relaxed);
+            // a closed outbox means the client is disconnecting
+            let _ = client.outbox.send(frames.to_vec());
+        }
+    }
+
+    fn emit(&self, to: &str, packet: &Packet) {
+        self.send(to, &packet.encode(), false);
+    }
+
+    /// Emits to everyone in `room`, but `except`.
+    fn broadcast(&self, room: &str, except: Option<&str>, packet: &Packet, volatile: bool) {
+        let frames = packet.encode();
+        for member in self.members.get(room).into_iter().flatten() {
+            if Some(member.as_str()) != except {
+                self.send(member, &frames, volatile);
+            }
+        }
+    }
+}
+
+/// Room state shared by the connections of a running [`RelayServer`].
+#[derive(Default)]
+pub struct Relay {
+    rooms: Mutex<Rooms>,
+    stopped: AtomicBool,
+}
+
+impl Relay {
+    fn connect(&self, id: &str, outbox: Sender<Frames>, backlog: Arc<AtomicUsize>) {
+        let mut rooms = self.rooms.lock().unwrap();
+        rooms.clients.insert(
+            id.to_string(),
+            Client {
+                outbox,
+                backlog,
+                rooms: Vec::new(),
+            },
+        );
+        rooms.emit(id, &Packet::event(INIT_ROOM, &[], Vec::new()));
+    }
+
+    fn handle(&self, id: &str, packet: &Packet) {
+        let (event, args) = match packet.as_event() {
+            Some(event) => event,
+            None => return,
+        };
+        let mut rooms = self.rooms.lock().unwrap();
+        match event {
+            JOIN_ROOM => {
+                let room = match args.first().and_then(Value::as_str) {
+                    Some(room) => room,
+                    None => return,
+                };
+                rooms.join(id, room);
+                let members = rooms.members(room);
+                if members.len() <= 1 {
+                    rooms.emit(id, &Packet::event(FIRST_IN_ROOM, &[], Vec::new()));
+                } else {
+                    let new_user = Packet::event(NEW_USER, &[Value::from(id)], Vec::new());
+                    rooms.broadcast(room, Some(id), &new_user, false);
+                }
+                let change = Packet::event(ROOM_USER_CHANGE, &[Value::from(members)], Vec::new());
+                rooms.broadcast(room, None, &change, false);
+            }
+            SERVER_BROADCAST | SERVER_VOLATILE_BROADCAST => {
+                let (room, payload) = match args.split_first() {
+                    Some((Value::String(room), payload)) => (room, payload),
+                    _ => return,
+                };
+                // the placeholders keep their numbers, the room id before
+                // them was never binary
+                let broadcast =
+                    Packet::event(CLIENT_BROADCAST, payload, packet.attachments.clone());
+                let volatile = event == SERVER_VOLATILE_BROADCAST;
+                rooms.broadcast(room, Some(id), &broadcast, volatile);
+            }
+            USER_FOLLOW_CHANGE => {
+                let payload = match args.first() {
+                    Some(payload) => payload,
+                    None => return,
+                };
+                let followed = match payload
+                    .pointer("/userToFollow/socketId")
+                    .and_then(Value::as_str)
+                {
+                    Some(followed) => followed,
+                    None => return,
+                };
+                let room = format!("{}{}", FOLLOW_ROOM_PREFIX, followed);
+                match payload.get("action").and_then(Value::as_str) {
+                    Some("FOLLOW") => rooms.join(id, &room),
+                    Some("UNFOLLOW") => rooms.leave(id, &room),
+                    _ => return,
+                }
+                let followed_by = Value::from(rooms.members(&room));
+                let change = Packet::event(USER_FOLLOW_ROOM_CHANGE, &[followed_by], Vec::new());
+                rooms.emit(followed, &change);
+            }
+            _ => log::debug!("ignoring collab event {}", event),
+        }
+    }
+
+    fn disconnect(&self, id: &str) {
+        let mut rooms = self.rooms.lock().unwrap();
+        let client = match rooms.clients.remove(id) {
+            Some(client) => client,
+            None => return,
+        };
+        for room in &client.rooms {
+            rooms.leave(id, room);
+            let others = rooms.members(room);
+            match room.strip_prefix(FOLLOW_ROOM_PREFIX) {
+                // the followed user learns it lost a follower
+                Some(followed) => {
+                    let change =
+                        Packet::event(USER_FOLLOW_ROOM_CHANGE, &[Value::from(others)], Vec::new());
+                    rooms.emit(followed, &change);
+                }
+                None if !others.is_empty() => {
+                    let change =
+                        Packet::event(ROOM_USER_CHANGE, &[Value::from(others)], Vec::new());
+                    rooms.broadcast(room, Some(id), &change, false);
+                }
+                None => {}
+            }
+        }
+    }
+
+    /// Number of connected clients.
+    pub fn client_count(&self) -> usize {
+        self.rooms.lock().unwrap().clients.len()
+    }
+}
+
+/// A relay listening for `socket.io-client` WebSocket connections, until
+/// stopped.
+pub struct RelayServer {
+    addr: SocketAddr,
+    relay: Arc<Relay>,
+    listener: Option<JoinHandle<()>>,
+}
+
+impl RelayServer {
+    pub fn start(addr: SocketAddr) -> io::Result<Self> {
+        let listener = TcpListener::bind(addr)?;
+        let addr = listener.local_addr()?;
+        let relay = Arc::new(Relay::default());
+
+        let shared = Arc::clone(&relay);
+        let listener = thread::spawn(move || {
+            for stream in listener.incoming() {
+                if shared.stopped.load(Ordering::Relaxed) {
+                    break;
+                }
+                let stream = match stream {
+                    Ok(stream) => stream,
+                    Err(err) => {
+                        log::warn!("failed to accept collab connection: {}", err);
+                        continue;
+                    }
+                };
+                let relay = Arc::clone(&shared);
+                thread::spawn(move || {
+                    let peer = stream.peer_addr().ok();
+                    if let Err(err) = serve(stream, &relay) {
+                        log::debug!("collab connection {:?} closed: {}", peer, err);
+                    }
+                });
+            }
+        });
+
+        Ok(Self {
+            addr,
+            relay,
+            listener: Some(listener),
+        })
+    }
+
+    pub fn local_addr(&self) -> SocketAddr {
+        self.addr
+    }
+
+    pub fn relay(&self) -> &Relay {
+        &self.relay
+    }
+
+    /// Stops accepting connections and closes the open ones.
+    pub fn stop(mut self) {
+        self.relay.stopped.store(true, Ordering::Relaxed);
+        // wake the listener blocked in accept so it sees the flag
+        let mut wake = self.addr;
+        if wake.ip().is_unspecified() {
+            wake.set_ip([127, 0, 0, 1].into());
+        }
+        let _ = TcpStream::connect_timeout(&wake, Duration::from_millis(500));
+        if let Some(listener) = self.listener.take() {
+            let _ = listener.join();
+        }
+    }
+}
+
+fn random_id() -> String {
+    rand::thread_rng()
+        .sample_iter(&Alphanumeric)
+        .take(20)
+        .map(char::from)
+        .collect()
+}
+
+/// Only accepts engine.io v4 WebSocket connections, `io(url)` connects to
+/// `/socket.io/?EIO=4&transport=websocket`.
+// the signature is tungstenite's handshake callback
+#[allow(clippy::result_large_err)]
+fn check_request(
+    request: &Request,
+    response: Response,
+) -> std::result::Result<Response, ErrorResponse> {
+    let query = request.uri().query().unwrap_or("");
+    let has = |param: &str| query.split('&').any(|pair| pair == param);
+    let status = if request.uri().path() != ENGINE_PATH {
+        StatusCode::NOT_FOUND
+    } else if !has("EIO=4") || !has("transport=websocket") {
+        StatusCode::BAD_REQUEST
+    } else {
+        return Ok(response);
+    };
+    let mut error = ErrorResponse::new(Some(status.to_string()));
+    *error.status_mut() = status;
+    Err(error)
+}
+
+fn is_timeout(err: &tungstenite::Error) -> bool {
+    matches!(
+        err,
+        tungstenite::Error::Io(err)
+            if matches!(err.kind(), io::ErrorKind::WouldBlock | io::ErrorKind::TimedOut)
+    )
+}
+
+/// One engine.io session: the socket.io connection, the queue the relay
+/// emits to it through, and any binary packet still waiting for its
+/// attachments.
+struct Session {
+    id: Option<String>,
+    outbox: Option<Sender<Frames>>,
+    backlog: Arc<AtomicUsize>,
+    pending: Option<(Packet, usize)>,
+}
+
+fn serve(stream: TcpStream, relay: &Relay) -> Result<()> {
+    let config = WebSocketConfig {
+        max_message_size: Some(MAX_PAYLOAD),
+        ..WebSocketConfig::default()
+    };
+    let mut socket = tungstenite::accept_hdr_with_config(stream, check_request, Some(config))
+        .map_err(|err| Error::Other(err.to_string()))?;
+    socket.get_ref().set_read_timeout(Some(POLL_INTERVAL))?;
+
+    let (outbox, inbox) = mpsc::channel();
+    let mut session = Session {
+        id: None,
+        outbox: Some(outbox),
+        backlog: Arc::new(AtomicUsize::new(0)),
+        pending: None,
+    };
+    let result = run(&mut socket, relay, &mut session, &inbox);
+    if let Some(id) = &session.id {
+        relay.disconnect(id);
+    }
+    let _ = socket.close(None);
+    let _ = socket.flush();
+    result
+}
+
+fn run(
+    socket: &mut WebSocket<TcpStream>,
+    relay: &Relay,
+    session: &mut Session,
+    inbox: &Receiver<Frames>,
+) -> Result<()> {
+    let ws = |err: tungstenite::Error| Error::Other(err.to_string());
+    socket
+        .send(packet::open(
+            &random_id(),
+            PING_INTERVAL.as_millis() as u64,
+            PING_TIMEOUT.as_millis() as u64,
+            MAX_PAYLOAD,
+        ))
+        .map_err(ws)?;
+    let mut last_ping = Instant::now();
+    let mut awaiting_pong = false;
+
+    while !relay.stopped.load(Ordering::Relaxed) {
+        match socket.read() {
+            Ok(Message::Text(text)) => {
+                let mut chars = text.chars();
+                match chars.next() {
+                    Some(packet::PONG) => awaiting_pong = false,
+                    Some(packet::CLOSE) => return Ok(()),
+                    Some(packet::MESSAGE) => {
+                        let (packet, expected) = Packet::decode(chars.as_str())?;
+                        if expected > 0 {
+                            session.pending = Some((packet, expected));
+                        } else if let Some(reply) = receive(relay, session, packet) {
+                            socket.send(reply).map_err(ws)?;
+                        }
+                    }
+                    _ => log::debug!("ignoring engine.io packet {:?}", text),
+                }
+            }
+            Ok(Message::Binary(data)) => {
+                let complete = match &mut session.pending {
+                    Some((packet, expected)) => {
+                        packet.attachments.push(data);
+                        packet.attachments.len() == *expected
+                    }
+                    None => return Err(Error::Other("unexpected binary frame".into())),
+                };
+                if complete {
+                    let (packet, _) = session.pending.take().unwrap();
+                    receive(relay, session, packet);
+                }
+            }
+            Ok(Message::Close(_)) => return Ok(()),
+            Ok(_) => {}
+            Err(err) if is_timeout(&err) => {}
+            Err(tungstenite::Error::ConnectionClosed) => return Ok(()),
+            Err(err) => return Err(ws(err)),
+        }
+
+        for frames in inbox.try_iter() {
+            session.backlog.fetch_sub(1, Ordering::Relaxed);
+            for frame in frames {
+                socket.write(frame).map_err(ws)?;
+            }
+        }
+        if last_ping.elapsed() >= PING_INTERVAL {
+            if awaiting_pong {
+                return Err(Error::Other("ping timed out".into()));
+            }
+            socket
+                .write(Message::Text(packet::PING.to_string()))
+                .map_err(ws)?;
+            last_ping = Instant::now();
+            awaiting_pong = true;
+        } else if awaiting_pong && last_ping.elapsed() >= PING_TIMEOUT {
+            return Err(Error::Other("ping timed out".into()));
+        }
+        match socket.flush() {
+            Err(err) if !is_timeout(&err) => return Err(ws(err)),
+            _ => {}
+        }
+    }
+    Ok(())
+}
+
+/// Handles a complete socket.io packet, returning the frame to answer with
+/// directly, if any.
+fn receive(relay: &Relay, session: &mut Session, packet: Packet) -> Option<Message> {
+    match (packet.kind, &session.id) {
+        (PacketType::Connect, None) if packet.namespace == DEFAULT_NAMESPACE => {
+            let id = random_id();
+            let ack = Packet::connect(&id).encode().remove(0);
+            relay.connect(&id, session.outbox.take()?, Arc::clone(&session.backlog));
+            session.id = Some(id);
+            Some(ack)
+        }
+        (PacketType::Connect, _) => Some(
+            Packet::connect_error(&packet.namespace, "Invalid namespace")
+                .encode()
+                .remove(0),
+        ),
+        (PacketType::Disconnect, Some(id)) => {
+            relay.disconnect(id);
+            session.id = None;
+            None
+        }
+        (_, Some(id)) => {
+            relay.handle(id, &packet);
+            None
+        }
+        (_, None) => None,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+    use tungstenite::stream::MaybeTlsStream;
+
+    struct TestClient {
+        socket: WebSocket<MaybeTlsStream<TcpStream>>,
+        id: String,
+    }
+
+    impl TestClient {
+        /// Connects like `io(url, { transports: ["websocket"] })`.
+        fn connect(server: &RelayServer) -> Self {
+            let url = format!(
+                "ws://{}/socket.io/?EIO=4&transport=websocket",
+                server.local_addr()
+            );
+            let (mut socket, _) = tungstenite::connect(url).unwrap();
+            if let MaybeTlsStream::Plain(stream) = socket.get_mut() {
+                stream
+                    .set_read_timeout(Some(Duration::from_secs(5)))
+                    .unwrap();
+            }
+            let open = socket.read().unwrap().into_text().unwrap();
+            assert!(open.starts_with(packet::OPEN), "{}", open);
+            socket.send(Message::Text("40".into())).unwrap();
+
+            let mut client = Self {
+                socket,
+                id: String::new(),
+            };
+            let ack = client.recv();
+            assert_eq!(ack.kind, PacketType::Connect);
+            client.id = ack.data.unwrap()["sid"].as_str().unwrap().to_string();
+            assert_eq!(client.recv_event(), (INIT_ROOM.to_string(), Vec::new()));
+            client
+        }
+
+        fn emit(&mut self, packet: Packet) {
+            for frame in packet.encode() {
+                self.socket.send(frame).unwrap();
+            }
+        }
+
+        fn recv(&mut self) -> Packet {
+            let text = self.socket.read().unwrap().into_text().unwrap();
+            assert!(text.starts_with(packet::MESSAGE), "{}", text);
+            let (mut packet, expected) = Packet::decode(&text[1..]).unwrap();
+            for _ in 0..expected {
+                packet
+                    .attachments
+                    .push(self.socket.read().unwrap().into_data());
+            }
+            packet
+        }
+
+        fn recv_event(&mut self) -> (String, Vec<Value>) {
+            let packet = self.recv();
+            let (name, args) = packet.as_event().unwrap();
+            (name.to_string(), args.to_vec())
+        }
+
+        fn join(&mut self, room: &str) {
+            self.emit(Packet::event(JOIN_ROOM, &[json!(room)], Vec::new()));
+        }
+    }
+
+    fn start() -> RelayServer {
+        RelayServer::start(([127, 0, 0, 1], 0).into()).unwrap()
+    }
+
+    fn event(name: &str, args: Value) -> (String, Vec<Value>) {
+        (name.to_string(), args.as_array().unwrap().clone())
+    }
+
+    #[test]
+    fn relays_encrypted_updates_within_a_room() {
+        let server = start();
+        let mut alice = TestClient::connect(&server);
+        alice.join("room");
+        assert_eq!(alice.recv_event(), event(FIRST_IN_ROOM, json!([])));
+        assert_eq!(
+            alice.recv_event(),
+            event(ROOM_USER_CHANGE, json!([[alice.id]]))
+        );
+
+        let mut bob = TestClient::connect(&server);
+        bob.join("room");
+        assert_eq!(alice.recv_event(), event(NEW_USER, json!([bob.id])));
+        let users = event(ROOM_USER_CHANGE, json!([[alice.id, bob.id]]));
+        assert_eq!(alice.recv_event(), users);
+        assert_eq!(bob.recv_event(), users);
+
+        // Portal._broadcastSocketData: room id, encrypted buffer, iv
+        let placeholder = |num: usize| json!({ "_placeholder": true, "num": num });
+        bob.emit(Packet::event(
+            SERVER_BROADCAST,
+            &[json!("room"), placeholder(0), placeholder(1)],
+            vec![vec![1, 2, 3], vec![4; 12]],
+        ));
+        let update = alice.recv();
+        assert_eq!(update.kind, PacketType::BinaryEvent);
+        assert_eq!(
+            update.as_event(),
+            Some((CLIENT_BROADCAST, &[placeholder(0), placeholder(1)][..]))
+        );
+        assert_eq!(update.attachments, vec![vec![1, 2, 3], vec![4; 12]]);
+
+        alice.emit(Packet::event(
+            SERVER_VOLATILE_BROADCAST,
+            &[json!("room"), placeholder(0), placeholder(1)],
+            vec![vec![5], vec![6]],
+        ));
+        assert_eq!(bob.recv().attachments, vec![vec![5], vec![6]]);
+
+        bob.socket.close(None).unwrap();
+        assert_eq!(
+            alice.recv_event(),
+            event(ROOM_USER_CHANGE, json!([[alice.id]]))
+        );
+        server.stop();
+    }
+
+    #[test]
+    fn tells_users_who_follows_them() {
+        let server = start();
+        let mut alice = TestClient::connect(&server);
+        let mut bob = TestClient::connect(&server);
+
+        let follow = |action: &str, id: &str| {
+            Packet::event(
+                USER_FOLLOW_CHANGE,
+                &[json!({
+                    "userToFollow": { "socketId": id, "username": "alice" },
+                    "action": action,
+                })],
+                Vec::new(),
+            )
+        };
+        bob.emit(follow("FOLLOW", &alice.id));
+        assert_eq!(
+            alice.recv_event(),
+            event(USER_FOLLOW_ROOM_CHANGE, json!([[bob.id]]))
+        );
+        bob.emit(follow("UNFOLLOW", &alice.id));
+        assert_eq!(
+            alice.recv_event(),
+            event(USER_FOLLOW_ROOM_CHANGE, json!([[]]))
+        );
+
+        bob.emit(follow("FOLLOW", &alice.id));
+        alice.recv();
+        bob.socket.close(None).unwrap();
+        assert_eq!(
+            alice.recv_event(),
+            event(USER_FOLLOW_ROOM_CHANGE, json!([[]]))
+        );
+        server.stop();
+    }
+
+    #[test]
+    fn only_accepts_socket_io_connections() {
+        let server = start();
+        let addr = server.local_addr();
+        assert!(tungstenite::connect(format!("ws://{}/", addr)).is_err());
+        assert!(tungstenite::connect(format!(
+            "ws://{}/socket.io/?EIO=3&transport=websocket",
+            addr
+        ))
+        .is_err());
+
+        let mut client = TestClient::connect(&server);
+        client
+            .socket
+            .send(Message::Text("40/admin,".into()))
+            .unwrap();
+        let error = client.recv();
+        assert_eq!(error.kind, PacketType::ConnectError);
+        assert_eq!(error.namespace, "/admin");
+        assert_eq!(server.relay().client_count(), 1);
+
+        server.stop();
+        // stopping closes open connections
+        assert!(matches!(client.socket.read(), Ok(Message::Close(_))));
+        assert!(tungstenite::connect(format!(
+            "ws://{}/socket.io/?EIO=4&transport=websocket",
+            addr
+        ))
+        .is_err());
+    }
+}
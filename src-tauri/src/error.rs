@@ -0,0 +1,25 @@
+use serde::{Serialize, Serializer};
+
+/// Error returned by the app's commands and subsystems.
+///
+/// Serializes to its display string so the webview receives a readable
+/// message when an `invoke` call rejects.
+#[derive(Debug, thiserror::Error)]
+pub enum Error {
+    #[error(transparent)]
+    Io(#[from] std::io::Error),
+    #[error(transparent)]
+    Json(#[from] serde_json::Error),
+    #[error(transparent)]
+    Tauri(#[from] tauri::Error),
+    #[error("{0}")]
+    Other(String),
+}
+
+impl Serialize for Error {
+    fn serialize<S: Serializer>(&self, serializer: S) -> std::result::Result<S::Ok, S::Error> {
+        serializer.serialize_str(&self.to_string())
+    }
+}
+
+pub type Result<T> = std::result::Result<T, Error>;
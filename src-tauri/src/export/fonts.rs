@@ -0,0 +1,260 @@
+//! The fonts `packages/excalidraw/fonts` registers, bundled into the binary
+//! so exports render the same text on machines without them installed.
+
+use std::{collections::BTreeSet, sync::Arc};
+
+use base64::{engine::general_purpose::STANDARD, Engine};
+use once_cell::sync::OnceCell;
+use resvg::usvg::fontdb::{Database, Language, Source};
+
+use crate::error::{Error, Result};
+
+/// `WINDOWS_EMOJI_FALLBACK_FONT`, the last entry of every font stack.
+const EMOJI_FALLBACK_FONT: &str = "Segoe UI Emoji";
+/// Renders `FONT_FAMILY.Helvetica`, which the app leaves to the system.
+const HELVETICA_SUBSTITUTE: &str = "Liberation Sans";
+
+/// `RANGES`
+const LATIN: &str = "U+0000-00FF, U+0131, U+0152-0153, U+02BB-02BC, U+02C6, U+02DA, U+02DC, U+0304, U+0308, U+0329, U+2000-206F, U+2074, U+20AC, U+2122, U+2191, U+2193, U+2212, U+2215, U+FEFF, U+FFFD";
+const LATIN_EXT: &str = "U+0100-02AF, U+0304, U+0308, U+0329, U+1E00-1E9F, U+1EF2-1EFF, U+2020, U+20A0-20AB, U+20AD-20C0, U+2113, U+2C60-2C7F, U+A720-A7FF";
+const CYRILIC_EXT: &str = "U+0460-052F, U+1C80-1C88, U+20B4, U+2DE0-2DFF, U+A640-A69F, U+FE2E-FE2F";
+const CYRILIC: &str = "U+0301, U+0400-045F, U+0490-0491, U+04B0-04B1, U+2116";
+const VIETNAMESE: &str = "U+0102-0103, U+0110-0111, U+0128-0129, U+0168-0169, U+01A0-01A1, U+01AF-01B0, U+0300-0301, U+0303-0304, U+0308-0309, U+0323, U+0329, U+1EA0-1EF9, U+20AB";
+
+macro_rules! asset {
+    ($file:literal) => {
+        include_bytes!(concat!("../../../packages/excalidraw/fonts/assets/", $file))
+    };
+}
+
+/// One `@font-face` of a family.
+pub struct FontFace {
+    pub family: &'static str,
+    pub woff2: &'static [u8],
+    pub unicode_range: Option<&'static str>,
+    pub weight: Option<&'static str>,
+}
+
+const fn face(family: &'static str, woff2: &'static [u8]) -> FontFace {
+    FontFace {
+        family,
+        woff2,
+        unicode_range: None,
+        weight: None,
+    }
+}
+
+const fn subset(
+    family: &'static str,
+    woff2: &'static [u8],
+    unicode_range: &'static str,
+    weight: Option<&'static str>,
+) -> FontFace {
+    FontFace {
+        family,
+        woff2,
+        unicode_range: Some(unicode_range),
+        weight,
+    }
+}
+
+/// Every face `Fonts.init` registers, except the system Helvetica.
+pub const FONT_FACES: &[FontFace] = &[
+    face("Virgil", asset!("Virgil-Regular.woff2")),
+    face("Excalifont", asset!("Excalifont-Regular.woff2")),
+    face("Liberation Sans", asset!("LiberationSans-Regular.woff2")),
+    face("Cascadia", asset!("CascadiaCode-Regular.woff2")),
+    face("Comic Shanns", asset!("ComicShanns-Regular.woff2")),
+    subset(
+        "Lilita One",
+        asset!("Lilita-Regular-i7dPIFZ9Zz-WBtRtedDbYE98RXi4EwSsbg.woff2"),
+        LATIN_EXT,
+        None,
+    ),
+    subset(
+        "Lilita One",
+        asset!("Lilita-Regular-i7dPIFZ9Zz-WBtRtedDbYEF8RXi4EwQ.woff2"),
+        LATIN,
+        None,
+    ),
+    subset(
+        "Nunito",
+        asset!("Nunito-Regular-XRXI3I6Li01BKofiOc5wtlZ2di8HDIkhdTk3j6zbXWjgevT5.woff2"),
+        CYRILIC_EXT,
+        Some("500"),
+    ),
+    subset(
+        "Nunito",
+        asset!("Nunito-Regular-XRXI3I6Li01BKofiOc5wtlZ2di8HDIkhdTA3j6zbXWjgevT5.woff2"),
+        CYRILIC,
+        Some("500"),
+    ),
+    subset(
+        "Nunito",
+        asset!("Nunito-Regular-XRXI3I6Li01BKofiOc5wtlZ2di8HDIkhdTs3j6zbXWjgevT5.woff2"),
+        VIETNAMESE,
+        Some("500"),
+    ),
+    subset(
+        "Nunito",
+        asset!("Nunito-Regular-XRXI3I6Li01BKofiOc5wtlZ2di8HDIkhdTo3j6zbXWjgevT5.woff2"),
+        LATIN_EXT,
+        Some("500"),
+    ),
+    subset(
+        "Nunito",
+        asset!("Nunito-Regular-XRXI3I6Li01BKofiOc5wtlZ2di8HDIkhdTQ3j6zbXWjgeg.woff2"),
+        LATIN,
+        Some("500"),
+    ),
+];
+
+/// `FONT_METADATA[fontFamily].metrics`
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct FontMetrics {
+    pub units_per_em: f64,
+    pub ascender: f64,
+    pub descender: f64,
+}
+
+const fn metrics(units_per_em: f64, ascender: f64, descender: f64) -> FontMetrics {
+    FontMetrics {
+        units_per_em,
+        ascender,
+        descender,
+    }
+}
+
+/// The `FONT_FAMILY` name of a text element's `fontFamily`.
+pub fn family_name(font_family: u32) -> Option<&'static str> {
+    Some(match font_family {
+        1 => "Virgil",
+        2 => "Helvetica",
+        3 => "Cascadia",
+        5 => "Excalifont",
+        6 => "Nunito",
+        7 => "Lilita One",
+        8 => "Comic Shanns",
+        9 => "Liberation Sans",
+        _ => return None,
+    })
+}
+
+/// Metrics of `font_family`, falling back to Virgil's like the app does.
+pub fn font_metrics(font_family: u32) -> FontMetrics {
+    match font_family {
+        2 => metrics(2048.0, 1577.0, -471.0),
+        3 => metrics(2048.0, 1900.0, -480.0),
+        6 => metrics(1000.0, 1011.0, -353.0),
+        7 => metrics(1000.0, 923.0, -220.0),
+        8 => metrics(1000.0, 750.0, -250.0),
+        9 => metrics(2048.0, 1854.0, -434.0),
+        _ => metrics(1000.0, 886.0, -374.0),
+    }
+}
+
+/// `getFontFamilyString`
+pub fn font_family_string(font_family: u32) -> String {
+    match family_name(font_family) {
+        Some(name) => format!("{}, {}", name, EMOJI_FALLBACK_FONT),
+        None => EMOJI_FALLBACK_FONT.into(),
+    }
+}
+
+/// `getVerticalOffset`: the baseline of a text line from its top.
+pub fn vertical_offset(font_family: u32, font_size: f64, line_height_px: f64) -> f64 {
+    let metrics = font_metrics(font_family);
+    let font_size_em = font_size / metrics.units_per_em;
+    let line_gap =
+        (line_height_px - font_size_em * metrics.ascender + font_size_em * metrics.descender) / 2.0;
+    font_size_em * metrics.ascender + line_gap
+}
+
+/// `@font-face` rules inlining the bundled faces of `families`, for SVGs
+/// that render the same outside the app.
+pub fn font_face_css(families: &BTreeSet<&str>) -> Vec<String> {
+    FONT_FACES
+        .iter()
+        .filter(|face| families.contains(face.family))
+        .map(|face| {
+            let mut css = format!(
+                "@font-face {{ font-family: {}; src: url(data:font/woff2;base64,{});",
+                face.family,
+                STANDARD.encode(face.woff2)
+            );
+            if let Some(range) = face.unicode_range {
+                css.push_str(&format!(" unicode-range: {};", range));
+            }
+            if let Some(weight) = face.weight {
+                css.push_str(&format!(" font-weight: {};", weight));
+            }
+            css.push_str(" }");
+            css
+        })
+        .collect()
+}
+
+/// Decompressing every face takes longer than most exports, so it's done
+/// once.
+static DATABASE: OnceCell<Arc<Database>> = OnceCell::new();
+
+/// A font database with every bundled face, named the way text elements
+/// refer to them.
+pub fn database() -> Result<Arc<Database>> {
+    DATABASE.get_or_try_init(load_database).map(Arc::clone)
+}
+
+fn load_database() -> Result<Arc<Database>> {
+    let mut db = Database::new();
+    for face in FONT_FACES {
+        let ttf = wuff::decompress_woff2(face.woff2).map_err(|err| {
+            Error::Other(format!(
+                "failed to decode the {} font: {:?}",
+                face.family, err
+            ))
+        })?;
+        for id in db.load_font_source(Source::Binary(Arc::new(ttf))) {
+            // the fonts' own names don't always match FONT_FAMILY, e.g.
+            // "Cascadia Code"
+            if let Some(mut info) = db.face(id).cloned() {
+                db.remove_face(id);
+                info.families = vec![(face.family.into(), Language::English_UnitedStates)];
+                if face.family == HELVETICA_SUBSTITUTE {
+                    info.families
+                        .push(("Helvetica".into(), Language::English_UnitedStates));
+                }
+                db.push_face_info(info);
+            }
+        }
+    }
+    db.set_sans_serif_family(HELVETICA_SUBSTITUTE);
+    Ok(Arc::new(db))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn names_faces_like_font_family() {
+        let db = database().unwrap();
+        assert!(Arc::ptr_eq(&db, &database().unwrap()));
+        for family in (1..=9).filter_map(family_name) {
+            let query = resvg::usvg::fontdb::Query {
+                families: &[resvg::usvg::fontdb::Family::Name(family)],
+                ..Default::default()
+            };
+            assert!(db.query(&query).is_some(), "{}", family);
+        }
+    }
+
+    #[test]
+    fn inlines_used_families() {
+        let css = font_face_css(&["Nunito", "Excalifont"].iter().copied().collect());
+        assert_eq!(css.len(), 6);
+        assert!(css[0].starts_with(
+            "@font-face { font-family: Excalifont; src: url(data:font/woff2;base64,d09GMg"
+        ));
+        assert!(css[1].contains("unicode-range: U+0460-052F"));
+    }
+}
@@ -0,0 +1,315 @@
+//! Outline of freedraw strokes, a port of the `perfect-freehand` `getStroke`
+//! with the options `getFreeDrawSvgPath` in `renderer/renderElement.ts` uses.
+
+use std::f64::consts::PI;
+
+use crate::scene::FreeDrawElement;
+
+type Vec2 = [f64; 2];
+
+const RATE_OF_PRESSURE_CHANGE: f64 = 0.275;
+const FIXED_PI: f64 = PI + 0.0001;
+const THINNING: f64 = 0.6;
+const SMOOTHING: f64 = 0.5;
+const STREAMLINE: f64 = 0.5;
+
+fn add(a: Vec2, b: Vec2) -> Vec2 {
+    [a[0] + b[0], a[1] + b[1]]
+}
+
+fn sub(a: Vec2, b: Vec2) -> Vec2 {
+    [a[0] - b[0], a[1] - b[1]]
+}
+
+fn mul(a: Vec2, n: f64) -> Vec2 {
+    [a[0] * n, a[1] * n]
+}
+
+fn per(a: Vec2) -> Vec2 {
+    [a[1], -a[0]]
+}
+
+fn dpr(a: Vec2, b: Vec2) -> f64 {
+    a[0] * b[0] + a[1] * b[1]
+}
+
+fn dist(a: Vec2, b: Vec2) -> f64 {
+    (a[0] - b[0]).hypot(a[1] - b[1])
+}
+
+fn dist2(a: Vec2, b: Vec2) -> f64 {
+    let d = sub(a, b);
+    d[0] * d[0] + d[1] * d[1]
+}
+
+fn uni(a: Vec2) -> Vec2 {
+    let len = a[0].hypot(a[1]);
+    if len == 0.0 {
+        a
+    } else {
+        mul(a, 1.0 / len)
+    }
+}
+
+fn lrp(a: Vec2, b: Vec2, t: f64) -> Vec2 {
+    add(a, mul(sub(b, a), t))
+}
+
+fn rot_around(a: Vec2, c: Vec2, r: f64) -> Vec2 {
+    let (s, co) = r.sin_cos();
+    let p = sub(a, c);
+    [p[0] * co - p[1] * s + c[0], p[0] * s + p[1] * co + c[1]]
+}
+
+/// easeOutSine
+fn easing(t: f64) -> f64 {
+    (t * PI / 2.0).sin()
+}
+
+fn stroke_radius(size: f64, pressure: f64) -> f64 {
+    size * easing(0.5 - THINNING * (0.5 - pressure))
+}
+
+struct StrokePoint {
+    point: Vec2,
+    pressure: f64,
+    vector: Vec2,
+    distance: f64,
+    running_length: f64,
+}
+
+/// `getStrokePoints`: the input points streamlined, with their direction and
+/// distance along the stroke.
+fn stroke_points(mut input: Vec<(Vec2, f64)>, size: f64, complete: bool) -> Vec<StrokePoint> {
+    let t = 0.15 + (1.0 - STREAMLINE) * 0.85;
+    if input.is_empty() {
+        return Vec::new();
+    }
+    if input.len() == 2 {
+        let (last, pressure) = input[1];
+        let first = input[0].0;
+        input.truncate(1);
+        for i in 1..5 {
+            input.push((lrp(first, last, i as f64 / 4.0), pressure));
+        }
+    }
+    if input.len() == 1 {
+        let (point, pressure) = input[0];
+        input.push((add(point, [1.0, 1.0]), pressure));
+    }
+
+    let mut points = vec![StrokePoint {
+        point: input[0].0,
+        pressure: input[0].1,
+        vector: [1.0, 1.0],
+        distance: 0.0,
+        running_length: 0.0,
+    }];
+    let mut reached_minimum_length = false;
+    let mut running_length = 0.0;
+    let max = input.len() - 1;
+    for (i, &(target, pressure)) in input.iter().enumerate().skip(1) {
+        let previous = points[points.len() - 1].point;
+        let point = if complete && i == max {
+            target
+        } else {
+            lrp(previous, target, t)
+        };
+        if point == previous {
+            continue;
+        }
+        let distance = dist(point, previous);
+        running_length += distance;
+        if i < max && !reached_minimum_length {
+            if running_length < size {
+                continue;
+            }
+            reached_minimum_length = true;
+        }
+        points.push(StrokePoint {
+            point,
+            pressure,
+            vector: uni(sub(previous, point)),
+            distance,
+            running_length,
+        });
+    }
+    points[0].vector = points.get(1).map_or([0.0, 0.0], |point| point.vector);
+    points
+}
+
+/// `getStrokeOutlinePoints` without tapering, which excalidraw doesn't use.
+fn outline_points(points: &[StrokePoint], size: f64, simulate_pressure: bool) -> Vec<Vec2> {
+    let len = points.len();
+    if len == 0 {
+        return Vec::new();
+    }
+    let total_length = points[len - 1].running_length;
+    let min_distance = (size * SMOOTHING).powi(2);
+    let simulate = |previous: f64, point: &StrokePoint| {
+        let sp = (point.distance / size).min(1.0);
+        let rp = (1.0 - sp).min(1.0);
+        (previous + (rp - previous) * (sp * RATE_OF_PRESSURE_CHANGE)).min(1.0)
+    };
+
+    let mut previous_pressure = points
+        .iter()
+        .take(10)
+        .fold(points[0].pressure, |acc, point| {
+            let pressure = if simulate_pressure {
+                simulate(acc, point)
+            } else {
+                point.pressure
+            };
+            (acc + pressure) / 2.0
+        });
+    let mut radius = stroke_radius(size, points[len - 1].pressure);
+    let mut first_radius = None;
+    let mut previous_vector = points[0].vector;
+    let mut pl = points[0].point;
+    let mut pr = pl;
+    let mut left = Vec::new();
+    let mut right = Vec::new();
+    let mut previous_sharp = false;
+
+    for i in 0..len {
+        let StrokePoint {
+            point,
+            vector,
+            running_length,
+            ..
+        } = points[i];
+        if i < len - 1 && total_length - running_length < 3.0 {
+            continue;
+        }
+        let pressure = if simulate_pressure {
+            simulate(previous_pressure, &points[i])
+        } else {
+            points[i].pressure
+        };
+        radius = stroke_radius(size, pressure).max(0.01);
+        if first_radius.is_none() {
+            first_radius = Some(radius);
+        }
+
+        let next_vector = points.get(i + 1).map_or(vector, |next| next.vector);
+        let next_dpr = if i < len - 1 {
+            dpr(vector, next_vector)
+        } else {
+            1.0
+        };
+        let sharp = dpr(vector, previous_vector) < 0.0 && !previous_sharp;
+        let next_sharp = next_dpr < 0.0;
+        if sharp || next_sharp {
+            // a rounded cap around the corner
+            let offset = mul(per(previous_vector), radius);
+            for step in 0..=13 {
+                let t = step as f64 / 13.0;
+                pl = rot_around(sub(point, offset), point, FIXED_PI * t);
+                left.push(pl);
+                pr = rot_around(add(point, offset), point, FIXED_PI * -t);
+                right.push(pr);
+            }
+            if next_sharp {
+                previous_sharp = true;
+            }
+            continue;
+        }
+        previous_sharp = false;
+
+        if i == len - 1 {
+            let offset = mul(per(vector), radius);
+            left.push(sub(point, offset));
+            right.push(add(point, offset));
+            continue;
+        }
+
+        let offset = mul(per(lrp(next_vector, vector, next_dpr)), radius);
+        let tl = sub(point, offset);
+        if i <= 1 || dist2(pl, tl) > min_distance {
+            left.push(tl);
+            pl = tl;
+        }
+        let tr = add(point, offset);
+        if i <= 1 || dist2(pr, tr) > min_distance {
+            right.push(tr);
+            pr = tr;
+        }
+        previous_pressure = pressure;
+        previous_vector = vector;
+    }
+
+    let first_point = points[0].point;
+    let last_point = if len > 1 {
+        points[len - 1].point
+    } else {
+        add(first_point, [1.0, 1.0])
+    };
+    if len == 1 {
+        let direction = uni(per(sub(first_point, last_point)));
+        let start = add(first_point, mul(direction, -first_radius.unwrap_or(radius)));
+        return (1..=13)
+            .map(|step| rot_around(start, first_point, FIXED_PI * 2.0 * step as f64 / 13.0))
+            .collect();
+    }
+
+    let start_cap: Vec<Vec2> = match right.first() {
+        Some(&first) => (1..=13)
+            .map(|step| rot_around(first, first_point, FIXED_PI * step as f64 / 13.0))
+            .collect(),
+        None => Vec::new(),
+    };
+    let direction = per(mul(points[len - 1].vector, -1.0));
+    let start = add(last_point, mul(direction, radius));
+    let end_cap =
+        (1..29).map(|step| rot_around(start, last_point, FIXED_PI * 3.0 * step as f64 / 29.0));
+
+    let mut outline = left;
+    outline.extend(end_cap);
+    outline.extend(right.into_iter().rev());
+    outline.extend(start_cap);
+    outline
+}
+
+/// Two decimals at most, like `TO_FIXED_PRECISION` trims them.
+fn fixed(value: f64) -> String {
+    let value = (value * 100.0).trunc() / 100.0;
+    format!("{}", if value == 0.0 { 0.0 } else { value })
+}
+
+/// `getFreeDrawSvgPath`: the filled outline of the stroke, relative to the
+/// element's position.
+pub fn svg_path(element: &FreeDrawElement) -> String {
+    let input: Vec<(Vec2, f64)> = if element.points.is_empty() {
+        vec![([0.0, 0.0], 0.5)]
+    } else {
+        element
+            .points
+            .iter()
+            .enumerate()
+            .map(|(i, &point)| {
+                let pressure = if element.simulate_pressure {
+                    0.5
+                } else {
+                    element.pressures.get(i).copied().unwrap_or(0.5)
+                };
+                (point, pressure)
+            })
+            .collect()
+    };
+    let size = element.base.stroke_width * 4.25;
+    let points = stroke_points(input, size, element.last_committed_point.is_some());
+    let outline = outline_points(&points, size, element.simulate_pressure);
+    if outline.is_empty() {
+        return String::new();
+    }
+
+    let point = |p: Vec2| format!("{},{}", fixed(p[0]), fixed(p[1]));
+    let mut path = format!("M {} Q", point(outline[0]));
+    for (i, &p) in outline.iter().enumerate() {
+        let next = outline[(i + 1) % outline.len()];
+        path.push_str(&format!(" {} {}", point(p), point(lrp(p, next, 0.5))));
+    }
+    path.push_str(&format!(" L {} Z", point(outline[0])));
+    path
+}
@@ -0,0 +1,207 @@
+//! Native rendering of scenes to SVG, PNG and PDF, for exports that don't go
+//! through the webview.
+
+pub mod fonts;
+mod freedraw;
+pub mod pdf;
+pub mod png;
+pub mod svg;
+
+use std::path::PathBuf;
+
+use serde::Deserialize;
+use tauri::api::dialog::blocking::FileDialogBuilder;
+
+use crate::{
+    error::{Error, Result},
+    files,
+    scene::{image, restore, Scene},
+};
+
+/// `EXPORT_SCALES`, the scales the export dialog offers.
+pub const EXPORT_SCALES: &[f64] = &[1.0, 2.0, 3.0];
+/// `DEFAULT_EXPORT_PADDING`
+pub const DEFAULT_EXPORT_PADDING: f64 = 10.0;
+
+const PNG_EXTENSION: &str = "png";
+const PDF_EXTENSION: &str = "pdf";
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct ExportOptions {
+    /// Multiplies the size of the output, like `exportScale`.
+    pub scale: f64,
+    /// Space around the elements, in scene pixels.
+    pub padding: f64,
+    /// Applies the dark theme filter, like `exportWithDarkMode`.
+    pub dark: bool,
+    /// Canvas color, the scene's `viewBackgroundColor` if not set.
+    /// `transparent` leaves the background out.
+    pub background: Option<String>,
+}
+
+impl Default for ExportOptions {
+    fn default() -> Self {
+        Self {
+            scale: 1.0,
+            padding: DEFAULT_EXPORT_PADDING,
+            dark: false,
+            background: None,
+        }
+    }
+}
+
+/// The export dialog's settings from `appState`.
+#[derive(Debug, Clone, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct PngExport {
+    /// `exportScale`, one of [`EXPORT_SCALES`].
+    pub export_scale: f64,
+    pub export_with_dark_mode: bool,
+    /// `exportBackground`, whether to fill the canvas with the scene's
+    /// background color.
+    pub export_background: bool,
+    /// `exportEmbedScene`, whether the PNG carries the scene so it can be
+    /// opened again.
+    pub export_embed_scene: bool,
+}
+
+impl PngExport {
+    fn options(&self) -> Result<ExportOptions> {
+        if !EXPORT_SCALES.contains(&self.export_scale) {
+            return Err(Error::Other(format!(
+                "unsupported export scale {}",
+                self.export_scale
+            )));
+        }
+        Ok(ExportOptions {
+            scale: self.export_scale,
+            dark: self.export_with_dark_mode,
+            background: background(self.export_background),
+            ..ExportOptions::default()
+        })
+    }
+}
+
+/// The export dialog's settings that apply to PDFs.
+#[derive(Debug, Clone, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct PdfExport {
+    pub export_with_dark_mode: bool,
+    pub export_background: bool,
+}
+
+impl PdfExport {
+    fn options(&self) -> ExportOptions {
+        ExportOptions {
+            dark: self.export_with_dark_mode,
+            background: background(self.export_background),
+            ..ExportOptions::default()
+        }
+    }
+}
+
+fn background(export_background: bool) -> Option<String> {
+    if export_background {
+        None
+    } else {
+        Some("transparent".into())
+    }
+}
+
+/// Shows a native save dialog for a `kind` file, adding `extension` when
+/// the user leaves it out.
+fn pick_export_path(kind: &str, file_name: &str, extension: &str) -> Option<PathBuf> {
+    let path = FileDialogBuilder::new()
+        .add_filter(kind, &[extension])
+        .set_file_name(file_name)
+        .save_file()?;
+    if path.extension().is_none() {
+        Some(path.with_extension(extension))
+    } else {
+        Some(path)
+    }
+}
+
+/// Renders the scene to a PNG the user picks with a native save dialog,
+/// returning its path, or `None` if the dialog was dismissed. Large scenes
+/// rasterize here instead of freezing the webview's canvas.
+#[tauri::command]
+pub async fn export_png(
+    contents: String,
+    export: PngExport,
+    file_name: Option<String>,
+) -> Result<Option<PathBuf>> {
+    let options = export.options()?;
+    let file_name = file_name.unwrap_or_else(|| "Untitled".into());
+    // `.excalidraw.png` marks images that open back as scenes
+    let file_name = if export.export_embed_scene {
+        format!("{}.excalidraw.{}", file_name, PNG_EXTENSION)
+    } else {
+        format!("{}.{}", file_name, PNG_EXTENSION)
+    };
+    let path = match pick_export_path("PNG", &file_name, PNG_EXTENSION) {
+        Some(path) => path,
+        None => return Ok(None),
+    };
+
+    let json = restore::restore_json(&contents)?;
+    let mut png = png::render(&Scene::parse(&json)?, &options)?;
+    if export.export_embed_scene {
+        png = image::encode_png_metadata(&png, &json)?;
+    }
+    files::write_atomic(&path, &png)?;
+    Ok(Some(path))
+}
+
+/// Renders the scene to a vector PDF the user picks with a native save
+/// dialog, a page per frame, returning its path or `None` if the dialog was
+/// dismissed.
+#[tauri::command]
+pub async fn export_pdf(
+    contents: String,
+    export: PdfExport,
+    file_name: Option<String>,
+) -> Result<Option<PathBuf>> {
+    let file_name = format!(
+        "{}.{}",
+        file_name.as_deref().unwrap_or("Untitled"),
+        PDF_EXTENSION
+    );
+    let path = match pick_export_path("PDF", &file_name, PDF_EXTENSION) {
+        Some(path) => path,
+        None => return Ok(None),
+    };
+
+    let json = restore::restore_json(&contents)?;
+    let pdf = pdf::render(&Scene::parse(&json)?, &export.options())?;
+    files::write_atomic(&path, &pdf)?;
+    Ok(Some(path))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn maps_the_export_dialog_settings() {
+        let export: PngExport = serde_json::from_str(
+            r#"{"exportScale":3,"exportWithDarkMode":true,"exportBackground":false,"exportEmbedScene":false}"#,
+        )
+        .unwrap();
+        assert_eq!(
+            export.options().unwrap(),
+            ExportOptions {
+                scale: 3.0,
+                padding: DEFAULT_EXPORT_PADDING,
+                dark: true,
+                background: Some("transparent".into()),
+            }
+        );
+
+        let export = PngExport {
+            export_scale: 4.0,
+            ..export
+        };
+        assert!(export.options().is_err());
+    }
+}
@@ -0,0 +1,1037 @@
+//! Scenes as vector PDF, with a page per frame so frames can be presented
+//! as slides, and one more for anything outside them. Pages are drawn from
+//! the same SVG as the other exports, walked into PDF operators instead of
+//! rasterized, so shapes stay vectors and text stays text, set in the
+//! bundled fonts.
+
+use std::{
+    collections::{BTreeMap, HashMap},
+    io::Write,
+    sync::Arc,
+};
+
+use flate2::{write::ZlibEncoder, Compression};
+use pdf_writer::{
+    types::{
+        CidFontType, ColorSpaceOperand, FontFlags, LineCapStyle, LineJoinStyle, MaskType,
+        PaintType, SystemInfo, TilingType, UnicodeCmap,
+    },
+    writers, Content, Dict, Filter, Finish, Name, Pdf, Rect, Ref, Str,
+};
+use resvg::{
+    tiny_skia::{NonZeroRect, PathSegment, Pixmap, Transform},
+    usvg::{
+        self,
+        filter::{ColorMatrixKind, Kind, TransferFunction},
+        fontdb, FillRule, Group, ImageKind, LineCap, LineJoin, Node, Paint, PaintOrder,
+    },
+};
+
+use crate::{
+    error::{Error, Result},
+    scene::{Element, Scene},
+};
+
+use super::{fonts, svg, ExportOptions};
+
+/// Scene pixels are CSS pixels, 96 to the inch.
+const PT_PER_PX: f32 = 0.75;
+
+const IDENTITY_H: Name = Name(b"Identity-H");
+const SYSTEM_INFO: SystemInfo = SystemInfo {
+    registry: Str(b"Adobe"),
+    ordering: Str(b"Identity"),
+    supplement: 0,
+};
+
+/// Renders `scene` to a PDF with a page per frame, followed by a page with
+/// whatever lies outside the frames, or a single page with everything when
+/// there are no frames.
+pub fn render(scene: &Scene, options: &ExportOptions) -> Result<Vec<u8>> {
+    let frames: Vec<&str> = scene
+        .elements
+        .iter()
+        .filter(|element| !element.base().is_deleted)
+        .filter(|element| matches!(element, Element::Frame(_) | Element::Magicframe(_)))
+        .map(|frame| frame.base().id.as_str())
+        .collect();
+    let pages = if frames.is_empty() {
+        vec![svg::render(scene, options)?]
+    } else {
+        let mut pages = frames
+            .iter()
+            .map(|frame| svg::render_frame(scene, frame, options))
+            .collect::<Result<Vec<_>>>()?;
+        pages.extend(svg::render_unframed(scene, options)?);
+        pages
+    };
+
+    let usvg_options = usvg::Options {
+        fontdb: fonts::database()?,
+        ..usvg::Options::default()
+    };
+    let mut writer = Writer::new();
+    for page in &pages {
+        let tree = usvg::Tree::from_str(page, &usvg_options)
+            .map_err(|err| Error::Other(format!("failed to render the scene: {}", err)))?;
+        writer.page(&tree)?;
+    }
+    writer.finish()
+}
+
+fn deflate(data: &[u8]) -> Result<Vec<u8>> {
+    let mut encoder = ZlibEncoder::new(Vec::new(), Compression::default());
+    encoder.write_all(data)?;
+    Ok(encoder.finish()?)
+}
+
+fn matrix(ts: Transform) -> [f32; 6] {
+    [ts.sx, ts.ky, ts.kx, ts.sy, ts.tx, ts.ty]
+}
+
+fn rect(rect: NonZeroRect) -> Rect {
+    Rect::new(rect.x(), rect.y(), rect.right(), rect.bottom())
+}
+
+/// Port of resvg's `feComponentTransfer` functions.
+fn transfer(function: &TransferFunction, c: f32) -> f32 {
+    match function {
+        TransferFunction::Identity => c,
+        TransferFunction::Table(values) if values.is_empty() => c,
+        TransferFunction::Table(values) => {
+            let n = values.len() - 1;
+            let k = ((c * n as f32).floor() as usize).min(n);
+            if k == n {
+                values[k]
+            } else {
+                values[k] + (c - k as f32 / n as f32) * n as f32 * (values[k + 1] - values[k])
+            }
+        }
+        TransferFunction::Discrete(values) if values.is_empty() => c,
+        TransferFunction::Discrete(values) => {
+            let n = values.len();
+            values[((c * n as f32).floor() as usize).min(n - 1)]
+        }
+        TransferFunction::Linear { slope, intercept } => slope * c + intercept,
+        TransferFunction::Gamma {
+            amplitude,
+            exponent,
+            offset,
+        } => amplitude * c.powf(*exponent) + offset,
+    }
+}
+
+/// Port of resvg's `feColorMatrix` kinds.
+fn color_matrix(kind: &ColorMatrixKind, [r, g, b, a]: [f32; 4]) -> [f32; 4] {
+    let rgb = |m: [f32; 9]| {
+        [
+            r * m[0] + g * m[1] + b * m[2],
+            r * m[3] + g * m[4] + b * m[5],
+            r * m[6] + g * m[7] + b * m[8],
+            a,
+        ]
+    };
+    match kind {
+        ColorMatrixKind::Matrix(m) => [
+            r * m[0] + g * m[1] + b * m[2] + a * m[3] + m[4],
+            r * m[5] + g * m[6] + b * m[7] + a * m[8] + m[9],
+            r * m[10] + g * m[11] + b * m[12] + a * m[13] + m[14],
+            r * m[15] + g * m[16] + b * m[17] + a * m[18] + m[19],
+        ],
+        ColorMatrixKind::Saturate(v) => {
+            let v = v.get().max(0.0);
+            rgb([
+                0.213 + 0.787 * v,
+                0.715 - 0.715 * v,
+                0.072 - 0.072 * v,
+                0.213 - 0.213 * v,
+                0.715 + 0.285 * v,
+                0.072 - 0.072 * v,
+                0.213 - 0.213 * v,
+                0.715 - 0.715 * v,
+                0.072 + 0.928 * v,
+            ])
+        }
+        ColorMatrixKind::HueRotate(angle) => {
+            let (a2, a1) = angle.to_radians().sin_cos();
+            rgb([
+                0.213 + 0.787 * a1 - 0.213 * a2,
+                0.715 - 0.715 * a1 - 0.715 * a2,
+                0.072 - 0.072 * a1 + 0.928 * a2,
+                0.213 - 0.213 * a1 + 0.143 * a2,
+                0.715 + 0.285 * a1 + 0.140 * a2,
+                0.072 - 0.072 * a1 - 0.283 * a2,
+                0.213 - 0.213 * a1 - 0.787 * a2,
+                0.715 - 0.715 * a1 + 0.715 * a2,
+                0.072 + 0.928 * a1 + 0.072 * a2,
+            ])
+        }
+        ColorMatrixKind::LuminanceToAlpha => [0.0, 0.0, 0.0, r * 0.2125 + g * 0.7154 + b * 0.0721],
+    }
+}
+
+/// Color-only filters, like the dark theme's `invert() hue-rotate()`,
+/// applied to every paint and pixel below them since PDF has no filters.
+/// Innermost first.
+#[derive(Clone, Default)]
+struct ColorFilter(Vec<Kind>);
+
+impl ColorFilter {
+    /// This filter with `group`'s own applied before it.
+    fn with(&self, group: &Group) -> Self {
+        let mut kinds = Vec::new();
+        for filter in group.filters() {
+            let primitives: Vec<&Kind> = filter
+                .primitives()
+                .iter()
+                .map(|primitive| primitive.kind())
+                .collect();
+            if primitives
+                .iter()
+                .all(|kind| matches!(kind, Kind::ColorMatrix(_) | Kind::ComponentTransfer(_)))
+            {
+                kinds.extend(primitives.into_iter().cloned());
+            } else {
+                log::warn!("skipping a filter PDF exports can't reproduce");
+            }
+        }
+        kinds.extend(self.0.iter().cloned());
+        Self(kinds)
+    }
+
+    fn apply(&self, color: [f32; 4]) -> [f32; 4] {
+        self.0.iter().fold(color, |color, kind| {
+            let [r, g, b, a] = match kind {
+                Kind::ColorMatrix(matrix) => color_matrix(matrix.kind(), color),
+                Kind::ComponentTransfer(transfer_functions) => [
+                    transfer(transfer_functions.func_r(), color[0]),
+                    transfer(transfer_functions.func_g(), color[1]),
+                    transfer(transfer_functions.func_b(), color[2]),
+                    transfer(transfer_functions.func_a(), color[3]),
+                ],
+                _ => color,
+            };
+            [
+                r.clamp(0.0, 1.0),
+                g.clamp(0.0, 1.0),
+                b.clamp(0.0, 1.0),
+                a.clamp(0.0, 1.0),
+            ]
+        })
+    }
+}
+
+/// Named resources a content stream uses.
+#[derive(Default)]
+struct Resources {
+    fonts: BTreeMap<String, Ref>,
+    states: BTreeMap<String, Ref>,
+    x_objects: BTreeMap<String, Ref>,
+    patterns: BTreeMap<String, Ref>,
+}
+
+impl Resources {
+    fn write(&self, mut resources: writers::Resources) {
+        entries(resources.fonts(), &self.fonts);
+        entries(resources.ext_g_states(), &self.states);
+        entries(resources.x_objects(), &self.x_objects);
+        entries(resources.patterns(), &self.patterns);
+    }
+}
+
+fn entries(mut dict: Dict, entries: &BTreeMap<String, Ref>) {
+    for (name, reference) in entries {
+        dict.pair(Name(name.as_bytes()), *reference);
+    }
+}
+
+struct Stream {
+    content: Content,
+    resources: Resources,
+}
+
+impl Default for Stream {
+    fn default() -> Self {
+        Self {
+            content: Content::new(),
+            resources: Resources::default(),
+        }
+    }
+}
+
+/// A face text is set in, embedded whole once the pages are done.
+struct Font {
+    reference: Ref,
+    name: String,
+    post_script_name: String,
+    data: Vec<u8>,
+    index: u32,
+    units_per_em: f32,
+    /// The text each used glyph stands for, so the PDF can be searched and
+    /// copied from.
+    glyphs: BTreeMap<u16, String>,
+}
+
+struct Writer {
+    pdf: Pdf,
+    next: Ref,
+    catalog: Ref,
+    page_tree: Ref,
+    pages: Vec<Ref>,
+    db: Arc<fontdb::Database>,
+    fonts: BTreeMap<fontdb::ID, Font>,
+    alpha_states: HashMap<(u32, u32), Ref>,
+}
+
+impl Writer {
+    fn new() -> Self {
+        let mut next = Ref::new(1);
+        Self {
+            pdf: Pdf::new(),
+            catalog: next.bump(),
+            page_tree: next.bump(),
+            next,
+            pages: Vec::new(),
+            db: Arc::default(),
+            fonts: BTreeMap::new(),
+            alpha_states: HashMap::new(),
+        }
+    }
+
+    fn alloc(&mut self) -> Ref {
+        self.next.bump()
+    }
+
+    fn page(&mut self, tree: &usvg::Tree) -> Result<()> {
+        self.db = tree.fontdb().clone();
+        let width = tree.size().width() * PT_PER_PX;
+        let height = tree.size().height() * PT_PER_PX;
+        // PDF's y axis points up
+        let base = Transform::from_row(PT_PER_PX, 0.0, 0.0, -PT_PER_PX, 0.0, height);
+        let mut stream = Stream::default();
+        stream.content.transform(matrix(base));
+        self.group(&mut stream, tree.root(), base, &ColorFilter::default())?;
+
+        let page = self.alloc();
+        let contents = self.alloc();
+        self.pdf
+            .stream(contents, &deflate(&stream.content.finish())?)
+            .filter(Filter::FlateDecode);
+        let mut writer = self.pdf.page(page);
+        writer
+            .media_box(Rect::new(0.0, 0.0, width, height))
+            .parent(self.page_tree)
+            .contents(contents);
+        stream.resources.write(writer.resources());
+        writer.finish();
+        self.pages.push(page);
+        Ok(())
+    }
+
+    /// Draws `node`. `ts` maps its coordinates to the stream's default space,
+    /// which patterns are positioned in.
+    fn node(
+        &mut self,
+        stream: &mut Stream,
+        node: &Node,
+        ts: Transform,
+        filter: &ColorFilter,
+    ) -> Result<()> {
+        match node {
+            Node::Group(group) => self.group(stream, group, ts, filter),
+            Node::Path(path) => self.path(stream, path, ts, filter),
+            Node::Image(image) => self.image(stream, node, image, ts, filter),
+            Node::Text(text) => self.text(stream, text, ts, filter),
+        }
+    }
+
+    fn group(
+        &mut self,
+        stream: &mut Stream,
+        group: &Group,
+        ts: Transform,
+        filter: &ColorFilter,
+    ) -> Result<()> {
+        let filter = filter.with(group);
+        let opacity = group.opacity().get();
+        stream.content.save_state();
+        if !group.transform().is_identity() {
+            stream.content.transform(matrix(group.transform()));
+        }
+        if let Some(clip) = group.clip_path() {
+            self.clip(stream, clip);
+        }
+
+        if opacity < 1.0 || group.mask().is_some() {
+            // a transparency group, so overlapping children don't show
+            // through each other
+            let mut inner = Stream::default();
+            for child in group.children() {
+                self.node(&mut inner, child, Transform::default(), &filter)?;
+            }
+            let bbox = group.layer_bounding_box();
+            let form = self.form(inner, bbox)?;
+            let mask = match group.mask() {
+                Some(mask) => Some((mask.kind(), self.mask(mask, &filter)?)),
+                None => None,
+            };
+            let state = self.alloc();
+            let mut writer = self.pdf.ext_graphics(state);
+            writer.non_stroking_alpha(opacity).stroking_alpha(opacity);
+            if let Some((kind, mask)) = mask {
+                let kind = match kind {
+                    usvg::MaskType::Luminance => MaskType::Luminosity,
+                    usvg::MaskType::Alpha => MaskType::Alpha,
+                };
+                writer.soft_mask().subtype(kind).group(mask);
+            }
+            writer.finish();
+
+            let state_name = format!("G{}", state.get());
+            let form_name = format!("X{}", form.get());
+            stream
+                .content
+                .set_parameters(Name(state_name.as_bytes()))
+                .x_object(Name(form_name.as_bytes()));
+            stream.resources.states.insert(state_name, state);
+            stream.resources.x_objects.insert(form_name, form);
+        } else {
+            let ts = ts.pre_concat(group.transform());
+            for child in group.children() {
+                self.node(stream, child, ts, &filter)?;
+            }
+        }
+        stream.content.restore_state();
+        Ok(())
+    }
+
+    /// A transparency group form of `stream`, drawn in the space it's used in.
+    fn form(&mut self, stream: Stream, bbox: NonZeroRect) -> Result<Ref> {
+        let reference = self.alloc();
+        let data = deflate(&stream.content.finish())?;
+        let mut form = self.pdf.form_xobject(reference, &data);
+        form.filter(Filter::FlateDecode);
+        form.bbox(rect(bbox));
+        form.group()
+            .transparency()
+            .isolated(true)
+            .color_space()
+            .device_rgb();
+        stream.resources.write(form.resources());
+        form.finish();
+        Ok(reference)
+    }
+
+    fn mask(&mut self, mask: &usvg::Mask, filter: &ColorFilter) -> Result<Ref> {
+        let mut stream = Stream::default();
+        let bounds = mask.rect();
+        // outside its rect a mask hides everything
+        stream
+            .content
+            .rect(bounds.x(), bounds.y(), bounds.width(), bounds.height())
+            .clip_nonzero()
+            .end_path();
+        self.group(&mut stream, mask.root(), Transform::default(), filter)?;
+        self.form(stream, bounds)
+    }
+
+    /// Intersects the clip with the union of `clip`'s paths.
+    fn clip(&mut self, stream: &mut Stream, clip: &usvg::ClipPath) {
+        if let Some(inner) = clip.clip_path() {
+            self.clip(stream, inner);
+        }
+        let mut even_odd = false;
+        let mut empty = true;
+        let mut paths = vec![(clip.root(), clip.transform())];
+        while let Some((group, ts)) = paths.pop() {
+            let ts = ts.pre_concat(group.transform());
+            for child in group.children() {
+                match child {
+                    Node::Group(group) => paths.push((group, ts)),
+                    Node::Path(path) => {
+                        if let Some(data) = path.data().clone().transform(ts) {
+                            even_odd |= path
+                                .fill()
+                                .map_or(false, |fill| fill.rule() == FillRule::EvenOdd);
+                            empty = false;
+                            path_data(&mut stream.content, &data);
+                        }
+                    }
+                    Node::Text(text) => paths.push((text.flattened(), ts)),
+                    Node::Image(_) => {}
+                }
+            }
+        }
+        if empty {
+            stream.content.rect(0.0, 0.0, 0.0, 0.0);
+        }
+        if even_odd {
+            stream.content.clip_even_odd();
+        } else {
+            stream.content.clip_nonzero();
+        }
+        stream.content.end_path();
+    }
+
+    /// Sets the alpha of fills, or of strokes.
+    fn alpha(&mut self, stream: &mut Stream, alpha: f32, stroke: bool) {
+        if alpha >= 1.0 {
+            return;
+        }
+        let key = (alpha.to_bits(), stroke as u32);
+        let state = match self.alpha_states.get(&key) {
+            Some(state) => *state,
+            None => {
+                let state = self.alloc();
+                let mut writer = self.pdf.ext_graphics(state);
+                if stroke {
+                    writer.stroking_alpha(alpha);
+                } else {
+                    writer.non_stroking_alpha(alpha);
+                }
+                writer.finish();
+                self.alpha_states.insert(key, state);
+                state
+            }
+        };
+        let name = format!("G{}", state.get());
+        stream.content.set_parameters(Name(name.as_bytes()));
+        stream.resources.states.insert(name, state);
+    }
+
+    fn paint(
+        &mut self,
+        stream: &mut Stream,
+        paint: &Paint,
+        opacity: f32,
+        ts: Transform,
+        filter: &ColorFilter,
+        stroke: bool,
+    ) -> Result<()> {
+        let color = match paint {
+            Paint::Color(color) => Some(*color),
+            // exports don't use gradients, so a flat stop is close enough
+            Paint::LinearGradient(gradient) => gradient.stops().first().map(|stop| stop.color()),
+            Paint::RadialGradient(gradient) => gradient.stops().first().map(|stop| stop.color()),
+            Paint::Pattern(pattern) => {
+                let reference = self.pattern(pattern, ts, filter)?;
+                let name = format!("P{}", reference.get());
+                if stroke {
+                    stream
+                        .content
+                        .set_stroke_color_space(ColorSpaceOperand::Pattern)
+                        .set_stroke_pattern(None, Name(name.as_bytes()));
+                } else {
+                    stream
+                        .content
+                        .set_fill_color_space(ColorSpaceOperand::Pattern)
+                        .set_fill_pattern(None, Name(name.as_bytes()));
+                }
+                stream.resources.patterns.insert(name, reference);
+                None
+            }
+        };
+        let alpha = match color {
+            Some(color) => {
+                let [r, g, b, a] = filter.apply([
+                    f32::from(color.red) / 255.0,
+                    f32::from(color.green) / 255.0,
+                    f32::from(color.blue) / 255.0,
+                    opacity,
+                ]);
+                if stroke {
+                    stream.content.set_stroke_rgb(r, g, b);
+                } else {
+                    stream.content.set_fill_rgb(r, g, b);
+                }
+                a
+            }
+            None => opacity,
+        };
+        self.alpha(stream, alpha, stroke);
+        Ok(())
+    }
+
+    fn pattern(
+        &mut self,
+        pattern: &usvg::Pattern,
+        ts: Transform,
+        filter: &ColorFilter,
+    ) -> Result<Ref> {
+        let bounds = pattern.rect();
+        let mut stream = Stream::default();
+        for child in pattern.root().children() {
+            self.node(&mut stream, child, Transform::default(), filter)?;
+        }
+        let reference = self.alloc();
+        let data = deflate(&stream.content.finish())?;
+        let mut tiling = self.pdf.tiling_pattern(reference, &data);
+        tiling.filter(Filter::FlateDecode);
+        tiling
+            .tiling_type(TilingType::ConstantSpacing)
+            .paint_type(PaintType::Colored)
+            .bbox(Rect::new(0.0, 0.0, bounds.width(), bounds.height()))
+            .x_step(bounds.width())
+            .y_step(bounds.height())
+            .matrix(matrix(
+                ts.pre_concat(pattern.transform())
+                    .pre_translate(bounds.x(), bounds.y()),
+            ));
+        stream.resources.write(tiling.resources());
+        tiling.finish();
+        Ok(reference)
+    }
+
+    fn path(
+        &mut self,
+        stream: &mut Stream,
+        path: &usvg::Path,
+        ts: Transform,
+        filter: &ColorFilter,
+    ) -> Result<()> {
+        if !path.is_visible() {
+            return Ok(());
+        }
+        let stroke_first = path.paint_order() == PaintOrder::StrokeAndFill;
+        for stroke in [stroke_first, !stroke_first] {
+            if stroke {
+                self.stroke(stream, path, ts, filter)?;
+            } else {
+                self.fill(stream, path, ts, filter)?;
+            }
+        }
+        Ok(())
+    }
+
+    fn fill(
+        &mut self,
+        stream: &mut Stream,
+        path: &usvg::Path,
+        ts: Transform,
+        filter: &ColorFilter,
+    ) -> Result<()> {
+        let fill = match path.fill() {
+            Some(fill) => fill,
+            None => return Ok(()),
+        };
+        stream.content.save_state();
+        self.paint(
+            stream,
+            fill.paint(),
+            fill.opacity().get(),
+            ts,
+            filter,
+            false,
+        )?;
+        path_data(&mut stream.content, path.data());
+        match fill.rule() {
+            FillRule::NonZero => stream.content.fill_nonzero(),
+            FillRule::EvenOdd => stream.content.fill_even_odd(),
+        };
+        stream.content.restore_state();
+        Ok(())
+    }
+
+    fn stroke(
+        &mut self,
+        stream: &mut Stream,
+        path: &usvg::Path,
+        ts: Transform,
+        filter: &ColorFilter,
+    ) -> Result<()> {
+        let stroke = match path.stroke() {
+            Some(stroke) => stroke,
+            None => return Ok(()),
+        };
+        stream.content.save_state();
+        self.paint(
+            stream,
+            stroke.paint(),
+            stroke.opacity().get(),
+            ts,
+            filter,
+            true,
+        )?;
+        stream
+            .content
+            .set_line_width(stroke.width().get())
+            .set_miter_limit(stroke.miterlimit().get())
+            .set_line_cap(match stroke.linecap() {
+                LineCap::Butt => LineCapStyle::ButtCap,
+                LineCap::Round => LineCapStyle::RoundCap,
+                LineCap::Square => LineCapStyle::ProjectingSquareCap,
+            })
+            .set_line_join(match stroke.linejoin() {
+                LineJoin::Miter | LineJoin::MiterClip => LineJoinStyle::MiterJoin,
+                LineJoin::Round => LineJoinStyle::RoundJoin,
+                LineJoin::Bevel => LineJoinStyle::BevelJoin,
+            });
+        if let Some(dashes) = stroke.dasharray() {
+            stream
+                .content
+                .set_dash_pattern(dashes.iter().copied(), stroke.dashoffset());
+        }
+        path_data(&mut stream.content, path.data());
+        stream.content.stroke();
+        stream.content.restore_state();
+        Ok(())
+    }
+
+    fn image(
+        &mut self,
+        stream: &mut Stream,
+        node: &Node,
+        image: &usvg::Image,
+        ts: Transform,
+        filter: &ColorFilter,
+    ) -> Result<()> {
+        if !image.is_visible() {
+            return Ok(());
+        }
+        if let ImageKind::SVG(tree) = image.kind() {
+            return self.group(stream, tree.root(), ts, filter);
+        }
+
+        // let resvg decode whatever format the image is in, at its own size
+        let size = image.size();
+        let bbox = match node.abs_layer_bounding_box() {
+            Some(bbox) => bbox,
+            None => return Ok(()),
+        };
+        let mut pixmap = Pixmap::new(size.width().ceil() as u32, size.height().ceil() as u32)
+            .ok_or_else(|| Error::Other("an image in the scene is too large".into()))?;
+        // render_node moves the image's canvas bounds to the pixmap origin,
+        // but raster images are drawn at the origin already
+        resvg::render_node(
+            node,
+            Transform::from_translate(bbox.x(), bbox.y()),
+            &mut pixmap.as_mut(),
+        );
+
+        let mut rgb = Vec::with_capacity(pixmap.pixels().len() * 3);
+        let mut alpha = Vec::with_capacity(pixmap.pixels().len());
+        for pixel in pixmap.pixels() {
+            let pixel = pixel.demultiply();
+            let [r, g, b, a] = filter.apply([
+                f32::from(pixel.red()) / 255.0,
+                f32::from(pixel.green()) / 255.0,
+                f32::from(pixel.blue()) / 255.0,
+                f32::from(pixel.alpha()) / 255.0,
+            ]);
+            rgb.extend([r, g, b].iter().map(|c| (c * 255.0).round() as u8));
+            alpha.push((a * 255.0).round() as u8);
+        }
+
+        let (width, height) = (pixmap.width() as i32, pixmap.height() as i32);
+        let mask = if alpha.iter().all(|a| *a == 255) {
+            None
+        } else {
+            let reference = self.alloc();
+            let data = deflate(&alpha)?;
+            let mut writer = self.pdf.image_xobject(reference, &data);
+            writer.filter(Filter::FlateDecode);
+            writer.width(width).height(height).bits_per_component(8);
+            writer.color_space().device_gray();
+            writer.finish();
+            Some(reference)
+        };
+        let reference = self.alloc();
+        let data = deflate(&rgb)?;
+        let mut writer = self.pdf.image_xobject(reference, &data);
+        writer.filter(Filter::FlateDecode);
+        writer.width(width).height(height).bits_per_component(8);
+        writer.color_space().device_rgb();
+        if let Some(mask) = mask {
+            writer.s_mask(mask);
+        }
+        writer.finish();
+
+        let name = format!("X{}", reference.get());
+        stream
+            .content
+            .save_state()
+            // images fill the unit square, bottom row first
+            .transform([width as f32, 0.0, 0.0, -height as f32, 0.0, height as f32])
+            .x_object(Name(name.as_bytes()))
+            .restore_state();
+        stream.resources.x_objects.insert(name, reference);
+        Ok(())
+    }
+
+    fn text(
+        &mut self,
+        stream: &mut Stream,
+        text: &usvg::Text,
+        ts: Transform,
+        filter: &ColorFilter,
+    ) -> Result<()> {
+        for span in text.layouted().iter().filter(|span| span.visible) {
+            for decoration in [&span.underline, &span.overline].into_iter().flatten() {
+                self.path(stream, decoration, ts, filter)?;
+            }
+            if let Some(fill) = &span.fill {
+                stream.content.save_state();
+                self.paint(
+                    stream,
+                    fill.paint(),
+                    fill.opacity().get(),
+                    ts,
+                    filter,
+                    false,
+                )?;
+                stream.content.begin_text();
+                for glyph in &span.positioned_glyphs {
+                    let font = match self.font(glyph.font) {
+                        Some(font) => font,
+                        None => continue,
+                    };
+                    font.glyphs
+                        .entry(glyph.id.0)
+                        .or_insert_with(|| glyph.text.clone());
+                    // a font size of one em in font units, so glyphs can be
+                    // placed with the same transform as their outlines
+                    stream
+                        .content
+                        .set_font(Name(font.name.as_bytes()), font.units_per_em)
+                        .set_text_matrix(matrix(glyph.outline_transform()))
+                        .show(Str(&glyph.id.0.to_be_bytes()));
+                    stream
+                        .resources
+                        .fonts
+                        .insert(font.name.clone(), font.reference);
+                }
+                stream.content.end_text();
+                stream.content.restore_state();
+            }
+            if let Some(line_through) = &span.line_through {
+                self.path(stream, line_through, ts, filter)?;
+            }
+        }
+        Ok(())
+    }
+
+    fn font(&mut self, id: fontdb::ID) -> Option<&mut Font> {
+        if !self.fonts.contains_key(&id) {
+            let (data, index) = self
+                .db
+                .with_face_data(id, |data, index| (data.to_vec(), index))?;
+            let units_per_em = ttf_parser::Face::parse(&data, index).ok()?.units_per_em();
+            let post_script_name = self.db.face(id)?.post_script_name.clone();
+            let reference = self.alloc();
+            self.fonts.insert(
+                id,
+                Font {
+                    reference,
+                    name: format!("F{}", reference.get()),
+                    post_script_name,
+                    data,
+                    index,
+                    units_per_em: f32::from(units_per_em),
+                    glyphs: BTreeMap::new(),
+                },
+            );
+        }
+        self.fonts.get_mut(&id)
+    }
+
+    /// Embeds each font as a CID-keyed TrueType font addressed by glyph id.
+    fn write_fonts(&mut self) -> Result<()> {
+        for font in std::mem::take(&mut self.fonts).into_values() {
+            let face = ttf_parser::Face::parse(&font.data, font.index)
+                .map_err(|err| Error::Other(format!("failed to embed a font: {}", err)))?;
+            let scale = 1000.0 / font.units_per_em;
+            let base_font: String = font
+                .post_script_name
+                .chars()
+                .filter(|c| c.is_ascii_alphanumeric() || *c == '-')
+                .collect();
+            let base_font = Name(base_font.as_bytes());
+            let cid_font = self.alloc();
+            let descriptor = self.alloc();
+            let file = self.alloc();
+            let cmap = self.alloc();
+
+            self.pdf
+                .type0_font(font.reference)
+                .base_font(base_font)
+                .encoding_predefined(IDENTITY_H)
+                .descendant_font(cid_font)
+                .to_unicode(cmap);
+
+            let mut writer = self.pdf.cid_font(cid_font);
+            writer
+                .subtype(CidFontType::Type2)
+                .base_font(base_font)
+                .system_info(SYSTEM_INFO)
+                .font_descriptor(descriptor)
+                .default_width(0.0)
+                .cid_to_gid_map_predefined(Name(b"Identity"));
+            let mut widths = writer.widths();
+            for id in font.glyphs.keys() {
+                let advance = face
+                    .glyph_hor_advance(ttf_parser::GlyphId(*id))
+                    .unwrap_or(0);
+                widths.consecutive(*id, [f32::from(advance) * scale]);
+            }
+            widths.finish();
+            writer.finish();
+
+            let bbox = face.global_bounding_box();
+            let ascender = f32::from(face.ascender()) * scale;
+            self.pdf
+                .font_descriptor(descriptor)
+                .name(base_font)
+                .flags(FontFlags::NON_SYMBOLIC)
+                .bbox(Rect::new(
+                    f32::from(bbox.x_min) * scale,
+                    f32::from(bbox.y_min) * scale,
+                    f32::from(bbox.x_max) * scale,
+                    f32::from(bbox.y_max) * scale,
+                ))
+                .italic_angle(face.italic_angle())
+                .ascent(ascender)
+                .descent(f32::from(face.descender()) * scale)
+                .cap_height(
+                    face.capital_height()
+                        .map_or(ascender, |height| f32::from(height) * scale),
+                )
+                .stem_v(80.0)
+                .font_file2(file);
+
+            self.pdf
+                .stream(file, &deflate(&font.data)?)
+                .filter(Filter::FlateDecode)
+                .pair(Name(b"Length1"), font.data.len() as i32);
+
+            let mut unicode = UnicodeCmap::new(Name(b"Custom"), SYSTEM_INFO);
+            for (id, text) in &font.glyphs {
+                unicode.pair_with_multiple(*id, text.chars());
+            }
+            self.pdf.cmap(cmap, &unicode.finish());
+        }
+        Ok(())
+    }
+
+    fn finish(mut self) -> Result<Vec<u8>> {
+        self.write_fonts()?;
+        self.pdf
+            .pages(self.page_tree)
+            .kids(self.pages.iter().copied())
+            .count(self.pages.len() as i32);
+        self.pdf.catalog(self.catalog).pages(self.page_tree);
+        Ok(self.pdf.finish())
+    }
+}
+
+fn path_data(content: &mut Content, data: &resvg::tiny_skia::Path) {
+    let mut last = (0.0, 0.0);
+    for segment in data.segments() {
+        match segment {
+            PathSegment::MoveTo(p) => {
+                content.move_to(p.x, p.y);
+                last = (p.x, p.y);
+            }
+            PathSegment::LineTo(p) => {
+                content.line_to(p.x, p.y);
+                last = (p.x, p.y);
+            }
+            PathSegment::QuadTo(c, p) => {
+                // PDF only has cubic curves
+                content.cubic_to(
+                    last.0 + 2.0 / 3.0 * (c.x - last.0),
+                    last.1 + 2.0 / 3.0 * (c.y - last.1),
+                    p.x + 2.0 / 3.0 * (c.x - p.x),
+                    p.y + 2.0 / 3.0 * (c.y - p.y),
+                    p.x,
+                    p.y,
+                );
+                last = (p.x, p.y);
+            }
+            PathSegment::CubicTo(c1, c2, p) => {
+                content.cubic_to(c1.x, c1.y, c2.x, c2.y, p.x, p.y);
+                last = (p.x, p.y);
+            }
+            PathSegment::Close => {
+                content.close_path();
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const FIXTURE: &str = include_str!("../../fixtures/scene.excalidraw");
+
+    fn count(pdf: &[u8], needle: &str) -> usize {
+        pdf.windows(needle.len())
+            .filter(|window| *window == needle.as_bytes())
+            .count()
+    }
+
+    #[test]
+    fn renders_a_page_per_frame() {
+        let scene = Scene::parse(FIXTURE).unwrap();
+        let pdf = render(&scene, &ExportOptions::default()).unwrap();
+        assert!(pdf.starts_with(b"%PDF-"));
+        // the freedraw left of the frame gets a page of its own
+        assert_eq!(count(&pdf, "/Type /Page\n"), 2);
+        // the frame's 620x300 px, in points
+        assert_eq!(count(&pdf, "/MediaBox [0 0 465 225]"), 1);
+    }
+
+    #[test]
+    fn skips_the_outside_page_when_frames_hold_everything() {
+        let mut scene: serde_json::Value = serde_json::from_str(FIXTURE).unwrap();
+        scene["elements"]
+            .as_array_mut()
+            .unwrap()
+            .retain(|element| element["id"] != "freedraw-1");
+        let scene = Scene::parse(&scene.to_string()).unwrap();
+        let pdf = render(&scene, &ExportOptions::default()).unwrap();
+        assert_eq!(count(&pdf, "/Type /Page\n"), 1);
+
+        // the frame's page cuts off what sticks out of it
+        let mut scene: serde_json::Value = serde_json::from_str(FIXTURE).unwrap();
+        let elements = scene["elements"].as_array_mut().unwrap();
+        elements.retain(|element| element["id"] != "freedraw-1");
+        for element in elements {
+            if element["id"] == "arrow-1" {
+                element["x"] = 600.into();
+            }
+        }
+        let scene = Scene::parse(&scene.to_string()).unwrap();
+        let pdf = render(&scene, &ExportOptions::default()).unwrap();
+        assert_eq!(count(&pdf, "/Type /Page\n"), 2);
+    }
+
+    #[test]
+    fn embeds_fonts_for_text() {
+        let scene = Scene::parse(
+            r##"{"type":"excalidraw","version":2,"source":"test","elements":[
+                {"type":"text","id":"t","x":0,"y":0,"width":60,"height":25,"angle":0,"strokeColor":"#1e1e1e","backgroundColor":"transparent","fillStyle":"solid","strokeWidth":2,"strokeStyle":"solid","roughness":1,"opacity":100,"groupIds":[],"frameId":null,"index":"a0","roundness":null,"seed":1,"version":1,"versionNonce":1,"isDeleted":false,"boundElements":null,"updated":1,"link":null,"locked":false,"text":"Hello","fontSize":20,"fontFamily":5,"textAlign":"left","verticalAlign":"top","containerId":null,"originalText":"Hello","autoResize":true,"lineHeight":1.25}
+            ],"appState":{"viewBackgroundColor":"#ffffff"},"files":{}}"##,
+        )
+        .unwrap();
+        let pdf = render(&scene, &ExportOptions::default()).unwrap();
+        assert_eq!(count(&pdf, "/Type /Page\n"), 1);
+        assert_eq!(count(&pdf, "/Subtype /CIDFontType2"), 1);
+        assert_eq!(count(&pdf, "/FontFile2"), 1);
+        assert_eq!(count(&pdf, "/ToUnicode"), 1);
+    }
+
+    #[test]
+    fn applies_color_filters() {
+        assert_eq!(
+            ColorFilter::default().apply([0.2, 0.4, 0.6, 1.0]),
+            [0.2, 0.4, 0.6, 1.0]
+        );
+        // the dark theme's invert(93%)
+        let invert = TransferFunction::Table(vec![0.93, 0.07]);
+        assert_eq!(transfer(&invert, 1.0), 0.07);
+        assert_eq!(transfer(&invert, 0.0), 0.93);
+        let rotated = color_matrix(&ColorMatrixKind::HueRotate(180.0), [1.0, 1.0, 1.0, 1.0]);
+        assert!(rotated.iter().all(|c| (c - 1.0).abs() < 1e-3));
+    }
+}
@@ -0,0 +1,82 @@
+//! SVG to PNG on the CPU, so exports work without a webview or GPU.
+
+use resvg::{tiny_skia::Pixmap, usvg};
+
+use crate::{
+    error::{Error, Result},
+    scene::Scene,
+};
+
+use super::{fonts, svg, ExportOptions};
+
+/// Rasterizes `scene` at `options.scale`.
+pub fn render(scene: &Scene, options: &ExportOptions) -> Result<Vec<u8>> {
+    let svg = svg::render(scene, options)?;
+    let usvg_options = usvg::Options {
+        fontdb: fonts::database()?,
+        ..usvg::Options::default()
+    };
+    let tree = usvg::Tree::from_str(&svg, &usvg_options)
+        .map_err(|err| Error::Other(format!("failed to render the scene: {}", err)))?;
+
+    // the svg's width and height already include the scale
+    let size = tree.size().to_int_size();
+    let mut pixmap = Pixmap::new(size.width(), size.height())
+        .ok_or_else(|| Error::Other("the scene is too large to export".into()))?;
+    resvg::render(&tree, usvg::Transform::default(), &mut pixmap.as_mut());
+    pixmap
+        .encode_png()
+        .map_err(|err| Error::Other(format!("failed to encode the PNG: {}", err)))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const FIXTURE: &str = include_str!("../../fixtures/scene.excalidraw");
+
+    fn pixels(png: &[u8]) -> Pixmap {
+        Pixmap::decode_png(png).unwrap()
+    }
+
+    #[test]
+    fn rasterizes_at_scale() {
+        let scene = Scene::parse(FIXTURE).unwrap();
+        let one = pixels(&render(&scene, &ExportOptions::default()).unwrap());
+        let two = pixels(
+            &render(
+                &scene,
+                &ExportOptions {
+                    scale: 2.0,
+                    ..ExportOptions::default()
+                },
+            )
+            .unwrap(),
+        );
+        assert_eq!(two.width(), one.width() * 2);
+        assert!(one.pixels().iter().any(|pixel| pixel.alpha() == 255));
+    }
+
+    #[test]
+    fn draws_text_with_bundled_fonts() {
+        let text = |font_family: u32| {
+            format!(
+                r##"{{"type":"excalidraw","version":2,"source":"test","appState":{{"viewBackgroundColor":"transparent"}},"elements":[
+                    {{"type":"text","id":"t","x":0,"y":0,"width":100,"height":25,"angle":0,"strokeColor":"#1e1e1e","backgroundColor":"transparent","fillStyle":"solid","strokeWidth":2,"strokeStyle":"solid","roughness":1,"opacity":100,"groupIds":[],"frameId":null,"index":"a0","roundness":null,"seed":1,"version":1,"versionNonce":1,"isDeleted":false,"boundElements":null,"updated":1,"link":null,"locked":false,
+                    "text":"Hello","originalText":"Hello","fontSize":20,"fontFamily":{},"textAlign":"left","verticalAlign":"top","containerId":null,"autoResize":true,"lineHeight":1.25}}
+                ]}}"##,
+                font_family
+            )
+        };
+        for font_family in [1, 2, 3, 5, 6, 7, 8, 9] {
+            let scene = Scene::parse(&text(font_family)).unwrap();
+            let png = pixels(&render(&scene, &ExportOptions::default()).unwrap());
+            let inked = png
+                .pixels()
+                .iter()
+                .filter(|pixel| pixel.alpha() > 0)
+                .count();
+            assert!(inked > 50, "font family {}", font_family);
+        }
+    }
+}
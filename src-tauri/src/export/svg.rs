@@ -0,0 +1,1202 @@
+//! Scene to SVG, following `exportToSvg` and `renderSceneToSvg` in
+//! `packages/excalidraw`. Shapes are drawn with clean geometry rather than
+//! roughjs strokes, so sketchy elements come out as if their roughness were
+//! 0 ("architect").
+
+use std::collections::{BTreeSet, HashMap, HashSet};
+
+use crate::{
+    error::{Error, Result},
+    scene::{
+        Arrowhead, BinaryFileData, Element, ElementBase, FillStyle, FrameElement, ImageElement,
+        LinearElement, Roundness, Scene, StrokeStyle, TextAlign, TextElement, VerticalAlign,
+    },
+};
+
+use super::{fonts, freedraw, ExportOptions};
+
+const SVG_NS: &str = "http://www.w3.org/2000/svg";
+/// `SVG_EXPORT_TAG`
+const SVG_EXPORT_TAG: &str = "<!-- svg-source:excalidraw -->";
+/// `THEME_FILTER`
+const THEME_FILTER: &str = "invert(93%) hue-rotate(180deg)";
+/// `IMAGE_INVERT_FILTER`, undoing the theme filter for bitmaps.
+const IMAGE_INVERT_FILTER: &str = "invert(100%) hue-rotate(180deg) saturate(1.25)";
+const SVG_MIME_TYPE: &str = "image/svg+xml";
+
+const DEFAULT_PROPORTIONAL_RADIUS: f64 = 0.25;
+const DEFAULT_ADAPTIVE_RADIUS: f64 = 32.0;
+const ROUNDNESS_ADAPTIVE: u8 = 3;
+/// `LINE_CONFIRM_THRESHOLD`, how close the ends of a line must be to count
+/// as a loop.
+const LINE_CONFIRM_THRESHOLD: f64 = 8.0;
+
+// FRAME_STYLE
+const FRAME_STROKE_COLOR: &str = "#bbb";
+const FRAME_STROKE_WIDTH: f64 = 2.0;
+const FRAME_RADIUS: f64 = 8.0;
+const FRAME_NAME_OFFSET_Y: f64 = 3.0;
+const FRAME_NAME_COLOR_LIGHT: &str = "#999999";
+const FRAME_NAME_COLOR_DARK: &str = "#7a7a7a";
+const FRAME_NAME_FONT_SIZE: f64 = 14.0;
+const FRAME_NAME_LINE_HEIGHT: f64 = 1.25;
+/// `FONT_FAMILY.Helvetica`
+const FRAME_NAME_FONT_FAMILY: u32 = 2;
+
+/// Escapes text and attribute values.
+fn escape(text: &str) -> String {
+    let mut escaped = String::with_capacity(text.len());
+    for c in text.chars() {
+        match c {
+            '&' => escaped.push_str("&amp;"),
+            '<' => escaped.push_str("&lt;"),
+            '>' => escaped.push_str("&gt;"),
+            '"' => escaped.push_str("&quot;"),
+            '\'' => escaped.push_str("&#39;"),
+            _ => escaped.push(c),
+        }
+    }
+    escaped
+}
+
+/// A coordinate with at most `MAX_DECIMALS_FOR_SVG_EXPORT` decimals.
+fn num(value: f64) -> String {
+    let value = (value * 100.0).round() / 100.0;
+    format!("{}", if value == 0.0 { 0.0 } else { value })
+}
+
+/// `isTransparent`
+pub fn is_transparent(color: &str) -> bool {
+    color == "transparent"
+        || (color.len() == 5 && color.ends_with('0'))
+        || (color.len() == 9 && color.ends_with("00"))
+}
+
+/// `getCornerRadius`
+fn corner_radius(x: f64, roundness: &Option<Roundness>) -> f64 {
+    match roundness {
+        Some(Roundness {
+            kind: ROUNDNESS_ADAPTIVE,
+            value,
+        }) => {
+            let fixed = value.unwrap_or(DEFAULT_ADAPTIVE_RADIUS);
+            if x <= fixed / DEFAULT_PROPORTIONAL_RADIUS {
+                x * DEFAULT_PROPORTIONAL_RADIUS
+            } else {
+                fixed
+            }
+        }
+        Some(_) => x * DEFAULT_PROPORTIONAL_RADIUS,
+        None => 0.0,
+    }
+}
+
+fn rotate(point: [f64; 2], center: [f64; 2], angle: f64) -> [f64; 2] {
+    let (sin, cos) = angle.sin_cos();
+    let (dx, dy) = (point[0] - center[0], point[1] - center[1]);
+    [
+        dx * cos - dy * sin + center[0],
+        dx * sin + dy * cos + center[1],
+    ]
+}
+
+/// `isPathALoop`
+fn is_loop(points: &[[f64; 2]]) -> bool {
+    match points {
+        [first, .., last] if points.len() >= 3 => {
+            (first[0] - last[0]).hypot(first[1] - last[1]) <= LINE_CONFIRM_THRESHOLD
+        }
+        _ => false,
+    }
+}
+
+/// `isRTL`: whether the first strongly directional character is right to
+/// left.
+fn is_rtl(text: &str) -> bool {
+    for c in text.chars() {
+        let c = c as u32;
+        let ltr = matches!(c,
+            0x41..=0x5a | 0x61..=0x7a | 0xc0..=0xd6 | 0xd8..=0xf6 | 0xf8..=0x2b8
+                | 0x300..=0x590 | 0x800..=0x1fff | 0x2c00..=0xfb1c | 0xfdfe..=0xfe6f
+                | 0xfefd..=0xffff);
+        if ltr {
+            return false;
+        }
+        if matches!(c, 0x591..=0x7ff | 0xfb1d..=0xfdfd | 0xfe70..=0xfefc) {
+            return true;
+        }
+    }
+    false
+}
+
+/// `getElementAbsoluteCoords`: the unrotated bounds of an element.
+fn absolute_coords(element: &Element) -> [f64; 4] {
+    let base = element.base();
+    let points = match element {
+        Element::Line(linear) => Some(&linear.points),
+        Element::Arrow(arrow) => Some(&arrow.linear.points),
+        Element::Freedraw(freedraw) => Some(&freedraw.points),
+        _ => None,
+    };
+    match points.filter(|points| !points.is_empty()) {
+        Some(points) => {
+            let xs = points.iter().map(|point| point[0]);
+            let ys = points.iter().map(|point| point[1]);
+            [
+                base.x + xs.clone().fold(f64::INFINITY, f64::min),
+                base.y + ys.clone().fold(f64::INFINITY, f64::min),
+                base.x + xs.fold(f64::NEG_INFINITY, f64::max),
+                base.y + ys.fold(f64::NEG_INFINITY, f64::max),
+            ]
+        }
+        None => [base.x, base.y, base.x + base.width, base.y + base.height],
+    }
+}
+
+/// `getElementBounds`: the bounds of an element after rotation.
+fn element_bounds(element: &Element) -> [f64; 4] {
+    let [x1, y1, x2, y2] = absolute_coords(element);
+    let base = element.base();
+    let center = [(x1 + x2) / 2.0, (y1 + y2) / 2.0];
+    let corners: Vec<[f64; 2]> = match element {
+        Element::Line(LinearElement { points, .. })
+        | Element::Arrow(crate::scene::ArrowElement {
+            linear: LinearElement { points, .. },
+            ..
+        })
+        | Element::Freedraw(crate::scene::FreeDrawElement { points, .. }) => points
+            .iter()
+            .map(|point| [base.x + point[0], base.y + point[1]])
+            .collect(),
+        _ => vec![[x1, y1], [x2, y1], [x2, y2], [x1, y2]],
+    };
+    let mut bounds = [
+        f64::INFINITY,
+        f64::INFINITY,
+        f64::NEG_INFINITY,
+        f64::NEG_INFINITY,
+    ];
+    for corner in corners {
+        let [x, y] = rotate(corner, center, base.angle);
+        bounds = [
+            bounds[0].min(x),
+            bounds[1].min(y),
+            bounds[2].max(x),
+            bounds[3].max(y),
+        ];
+    }
+    bounds
+}
+
+/// `getCommonBounds`
+pub fn common_bounds<'a>(elements: impl IntoIterator<Item = &'a Element>) -> Option<[f64; 4]> {
+    elements
+        .into_iter()
+        .map(element_bounds)
+        .filter(|bounds| bounds.iter().all(|value| value.is_finite()))
+        .reduce(|a, b| {
+            [
+                a[0].min(b[0]),
+                a[1].min(b[1]),
+                a[2].max(b[2]),
+                a[3].max(b[3]),
+            ]
+        })
+}
+
+/// The text elements `addFrameLabelsAsTextElements` puts above frames.
+fn frame_label(frame: &FrameElement, magic: bool, dark: bool) -> Element {
+    let text = frame.name.clone().unwrap_or_else(|| {
+        if magic {
+            "AI Frame".into()
+        } else {
+            "Frame".into()
+        }
+    });
+    let height = FRAME_NAME_FONT_SIZE * FRAME_NAME_LINE_HEIGHT;
+    let color = if dark {
+        FRAME_NAME_COLOR_DARK
+    } else {
+        FRAME_NAME_COLOR_LIGHT
+    };
+    Element::Text(TextElement {
+        base: ElementBase {
+            id: format!("{}-label", frame.base.id),
+            y: frame.base.y - FRAME_NAME_OFFSET_Y - height,
+            stroke_color: color.into(),
+            opacity: 100.0,
+            angle: 0.0,
+            height,
+            frame_id: None,
+            link: None,
+            bound_elements: None,
+            group_ids: Vec::new(),
+            ..frame.base.clone()
+        },
+        font_size: FRAME_NAME_FONT_SIZE,
+        font_family: FRAME_NAME_FONT_FAMILY,
+        original_text: text.clone(),
+        text,
+        text_align: TextAlign::Left,
+        vertical_align: VerticalAlign::Top,
+        container_id: None,
+        auto_resize: true,
+        line_height: FRAME_NAME_LINE_HEIGHT,
+    })
+}
+
+/// The elements to draw, in z-order, with frame labels added.
+fn elements_for_render(scene: &Scene, dark: bool) -> Vec<Element> {
+    let mut elements = Vec::new();
+    for element in scene.elements.iter().filter(|e| !e.base().is_deleted) {
+        match element {
+            Element::Selection(_) => continue,
+            Element::Frame(frame) => elements.push(frame_label(frame, false, dark)),
+            Element::Magicframe(frame) => elements.push(frame_label(frame, true, dark)),
+            _ => {}
+        }
+        elements.push(element.clone());
+    }
+    elements
+}
+
+struct Renderer<'a> {
+    elements: HashMap<&'a str, &'a Element>,
+    files: Option<&'a std::collections::BTreeMap<String, BinaryFileData>>,
+    options: &'a ExportOptions,
+    canvas_background: String,
+    offset: [f64; 2],
+    defs: String,
+    body: String,
+}
+
+impl<'a> Renderer<'a> {
+    /// The frame clipping `element`, if any.
+    fn containing_frame(&self, element: &Element) -> Option<&'a Element> {
+        let frame = self.elements.get(element.base().frame_id.as_deref()?)?;
+        match frame {
+            Element::Frame(_) | Element::Magicframe(_) => Some(*frame),
+            _ => None,
+        }
+    }
+
+    /// Appends `node`, clipped to the element's frame and wrapped in a link.
+    fn add(&mut self, element: &Element, node: String) {
+        let node = match self.containing_frame(element) {
+            Some(frame) => format!(
+                r#"<g clip-path="url(#{})">{}</g>"#,
+                escape(&frame.base().id),
+                node
+            ),
+            None => node,
+        };
+        match &element.base().link {
+            Some(link) if !link.is_empty() => {
+                self.body
+                    .push_str(&format!(r#"<a href="{}">{}</a>"#, escape(link), node))
+            }
+            _ => self.body.push_str(&node),
+        }
+    }
+
+    fn opacity(&self, element: &Element) -> f64 {
+        let frame_opacity = self
+            .containing_frame(element)
+            .map_or(100.0, |frame| frame.base().opacity);
+        frame_opacity * element.base().opacity / 10000.0
+    }
+
+    /// `translate(...) rotate(...)` placing an element drawn at the origin.
+    fn transform(&self, element: &Element) -> String {
+        let base = element.base();
+        let [x1, y1, x2, y2] = absolute_coords(element);
+        let cx = (x2 - x1) / 2.0 - (base.x - x1);
+        let cy = (y2 - y1) / 2.0 - (base.y - y1);
+        format!(
+            "translate({} {}) rotate({} {} {})",
+            base.x + self.offset[0],
+            base.y + self.offset[1],
+            base.angle.to_degrees(),
+            cx,
+            cy
+        )
+    }
+
+    fn opacity_attributes(&self, element: &Element) -> String {
+        let opacity = self.opacity(element);
+        if opacity == 1.0 {
+            String::new()
+        } else {
+            format!(
+                r#" stroke-opacity="{}" fill-opacity="{}""#,
+                opacity, opacity
+            )
+        }
+    }
+
+    /// `fill` for a background, defining a hachure pattern if the fill
+    /// style needs one.
+    fn fill(&mut self, base: &ElementBase) -> String {
+        if is_transparent(&base.background_color) {
+            return "none".into();
+        }
+        if base.fill_style == FillStyle::Solid {
+            return escape(&base.background_color);
+        }
+        let id = format!("fill-{}", escape(&base.id));
+        let gap = (base.stroke_width * 4.0).max(1.0);
+        let line = |angle: f64| {
+            format!(
+                r#"<pattern id="{}-{}" patternUnits="userSpaceOnUse" width="{}" height="{}" patternTransform="rotate({})"><path d="M0 {} H{}" stroke="{}" stroke-width="{}"/></pattern>"#,
+                id,
+                angle.abs(),
+                gap,
+                gap,
+                angle,
+                gap / 2.0,
+                gap,
+                escape(&base.background_color),
+                base.stroke_width / 2.0
+            )
+        };
+        // roughjs' default hachure angle, plus the perpendicular lines of
+        // cross-hatch
+        self.defs.push_str(&line(-41.0));
+        if base.fill_style == FillStyle::CrossHatch {
+            self.defs.push_str(&line(49.0));
+            self.defs.push_str(&format!(
+                r#"<pattern id="{}" patternUnits="userSpaceOnUse" width="{}" height="{}"><rect width="{}" height="{}" fill="url(#{}-41)"/><rect width="{}" height="{}" fill="url(#{}-49)"/></pattern>"#,
+                id, gap * 100.0, gap * 100.0, gap * 100.0, gap * 100.0, id, gap * 100.0, gap * 100.0, id
+            ));
+            return format!("url(#{})", id);
+        }
+        format!("url(#{}-41)", id)
+    }
+
+    /// Stroke attributes, `solid` overriding the element's stroke style.
+    fn stroke(&self, base: &ElementBase, solid: bool) -> String {
+        let mut stroke = format!(
+            r#"stroke="{}" stroke-width="{}""#,
+            escape(&base.stroke_color),
+            base.stroke_width
+        );
+        let dash = match base.stroke_style {
+            _ if solid => None,
+            StrokeStyle::Solid | StrokeStyle::Unknown(_) => None,
+            StrokeStyle::Dashed => Some([8.0, 8.0 + base.stroke_width]),
+            StrokeStyle::Dotted => Some([1.5, 6.0 + base.stroke_width]),
+        };
+        if let Some([dash, gap]) = dash {
+            stroke.push_str(&format!(r#" stroke-dasharray="{} {}""#, dash, gap));
+        }
+        stroke
+    }
+
+    fn render(&mut self, element: &Element) {
+        match element {
+            Element::Selection(_) | Element::Other(_) => {}
+            Element::Rectangle(base)
+            | Element::Diamond(base)
+            | Element::Ellipse(base)
+            | Element::Embeddable(base)
+            | Element::Iframe(base) => {
+                let d = match element {
+                    Element::Diamond(_) => diamond_path(base),
+                    Element::Ellipse(_) => ellipse_path(base),
+                    _ => rectangle_path(base),
+                };
+                let fill = self.fill(base);
+                let node = format!(
+                    r#"<path d="{}" fill="{}" {} stroke-linecap="round" stroke-linejoin="round"{} transform="{}"/>"#,
+                    d,
+                    fill,
+                    self.stroke(base, false),
+                    self.opacity_attributes(element),
+                    self.transform(element)
+                );
+                self.add(element, node);
+            }
+            Element::Line(linear) => self.render_linear(element, linear),
+            Element::Arrow(arrow) => self.render_linear(element, &arrow.linear),
+            Element::Freedraw(freedraw) => {
+                let base = &freedraw.base;
+                let mut node = format!(
+                    r#"<g stroke="none"{} transform="{}">"#,
+                    self.opacity_attributes(element),
+                    self.transform(element)
+                );
+                if is_loop(&freedraw.points) && !is_transparent(&base.background_color) {
+                    let fill = self.fill(base);
+                    node.push_str(&format!(
+                        r#"<path d="{}" fill="{}"/>"#,
+                        polyline(&freedraw.points, true),
+                        fill
+                    ));
+                }
+                node.push_str(&format!(
+                    r#"<path fill="{}" d="{}"/></g>"#,
+                    escape(&base.stroke_color),
+                    freedraw::svg_path(freedraw)
+                ));
+                self.add(element, node);
+            }
+            Element::Text(text) => {
+                let node = self.text(element, text);
+                self.add(element, node);
+            }
+            Element::Image(image) => {
+                if let Some(node) = self.image(element, image) {
+                    self.add(element, node);
+                }
+            }
+            Element::Frame(frame) | Element::Magicframe(frame) => {
+                let base = &frame.base;
+                let node = format!(
+                    r#"<rect transform="{}" width="{}" height="{}" rx="{}" ry="{}" fill="none" stroke="{}" stroke-width="{}"/>"#,
+                    self.transform(element),
+                    base.width,
+                    base.height,
+                    FRAME_RADIUS,
+                    FRAME_RADIUS,
+                    FRAME_STROKE_COLOR,
+                    FRAME_STROKE_WIDTH
+                );
+                // frames aren't clipped to themselves
+                self.body.push_str(&node);
+            }
+        }
+    }
+
+    fn render_linear(&mut self, element: &Element, linear: &LinearElement) {
+        let base = &linear.base;
+        let points = &linear.points;
+        if points.is_empty() {
+            return;
+        }
+        let segments = if base.roundness.is_some() && points.len() > 2 {
+            curve(points)
+        } else {
+            points
+                .windows(2)
+                .map(|pair| [pair[0], pair[0], pair[1], pair[1]])
+                .collect()
+        };
+        let closed = matches!(element, Element::Line(_))
+            && is_loop(points)
+            && !is_transparent(&base.background_color);
+
+        let transform = self.transform(element);
+        let opacity = self.opacity_attributes(element);
+        let mut group = String::new();
+        let fill = if closed {
+            self.fill(base)
+        } else {
+            "none".into()
+        };
+        group.push_str(&format!(
+            r#"<path d="{}" fill="{}"{} {} stroke-linejoin="round"{} transform="{}"/>"#,
+            bezier_path(points[0], &segments, closed),
+            fill,
+            if closed {
+                r#" fill-rule="evenodd""#
+            } else {
+                ""
+            },
+            self.stroke(base, false),
+            opacity,
+            transform
+        ));
+
+        if let Element::Arrow(_) = element {
+            for (arrowhead, start) in [
+                (&linear.start_arrowhead, true),
+                (&linear.end_arrowhead, false),
+            ] {
+                if let Some(arrowhead) = arrowhead {
+                    if let Some(node) = self.arrowhead(linear, &segments, start, arrowhead) {
+                        group.push_str(&format!(
+                            r#"<g{} transform="{}">{}</g>"#,
+                            opacity, transform, node
+                        ));
+                    }
+                }
+            }
+        }
+
+        // cut the line where its label sits
+        let label = base
+            .bound_elements
+            .iter()
+            .flatten()
+            .filter_map(|bound| self.elements.get(bound.id.as_str()))
+            .find_map(|bound| match bound {
+                Element::Text(text) if !text.base.is_deleted => Some(&text.base),
+                _ => None,
+            });
+        let mut node = String::new();
+        match label {
+            Some(label) => {
+                let id = format!("mask-{}", escape(&base.id));
+                let offset_x = base.x + self.offset[0];
+                let offset_y = base.y + self.offset[1];
+                node.push_str(&format!(
+                    r##"<g mask="url(#{})" stroke-linecap="round">{}</g><mask id="{}"><rect x="0" y="0" fill="#fff" width="{}" height="{}"/><rect x="{}" y="{}" fill="#000" width="{}" height="{}" opacity="1"/></mask>"##,
+                    id,
+                    group,
+                    id,
+                    base.width + 100.0 + offset_x,
+                    base.height + 100.0 + offset_y,
+                    label.x + self.offset[0],
+                    label.y + self.offset[1],
+                    label.width,
+                    label.height
+                ));
+            }
+            None => node.push_str(&format!(r#"<g stroke-linecap="round">{}</g>"#, group)),
+        }
+        self.add(element, node);
+    }
+
+    /// `getArrowheadShapes`, relative to the element.
+    fn arrowhead(
+        &self,
+        linear: &LinearElement,
+        segments: &[[[f64; 2]; 4]],
+        start: bool,
+        arrowhead: &Arrowhead,
+    ) -> Option<String> {
+        if let Arrowhead::Unknown(_) = arrowhead {
+            return None;
+        }
+        let base = &linear.base;
+        let points = &linear.points;
+        let [p0, p1, p2, p3] = if start {
+            *segments.first()?
+        } else {
+            *segments.last()?
+        };
+        // the TS version evaluates the curve near its end, weights reversed
+        let equation = |t: f64, i: usize| {
+            (1.0 - t).powi(3) * p3[i]
+                + 3.0 * t * (1.0 - t).powi(2) * p2[i]
+                + 3.0 * t.powi(2) * (1.0 - t) * p1[i]
+                + p0[i] * t.powi(3)
+        };
+        let [x2, y2] = if start { p0 } else { p3 };
+        let (x1, y1) = (equation(0.3, 0), equation(0.3, 1));
+        let distance = (x2 - x1).hypot(y2 - y1);
+        if distance == 0.0 {
+            return None;
+        }
+        let (nx, ny) = ((x2 - x1) / distance, (y2 - y1) / distance);
+
+        let size = match arrowhead {
+            Arrowhead::Arrow => 25.0f64,
+            Arrowhead::Diamond | Arrowhead::DiamondOutline => 12.0,
+            _ => 15.0,
+        };
+        let (cx, cy) = if start {
+            (points[0][0], points[0][1])
+        } else {
+            (points[points.len() - 1][0], points[points.len() - 1][1])
+        };
+        let (px, py) = match points.len() {
+            0 | 1 => (0.0, 0.0),
+            _ if start => (points[1][0], points[1][1]),
+            len => (points[len - 2][0], points[len - 2][1]),
+        };
+        let length = (cx - px).hypot(cy - py);
+        let multiplier = match arrowhead {
+            Arrowhead::Diamond | Arrowhead::DiamondOutline => 0.25,
+            _ => 0.5,
+        };
+        let min_size = size.min(length * multiplier);
+        let (xs, ys) = (x2 - nx * min_size, y2 - ny * min_size);
+
+        let stroke = self.stroke(base, true);
+        let filled = |outline: bool| {
+            if outline {
+                escape(&self.canvas_background)
+            } else {
+                escape(&base.stroke_color)
+            }
+        };
+        if let Arrowhead::Dot | Arrowhead::Circle | Arrowhead::CircleOutline = arrowhead {
+            let diameter = (ys - y2).hypot(xs - x2) + base.stroke_width - 2.0;
+            return Some(format!(
+                r#"<circle cx="{}" cy="{}" r="{}" fill="{}" {}/>"#,
+                num(x2),
+                num(y2),
+                num(diameter.max(0.0) / 2.0),
+                filled(*arrowhead == Arrowhead::CircleOutline),
+                stroke
+            ));
+        }
+
+        let angle = match arrowhead {
+            Arrowhead::Bar => 90.0f64,
+            Arrowhead::Arrow => 20.0,
+            _ => 25.0,
+        }
+        .to_radians();
+        let [x3, y3] = rotate([xs, ys], [x2, y2], -angle);
+        let [x4, y4] = rotate([xs, ys], [x2, y2], angle);
+        let point = |x: f64, y: f64| format!("{} {}", num(x), num(y));
+        Some(match arrowhead {
+            Arrowhead::Triangle | Arrowhead::TriangleOutline => format!(
+                r#"<path d="M{} L{} L{} Z" fill="{}" {} stroke-linejoin="round"/>"#,
+                point(x2, y2),
+                point(x3, y3),
+                point(x4, y4),
+                filled(*arrowhead == Arrowhead::TriangleOutline),
+                stroke
+            ),
+            Arrowhead::Diamond | Arrowhead::DiamondOutline => {
+                let [ox, oy] = if start {
+                    rotate(
+                        [x2 + min_size * 2.0, y2],
+                        [x2, y2],
+                        (py - y2).atan2(px - x2),
+                    )
+                } else {
+                    rotate(
+                        [x2 - min_size * 2.0, y2],
+                        [x2, y2],
+                        (y2 - py).atan2(x2 - px),
+                    )
+                };
+                format!(
+                    r#"<path d="M{} L{} L{} L{} Z" fill="{}" {} stroke-linejoin="round"/>"#,
+                    point(x2, y2),
+                    point(x3, y3),
+                    point(ox, oy),
+                    point(x4, y4),
+                    filled(*arrowhead == Arrowhead::DiamondOutline),
+                    stroke
+                )
+            }
+            _ => {
+                let stroke = if base.stroke_style == StrokeStyle::Dotted {
+                    // for dotted arrows caps, reduce gap to make it more legible
+                    format!(
+                        r#"{} stroke-dasharray="1.5 {}""#,
+                        stroke,
+                        6.0 + base.stroke_width - 2.0
+                    )
+                } else {
+                    stroke
+                };
+                format!(
+                    r#"<path d="M{} L{} M{} L{}" fill="none" {}/>"#,
+                    point(x3, y3),
+                    point(x2, y2),
+                    point(x4, y4),
+                    point(x2, y2),
+                    stroke
+                )
+            }
+        })
+    }
+
+    fn text(&self, element: &Element, text: &TextElement) -> String {
+        let base = &text.base;
+        let mut node = format!(
+            r#"<g{} transform="{}">"#,
+            self.opacity_attributes(element),
+            self.transform(element)
+        );
+        let line_height = text.font_size * text.line_height;
+        let horizontal_offset = match text.text_align {
+            TextAlign::Left | TextAlign::Unknown(_) => 0.0,
+            TextAlign::Center => base.width / 2.0,
+            TextAlign::Right => base.width,
+        };
+        let vertical_offset = fonts::vertical_offset(text.font_family, text.font_size, line_height);
+        let rtl = is_rtl(&text.text);
+        let anchor = match text.text_align {
+            TextAlign::Center => "middle",
+            TextAlign::Right => "end",
+            TextAlign::Left | TextAlign::Unknown(_) if rtl => "end",
+            TextAlign::Left | TextAlign::Unknown(_) => "start",
+        };
+        let normalized = text.text.replace("\r\n", "\n").replace('\r', "\n");
+        for (i, line) in normalized.split('\n').enumerate() {
+            node.push_str(&format!(
+                r#"<text x="{}" y="{}" font-family="{}" font-size="{}px" fill="{}" text-anchor="{}" style="white-space: pre;" direction="{}" dominant-baseline="alphabetic">{}</text>"#,
+                horizontal_offset,
+                i as f64 * line_height + vertical_offset,
+                escape(&fonts::font_family_string(text.font_family)),
+                text.font_size,
+                escape(&base.stroke_color),
+                anchor,
+                if rtl { "rtl" } else { "ltr" },
+                escape(line)
+            ));
+        }
+        node.push_str("</g>");
+        node
+    }
+
+    fn image(&mut self, element: &Element, image: &ImageElement) -> Option<String> {
+        let base = &image.base;
+        let file = self.files?.get(image.file_id.as_deref()?)?;
+        let width = base.width.round();
+        let height = base.height.round();
+        let mut use_attributes = String::new();
+        if self.options.dark && file.mime_type != SVG_MIME_TYPE {
+            use_attributes.push_str(&format!(r#" filter="{}""#, IMAGE_INVERT_FILTER));
+        }
+        if image.scale != [1.0, 1.0] {
+            let translate_x = if image.scale[0] != 1.0 { -width } else { 0.0 };
+            let translate_y = if image.scale[1] != 1.0 { -height } else { 0.0 };
+            use_attributes.push_str(&format!(
+                r#" transform="scale({}, {}) translate({} {})""#,
+                image.scale[0], image.scale[1], translate_x, translate_y
+            ));
+        }
+
+        let mut clip = String::new();
+        if base.roundness.is_some() {
+            let id = format!("image-clipPath-{}", escape(&base.id));
+            let radius = corner_radius(base.width.min(base.height), &base.roundness);
+            self.defs.push_str(&format!(
+                r#"<clipPath id="{}"><rect width="{}" height="{}" rx="{}" ry="{}"/></clipPath>"#,
+                id, base.width, base.height, radius, radius
+            ));
+            clip = format!(r#" clip-path="url(#{})""#, id);
+        }
+        Some(format!(
+            r#"<g transform="{}"{}><image width="{}" height="{}" href="{}" preserveAspectRatio="none" opacity="{}"{}/></g>"#,
+            self.transform(element),
+            clip,
+            width,
+            height,
+            escape(&file.data_url),
+            self.opacity(element),
+            use_attributes
+        ))
+    }
+}
+
+fn rectangle_path(base: &ElementBase) -> String {
+    let (w, h) = (base.width, base.height);
+    if base.roundness.is_none() {
+        return format!("M0 0 L{} 0 L{} {} L0 {} Z", num(w), num(w), num(h), num(h));
+    }
+    let r = corner_radius(w.min(h), &base.roundness);
+    format!(
+        "M{r} 0 L{wr} 0 Q{w} 0, {w} {r} L{w} {hr} Q{w} {h}, {wr} {h} L{r} {h} Q0 {h}, 0 {hr} L0 {r} Q0 0, {r} 0 Z",
+        r = num(r),
+        w = num(w),
+        h = num(h),
+        wr = num(w - r),
+        hr = num(h - r)
+    )
+}
+
+/// `getDiamondPoints` and the rounded path `_generateElementShape` draws.
+fn diamond_path(base: &ElementBase) -> String {
+    let top = [(base.width / 2.0).floor() + 1.0, 0.0];
+    let right = [base.width, (base.height / 2.0).floor() + 1.0];
+    let bottom = [top[0], base.height];
+    let left = [0.0, right[1]];
+    let p = |x: f64, y: f64| format!("{} {}", num(x), num(y));
+    if base.roundness.is_none() {
+        return format!(
+            "M{} L{} L{} L{} Z",
+            p(top[0], top[1]),
+            p(right[0], right[1]),
+            p(bottom[0], bottom[1]),
+            p(left[0], left[1])
+        );
+    }
+    let vr = corner_radius((top[0] - left[0]).abs(), &base.roundness);
+    let hr = corner_radius((right[1] - top[1]).abs(), &base.roundness);
+    format!(
+        "M{} L{} C{}, {}, {} L{} C{}, {}, {} L{} C{}, {}, {} L{} C{}, {}, {} Z",
+        p(top[0] + vr, top[1] + hr),
+        p(right[0] - vr, right[1] - hr),
+        p(right[0], right[1]),
+        p(right[0], right[1]),
+        p(right[0] - vr, right[1] + hr),
+        p(bottom[0] + vr, bottom[1] - hr),
+        p(bottom[0], bottom[1]),
+        p(bottom[0], bottom[1]),
+        p(bottom[0] - vr, bottom[1] - hr),
+        p(left[0] + vr, left[1] + hr),
+        p(left[0], left[1]),
+        p(left[0], left[1]),
+        p(left[0] + vr, left[1] - hr),
+        p(top[0] - vr, top[1] + hr),
+        p(top[0], top[1]),
+        p(top[0], top[1]),
+        p(top[0] + vr, top[1] + hr)
+    )
+}
+
+fn ellipse_path(base: &ElementBase) -> String {
+    let (rx, ry) = (base.width / 2.0, base.height / 2.0);
+    format!(
+        "M0 {ry} A{rx} {ry} 0 1 0 {w} {ry} A{rx} {ry} 0 1 0 0 {ry} Z",
+        rx = num(rx),
+        ry = num(ry),
+        w = num(base.width)
+    )
+}
+
+fn polyline(points: &[[f64; 2]], closed: bool) -> String {
+    let mut d = String::new();
+    for (i, point) in points.iter().enumerate() {
+        d.push_str(if i == 0 { "M" } else { " L" });
+        d.push_str(&format!("{} {}", num(point[0]), num(point[1])));
+    }
+    if closed {
+        d.push_str(" Z");
+    }
+    d
+}
+
+/// The cubic segments roughjs' `curve` fits through `points`, a
+/// Catmull-Rom spline.
+fn curve(points: &[[f64; 2]]) -> Vec<[[f64; 2]; 4]> {
+    let at = |i: isize| points[i.clamp(0, points.len() as isize - 1) as usize];
+    (0..points.len() as isize - 1)
+        .map(|i| {
+            let (p0, p1, p2, p3) = (at(i - 1), at(i), at(i + 1), at(i + 2));
+            [
+                p1,
+                [p1[0] + (p2[0] - p0[0]) / 6.0, p1[1] + (p2[1] - p0[1]) / 6.0],
+                [p2[0] - (p3[0] - p1[0]) / 6.0, p2[1] - (p3[1] - p1[1]) / 6.0],
+                p2,
+            ]
+        })
+        .collect()
+}
+
+fn bezier_path(start: [f64; 2], segments: &[[[f64; 2]; 4]], closed: bool) -> String {
+    let mut d = format!("M{} {}", num(start[0]), num(start[1]));
+    for [_, c1, c2, end] in segments {
+        d.push_str(&format!(
+            " C{} {}, {} {}, {} {}",
+            num(c1[0]),
+            num(c1[1]),
+            num(c2[0]),
+            num(c2[1]),
+            num(end[0]),
+            num(end[1])
+        ));
+    }
+    if closed {
+        d.push_str(" Z");
+    }
+    d
+}
+
+fn is_frame(element: &Element) -> bool {
+    matches!(element, Element::Frame(_) | Element::Magicframe(_))
+}
+
+/// Renders the non-deleted elements of `scene` to a standalone SVG.
+pub fn render(scene: &Scene, options: &ExportOptions) -> Result<String> {
+    let elements = elements_for_render(scene, options.dark);
+    let frame_ids: HashSet<&str> = elements
+        .iter()
+        .filter(|element| is_frame(element))
+        .map(|frame| frame.base().id.as_str())
+        .collect();
+
+    // getRootElements: what frames clip doesn't grow the canvas
+    let bounds = common_bounds(elements.iter().filter(|element| {
+        element
+            .base()
+            .frame_id
+            .as_deref()
+            .map_or(true, |id| !frame_ids.contains(id))
+    }))
+    .unwrap_or([0.0, 0.0, 0.0, 0.0]);
+    let drawn: Vec<&Element> = elements.iter().collect();
+    Ok(render_elements(
+        scene,
+        &elements,
+        &drawn,
+        bounds,
+        options.padding,
+        options,
+    ))
+}
+
+/// `exportToSvg` with `exportingFrame`: what overlaps the frame, cropped to
+/// it without padding, and without the frame's own outline and name.
+pub fn render_frame(scene: &Scene, frame_id: &str, options: &ExportOptions) -> Result<String> {
+    let elements: Vec<Element> = scene
+        .elements
+        .iter()
+        .filter(|element| !element.base().is_deleted)
+        .cloned()
+        .collect();
+    let frame = elements
+        .iter()
+        .find(|element| is_frame(element) && element.base().id == frame_id)
+        .ok_or_else(|| Error::Other(format!("no frame {} in the scene", frame_id)))?;
+    let bounds = element_bounds(frame);
+    // getElementsOverlappingFrame
+    let drawn: Vec<&Element> = elements
+        .iter()
+        .filter(|element| !matches!(element, Element::Selection(_)))
+        .filter(|element| element.base().id != frame_id)
+        .filter(|element| {
+            element
+                .base()
+                .frame_id
+                .as_deref()
+                .map_or(true, |id| id == frame_id)
+        })
+        .filter(|element| {
+            let [min_x, min_y, max_x, max_y] = element_bounds(element);
+            min_x <= bounds[2] && max_x >= bounds[0] && min_y <= bounds[3] && max_y >= bounds[1]
+        })
+        .collect();
+    Ok(render_elements(
+        scene, &elements, &drawn, bounds, 0.0, options,
+    ))
+}
+
+/// What [`render_frame`]'s pages don't show in full: elements of no frame
+/// that lie outside every frame or stick out of one, with the usual padding.
+/// `None` when there are none.
+pub fn render_unframed(scene: &Scene, options: &ExportOptions) -> Result<Option<String>> {
+    let elements: Vec<Element> = scene
+        .elements
+        .iter()
+        .filter(|element| !element.base().is_deleted)
+        .cloned()
+        .collect();
+    let frames: Vec<(&str, [f64; 4])> = elements
+        .iter()
+        .filter(|element| is_frame(element))
+        .map(|frame| (frame.base().id.as_str(), element_bounds(frame)))
+        .collect();
+    let drawn: Vec<&Element> = elements
+        .iter()
+        .filter(|element| !is_frame(element) && !matches!(element, Element::Selection(_)))
+        .filter(|element| {
+            let frame_id = element.base().frame_id.as_deref();
+            !frames.iter().any(|(id, _)| frame_id == Some(*id))
+        })
+        .filter(|element| {
+            // the frame pages clip what sticks out, so only elements a frame
+            // holds whole are left off
+            let [min_x, min_y, max_x, max_y] = element_bounds(element);
+            !frames.iter().any(|(_, bounds)| {
+                min_x >= bounds[0] && max_x <= bounds[2] && min_y >= bounds[1] && max_y <= bounds[3]
+            })
+        })
+        .collect();
+    let bounds = match common_bounds(drawn.iter().copied()) {
+        Some(bounds) => bounds,
+        None => return Ok(None),
+    };
+    Ok(Some(render_elements(
+        scene,
+        &elements,
+        &drawn,
+        bounds,
+        options.padding,
+        options,
+    )))
+}
+
+/// Renders `drawn` onto a canvas spanning `bounds` plus `padding`. Bound
+/// text and frame clips are looked up in `elements`.
+fn render_elements(
+    scene: &Scene,
+    elements: &[Element],
+    drawn: &[&Element],
+    [min_x, min_y, max_x, max_y]: [f64; 4],
+    padding: f64,
+    options: &ExportOptions,
+) -> String {
+    let by_id: HashMap<&str, &Element> = elements
+        .iter()
+        .map(|element| (element.base().id.as_str(), element))
+        .collect();
+    let width = max_x - min_x + padding * 2.0;
+    let height = max_y - min_y + padding * 2.0;
+    let offset = [padding - min_x, padding - min_y];
+
+    let background = options
+        .background
+        .clone()
+        .or_else(|| scene.app_state.view_background_color.clone())
+        .unwrap_or_else(|| "#ffffff".into());
+    let mut renderer = Renderer {
+        elements: by_id.clone(),
+        files: scene.files.as_ref(),
+        options,
+        canvas_background: background.clone(),
+        offset,
+        defs: String::new(),
+        body: String::new(),
+    };
+
+    for frame in elements.iter().filter(|element| is_frame(element)) {
+        let base = frame.base();
+        renderer.defs.push_str(&format!(
+            r#"<clipPath id="{}"><rect transform="{}" width="{}" height="{}" rx="{}" ry="{}"/></clipPath>"#,
+            escape(&base.id),
+            renderer.transform(frame),
+            base.width,
+            base.height,
+            FRAME_RADIUS,
+            FRAME_RADIUS
+        ));
+    }
+
+    let (embeds, others): (Vec<&Element>, Vec<&Element>) = drawn
+        .iter()
+        .copied()
+        .partition(|element| matches!(element, Element::Embeddable(_) | Element::Iframe(_)));
+    for element in others {
+        if let Element::Text(text) = element {
+            if text
+                .container_id
+                .as_deref()
+                .map_or(false, |id| by_id.contains_key(id))
+            {
+                continue;
+            }
+        }
+        renderer.render(element);
+        let bound_text = element
+            .base()
+            .bound_elements
+            .iter()
+            .flatten()
+            .filter_map(|bound| by_id.get(bound.id.as_str()))
+            .find(|bound| matches!(bound, Element::Text(_)));
+        if let Some(text) = bound_text {
+            renderer.render(text);
+        }
+    }
+    for element in embeds {
+        renderer.render(element);
+    }
+
+    let families: BTreeSet<&str> = drawn
+        .iter()
+        .filter_map(|element| match element {
+            Element::Text(text) => fonts::family_name(text.font_family),
+            _ => None,
+        })
+        .collect();
+
+    let mut svg = format!(
+        r#"<svg version="1.1" xmlns="{}" viewBox="0 0 {} {}" width="{}" height="{}""#,
+        SVG_NS,
+        width,
+        height,
+        width * options.scale,
+        height * options.scale
+    );
+    if options.dark {
+        svg.push_str(&format!(r#" filter="{}""#, THEME_FILTER));
+    }
+    svg.push_str(&format!(
+        ">{}\n  <defs>\n    <style class=\"style-fonts\">\n      {}\n    </style>\n    {}\n  </defs>\n  ",
+        SVG_EXPORT_TAG,
+        fonts::font_face_css(&families).join("\n"),
+        renderer.defs
+    ));
+    if !is_transparent(&background) {
+        svg.push_str(&format!(
+            r#"<rect x="0" y="0" width="{}" height="{}" fill="{}"/>"#,
+            width,
+            height,
+            escape(&background)
+        ));
+    }
+    svg.push_str(&renderer.body);
+    svg.push_str("</svg>");
+    svg
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const FIXTURE: &str = include_str!("../../fixtures/scene.excalidraw");
+
+    #[test]
+    fn renders_every_element_type() {
+        let scene = Scene::parse(FIXTURE).unwrap();
+        let svg = render(&scene, &ExportOptions::default()).unwrap();
+        assert!(svg.starts_with(r#"<svg version="1.1" xmlns="http://www.w3.org/2000/svg""#));
+        assert!(svg.contains(SVG_EXPORT_TAG));
+        assert!(svg.contains("<text "));
+        assert!(svg.contains("<image "));
+        assert!(svg.contains("<clipPath id="));
+        assert!(svg.contains("@font-face"));
+        assert!(!svg.contains(" filter="));
+        resvg::usvg::Tree::from_str(&svg, &resvg::usvg::Options::default()).unwrap();
+
+        let dark = render(
+            &scene,
+            &ExportOptions {
+                dark: true,
+                ..ExportOptions::default()
+            },
+        )
+        .unwrap();
+        assert!(dark.contains(THEME_FILTER));
+    }
+
+    #[test]
+    fn crops_to_an_exported_frame() {
+        let scene = Scene::parse(FIXTURE).unwrap();
+        let svg = render_frame(&scene, "frame-1", &ExportOptions::default()).unwrap();
+        assert!(svg.contains(r#"viewBox="0 0 620 300""#));
+        assert!(!svg.contains(">Overview<"));
+        resvg::usvg::Tree::from_str(&svg, &resvg::usvg::Options::default()).unwrap();
+
+        assert!(render_frame(&scene, "missing", &ExportOptions::default()).is_err());
+    }
+
+    #[test]
+    fn pads_and_scales_the_canvas() {
+        let scene = Scene::parse(
+            r##"{"type":"excalidraw","version":2,"source":"test","appState":{"viewBackgroundColor":"#ffc9c9"},"elements":[
+                {"type":"rectangle","id":"r","x":10,"y":20,"width":100,"height":50,"angle":0,"strokeColor":"#1e1e1e","backgroundColor":"transparent","fillStyle":"solid","strokeWidth":2,"strokeStyle":"solid","roughness":1,"opacity":100,"groupIds":[],"frameId":null,"index":"a0","roundness":null,"seed":1,"version":1,"versionNonce":1,"isDeleted":false,"boundElements":null,"updated":1,"link":null,"locked":false}
+            ]}"##,
+        )
+        .unwrap();
+        let svg = render(
+            &scene,
+            &ExportOptions {
+                scale: 2.0,
+                padding: 5.0,
+                ..ExportOptions::default()
+            },
+        )
+        .unwrap();
+        assert!(svg.contains(r#"viewBox="0 0 110 60" width="220" height="120""#));
+        assert!(svg.contains(r##"fill="#ffc9c9""##));
+        assert!(svg.contains("translate(5 5)"));
+
+        let transparent = render(
+            &scene,
+            &ExportOptions {
+                background: Some("transparent".into()),
+                ..ExportOptions::default()
+            },
+        )
+        .unwrap();
+        assert!(!transparent.contains("#ffc9c9"));
+    }
+
+    #[test]
+    fn detects_rtl_text() {
+        assert!(is_rtl("שלום"));
+        assert!(!is_rtl("hello שלום"));
+        assert!(!is_rtl("123"));
+    }
+}
@@ -0,0 +1,334 @@
+use std::{
+    collections::HashSet,
+    fs::{self, OpenOptions},
+    io::{ErrorKind, Write},
+    path::{Path, PathBuf},
+    sync::Mutex,
+};
+
+use serde::Serialize;
+use tauri::{
+    api::dialog::{blocking, FileDialogBuilder},
+    AppHandle, Manager, Runtime, State,
+};
+
+use crate::{
+    error::{Error, Result},
+    overlay::MAIN_WINDOW,
+    scene::{
+        fractional_index::{self, IndexFix},
+        image, restore, Scene,
+    },
+    settings::{self, SettingsState},
+    tray,
+    watch::SceneWatcher,
+};
+
+/// Emitted to the webview with a scene file picked outside of it (tray,
+/// recent files).
+pub const OPEN_FILE: &str = "open-file";
+
+const MAX_RECENT_FILES: usize = 10;
+
+const SCENE_EXTENSION: &str = "excalidraw";
+/// Exports that can embed the scene, conventionally `.excalidraw.png` and
+/// `.excalidraw.svg`.
+const PNG_EXTENSION: &str = "png";
+const SVG_EXTENSION: &str = "svg";
+const SCENE_EXTENSIONS: &[&str] = &[SCENE_EXTENSION, "json", PNG_EXTENSION, SVG_EXTENSION];
+
+#[derive(Clone, Serialize)]
+pub struct SceneFile {
+    pub path: PathBuf,
+    pub contents: String,
+}
+
+/// Paths the user handed to the app through a native dialog or the recent
+/// files list. The webview may only write scenes back to these, so a
+/// compromised page can't overwrite arbitrary files.
+#[derive(Default)]
+pub struct FileScope(Mutex<HashSet<PathBuf>>);
+
+impl FileScope {
+    pub fn allow(&self, path: &Path) {
+        self.0.lock().unwrap().insert(path.to_path_buf());
+    }
+
+    pub fn ensure_allowed(&self, path: &Path) -> Result<()> {
+        if self.0.lock().unwrap().contains(path) {
+            Ok(())
+        } else {
+            Err(Error::Other(format!(
+                "{} was not opened by the user",
+                path.display()
+            )))
+        }
+    }
+}
+
+/// Writes `contents` next to `path` and renames it into place, so a crash
+/// mid-write never leaves a truncated file behind. Each write goes through
+/// its own temp file, so concurrent writers to `path` can't mix their
+/// contents; the last rename wins.
+pub fn write_atomic(path: &Path, contents: &[u8]) -> Result<()> {
+    let dir = path
+        .parent()
+        .ok_or_else(|| Error::Other(format!("{} has no parent dir", path.display())))?;
+    let file_name = path
+        .file_name()
+        .ok_or_else(|| Error::Other(format!("{} is not a file path", path.display())))?;
+    let (tmp_path, mut file) = loop {
+        let tmp_path = dir.join(format!(
+            ".{}.{}-{:08x}.tmp",
+            file_name.to_string_lossy(),
+            std::process::id(),
+            rand::random::<u32>()
+        ));
+        match OpenOptions::new()
+            .write(true)
+            .create_new(true)
+            .open(&tmp_path)
+        {
+            Ok(file) => break (tmp_path, file),
+            Err(err) if err.kind() == ErrorKind::AlreadyExists => continue,
+            Err(err) => return Err(err.into()),
+        }
+    };
+
+    let written = file.write_all(contents).and_then(|_| file.sync_all());
+    drop(file);
+    written
+        .and_then(|_| fs::rename(&tmp_path, path))
+        .map_err(|err| {
+            let _ = fs::remove_file(&tmp_path);
+            err.into()
+        })
+}
+
+fn has_extension(path: &Path, extension: &str) -> bool {
+    path.extension()
+        .map_or(false, |ext| ext.eq_ignore_ascii_case(extension))
+}
+
+fn ensure_image(path: &Path) -> Result<()> {
+    if has_extension(path, PNG_EXTENSION) || has_extension(path, SVG_EXTENSION) {
+        Ok(())
+    } else {
+        Err(Error::Other(format!(
+            "{} is not a PNG or SVG",
+            path.display()
+        )))
+    }
+}
+
+/// Reads the scene JSON at `path`, or embedded in the image at `path`.
+pub fn read_scene_json(path: &Path) -> Result<String> {
+    if has_extension(path, PNG_EXTENSION) {
+        image::decode_png_metadata(&fs::read(path)?)
+    } else if has_extension(path, SVG_EXTENSION) {
+        image::decode_svg_metadata(&fs::read_to_string(path)?)
+    } else {
+        Ok(fs::read_to_string(path)?)
+    }
+}
+
+/// Writes `contents` to `path`, or embeds it in the image at `path` in place
+/// of its current scene. The image itself is left as it was.
+fn write_scene_json(path: &Path, contents: &str) -> Result<()> {
+    if has_extension(path, PNG_EXTENSION) {
+        let png = image::encode_png_metadata(&fs::read(path)?, contents)?;
+        write_atomic(path, &png)
+    } else if has_extension(path, SVG_EXTENSION) {
+        let svg = image::encode_svg_metadata(&fs::read_to_string(path)?, contents)?;
+        write_atomic(path, svg.as_bytes())
+    } else {
+        write_atomic(path, contents.as_bytes())
+    }
+}
+
+/// Tells the watcher about a save, so it isn't mistaken for an external
+/// edit.
+fn saved(watcher: &SceneWatcher, path: &Path, contents: &str) {
+    if let Err(err) = watcher.update(Some(path), contents, true) {
+        log::warn!("failed to update the watched scene: {}", err);
+    }
+}
+
+/// Reads a scene the user chose, restored to the current format, granting it
+/// write access and recording it as recent.
+pub fn read_scene<R: Runtime>(app: &AppHandle<R>, path: PathBuf) -> Result<SceneFile> {
+    let contents = restore::restore_json(&read_scene_json(&path)?)?;
+    app.state::<FileScope>().allow(&path);
+    add_recent(app, path.clone())?;
+    Ok(SceneFile { path, contents })
+}
+
+/// Reads `path` and hands it to the webview, recording it as recent.
+pub fn open_path<R: Runtime>(app: &AppHandle<R>, path: PathBuf) -> Result<()> {
+    let scene = read_scene(app, path)?;
+    if let Some(window) = app.get_window(MAIN_WINDOW) {
+        window.emit(OPEN_FILE, scene)?;
+    }
+    Ok(())
+}
+
+/// Shows a native open dialog and loads the picked file.
+pub fn pick_and_open<R: Runtime>(app: &AppHandle<R>) {
+    let handle = app.clone();
+    FileDialogBuilder::new()
+        .add_filter("Excalidraw", SCENE_EXTENSIONS)
+        .pick_file(move |path| {
+            if let Some(path) = path {
+                if let Err(err) = open_path(&handle, path) {
+                    log::error!("failed to open file: {}", err);
+                }
+            }
+        });
+}
+
+pub fn recent_files<R: Runtime>(app: &AppHandle<R>) -> Vec<PathBuf> {
+    app.state::<SettingsState>()
+        .0
+        .lock()
+        .unwrap()
+        .recent_files
+        .clone()
+}
+
+/// Moves `path` to the front of the recent files list, persists it and
+/// refreshes the tray submenu.
+pub fn add_recent<R: Runtime>(app: &AppHandle<R>, path: PathBuf) -> Result<()> {
+    {
+        let state = app.state::<SettingsState>();
+        let mut settings = state.0.lock().unwrap();
+        settings.recent_files.retain(|recent| recent != &path);
+        settings.recent_files.insert(0, path);
+        settings.recent_files.truncate(MAX_RECENT_FILES);
+        settings::save(app, &settings)?;
+    }
+    tray::rebuild_menu(app)
+}
+
+#[tauri::command]
+pub fn open_file<R: Runtime>(app: AppHandle<R>) {
+    pick_and_open(&app);
+}
+
+#[tauri::command]
+pub fn open_recent_file<R: Runtime>(app: AppHandle<R>, path: PathBuf) -> Result<()> {
+    if !recent_files(&app).contains(&path) {
+        return Err(Error::Other(format!(
+            "{} is not a recent file",
+            path.display()
+        )));
+    }
+    open_path(&app, path)
+}
+
+#[tauri::command]
+pub fn get_recent_files(settings: State<SettingsState>) -> Vec<PathBuf> {
+    settings.0.lock().unwrap().recent_files.clone()
+}
+
+/// Shows a native open dialog and returns the picked scene, or `None` if the
+/// dialog was dismissed.
+#[tauri::command]
+pub async fn open_scene<R: Runtime>(app: AppHandle<R>) -> Result<Option<SceneFile>> {
+    blocking::FileDialogBuilder::new()
+        .add_filter("Excalidraw", SCENE_EXTENSIONS)
+        .pick_file()
+        .map(|path| read_scene(&app, path))
+        .transpose()
+}
+
+/// Overwrites a scene the user previously opened or saved. Scenes opened from
+/// `.png` or `.svg` exports are embedded back into the image.
+#[tauri::command]
+pub async fn save_scene(
+    scope: State<'_, FileScope>,
+    watcher: State<'_, SceneWatcher>,
+    path: PathBuf,
+    contents: String,
+) -> Result<()> {
+    scope.ensure_allowed(&path)?;
+    write_scene_json(&path, &contents)?;
+    saved(&watcher, &path, &contents);
+    Ok(())
+}
+
+/// Pulls the scene out of a `.png` or `.svg` export the user opened,
+/// restored to the current format.
+#[tauri::command]
+pub async fn extract_scene(scope: State<'_, FileScope>, path: PathBuf) -> Result<String> {
+    scope.ensure_allowed(&path)?;
+    ensure_image(&path)?;
+    restore::restore_json(&read_scene_json(&path)?)
+}
+
+/// A scene with its fractional indices repaired, and what was fixed.
+#[derive(Debug, Clone, Serialize)]
+pub struct IndexRepair {
+    pub contents: String,
+    pub fixes: Vec<IndexFix>,
+}
+
+/// `syncInvalidIndices` on the scene in `contents`: regenerates missing,
+/// malformed, duplicate and out of order indices and reports each one, so
+/// the webview can tell the user what changed.
+#[tauri::command]
+pub fn repair_indices(contents: String) -> Result<IndexRepair> {
+    let mut scene = Scene::parse(&contents)?;
+    let fixes = fractional_index::sync_invalid_indices(&mut scene.elements)?;
+    let contents = if fixes.is_empty() {
+        contents
+    } else {
+        scene.to_json()?
+    };
+    Ok(IndexRepair { contents, fixes })
+}
+
+/// Embeds `contents` into a `.png` or `.svg` export the user opened,
+/// replacing the scene it carried.
+#[tauri::command]
+pub async fn embed_scene(
+    scope: State<'_, FileScope>,
+    watcher: State<'_, SceneWatcher>,
+    path: PathBuf,
+    contents: String,
+) -> Result<()> {
+    scope.ensure_allowed(&path)?;
+    ensure_image(&path)?;
+    write_scene_json(&path, &contents)?;
+    saved(&watcher, &path, &contents);
+    Ok(())
+}
+
+/// Shows a native save dialog and writes the scene there, returning the
+/// chosen path so later saves can go through [`save_scene`].
+#[tauri::command]
+pub async fn save_scene_as<R: Runtime>(
+    app: AppHandle<R>,
+    contents: String,
+    file_name: Option<String>,
+) -> Result<Option<PathBuf>> {
+    let file_name = file_name.unwrap_or_else(|| "Untitled".into());
+    let path = match blocking::FileDialogBuilder::new()
+        .add_filter("Excalidraw", &[SCENE_EXTENSION])
+        .set_file_name(&format!("{}.{}", file_name, SCENE_EXTENSION))
+        .save_file()
+    {
+        Some(path) => path,
+        None => return Ok(None),
+    };
+    let path = if path.extension().is_none() {
+        path.with_extension(SCENE_EXTENSION)
+    } else {
+        path
+    };
+
+    write_atomic(&path, contents.as_bytes())?;
+    saved(&app.state::<SceneWatcher>(), &path, &contents);
+    app.state::<FileScope>().allow(&path);
+    add_recent(&app, path.clone())?;
+    Ok(Some(path))
+}
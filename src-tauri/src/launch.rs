@@ -0,0 +1,181 @@
+use std::{
+    ffi::OsString,
+    path::{Path, PathBuf},
+    sync::Mutex,
+};
+
+use serde::{Deserialize, Serialize};
+use tauri::{AppHandle, Manager, Runtime, State};
+
+use crate::{
+    error::Result,
+    files,
+    library::{self, Library, LibraryItem},
+    overlay::{self, OverlayCommand, OverlayMode, MAIN_WINDOW},
+};
+
+/// Emitted to the webview with the [`LaunchFile`]s of a later invocation.
+pub const LAUNCH_FILES: &str = "launch-files";
+
+const LIBRARY_EXTENSION: &str = "excalidrawlib";
+
+/// What the app was asked to do on the command line, e.g. by the OS when a
+/// `.excalidraw` file is double-clicked.
+#[derive(Debug, Default, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct LaunchArgs {
+    /// Absolute paths, so they stay valid when handed to another process.
+    pub files: Vec<PathBuf>,
+    /// Mode change requested with `--draw`, `--pass-through`, `--present`,
+    /// `--hide` or `--toggle`. The last one given wins.
+    pub overlay: Option<OverlayCommand>,
+}
+
+impl LaunchArgs {
+    /// Parses the arguments following the executable name, resolving
+    /// relative paths against `cwd`. Unknown flags are skipped, including
+    /// the `-psn_*` process serial number older macOS versions pass.
+    pub fn parse<I, S>(args: I, cwd: &Path) -> Self
+    where
+        I: IntoIterator<Item = S>,
+        S: Into<OsString>,
+    {
+        let mut parsed = Self::default();
+        for arg in args.into_iter().map(Into::into) {
+            let flag = match arg.to_str() {
+                Some(flag) if flag.starts_with('-') => flag,
+                _ => {
+                    parsed.files.push(cwd.join(arg));
+                    continue;
+                }
+            };
+            match flag {
+                "--draw" => parsed.overlay = Some(OverlayCommand::Enter(OverlayMode::Drawing)),
+                "--pass-through" => {
+                    parsed.overlay = Some(OverlayCommand::Enter(OverlayMode::PassThrough))
+                }
+                "--present" => {
+                    parsed.overlay = Some(OverlayCommand::Enter(OverlayMode::Presenting))
+                }
+                "--hide" => parsed.overlay = Some(OverlayCommand::Enter(OverlayMode::Hidden)),
+                "--toggle" => parsed.overlay = Some(OverlayCommand::ToggleDrawing),
+                _ => log::debug!("ignoring unknown argument {}", flag),
+            }
+        }
+        parsed
+    }
+
+    pub fn from_env() -> Self {
+        let cwd = std::env::current_dir().unwrap_or_default();
+        Self::parse(std::env::args_os().skip(1), &cwd)
+    }
+}
+
+#[derive(Debug, Clone, Serialize)]
+#[serde(tag = "kind", rename_all = "camelCase")]
+pub enum LaunchFile {
+    /// A scene to load onto the canvas.
+    Scene { path: PathBuf, contents: String },
+    /// A `.excalidrawlib` file, already imported into the library folder
+    /// at `path`.
+    Library {
+        path: PathBuf,
+        items: Vec<LibraryItem>,
+    },
+}
+
+/// Files from the initial launch, held until the webview is ready for them.
+pub struct PendingLaunch(pub Mutex<Vec<LaunchFile>>);
+
+/// Reads the files in `args`, skipping (and logging) any that can't be read.
+pub fn load<R: Runtime>(app: &AppHandle<R>, args: &LaunchArgs) -> Vec<LaunchFile> {
+    args.files
+        .iter()
+        .filter_map(|path| match load_file(app, path) {
+            Ok(file) => Some(file),
+            Err(err) => {
+                log::warn!("failed to open {}: {}", path.display(), err);
+                None
+            }
+        })
+        .collect()
+}
+
+fn load_file<R: Runtime>(app: &AppHandle<R>, path: &Path) -> Result<LaunchFile> {
+    if path
+        .extension()
+        .map_or(false, |ext| ext.eq_ignore_ascii_case(LIBRARY_EXTENSION))
+    {
+        let imported = library::import_file(&app.state::<Library>().dir(), path)?;
+        return Ok(LaunchFile::Library {
+            items: library::read_file(&imported)?,
+            path: imported,
+        });
+    }
+    let scene = files::read_scene(app, path.to_path_buf())?;
+    Ok(LaunchFile::Scene {
+        path: scene.path,
+        contents: scene.contents,
+    })
+}
+
+/// Acts on the arguments of an invocation that reached the already running
+/// app. Without a mode flag, relaunching brings the overlay up for drawing.
+pub fn dispatch<R: Runtime>(app: &AppHandle<R>, args: &LaunchArgs) -> Result<()> {
+    let command = args
+        .overlay
+        .unwrap_or(OverlayCommand::Enter(OverlayMode::Drawing));
+    overlay::dispatch(app, command)?;
+
+    let files = load(app, args);
+    if !files.is_empty() {
+        if let Some(window) = app.get_window(MAIN_WINDOW) {
+            window.emit(LAUNCH_FILES, files)?;
+        }
+    }
+    Ok(())
+}
+
+/// Hands the webview the files the app was started with. Subsequent calls
+/// return nothing.
+#[tauri::command]
+pub fn take_launch_files(pending: State<PendingLaunch>) -> Vec<LaunchFile> {
+    std::mem::take(&mut *pending.0.lock().unwrap())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn resolves_files_and_skips_unknown_flags() {
+        let cwd = Path::new("/home/user/drawings");
+        let args = LaunchArgs::parse(
+            [
+                "-psn_0_12345",
+                "plan.excalidraw",
+                "/tmp/shapes.excalidrawlib",
+            ],
+            cwd,
+        );
+        assert_eq!(
+            args.files,
+            [
+                PathBuf::from("/home/user/drawings/plan.excalidraw"),
+                PathBuf::from("/tmp/shapes.excalidrawlib"),
+            ]
+        );
+        assert_eq!(args.overlay, None);
+    }
+
+    #[test]
+    fn last_mode_flag_wins() {
+        let args = LaunchArgs::parse(["--hide", "--draw"], Path::new("/"));
+        assert_eq!(
+            args.overlay,
+            Some(OverlayCommand::Enter(OverlayMode::Drawing))
+        );
+
+        let args = LaunchArgs::parse(["--toggle"], Path::new("/"));
+        assert_eq!(args.overlay, Some(OverlayCommand::ToggleDrawing));
+    }
+}
@@ -0,0 +1,596 @@
+//! The library kept as a folder of `.excalidrawlib` files rather than in
+//! IndexedDB, so it can be versioned in git or shared between machines
+//! through a synced drive. Items the user adds land in `library.excalidrawlib`,
+//! imported libraries keep a file of their own, and the folder is watched
+//! so edits made elsewhere reach the webview as [`LIBRARY_CHANGED`] events.
+
+use std::{
+    collections::HashSet,
+    fs,
+    io::ErrorKind,
+    path::{Path, PathBuf},
+    sync::Mutex,
+    time::Duration,
+};
+
+use notify_debouncer_mini::{
+    new_debouncer,
+    notify::{RecommendedWatcher, RecursiveMode},
+    DebounceEventResult, Debouncer,
+};
+use serde::{Deserialize, Serialize};
+use serde_json::{json, Map, Value};
+use tauri::{api::dialog::blocking::FileDialogBuilder, AppHandle, Manager, Runtime, State};
+
+use crate::{
+    error::{Error, Result},
+    files,
+    overlay::MAIN_WINDOW,
+    scene::{self, random_id, restore, Element, EXPORT_SOURCE},
+    settings::{self, SettingsState},
+};
+
+/// Emitted to the webview with the library items whenever the folder
+/// changes.
+pub const LIBRARY_CHANGED: &str = "library-changed";
+
+const LIBRARY_DIR: &str = "library";
+const LIBRARY_EXTENSION: &str = "excalidrawlib";
+/// Where items added in the app go.
+const MAIN_FILE: &str = "library.excalidrawlib";
+/// `EXPORT_DATA_TYPES.excalidrawLibrary`
+const LIBRARY_TYPE: &str = "excalidrawlib";
+/// `VERSIONS.excalidrawLibrary`
+const LIBRARY_VERSION: u32 = 2;
+/// Synced drives write files in bursts; wait for them to settle.
+const DEBOUNCE: Duration = Duration::from_millis(500);
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum LibraryItemStatus {
+    Published,
+    Unpublished,
+}
+
+/// `LibraryItem`, the v2 format.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct LibraryItem {
+    pub id: String,
+    pub status: LibraryItemStatus,
+    pub elements: Vec<Element>,
+    /// Epoch milliseconds.
+    pub created: i64,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub name: Option<String>,
+    #[serde(flatten)]
+    pub extra: Map<String, Value>,
+}
+
+/// `restoreLibraryItems`: migrates v1 items, which are bare element arrays,
+/// fills in missing fields and drops deleted elements, then items left
+/// without any.
+pub fn restore_library_items(
+    items: &[Value],
+    default_status: LibraryItemStatus,
+) -> Result<Vec<LibraryItem>> {
+    let mut restored = Vec::new();
+    for item in items {
+        let (elements, fields) = match item {
+            Value::Array(elements) => (elements, None),
+            Value::Object(fields) => match fields.get("elements") {
+                Some(Value::Array(elements)) => (elements, Some(fields)),
+                _ => continue,
+            },
+            _ => continue,
+        };
+        let elements: Vec<Value> = elements
+            .iter()
+            .filter(|element| element["isDeleted"] != Value::Bool(true))
+            .cloned()
+            .collect();
+        let elements = restore::restore_elements(&elements, None, false)?;
+        if elements.is_empty() {
+            continue;
+        }
+
+        let field = |name: &str| fields.and_then(|fields| fields.get(name));
+        let mut extra = fields.cloned().unwrap_or_default();
+        for known in ["id", "status", "elements", "created", "name"] {
+            extra.remove(known);
+        }
+        restored.push(LibraryItem {
+            id: field("id")
+                .and_then(Value::as_str)
+                .filter(|id| !id.is_empty())
+                .map_or_else(random_id, String::from),
+            status: field("status")
+                .and_then(|status| serde_json::from_value(status.clone()).ok())
+                .unwrap_or(default_status),
+            elements,
+            created: field("created")
+                .and_then(Value::as_i64)
+                .filter(|&created| created != 0)
+                .unwrap_or_else(scene::now_millis),
+            name: field("name").and_then(Value::as_str).map(String::from),
+            extra,
+        });
+    }
+    Ok(restored)
+}
+
+/// `parseLibraryJSON`
+pub fn parse_library(json: &str, default_status: LibraryItemStatus) -> Result<Vec<LibraryItem>> {
+    let data: Value = serde_json::from_str(json)?;
+    let version = data["version"].as_u64();
+    if data["type"] != LIBRARY_TYPE || !matches!(version, Some(1) | Some(2)) {
+        return Err(Error::Other("invalid library".into()));
+    }
+    let items = match (&data["libraryItems"], &data["library"]) {
+        (Value::Array(items), _) | (_, Value::Array(items)) => items.as_slice(),
+        _ => &[],
+    };
+    restore_library_items(items, default_status)
+}
+
+/// `serializeLibraryAsJSON`
+pub fn serialize_library(items: &[LibraryItem]) -> Result<String> {
+    let data = json!({
+        "type": LIBRARY_TYPE,
+        "version": LIBRARY_VERSION,
+        "source": EXPORT_SOURCE,
+        "libraryItems": scene::to_js_value(&items)?,
+    });
+    Ok(serde_json::to_string_pretty(&data)?)
+}
+
+/// `isUniqueItem`, which compares the elements, so a library re-exported by
+/// someone else is still recognized, and also the item id.
+fn is_unique_item(existing: &[LibraryItem], target: &LibraryItem) -> bool {
+    !existing.iter().any(|item| {
+        item.id == target.id
+            || (item.elements.len() == target.elements.len()
+                && item.elements.iter().zip(&target.elements).all(|(a, b)| {
+                    let (a, b) = (a.base(), b.base());
+                    a.id == b.id && a.version_nonce == b.version_nonce
+                }))
+    })
+}
+
+/// `mergeLibraryItems`: the items of `other` not in `local` come first.
+pub fn merge_library_items(local: &[LibraryItem], other: Vec<LibraryItem>) -> Vec<LibraryItem> {
+    let mut merged: Vec<LibraryItem> = Vec::with_capacity(local.len() + other.len());
+    for item in other {
+        if is_unique_item(local, &item) && is_unique_item(&merged, &item) {
+            merged.push(item);
+        }
+    }
+    merged.extend_from_slice(local);
+    merged
+}
+
+/// The library files in `dir`, the main one first, then by name.
+fn library_files(dir: &Path) -> Result<Vec<PathBuf>> {
+    let mut paths = Vec::new();
+    let entries = match fs::read_dir(dir) {
+        Ok(entries) => entries,
+        Err(err) if err.kind() == ErrorKind::NotFound => return Ok(paths),
+        Err(err) => return Err(err.into()),
+    };
+    for entry in entries {
+        let path = entry?.path();
+        if path.is_file()
+            && path
+                .extension()
+                .map_or(false, |extension| extension == LIBRARY_EXTENSION)
+        {
+            paths.push(path);
+        }
+    }
+    paths.sort_by_key(|path| (path.file_name() != Some(MAIN_FILE.as_ref()), path.clone()));
+    Ok(paths)
+}
+
+/// The items of the library at `path`, none if there's no such file.
+pub fn read_file(path: &Path) -> Result<Vec<LibraryItem>> {
+    match fs::read_to_string(path) {
+        Ok(json) => parse_library(&json, LibraryItemStatus::Unpublished),
+        Err(err) if err.kind() == ErrorKind::NotFound => Ok(Vec::new()),
+        Err(err) => Err(err.into()),
+    }
+}
+
+/// Writes `items` to `path`, removing the file once it has none left.
+fn write_file(path: &Path, items: &[LibraryItem]) -> Result<()> {
+    if items.is_empty() {
+        match fs::remove_file(path) {
+            Err(err) if err.kind() != ErrorKind::NotFound => Err(err.into()),
+            _ => Ok(()),
+        }
+    } else {
+        files::write_atomic(path, serialize_library(items)?.as_bytes())
+    }
+}
+
+/// The items of every library in `dir`, each once. Files that don't parse
+/// are skipped, so one bad file on a shared drive doesn't hide the rest.
+pub fn load_dir(dir: &Path) -> Result<Vec<LibraryItem>> {
+    let mut items: Vec<LibraryItem> = Vec::new();
+    for path in library_files(dir)? {
+        match read_file(&path) {
+            Ok(file_items) => {
+                for item in file_items {
+                    if is_unique_item(&items, &item) {
+                        items.push(item);
+                    }
+                }
+            }
+            Err(err) => log::warn!("skipping library {}: {}", path.display(), err),
+        }
+    }
+    Ok(items)
+}
+
+/// Copies the library at `source` into `dir` as a file of its own.
+pub fn import_file(dir: &Path, source: &Path) -> Result<PathBuf> {
+    let items = read_file(source)?;
+    fs::create_dir_all(dir)?;
+    let stem = source
+        .file_stem()
+        .and_then(|stem| stem.to_str())
+        .unwrap_or(LIBRARY_DIR)
+        .trim_end_matches(&format!(".{}", LIBRARY_EXTENSION));
+    let mut path = dir.join(format!("{}.{}", stem, LIBRARY_EXTENSION));
+    let mut copy = 1;
+    while path.exists() {
+        copy += 1;
+        path = dir.join(format!("{}-{}.{}", stem, copy, LIBRARY_EXTENSION));
+    }
+    write_file(&path, &items)?;
+    Ok(path)
+}
+
+/// Merges `items` into the main library file, skipping ones any library in
+/// `dir` already has.
+pub fn merge_into_dir(dir: &Path, items: Vec<LibraryItem>) -> Result<()> {
+    let existing = load_dir(dir)?;
+    let new_items: Vec<LibraryItem> = items
+        .into_iter()
+        .filter(|item| is_unique_item(&existing, item))
+        .collect();
+    if new_items.is_empty() {
+        return Ok(());
+    }
+    fs::create_dir_all(dir)?;
+    let path = dir.join(MAIN_FILE);
+    let main = read_file(&path)?;
+    write_file(&path, &merge_library_items(&main, new_items))
+}
+
+/// Removes the items with `ids` from whichever files hold them.
+pub fn delete_from_dir(dir: &Path, ids: &[String]) -> Result<()> {
+    let ids: HashSet<&str> = ids.iter().map(String::as_str).collect();
+    for path in library_files(dir)? {
+        let items = match read_file(&path) {
+            Ok(items) => items,
+            Err(err) => {
+                log::warn!("skipping library {}: {}", path.display(), err);
+                continue;
+            }
+        };
+        let kept: Vec<LibraryItem> = items
+            .iter()
+            .filter(|item| !ids.contains(item.id.as_str()))
+            .cloned()
+            .collect();
+        if kept.len() != items.len() {
+            write_file(&path, &kept)?;
+        }
+    }
+    Ok(())
+}
+
+pub struct Library {
+    dir: Mutex<PathBuf>,
+    watcher: Mutex<Option<Debouncer<RecommendedWatcher>>>,
+}
+
+impl Library {
+    /// Serves the library in `dir`, watching it for changes made outside
+    /// the app.
+    pub fn start<R: Runtime>(app: &AppHandle<R>, dir: PathBuf) -> Self {
+        let library = Self {
+            dir: Mutex::new(dir),
+            watcher: Mutex::new(None),
+        };
+        library.watch(app);
+        library
+    }
+
+    pub fn dir(&self) -> PathBuf {
+        self.dir.lock().unwrap().clone()
+    }
+
+    /// Points the library at `dir`, watching it instead of the old one.
+    fn set_dir<R: Runtime>(&self, app: &AppHandle<R>, dir: PathBuf) {
+        *self.dir.lock().unwrap() = dir;
+        self.watch(app);
+    }
+
+    fn watch<R: Runtime>(&self, app: &AppHandle<R>) {
+        let dir = self.dir();
+        let mut watcher = self.watcher.lock().unwrap();
+        // dropping the old debouncer stops it
+        *watcher = None;
+        let handle = app.clone();
+        let watched = dir.clone();
+        let debouncer = fs::create_dir_all(&dir).map_err(Error::from).and_then(|_| {
+            let mut debouncer =
+                new_debouncer(DEBOUNCE, move |result: DebounceEventResult| match result {
+                    Ok(_) => {
+                        if let Err(err) = notify_changed(&handle, &watched) {
+                            log::error!("failed to reload the library: {}", err);
+                        }
+                    }
+                    Err(err) => log::warn!("library watcher error: {}", err),
+                })
+                .map_err(|err| Error::Other(err.to_string()))?;
+            debouncer
+                .watcher()
+                .watch(&dir, RecursiveMode::NonRecursive)
+                .map_err(|err| Error::Other(err.to_string()))?;
+            Ok(debouncer)
+        });
+        match debouncer {
+            Ok(debouncer) => *watcher = Some(debouncer),
+            Err(err) => log::warn!("not watching library {}: {}", dir.display(), err),
+        }
+    }
+}
+
+fn notify_changed<R: Runtime>(app: &AppHandle<R>, dir: &Path) -> Result<()> {
+    let items = load_dir(dir)?;
+    if let Some(window) = app.get_window(MAIN_WINDOW) {
+        window.emit(LIBRARY_CHANGED, items)?;
+    }
+    Ok(())
+}
+
+/// The folder set in the settings, or one in the app data dir.
+pub fn library_dir<R: Runtime>(app: &AppHandle<R>, configured: Option<PathBuf>) -> Result<PathBuf> {
+    match configured {
+        Some(dir) => Ok(dir),
+        None => app
+            .path_resolver()
+            .app_data_dir()
+            .map(|dir| dir.join(LIBRARY_DIR))
+            .ok_or_else(|| Error::Other("could not resolve the app data dir".into())),
+    }
+}
+
+#[tauri::command]
+pub fn get_library_dir(library: State<Library>) -> PathBuf {
+    library.dir()
+}
+
+/// Lets the user pick the folder the library lives in, e.g. one on a synced
+/// drive the team shares, and returns its items.
+#[tauri::command]
+pub async fn choose_library_dir<R: Runtime>(
+    app: AppHandle<R>,
+    library: State<'_, Library>,
+    settings: State<'_, SettingsState>,
+) -> Result<Option<Vec<LibraryItem>>> {
+    let dir = match FileDialogBuilder::new().pick_folder() {
+        Some(dir) => dir,
+        None => return Ok(None),
+    };
+    {
+        let mut settings = settings.0.lock().unwrap();
+        settings.library_dir = Some(dir.clone());
+        settings::save(&app, &settings)?;
+    }
+    library.set_dir(&app, dir.clone());
+    load_dir(&dir).map(Some)
+}
+
+#[tauri::command]
+pub async fn list_library(library: State<'_, Library>) -> Result<Vec<LibraryItem>> {
+    load_dir(&library.dir())
+}
+
+/// Asks for a `.excalidrawlib` file and adds it to the library folder.
+#[tauri::command]
+pub async fn import_library(library: State<'_, Library>) -> Result<Option<Vec<LibraryItem>>> {
+    let source = match FileDialogBuilder::new()
+        .add_filter("Excalidraw library", &[LIBRARY_EXTENSION])
+        .pick_file()
+    {
+        Some(source) => source,
+        None => return Ok(None),
+    };
+    let dir = library.dir();
+    import_file(&dir, &source)?;
+    load_dir(&dir).map(Some)
+}
+
+/// Adds `items` from the webview, e.g. the IndexedDB library it had before,
+/// leaving out ones the folder already has.
+#[tauri::command]
+pub async fn merge_library(
+    library: State<'_, Library>,
+    items: Vec<Value>,
+) -> Result<Vec<LibraryItem>> {
+    let dir = library.dir();
+    merge_into_dir(
+        &dir,
+        restore_library_items(&items, LibraryItemStatus::Unpublished)?,
+    )?;
+    load_dir(&dir)
+}
+
+/// Saves the items with `ids`, or the whole library, to a file the user
+/// picks.
+#[tauri::command]
+pub async fn export_library(
+    library: State<'_, Library>,
+    ids: Option<Vec<String>>,
+) -> Result<Option<PathBuf>> {
+    let mut items = load_dir(&library.dir())?;
+    if let Some(ids) = ids {
+        items.retain(|item| ids.contains(&item.id));
+    }
+    let path = match FileDialogBuilder::new()
+        .add_filter("Excalidraw library", &[LIBRARY_EXTENSION])
+        .set_file_name(&format!("{}.{}", LIBRARY_DIR, LIBRARY_EXTENSION))
+        .save_file()
+    {
+        Some(path) => path,
+        None => return Ok(None),
+    };
+    let path = if path.extension().is_none() {
+        path.with_extension(LIBRARY_EXTENSION)
+    } else {
+        path
+    };
+    files::write_atomic(&path, serialize_library(&items)?.as_bytes())?;
+    Ok(Some(path))
+}
+
+#[tauri::command]
+pub async fn delete_library_items(
+    library: State<'_, Library>,
+    ids: Vec<String>,
+) -> Result<Vec<LibraryItem>> {
+    let dir = library.dir();
+    delete_from_dir(&dir, &ids)?;
+    load_dir(&dir)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::scene::test_util::{rectangle_json, temp_dir};
+
+    fn element(id: &str, version_nonce: i64) -> Value {
+        rectangle_json(id, 1, version_nonce, Some("a0"))
+    }
+
+    fn item(id: &str, elements: &[Value]) -> Value {
+        json!({ "id": id, "status": "published", "elements": elements, "created": 1 })
+    }
+
+    fn library(items: &[Value]) -> String {
+        json!({ "type": "excalidrawlib", "version": 2, "libraryItems": items }).to_string()
+    }
+
+    fn ids(items: &[LibraryItem]) -> Vec<&str> {
+        items.iter().map(|item| item.id.as_str()).collect()
+    }
+
+    #[test]
+    fn restores_v1_and_v2_libraries() {
+        let mut deleted = element("b", 1);
+        deleted["isDeleted"] = json!(true);
+        let v1 = json!({
+            "type": "excalidrawlib",
+            "version": 1,
+            "library": [[element("a", 1)], [deleted]],
+        });
+        let items = parse_library(&v1.to_string(), LibraryItemStatus::Unpublished).unwrap();
+        assert_eq!(items.len(), 1);
+        assert_eq!(items[0].status, LibraryItemStatus::Unpublished);
+        assert!(!items[0].id.is_empty());
+
+        let v2 = library(&[
+            json!({ "id": "x", "elements": [element("a", 1)], "name": "Box", "custom": 1 }),
+            item("y", &[deleted]),
+        ]);
+        let items = parse_library(&v2, LibraryItemStatus::Unpublished).unwrap();
+        assert_eq!(ids(&items), ["x"]);
+        assert_eq!(items[0].name.as_deref(), Some("Box"));
+        assert_eq!(items[0].extra["custom"], 1);
+
+        let round_trip = parse_library(
+            &serialize_library(&items).unwrap(),
+            LibraryItemStatus::Published,
+        )
+        .unwrap();
+        assert_eq!(round_trip, items);
+
+        assert!(parse_library(
+            r#"{"type":"excalidraw","version":2}"#,
+            LibraryItemStatus::Published
+        )
+        .is_err());
+        assert!(parse_library(
+            r#"{"type":"excalidrawlib","version":3}"#,
+            LibraryItemStatus::Published
+        )
+        .is_err());
+    }
+
+    #[test]
+    fn merges_unique_items_first() {
+        let local = restore_library_items(
+            &[item("1", &[element("a", 1)]), item("2", &[element("b", 1)])],
+            LibraryItemStatus::Unpublished,
+        )
+        .unwrap();
+        let other = restore_library_items(
+            &[
+                // same elements under another id
+                item("3", &[element("a", 1)]),
+                // same id, different elements
+                item("2", &[element("c", 1)]),
+                item("4", &[element("a", 2)]),
+                item("5", &[element("a", 1), element("b", 1)]),
+                item("5", &[element("d", 1)]),
+            ],
+            LibraryItemStatus::Unpublished,
+        )
+        .unwrap();
+        let merged = merge_library_items(&local, other);
+        assert_eq!(ids(&merged), ["4", "5", "1", "2"]);
+    }
+
+    #[test]
+    fn manages_a_library_folder() {
+        let dir = temp_dir("library-folder");
+        let source = dir.with_extension("excalidrawlib");
+        fs::write(
+            &source,
+            library(&[
+                item("shared", &[element("s", 1)]),
+                item("1", &[element("a", 1)]),
+            ]),
+        )
+        .unwrap();
+
+        let added = restore_library_items(
+            &[item("1", &[element("a", 1)])],
+            LibraryItemStatus::Unpublished,
+        )
+        .unwrap();
+        merge_into_dir(&dir, added.clone()).unwrap();
+        let imported = import_file(&dir, &source).unwrap();
+        assert_eq!(imported.extension().unwrap(), LIBRARY_EXTENSION);
+        assert!(import_file(&dir, &source).unwrap().ends_with(format!(
+            "{}-2.excalidrawlib",
+            source.file_stem().unwrap().to_str().unwrap()
+        )));
+        fs::write(dir.join("broken.excalidrawlib"), "{").unwrap();
+
+        // the main file first, each item once
+        assert_eq!(ids(&load_dir(&dir).unwrap()), ["1", "shared"]);
+        merge_into_dir(&dir, added).unwrap();
+        assert_eq!(read_file(&dir.join(MAIN_FILE)).unwrap().len(), 1);
+
+        delete_from_dir(&dir, &["1".to_string()]).unwrap();
+        assert!(!dir.join(MAIN_FILE).exists());
+        assert_eq!(ids(&load_dir(&dir).unwrap()), ["shared"]);
+
+        fs::remove_dir_all(&dir).unwrap();
+        fs::remove_file(source).unwrap();
+    }
+}
@@ -0,0 +1,182 @@
+use std::{
+    fs::{self, File, OpenOptions},
+    io::{self, Write},
+    path::{Path, PathBuf},
+    sync::Mutex,
+};
+
+use log::{Level, LevelFilter, Log, Metadata, Record};
+use tauri::{AppHandle, Runtime};
+use time::{format_description::well_known::Rfc3339, OffsetDateTime};
+
+use crate::error::{Error, Result};
+
+const LOG_FILE: &str = "excalidraw.log";
+const MAX_FILE_SIZE: u64 = 1024 * 1024;
+/// Rotated files kept next to the current one (`excalidraw.1.log`, ...).
+const MAX_ROTATED_FILES: usize = 4;
+
+/// Target used for records forwarded from the webview.
+const WEBVIEW_TARGET: &str = "webview";
+
+/// Size-rotated log file. Once the current file outgrows [`MAX_FILE_SIZE`]
+/// it is shifted to `excalidraw.1.log` and older files move up by one.
+struct RotatingFile {
+    dir: PathBuf,
+    file: File,
+    size: u64,
+}
+
+impl RotatingFile {
+    fn open(dir: &Path) -> io::Result<Self> {
+        fs::create_dir_all(dir)?;
+        let file = OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(dir.join(LOG_FILE))?;
+        let size = file.metadata()?.len();
+        Ok(Self {
+            dir: dir.to_path_buf(),
+            file,
+            size,
+        })
+    }
+
+    fn write_line(&mut self, line: &str) -> io::Result<()> {
+        if self.size > 0 && self.size + line.len() as u64 > MAX_FILE_SIZE {
+            self.rotate()?;
+        }
+        self.file.write_all(line.as_bytes())?;
+        self.size += line.len() as u64;
+        Ok(())
+    }
+
+    fn rotate(&mut self) -> io::Result<()> {
+        for i in (1..MAX_ROTATED_FILES).rev() {
+            let from = rotated_path(&self.dir, i);
+            if from.exists() {
+                fs::rename(from, rotated_path(&self.dir, i + 1))?;
+            }
+        }
+        fs::rename(self.dir.join(LOG_FILE), rotated_path(&self.dir, 1))?;
+        *self = Self::open(&self.dir)?;
+        Ok(())
+    }
+}
+
+fn rotated_path(dir: &Path, index: usize) -> PathBuf {
+    dir.join(format!("excalidraw.{}.log", index))
+}
+
+struct FileLogger {
+    level: LevelFilter,
+    file: Mutex<RotatingFile>,
+}
+
+impl Log for FileLogger {
+    fn enabled(&self, metadata: &Metadata) -> bool {
+        metadata.level() <= self.level
+    }
+
+    fn log(&self, record: &Record) {
+        if !self.enabled(record.metadata()) {
+            return;
+        }
+        let timestamp = OffsetDateTime::now_utc()
+            .format(&Rfc3339)
+            .unwrap_or_default();
+        let line = format!(
+            "{} {:<5} [{}] {}\n",
+            timestamp,
+            record.level(),
+            record.target(),
+            record.args()
+        );
+        // release builds have no console, the file is the only sink there
+        if cfg!(debug_assertions) {
+            eprint!("{}", line);
+        }
+        let _ = self.file.lock().unwrap().write_line(&line);
+    }
+
+    fn flush(&self) {
+        let _ = self.file.lock().unwrap().file.flush();
+    }
+}
+
+fn log_dir<R: Runtime>(app: &AppHandle<R>) -> Result<PathBuf> {
+    app.path_resolver()
+        .app_log_dir()
+        .ok_or_else(|| Error::Other("could not resolve the app log dir".into()))
+}
+
+/// Installs the global logger writing to the app log dir.
+pub fn init<R: Runtime>(app: &AppHandle<R>, level: LevelFilter) -> Result<()> {
+    let file = RotatingFile::open(&log_dir(app)?)?;
+    log::set_boxed_logger(Box::new(FileLogger {
+        level,
+        file: Mutex::new(file),
+    }))
+    .map_err(|err| Error::Other(err.to_string()))?;
+    log::set_max_level(level);
+    Ok(())
+}
+
+/// Returns up to `lines` of the most recent log lines, oldest first,
+/// reading back into rotated files when the current one is too short.
+#[tauri::command]
+pub fn get_logs<R: Runtime>(app: AppHandle<R>, lines: Option<usize>) -> Result<Vec<String>> {
+    let dir = log_dir(&app)?;
+    let wanted = lines.unwrap_or(500);
+
+    let files = std::iter::once(dir.join(LOG_FILE))
+        .chain((1..=MAX_ROTATED_FILES).map(|i| rotated_path(&dir, i)));
+    let mut collected: Vec<String> = Vec::new();
+    for path in files {
+        if collected.len() >= wanted {
+            break;
+        }
+        let contents = match fs::read_to_string(&path) {
+            Ok(contents) => contents,
+            Err(err) if err.kind() == io::ErrorKind::NotFound => continue,
+            Err(err) => return Err(err.into()),
+        };
+        let mut older: Vec<String> = contents.lines().map(String::from).collect();
+        older.append(&mut collected);
+        collected = older;
+    }
+
+    let skip = collected.len().saturating_sub(wanted);
+    Ok(collected.split_off(skip))
+}
+
+/// Lets the webview write to the same log, e.g. errors Sentry can't report
+/// while offline.
+#[tauri::command]
+pub fn log_message(level: Level, message: String) {
+    log::log!(target: WEBVIEW_TARGET, level, "{}", message);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::scene::test_util::temp_dir;
+
+    #[test]
+    fn rotates_and_caps_files() {
+        let dir = temp_dir("logs");
+
+        let mut file = RotatingFile::open(&dir).unwrap();
+        let line = format!("{}\n", "x".repeat(MAX_FILE_SIZE as usize / 2));
+        for _ in 0..(MAX_ROTATED_FILES + 2) * 2 {
+            file.write_line(&line).unwrap();
+        }
+
+        assert!(dir.join(LOG_FILE).exists());
+        assert!(rotated_path(&dir, MAX_ROTATED_FILES).exists());
+        assert!(!rotated_path(&dir, MAX_ROTATED_FILES + 1).exists());
+        assert!(fs::metadata(dir.join(LOG_FILE)).unwrap().len() <= MAX_FILE_SIZE);
+
+        fs::remove_dir_all(&dir).unwrap();
+    }
+}
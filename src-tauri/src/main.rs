@@ -1,32 +1,215 @@
 // Prevents additional console window on Windows in release, DO NOT REMOVE!!
 #![cfg_attr(not(debug_assertions), windows_subsystem = "windows")]
 
-use tauri::{Manager, WindowEvent};
+mod autosave;
+mod cli;
+mod collab;
+mod error;
+mod export;
+mod files;
+mod launch;
+mod library;
+mod logging;
+mod overlay;
+mod scene;
+mod search;
+mod settings;
+mod share;
+mod shortcut;
+mod single_instance;
+mod storage;
+mod thumbnail;
+mod tray;
+mod watch;
+mod workspace;
+
+use std::sync::Mutex;
+
+use tauri::{Manager, RunEvent, WindowEvent};
+
+use autosave::Autosave;
+use collab::CollabHost;
+use files::FileScope;
+use launch::{LaunchArgs, PendingLaunch};
+use library::Library;
+use overlay::{OverlayCommand, OverlayState, MAIN_WINDOW};
+use search::Search;
+use settings::SettingsState;
+use share::ShareHost;
+use single_instance::{Instance, InstanceGuard};
+use storage::RoomStorage;
+use thumbnail::Thumbnails;
+use watch::SceneWatcher;
+use workspace::Workspace;
 
 fn main() {
+    // subcommands like `export` run headless, without a window or display
+    if let Some(code) = cli::run(std::env::args_os().skip(1)) {
+        std::process::exit(code);
+    }
+
+    let context = tauri::generate_context!();
+    let launch_args = LaunchArgs::from_env();
+
+    // a second overlay stacked on the first would swallow all input, so hand
+    // the invocation to the running one instead
+    let lock_path = tauri::api::path::app_data_dir(context.config())
+        .map(|dir| dir.join(single_instance::LOCK_FILE));
+    let instance_server = match lock_path.map(|path| single_instance::acquire(&path, &launch_args))
+    {
+        Some(Ok(Instance::Forwarded)) => return,
+        Some(Ok(Instance::Primary(server))) => Some(server),
+        Some(Err(err)) => {
+            eprintln!("failed to check for a running instance: {}", err);
+            None
+        }
+        None => None,
+    };
+
     tauri::Builder::default()
-        .invoke_handler(tauri::generate_handler![greet])
-        .setup(|app| {
-            let main_window = app.get_window("main").unwrap();
-            let main_window_clone = main_window.clone();
-
-            main_window.on_window_event(move |event| match event {
-                WindowEvent::Focused(is_focused) => {
-                    if *is_focused {
-                        let _ = main_window_clone.set_ignore_cursor_events(false);
-                        println!("Window gained focus");
-                    } else {
-                        let _ = main_window_clone.set_ignore_cursor_events(true);
-                        println!("Window lost focus");
+        .system_tray(tray::build())
+        .on_system_tray_event(tray::handle_event)
+        .register_uri_scheme_protocol(thumbnail::SCHEME, thumbnail::protocol)
+        .invoke_handler(tauri::generate_handler![
+            greet,
+            autosave::autosave_scene,
+            autosave::discard_recovery,
+            autosave::get_recovery,
+            autosave::list_autosaves,
+            collab::get_collab_host,
+            collab::reconcile_elements,
+            collab::start_collab_host,
+            collab::stop_collab_host,
+            export::export_pdf,
+            export::export_png,
+            files::embed_scene,
+            files::extract_scene,
+            files::repair_indices,
+            files::get_recent_files,
+            files::open_file,
+            files::open_recent_file,
+            files::open_scene,
+            files::save_scene,
+            files::save_scene_as,
+            launch::take_launch_files,
+            library::choose_library_dir,
+            library::delete_library_items,
+            library::export_library,
+            library::get_library_dir,
+            library::import_library,
+            library::list_library,
+            library::merge_library,
+            logging::get_logs,
+            logging::log_message,
+            overlay::clear_overlay_canvas,
+            overlay::get_overlay_mode,
+            overlay::set_focus_driven,
+            overlay::set_overlay_mode,
+            overlay::toggle_drawing,
+            overlay::toggle_overlay_visibility,
+            search::search,
+            share::compress_data,
+            share::decompress_data,
+            share::generate_encryption_key,
+            share::get_share_server,
+            share::start_share_server,
+            share::stop_share_server,
+            shortcut::get_toggle_shortcut,
+            shortcut::set_toggle_shortcut,
+            storage::load_room_files,
+            storage::load_room_scene,
+            storage::save_room_files,
+            storage::save_room_scene,
+            thumbnail::get_thumbnails,
+            tray::quit,
+            watch::unwatch_scene,
+            watch::update_watched_scene,
+            watch::watch_scene,
+            workspace::close_workspace,
+            workspace::list_workspace,
+            workspace::open_workspace,
+            workspace::open_workspace_file,
+            workspace::search_workspace,
+        ])
+        .setup(move |app| {
+            let handle = app.handle();
+            let settings = settings::load(&handle);
+            logging::init(&handle, settings.log_level)?;
+
+            app.manage(OverlayState::new(settings.focus_driven));
+            app.manage(FileScope::default());
+            app.manage(SceneWatcher::default());
+            app.manage(CollabHost::default());
+            app.manage(ShareHost::default());
+            app.manage(RoomStorage::new(storage::storage_dir(&handle)?));
+            if let Err(err) = shortcut::register(&handle, &settings.toggle_shortcut) {
+                log::warn!(
+                    "failed to register shortcut {}: {}",
+                    settings.toggle_shortcut,
+                    err
+                );
+            }
+            let library_dir = library::library_dir(&handle, settings.library_dir.clone())?;
+            app.manage(Library::start(&handle, library_dir));
+            app.manage(Workspace::default());
+            app.manage(Search::load(search::index_path(&handle)?));
+            app.manage(Thumbnails::new(thumbnail::thumbnail_dir(&handle)?));
+            workspace::restore(&handle, settings.workspace_dir.as_deref());
+            app.manage(SettingsState(Mutex::new(settings)));
+            tray::rebuild_menu(&handle)?;
+
+            app.manage(Autosave::start(autosave::autosave_dir(&handle)?)?);
+            autosave::offer_recovery(&handle);
+
+            let launch_files = launch::load(&handle, &launch_args);
+            app.manage(PendingLaunch(Mutex::new(launch_files)));
+            if let Some(command) = launch_args.overlay {
+                overlay::dispatch(&handle, command)?;
+            }
+            if let Some(server) = instance_server {
+                let handle = handle.clone();
+                app.manage(server.serve(move |args| {
+                    if let Err(err) = launch::dispatch(&handle, &args) {
+                        log::error!("failed to handle forwarded launch: {}", err);
                     }
+                }));
+            }
+
+            let main_window = app.get_window(MAIN_WINDOW).unwrap();
+
+            main_window.on_window_event(move |event| {
+                if let WindowEvent::Focused(is_focused) = event {
+                    let command = if *is_focused {
+                        log::debug!("window gained focus");
+                        OverlayCommand::FocusGained
+                    } else {
+                        log::debug!("window lost focus");
+                        OverlayCommand::FocusLost
+                    };
+                    let _ = overlay::dispatch(&handle, command);
                 }
-                _ => {}
             });
 
             Ok(())
         })
-        .run(tauri::generate_context!())
-        .expect("error while running tauri application");
+        .build(context)
+        .expect("error while building tauri application")
+        .run(|app, event| {
+            if let RunEvent::Exit = event {
+                if let Some(autosave) = app.try_state::<Autosave>() {
+                    autosave.shutdown();
+                }
+                if let Some(host) = app.try_state::<CollabHost>() {
+                    host.stop();
+                }
+                if let Some(host) = app.try_state::<ShareHost>() {
+                    host.stop();
+                }
+                if let Some(guard) = app.try_state::<InstanceGuard>() {
+                    guard.release();
+                }
+            }
+        });
 }
 
 #[tauri::command]
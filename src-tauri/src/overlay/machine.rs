@@ -0,0 +1,178 @@
+use serde::{Deserialize, Serialize};
+
+/// How the overlay window presents itself and treats the cursor.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub enum OverlayMode {
+    /// Visible and capturing input so the user can draw.
+    Drawing,
+    /// Visible but click-through; input reaches the windows below.
+    PassThrough,
+    /// Not shown at all.
+    Hidden,
+    /// Visible and capturing input while the webview presents the scene.
+    /// Unlike drawing, it is never left because of focus changes.
+    Presenting,
+}
+
+impl OverlayMode {
+    pub fn is_visible(self) -> bool {
+        self != Self::Hidden
+    }
+
+    pub fn captures_cursor(self) -> bool {
+        matches!(self, Self::Drawing | Self::Presenting)
+    }
+}
+
+/// Inputs that can move the overlay between modes.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub enum OverlayCommand {
+    Enter(OverlayMode),
+    ToggleDrawing,
+    ToggleVisibility,
+    FocusGained,
+    FocusLost,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct Transition {
+    pub from: OverlayMode,
+    pub to: OverlayMode,
+}
+
+/// Window-independent state machine behind the overlay.
+///
+/// Focus changes are only honoured when `focus_driven` is set; otherwise an
+/// alt-tab or a notification stealing focus leaves the mode alone.
+#[derive(Debug, Clone)]
+pub struct OverlayMachine {
+    mode: OverlayMode,
+    last_visible: OverlayMode,
+    pub focus_driven: bool,
+}
+
+impl OverlayMachine {
+    pub fn new(mode: OverlayMode, focus_driven: bool) -> Self {
+        Self {
+            mode,
+            last_visible: if mode.is_visible() {
+                mode
+            } else {
+                OverlayMode::Drawing
+            },
+            focus_driven,
+        }
+    }
+
+    pub fn mode(&self) -> OverlayMode {
+        self.mode
+    }
+
+    /// Returns the mode `command` leads to from the current one, or `None`
+    /// when it doesn't apply.
+    pub fn next(&self, command: OverlayCommand) -> Option<OverlayMode> {
+        use OverlayMode::*;
+
+        let next = match (command, self.mode) {
+            (OverlayCommand::Enter(mode), _) => mode,
+            (OverlayCommand::ToggleDrawing, Drawing) => PassThrough,
+            (OverlayCommand::ToggleDrawing, _) => Drawing,
+            (OverlayCommand::ToggleVisibility, Hidden) => self.last_visible,
+            (OverlayCommand::ToggleVisibility, _) => Hidden,
+            (OverlayCommand::FocusGained, PassThrough) if self.focus_driven => Drawing,
+            (OverlayCommand::FocusLost, Drawing) if self.focus_driven => PassThrough,
+            (OverlayCommand::FocusGained | OverlayCommand::FocusLost, _) => return None,
+        };
+        if next == self.mode {
+            None
+        } else {
+            Some(next)
+        }
+    }
+
+    /// Applies `command`, returning the transition if the mode changed.
+    pub fn apply(&mut self, command: OverlayCommand) -> Option<Transition> {
+        let to = self.next(command)?;
+        let from = std::mem::replace(&mut self.mode, to);
+        if to.is_visible() {
+            self.last_visible = to;
+        }
+        Some(Transition { from, to })
+    }
+}
+
+impl Default for OverlayMachine {
+    fn default() -> Self {
+        // the window is created focused and interactive
+        Self::new(OverlayMode::Drawing, false)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use OverlayMode::*;
+
+    #[test]
+    fn toggles_between_drawing_and_pass_through() {
+        let mut machine = OverlayMachine::default();
+        assert_eq!(
+            machine.apply(OverlayCommand::ToggleDrawing),
+            Some(Transition {
+                from: Drawing,
+                to: PassThrough
+            })
+        );
+        machine.apply(OverlayCommand::ToggleDrawing);
+        assert_eq!(machine.mode(), Drawing);
+
+        machine.apply(OverlayCommand::Enter(Presenting));
+        machine.apply(OverlayCommand::ToggleDrawing);
+        assert_eq!(machine.mode(), Drawing);
+    }
+
+    #[test]
+    fn ignores_focus_unless_focus_driven() {
+        let mut machine = OverlayMachine::default();
+        assert_eq!(machine.apply(OverlayCommand::FocusLost), None);
+        assert_eq!(machine.mode(), Drawing);
+
+        machine.focus_driven = true;
+        machine.apply(OverlayCommand::FocusLost);
+        assert_eq!(machine.mode(), PassThrough);
+        machine.apply(OverlayCommand::FocusGained);
+        assert_eq!(machine.mode(), Drawing);
+    }
+
+    #[test]
+    fn focus_never_interrupts_presenting_or_hidden() {
+        for mode in [Presenting, Hidden] {
+            let mut machine = OverlayMachine::new(mode, true);
+            assert_eq!(machine.apply(OverlayCommand::FocusLost), None);
+            assert_eq!(machine.apply(OverlayCommand::FocusGained), None);
+            assert_eq!(machine.mode(), mode);
+        }
+    }
+
+    #[test]
+    fn showing_restores_last_visible_mode() {
+        let mut machine = OverlayMachine::new(Presenting, false);
+        machine.apply(OverlayCommand::ToggleVisibility);
+        assert_eq!(machine.mode(), Hidden);
+        machine.apply(OverlayCommand::ToggleVisibility);
+        assert_eq!(machine.mode(), Presenting);
+
+        let mut machine = OverlayMachine::new(Hidden, false);
+        machine.apply(OverlayCommand::ToggleVisibility);
+        assert_eq!(machine.mode(), Drawing);
+    }
+
+    #[test]
+    fn entering_current_mode_is_a_no_op() {
+        let mut machine = OverlayMachine::default();
+        assert_eq!(machine.apply(OverlayCommand::Enter(Drawing)), None);
+    }
+}
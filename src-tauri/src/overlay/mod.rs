@@ -0,0 +1,180 @@
+mod machine;
+
+use std::sync::Mutex;
+
+use tauri::{AppHandle, Manager, Runtime, State, Window};
+
+use crate::{
+    error::Result,
+    settings::{self, SettingsState},
+    tray,
+};
+
+pub use machine::{OverlayCommand, OverlayMachine, OverlayMode, Transition};
+
+pub const MAIN_WINDOW: &str = "main";
+
+/// Emitted to the webview with a [`Transition`] whenever the mode changes.
+pub const OVERLAY_MODE_CHANGED: &str = "overlay-mode-changed";
+
+/// Asks the webview to clear the scene once the user confirms.
+pub const CLEAR_CANVAS: &str = "clear-canvas";
+
+pub struct OverlayState(pub Mutex<OverlayMachine>);
+
+impl OverlayState {
+    pub fn new(focus_driven: bool) -> Self {
+        Self(Mutex::new(OverlayMachine::new(
+            OverlayMode::Drawing,
+            focus_driven,
+        )))
+    }
+
+    pub fn mode(&self) -> OverlayMode {
+        self.0.lock().unwrap().mode()
+    }
+}
+
+/// Whatever displays the overlay. Implemented by the Tauri window and by
+/// test doubles, so transitions can be driven without a real window.
+pub trait OverlaySurface {
+    fn apply_mode(&self, mode: OverlayMode) -> Result<()>;
+}
+
+impl<R: Runtime> OverlaySurface for Window<R> {
+    fn apply_mode(&self, mode: OverlayMode) -> Result<()> {
+        if !mode.is_visible() {
+            self.hide()?;
+            return Ok(());
+        }
+        self.show()?;
+        self.set_ignore_cursor_events(!mode.captures_cursor())?;
+        if mode.captures_cursor() {
+            // a click-through window can't be focused by clicking it
+            self.set_focus()?;
+        }
+        Ok(())
+    }
+}
+
+/// Runs `command` through the machine and applies the resulting mode to
+/// `surface`, returning the transition if there was one.
+pub fn drive(
+    machine: &Mutex<OverlayMachine>,
+    surface: &impl OverlaySurface,
+    command: OverlayCommand,
+) -> Result<Option<Transition>> {
+    // released before touching the surface: focusing the window re-enters
+    // through the focus handler
+    let transition = machine.lock().unwrap().apply(command);
+    if let Some(transition) = transition {
+        surface.apply_mode(transition.to)?;
+    }
+    Ok(transition)
+}
+
+/// Drives the main window and tells the webview and tray about the change.
+pub fn dispatch<R: Runtime>(app: &AppHandle<R>, command: OverlayCommand) -> Result<OverlayMode> {
+    let state = app.state::<OverlayState>();
+    let window = match app.get_window(MAIN_WINDOW) {
+        Some(window) => window,
+        None => return Ok(state.mode()),
+    };
+
+    if let Some(transition) = drive(&state.0, &window, command)? {
+        window.emit(OVERLAY_MODE_CHANGED, transition)?;
+        tray::refresh(app)?;
+    }
+    Ok(state.mode())
+}
+
+/// Brings the overlay up for input first, so the confirmation can be
+/// answered.
+pub fn clear_canvas<R: Runtime>(app: &AppHandle<R>) -> Result<()> {
+    if matches!(
+        app.state::<OverlayState>().mode(),
+        OverlayMode::PassThrough | OverlayMode::Hidden
+    ) {
+        dispatch(app, OverlayCommand::Enter(OverlayMode::Drawing))?;
+    }
+    if let Some(window) = app.get_window(MAIN_WINDOW) {
+        window.emit(CLEAR_CANVAS, ())?;
+    }
+    Ok(())
+}
+
+#[tauri::command]
+pub fn get_overlay_mode(state: State<OverlayState>) -> OverlayMode {
+    state.mode()
+}
+
+#[tauri::command]
+pub fn set_overlay_mode<R: Runtime>(app: AppHandle<R>, mode: OverlayMode) -> Result<OverlayMode> {
+    dispatch(&app, OverlayCommand::Enter(mode))
+}
+
+#[tauri::command]
+pub fn toggle_drawing<R: Runtime>(app: AppHandle<R>) -> Result<OverlayMode> {
+    dispatch(&app, OverlayCommand::ToggleDrawing)
+}
+
+#[tauri::command]
+pub fn toggle_overlay_visibility<R: Runtime>(app: AppHandle<R>) -> Result<OverlayMode> {
+    dispatch(&app, OverlayCommand::ToggleVisibility)
+}
+
+#[tauri::command]
+pub fn clear_overlay_canvas<R: Runtime>(app: AppHandle<R>) -> Result<()> {
+    clear_canvas(&app)
+}
+
+/// Opts in or out of leaving drawing when the window loses focus.
+#[tauri::command]
+pub fn set_focus_driven<R: Runtime>(
+    app: AppHandle<R>,
+    state: State<OverlayState>,
+    settings: State<SettingsState>,
+    focus_driven: bool,
+) -> Result<()> {
+    state.0.lock().unwrap().focus_driven = focus_driven;
+    let mut settings = settings.0.lock().unwrap();
+    settings.focus_driven = focus_driven;
+    settings::save(&app, &settings)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::cell::RefCell;
+
+    use super::*;
+
+    #[derive(Default)]
+    struct FakeSurface(RefCell<Vec<OverlayMode>>);
+
+    impl OverlaySurface for FakeSurface {
+        fn apply_mode(&self, mode: OverlayMode) -> Result<()> {
+            self.0.borrow_mut().push(mode);
+            Ok(())
+        }
+    }
+
+    #[test]
+    fn only_applies_actual_transitions() {
+        let machine = Mutex::new(OverlayMachine::default());
+        let surface = FakeSurface::default();
+
+        drive(&machine, &surface, OverlayCommand::FocusLost).unwrap();
+        drive(&machine, &surface, OverlayCommand::ToggleDrawing).unwrap();
+        drive(&machine, &surface, OverlayCommand::ToggleVisibility).unwrap();
+        drive(&machine, &surface, OverlayCommand::ToggleVisibility).unwrap();
+
+        assert_eq!(
+            *surface.0.borrow(),
+            [
+                OverlayMode::PassThrough,
+                OverlayMode::Hidden,
+                OverlayMode::PassThrough
+            ]
+        );
+    }
+}
@@ -0,0 +1,358 @@
+//! Port of `packages/excalidraw/data/encode.ts`: the text encoding scenes
+//! embedded in images use, and `compressData`/`decompressData`, the binary
+//! format of share-link payloads and uploaded files, along with the AES-GCM
+//! helpers in `encryption.ts` it builds on.
+//!
+//! Payloads are byte-compatible with the TS implementation in both
+//! directions.
+
+use std::io::{Read, Write};
+
+use aes_gcm::{
+    aead::{Aead, KeyInit},
+    Aes128Gcm, Nonce,
+};
+use base64::{engine::general_purpose::URL_SAFE_NO_PAD, Engine};
+use flate2::{read::ZlibDecoder, write::ZlibEncoder, Compression};
+use rand::RngCore;
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+
+use crate::error::{Error, Result};
+
+use super::to_js_value;
+
+/// `IV_LENGTH_BYTES`
+pub const IV_LENGTH_BYTES: usize = 12;
+/// `ENCRYPTION_KEY_BITS`
+const ENCRYPTION_KEY_BYTES: usize = 128 / 8;
+
+const CONCAT_BUFFERS_VERSION: u32 = 1;
+/// Bytes of the version and chunk size headers, big endian like `DataView`.
+/// Changing them would break compatibility.
+const VERSION_DATAVIEW_BYTES: usize = 4;
+const NEXT_CHUNK_SIZE_DATAVIEW_BYTES: usize = 4;
+
+const COMPRESSION: &str = "pako@1";
+const ENCRYPTION: &str = "AES-GCM";
+
+/// `EncodedData`: text as a byte string, a string of chars in
+/// `U+0000..=U+00FF` standing for one byte each.
+#[derive(Debug, Serialize, Deserialize)]
+pub struct EncodedData {
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    version: Option<String>,
+    encoding: String,
+    /// Whether `encoded` is zlib compressed.
+    compressed: bool,
+    encoded: String,
+}
+
+fn to_byte_string(bytes: &[u8]) -> String {
+    bytes.iter().map(|&byte| char::from(byte)).collect()
+}
+
+fn from_byte_string(byte_string: &str) -> Result<Vec<u8>> {
+    byte_string
+        .chars()
+        .map(|c| u8::try_from(u32::from(c)))
+        .collect::<std::result::Result<_, _>>()
+        .map_err(|_| Error::Other("not a byte string".into()))
+}
+
+/// `encode`, always compressing.
+pub fn encode_text(text: &str) -> Result<EncodedData> {
+    let mut encoder = ZlibEncoder::new(Vec::new(), Compression::default());
+    encoder.write_all(text.as_bytes())?;
+    Ok(EncodedData {
+        version: Some("1".into()),
+        encoding: "bstring".into(),
+        compressed: true,
+        encoded: to_byte_string(&encoder.finish()?),
+    })
+}
+
+/// `decode`
+pub fn decode_text(data: &EncodedData) -> Result<String> {
+    if data.encoding != "bstring" {
+        return Err(Error::Other(format!(
+            "unknown encoding \"{}\"",
+            data.encoding
+        )));
+    }
+    let mut bytes = from_byte_string(&data.encoded)?;
+    if data.compressed {
+        let mut inflated = Vec::new();
+        ZlibDecoder::new(&bytes[..]).read_to_end(&mut inflated)?;
+        bytes = inflated;
+    }
+    String::from_utf8(bytes).map_err(|err| Error::Other(err.to_string()))
+}
+
+/// `FileEncodingInfo`, the first chunk of every payload.
+#[derive(Debug, Serialize, Deserialize)]
+struct FileEncodingInfo {
+    version: u8,
+    compression: Option<String>,
+    encryption: Option<String>,
+}
+
+#[derive(Debug, Serialize)]
+pub struct Decompressed {
+    /// JSON metadata stored alongside the data, `null` if there was none.
+    pub metadata: Value,
+    pub data: Vec<u8>,
+}
+
+/// Concatenates `buffers` behind a version header, each prefixed with its
+/// length.
+fn concat_buffers(buffers: &[&[u8]]) -> Result<Vec<u8>> {
+    let size = VERSION_DATAVIEW_BYTES
+        + NEXT_CHUNK_SIZE_DATAVIEW_BYTES * buffers.len()
+        + buffers.iter().map(|buffer| buffer.len()).sum::<usize>();
+    let mut concatenated = Vec::with_capacity(size);
+    concatenated.extend_from_slice(&CONCAT_BUFFERS_VERSION.to_be_bytes());
+    for buffer in buffers {
+        let length: u32 = buffer.len().try_into().map_err(|_| {
+            Error::Other(format!(
+                "chunk of {} bytes exceeds the 4 byte size header",
+                buffer.len()
+            ))
+        })?;
+        concatenated.extend_from_slice(&length.to_be_bytes());
+        concatenated.extend_from_slice(buffer);
+    }
+    Ok(concatenated)
+}
+
+/// Splits a buffer created by [`concat_buffers`].
+fn split_buffers(concatenated: &[u8]) -> Result<Vec<&[u8]>> {
+    let version = read_u32(concatenated, 0)?;
+    // anything newer likely isn't a payload at all
+    if version > CONCAT_BUFFERS_VERSION {
+        return Err(Error::Other(format!("invalid version {}", version)));
+    }
+
+    let mut buffers = Vec::new();
+    let mut cursor = VERSION_DATAVIEW_BYTES;
+    loop {
+        let size = read_u32(concatenated, cursor)? as usize;
+        cursor += NEXT_CHUNK_SIZE_DATAVIEW_BYTES;
+        let chunk = concatenated
+            .get(cursor..cursor + size)
+            .ok_or_else(truncated)?;
+        buffers.push(chunk);
+        cursor += size;
+        if cursor >= concatenated.len() {
+            return Ok(buffers);
+        }
+    }
+}
+
+fn read_u32(buffer: &[u8], offset: usize) -> Result<u32> {
+    let bytes = buffer
+        .get(offset..offset + NEXT_CHUNK_SIZE_DATAVIEW_BYTES)
+        .ok_or_else(truncated)?;
+    Ok(u32::from_be_bytes(bytes.try_into().unwrap()))
+}
+
+fn truncated() -> Error {
+    Error::Other("truncated payload".into())
+}
+
+/// `generateEncryptionKey`: a random AES-128 key, encoded like the `k`
+/// member of an exported JWK.
+pub fn generate_encryption_key() -> String {
+    let mut key = [0; ENCRYPTION_KEY_BYTES];
+    rand::thread_rng().fill_bytes(&mut key);
+    URL_SAFE_NO_PAD.encode(key)
+}
+
+/// `getCryptoKey`
+fn cipher(key: &str) -> Result<Aes128Gcm> {
+    let key = URL_SAFE_NO_PAD
+        .decode(key.trim_end_matches('='))
+        .map_err(|err| Error::Other(format!("invalid encryption key: {}", err)))?;
+    Aes128Gcm::new_from_slice(&key)
+        .map_err(|_| Error::Other("invalid encryption key length".into()))
+}
+
+/// A random IV for [`encrypt_data`].
+pub fn generate_iv() -> [u8; IV_LENGTH_BYTES] {
+    let mut iv = [0; IV_LENGTH_BYTES];
+    rand::thread_rng().fill_bytes(&mut iv);
+    iv
+}
+
+/// `encryptData`, with the IV chosen by the caller.
+pub fn encrypt_data(key: &str, iv: &[u8; IV_LENGTH_BYTES], data: &[u8]) -> Result<Vec<u8>> {
+    cipher(key)?
+        .encrypt(Nonce::from_slice(iv), data)
+        .map_err(|_| Error::Other("encryption failed".into()))
+}
+
+/// `decryptData`
+pub fn decrypt_data(iv: &[u8], encrypted: &[u8], key: &str) -> Result<Vec<u8>> {
+    if iv.len() != IV_LENGTH_BYTES {
+        return Err(Error::Other(format!("invalid IV length {}", iv.len())));
+    }
+    cipher(key)?
+        .decrypt(Nonce::from_slice(iv), encrypted)
+        .map_err(|_| Error::Other("decryption failed, wrong key or corrupted data".into()))
+}
+
+/// Compresses and encrypts `data` along with JSON `metadata`:
+///
+/// ```text
+/// [
+///   encoding metadata,
+///   iv,
+///   encrypt(deflate([contents metadata, data])),
+/// ]
+/// ```
+///
+/// where `[]` are [`concat_buffers`] wrappers.
+pub fn compress_data(data: &[u8], metadata: &Value, encryption_key: &str) -> Result<Vec<u8>> {
+    compress_data_with_iv(data, metadata, encryption_key, &generate_iv())
+}
+
+fn compress_data_with_iv(
+    data: &[u8],
+    metadata: &Value,
+    encryption_key: &str,
+    iv: &[u8; IV_LENGTH_BYTES],
+) -> Result<Vec<u8>> {
+    let file_info = FileEncodingInfo {
+        version: 2,
+        compression: Some(COMPRESSION.into()),
+        encryption: Some(ENCRYPTION.into()),
+    };
+    let encoding_metadata = serde_json::to_vec(&file_info)?;
+    let contents_metadata = serde_json::to_vec(&to_js_value(metadata)?)?;
+
+    let mut encoder = ZlibEncoder::new(Vec::new(), Compression::default());
+    encoder.write_all(&concat_buffers(&[&contents_metadata, data])?)?;
+    let encrypted = encrypt_data(encryption_key, iv, &encoder.finish()?)?;
+
+    concat_buffers(&[&encoding_metadata, iv, &encrypted])
+}
+
+/// Decrypts and decompresses a payload created by [`compress_data`] or its
+/// TS counterpart.
+pub fn decompress_data(payload: &[u8], decryption_key: &str) -> Result<Decompressed> {
+    let (encoding_metadata, iv, encrypted) = match split_buffers(payload)?[..] {
+        [encoding_metadata, iv, encrypted, ..] => (encoding_metadata, iv, encrypted),
+        _ => return Err(truncated()),
+    };
+    let encoding_metadata: FileEncodingInfo = serde_json::from_slice(encoding_metadata)?;
+
+    let mut contents = decrypt_data(iv, encrypted, decryption_key)?;
+    if encoding_metadata.compression.is_some() {
+        let mut inflated = Vec::new();
+        ZlibDecoder::new(&contents[..]).read_to_end(&mut inflated)?;
+        contents = inflated;
+    }
+
+    match split_buffers(&contents)?[..] {
+        [metadata, data, ..] => Ok(Decompressed {
+            metadata: serde_json::from_slice(metadata)?,
+            data: data.to_vec(),
+        }),
+        _ => Err(truncated()),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use serde_json::json;
+
+    use super::*;
+
+    // src-tauri/fixtures/compressed.test.ts generates the TS payloads;
+    // packages/excalidraw/tests/data/encode.test.ts checks that they and
+    // RUST_PAYLOAD decode
+    const KEY: &str = "a4TG7upSM0JQhhp1bRVWCg";
+    const IV: [u8; IV_LENGTH_BYTES] = [0, 1, 2, 3, 4, 5, 6, 7, 8, 9, 10, 11];
+    const SCENE: &[u8] = include_bytes!("../../fixtures/scene.excalidraw");
+    const SCENE_PAYLOAD: &[u8] = include_bytes!("../../fixtures/scene.compressed");
+    const FILE_PAYLOAD: &[u8] = include_bytes!("../../fixtures/file.compressed");
+    const RUST_PAYLOAD: &[u8] = include_bytes!("../../fixtures/scene.rs.compressed");
+    const FILE_DATA_URL: &[u8] = b"data:image/png;base64,iVBORw0KGgo=";
+
+    fn file_metadata() -> Value {
+        json!({ "id": "file-1", "mimeType": "image/png", "created": 1, "lastRetrieved": 2 })
+    }
+
+    #[test]
+    fn decodes_ts_payloads() {
+        let scene = decompress_data(SCENE_PAYLOAD, KEY).unwrap();
+        assert_eq!(scene.metadata, Value::Null);
+        assert_eq!(scene.data, SCENE);
+
+        let file = decompress_data(FILE_PAYLOAD, KEY).unwrap();
+        assert_eq!(file.metadata, file_metadata());
+        assert_eq!(file.data, FILE_DATA_URL);
+    }
+
+    /// What a payload encrypts, inflated. Deflate implementations are free
+    /// to compress differently, so payloads are compared on this.
+    fn contents(payload: &[u8]) -> (Vec<u8>, Vec<u8>, Vec<u8>) {
+        let buffers = split_buffers(payload).unwrap();
+        let mut inflated = Vec::new();
+        ZlibDecoder::new(&decrypt_data(buffers[1], buffers[2], KEY).unwrap()[..])
+            .read_to_end(&mut inflated)
+            .unwrap();
+        (buffers[0].to_vec(), buffers[1].to_vec(), inflated)
+    }
+
+    #[test]
+    fn encodes_like_ts() {
+        let scene = compress_data_with_iv(SCENE, &Value::Null, KEY, &IV).unwrap();
+        assert_eq!(contents(&scene), contents(SCENE_PAYLOAD));
+
+        let file = compress_data_with_iv(FILE_DATA_URL, &file_metadata(), KEY, &IV).unwrap();
+        assert_eq!(contents(&file), contents(FILE_PAYLOAD));
+
+        // encode.test.ts checks the TS side decodes this one
+        assert_eq!(decompress_data(RUST_PAYLOAD, KEY).unwrap().data, SCENE);
+    }
+
+    #[test]
+    fn round_trips_with_generated_key() {
+        let key = generate_encryption_key();
+        let payload = compress_data(b"", &json!({ "n": 1.0 }), &key).unwrap();
+        let decompressed = decompress_data(&payload, &key).unwrap();
+        assert_eq!(decompressed.metadata, json!({ "n": 1 }));
+        assert!(decompressed.data.is_empty());
+
+        assert!(decompress_data(&payload, &generate_encryption_key()).is_err());
+    }
+
+    #[test]
+    fn round_trips_text() {
+        let encoded = encode_text("😀 text").unwrap();
+        assert!(encoded.encoded.chars().all(|c| u32::from(c) <= 0xff));
+        assert_eq!(decode_text(&encoded).unwrap(), "😀 text");
+
+        let uncompressed = EncodedData {
+            version: None,
+            encoding: "bstring".into(),
+            compressed: false,
+            encoded: to_byte_string("😀".as_bytes()),
+        };
+        assert_eq!(decode_text(&uncompressed).unwrap(), "😀");
+    }
+
+    #[test]
+    fn rejects_malformed_buffers() {
+        assert!(split_buffers(&[0, 0, 0, 2, 0, 0, 0, 0]).is_err());
+        assert!(split_buffers(&[0, 0, 0, 1, 0, 0, 0, 9, 1]).is_err());
+        assert!(decompress_data(&SCENE_PAYLOAD[..40], KEY).is_err());
+
+        let buffers = concat_buffers(&[b"a", b"", b"bc"]).unwrap();
+        assert_eq!(
+            split_buffers(&buffers).unwrap(),
+            [&b"a"[..], &b""[..], &b"bc"[..]]
+        );
+    }
+}
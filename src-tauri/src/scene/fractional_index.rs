@@ -0,0 +1,641 @@
+//! Fractional indices ordering elements for collaboration, compatible with
+//! the `fractional-indexing` package and `packages/excalidraw/fractionalIndex.ts`.
+//!
+//! Keys are base 62: a head character encoding the length of the integer
+//! part (`a0`, `a1`, ..., `b10`, ...), followed by an optional fraction that
+//! never ends in `0`.
+
+use std::collections::HashMap;
+
+use serde::Serialize;
+
+use crate::error::{Error, Result};
+
+use super::Element;
+
+const DIGITS: &[u8] = b"0123456789ABCDEFGHIJKLMNOPQRSTUVWXYZabcdefghijklmnopqrstuvwxyz";
+const ZERO: u8 = b'0';
+const LAST: u8 = b'z';
+/// `"A"` followed by 26 zeros, smaller than every valid key.
+const SMALLEST_INTEGER: &str = "A00000000000000000000000000";
+
+fn invalid(message: String) -> Error {
+    Error::Other(format!("invalid fractional index: {}", message))
+}
+
+fn digit(c: u8) -> Result<usize> {
+    DIGITS
+        .iter()
+        .position(|&d| d == c)
+        .ok_or_else(|| invalid(format!("unexpected character {:?}", c as char)))
+}
+
+fn to_string(bytes: Vec<u8>) -> String {
+    // keys only ever contain ASCII digits
+    String::from_utf8(bytes).unwrap_or_default()
+}
+
+/// A key strictly between the fractions `a` and `b`, `b == None` meaning
+/// no upper bound.
+fn midpoint(a: &[u8], b: Option<&[u8]>) -> Result<Vec<u8>> {
+    if let Some(b) = b {
+        if a >= b {
+            return Err(invalid(format!(
+                "{} >= {}",
+                String::from_utf8_lossy(a),
+                String::from_utf8_lossy(b)
+            )));
+        }
+    }
+    if a.last() == Some(&ZERO) || b.and_then(|b| b.last()) == Some(&ZERO) {
+        return Err(invalid("trailing zero".into()));
+    }
+    if let Some(b) = b {
+        // strip the common prefix, padding `a` with zeros as needed
+        let mut n = 0;
+        while b.get(n).map_or(false, |&d| d == *a.get(n).unwrap_or(&ZERO)) {
+            n += 1;
+        }
+        if n > 0 {
+            let mut key = b[..n].to_vec();
+            key.extend(midpoint(a.get(n..).unwrap_or_default(), Some(&b[n..]))?);
+            return Ok(key);
+        }
+    }
+
+    // the first digits (or lack of one) differ
+    let digit_a = match a.first() {
+        Some(&c) => digit(c)? as isize,
+        None => 0,
+    };
+    let digit_b = match b.map(|b| b.first()) {
+        Some(Some(&c)) => digit(c)? as isize,
+        Some(None) => return Err(invalid("empty upper bound".into())),
+        None => DIGITS.len() as isize,
+    };
+    if digit_b - digit_a > 1 {
+        // Math.round rounds halves up
+        let mid = (digit_a + digit_b + 1) / 2;
+        Ok(vec![DIGITS[mid as usize]])
+    } else {
+        match b {
+            Some(b) if b.len() > 1 => Ok(b[..1].to_vec()),
+            // e.g. midpoint("49", "5") is "4" + midpoint("9", None) = "495"
+            _ => {
+                let mut key = vec![DIGITS[digit_a as usize]];
+                key.extend(midpoint(a.get(1..).unwrap_or_default(), None)?);
+                Ok(key)
+            }
+        }
+    }
+}
+
+fn integer_length(head: u8) -> Result<usize> {
+    match head {
+        b'a'..=b'z' => Ok((head - b'a') as usize + 2),
+        b'A'..=b'Z' => Ok((b'Z' - head) as usize + 2),
+        _ => Err(invalid(format!("unexpected head {:?}", head as char))),
+    }
+}
+
+fn integer_part(key: &str) -> Result<&str> {
+    let head = *key
+        .as_bytes()
+        .first()
+        .ok_or_else(|| invalid("empty key".into()))?;
+    let length = integer_length(head)?;
+    key.get(..length)
+        .ok_or_else(|| invalid(format!("{} is too short", key)))
+}
+
+fn validate_key(key: &str) -> Result<()> {
+    if key == SMALLEST_INTEGER {
+        return Err(invalid(key.into()));
+    }
+    let integer = integer_part(key)?;
+    if key.as_bytes()[integer.len()..].last() == Some(&ZERO) {
+        return Err(invalid(format!("{} has a trailing zero", key)));
+    }
+    Ok(())
+}
+
+/// Whether `key` is a well-formed fractional index that keys can be
+/// generated next to. The TS code only checks the order of indices, and
+/// `fractional-indexing` throws on malformed ones when they end up as the
+/// bounds of new keys.
+pub fn is_valid_key(key: &str) -> bool {
+    key.bytes().all(|byte| DIGITS.contains(&byte)) && validate_key(key).is_ok()
+}
+
+fn split_integer(integer: &str) -> Result<(u8, Vec<u8>)> {
+    let bytes = integer.as_bytes();
+    if bytes.is_empty() || integer_length(bytes[0])? != bytes.len() {
+        return Err(invalid(format!("bad integer part {}", integer)));
+    }
+    Ok((bytes[0], bytes[1..].to_vec()))
+}
+
+fn increment_integer(integer: &str) -> Result<Option<String>> {
+    let (head, mut digits) = split_integer(integer)?;
+    let mut carry = true;
+    for d in digits.iter_mut().rev() {
+        let next = digit(*d)? + 1;
+        if next == DIGITS.len() {
+            *d = ZERO;
+        } else {
+            *d = DIGITS[next];
+            carry = false;
+            break;
+        }
+    }
+    if !carry {
+        let mut key = vec![head];
+        key.extend(digits);
+        return Ok(Some(to_string(key)));
+    }
+    match head {
+        b'Z' => Ok(Some("a0".into())),
+        b'z' => Ok(None),
+        _ => {
+            let head = head + 1;
+            if head > b'a' {
+                digits.push(ZERO);
+            } else {
+                digits.pop();
+            }
+            let mut key = vec![head];
+            key.extend(digits);
+            Ok(Some(to_string(key)))
+        }
+    }
+}
+
+fn decrement_integer(integer: &str) -> Result<Option<String>> {
+    let (head, mut digits) = split_integer(integer)?;
+    let mut borrow = true;
+    for d in digits.iter_mut().rev() {
+        let value = digit(*d)?;
+        if value == 0 {
+            *d = LAST;
+        } else {
+            *d = DIGITS[value - 1];
+            borrow = false;
+            break;
+        }
+    }
+    if !borrow {
+        let mut key = vec![head];
+        key.extend(digits);
+        return Ok(Some(to_string(key)));
+    }
+    match head {
+        b'a' => Ok(Some("Zz".into())),
+        b'A' => Ok(None),
+        _ => {
+            let head = head - 1;
+            if head < b'Z' {
+                digits.push(LAST);
+            } else {
+                digits.pop();
+            }
+            let mut key = vec![head];
+            key.extend(digits);
+            Ok(Some(to_string(key)))
+        }
+    }
+}
+
+/// A key sorting strictly between `a` and `b`, either of which may be
+/// unbounded.
+pub fn generate_key_between(a: Option<&str>, b: Option<&str>) -> Result<String> {
+    if let Some(a) = a {
+        validate_key(a)?;
+    }
+    if let Some(b) = b {
+        validate_key(b)?;
+    }
+    match (a, b) {
+        (None, None) => Ok("a0".into()),
+        (None, Some(b)) => {
+            let integer = integer_part(b)?;
+            let fraction = &b[integer.len()..];
+            if integer == SMALLEST_INTEGER {
+                let mut key = integer.as_bytes().to_vec();
+                key.extend(midpoint(b"", Some(fraction.as_bytes()))?);
+                return Ok(to_string(key));
+            }
+            if integer < b {
+                return Ok(integer.into());
+            }
+            decrement_integer(integer)?.ok_or_else(|| invalid("cannot decrement any more".into()))
+        }
+        (Some(a), None) => {
+            let integer = integer_part(a)?;
+            let fraction = &a[integer.len()..];
+            match increment_integer(integer)? {
+                Some(key) => Ok(key),
+                None => {
+                    let mut key = integer.as_bytes().to_vec();
+                    key.extend(midpoint(fraction.as_bytes(), None)?);
+                    Ok(to_string(key))
+                }
+            }
+        }
+        (Some(a), Some(b)) => {
+            if a >= b {
+                return Err(invalid(format!("{} >= {}", a, b)));
+            }
+            let integer_a = integer_part(a)?;
+            let fraction_a = &a[integer_a.len()..];
+            let integer_b = integer_part(b)?;
+            let fraction_b = &b[integer_b.len()..];
+            if integer_a == integer_b {
+                let mut key = integer_a.as_bytes().to_vec();
+                key.extend(midpoint(
+                    fraction_a.as_bytes(),
+                    Some(fraction_b.as_bytes()),
+                )?);
+                return Ok(to_string(key));
+            }
+            let incremented = increment_integer(integer_a)?
+                .ok_or_else(|| invalid("cannot increment any more".into()))?;
+            if incremented.as_str() < b {
+                return Ok(incremented);
+            }
+            let mut key = integer_a.as_bytes().to_vec();
+            key.extend(midpoint(fraction_a.as_bytes(), None)?);
+            Ok(to_string(key))
+        }
+    }
+}
+
+/// `n` ascending keys between `a` and `b`, spread like `generateNKeysBetween`.
+pub fn generate_n_keys_between(a: Option<&str>, b: Option<&str>, n: usize) -> Result<Vec<String>> {
+    match n {
+        0 => return Ok(Vec::new()),
+        1 => return Ok(vec![generate_key_between(a, b)?]),
+        _ => {}
+    }
+    match (a, b) {
+        (_, None) => {
+            let mut keys = vec![generate_key_between(a, b)?];
+            for _ in 1..n {
+                let next = generate_key_between(keys.last().map(String::as_str), b)?;
+                keys.push(next);
+            }
+            Ok(keys)
+        }
+        (None, Some(_)) => {
+            let mut keys = vec![generate_key_between(a, b)?];
+            for _ in 1..n {
+                let next = generate_key_between(a, keys.last().map(String::as_str))?;
+                keys.push(next);
+            }
+            keys.reverse();
+            Ok(keys)
+        }
+        (Some(_), Some(_)) => {
+            let mid = n / 2;
+            let key = generate_key_between(a, b)?;
+            let mut keys = generate_n_keys_between(a, Some(&key), mid)?;
+            let after = generate_n_keys_between(Some(&key), b, n - mid - 1)?;
+            keys.push(key);
+            keys.extend(after);
+            Ok(keys)
+        }
+    }
+}
+
+/// Whether `index` sorts between its neighbours. Like the TS version this
+/// only checks the order, not whether the key is well-formed.
+fn is_valid_index(index: Option<&str>, predecessor: Option<&str>, successor: Option<&str>) -> bool {
+    let index = match index {
+        Some(index) => index,
+        None => return false,
+    };
+    predecessor.map_or(true, |predecessor| predecessor < index)
+        && successor.map_or(true, |successor| index < successor)
+}
+
+/// Groups of positions whose indices are out of order, as found by
+/// `getInvalidIndicesGroups`. The first and last position of each group are
+/// its (exclusive, possibly out of range) lower and upper bounds.
+fn invalid_index_groups(indices: &[Option<&str>]) -> Vec<Vec<isize>> {
+    let at = |i: isize| -> Option<&str> {
+        if i < 0 {
+            None
+        } else {
+            indices.get(i as usize).copied().flatten()
+        }
+    };
+    let len = indices.len() as isize;
+
+    // bounds only ever move right, so the last ones found are cached
+    let lower_bound = |i: isize, cached: isize| -> (Option<&str>, isize) {
+        let bound = at(cached);
+        match (bound, at(i - 1)) {
+            (None, Some(candidate)) => (Some(candidate), i - 1),
+            (Some(bound), Some(candidate)) if candidate > bound => (Some(candidate), i - 1),
+            _ => (bound, cached),
+        }
+    };
+    let upper_bound = |i: isize, cached: isize| -> (Option<&str>, isize) {
+        let bound = at(cached);
+        if bound.is_some() && i < cached {
+            return (bound, cached);
+        }
+        let mut j = cached;
+        loop {
+            j += 1;
+            if j >= len {
+                return (None, j);
+            }
+            match (bound, at(j)) {
+                (None, Some(candidate)) => return (Some(candidate), j),
+                (Some(bound), Some(candidate)) if candidate > bound => return (Some(candidate), j),
+                _ => {}
+            }
+        }
+    };
+
+    let mut groups = Vec::new();
+    let mut lower_index = -1;
+    let mut upper_index = 0;
+    let mut i = 0;
+    while i < len {
+        let (lower, next_lower_index) = lower_bound(i, lower_index);
+        let (upper, next_upper_index) = upper_bound(i, upper_index);
+        lower_index = next_lower_index;
+        upper_index = next_upper_index;
+        if is_valid_index(at(i), lower, upper) {
+            i += 1;
+            continue;
+        }
+
+        let mut group = vec![lower_index, i];
+        loop {
+            i += 1;
+            if i >= len {
+                break;
+            }
+            let (lower, next_lower_index) = lower_bound(i, lower_index);
+            let (upper, next_upper_index) = upper_bound(i, upper_index);
+            if is_valid_index(at(i), lower, upper) {
+                break;
+            }
+            // only the bounds of elements that get a new index move on
+            lower_index = next_lower_index;
+            upper_index = next_upper_index;
+            group.push(i);
+        }
+        group.push(upper_index);
+        groups.push(group);
+    }
+    groups
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub enum IndexProblem {
+    Missing,
+    /// Not a key `fractional-indexing` can generate keys next to.
+    Malformed,
+    /// Shared with another element.
+    Duplicate,
+    /// Doesn't sort between the elements around it.
+    OutOfOrder,
+}
+
+/// An index [`sync_invalid_indices`] replaced.
+#[derive(Debug, Clone, PartialEq, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct IndexFix {
+    pub id: String,
+    pub problem: IndexProblem,
+    pub previous: Option<String>,
+    pub index: String,
+}
+
+/// Assigns new indices to elements whose index is missing or out of order
+/// with the array, bumping their version like `syncInvalidIndices`, and
+/// returns what it changed. Valid indices are left alone.
+///
+/// Malformed indices count as missing, where the TS version would fail to
+/// generate keys next to them.
+pub fn sync_invalid_indices(elements: &mut [Element]) -> Result<Vec<IndexFix>> {
+    let updates = {
+        let indices: Vec<Option<&str>> = elements
+            .iter()
+            .map(|element| {
+                element
+                    .base()
+                    .index
+                    .as_deref()
+                    .filter(|index| is_valid_key(index))
+            })
+            .collect();
+        let at = |i: isize| -> Option<&str> {
+            if i < 0 {
+                None
+            } else {
+                indices.get(i as usize).copied().flatten()
+            }
+        };
+
+        let mut updates = Vec::new();
+        for group in invalid_index_groups(&indices) {
+            let (lower, positions, upper) =
+                (group[0], &group[1..group.len() - 1], group[group.len() - 1]);
+            let keys = generate_n_keys_between(at(lower), at(upper), positions.len())?;
+            updates.extend(positions.iter().map(|&i| i as usize).zip(keys));
+        }
+        updates
+    };
+
+    let mut counts: HashMap<&str, usize> = HashMap::new();
+    for element in elements.iter() {
+        if let Some(index) = element.base().index.as_deref() {
+            *counts.entry(index).or_default() += 1;
+        }
+    }
+    let fixes: Vec<IndexFix> = updates
+        .iter()
+        .map(|(i, key)| {
+            let base = elements[*i].base();
+            let problem = match base.index.as_deref() {
+                None | Some("") => IndexProblem::Missing,
+                Some(index) if !is_valid_key(index) => IndexProblem::Malformed,
+                Some(index) if counts[index] > 1 => IndexProblem::Duplicate,
+                Some(_) => IndexProblem::OutOfOrder,
+            };
+            IndexFix {
+                id: base.id.clone(),
+                problem,
+                previous: base.index.clone(),
+                index: key.clone(),
+            }
+        })
+        .collect();
+
+    for (i, key) in updates {
+        let base = elements[i].base_mut();
+        base.index = Some(key);
+        base.bump_version(None);
+    }
+    Ok(fixes)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::scene::test_util::rectangle;
+
+    #[test]
+    fn generates_keys_like_fractional_indexing() {
+        let cases: &[(Option<&str>, Option<&str>, &str)] = &[
+            (None, None, "a0"),
+            (None, Some("a0"), "Zz"),
+            (None, Some("Zz"), "Zy"),
+            (Some("a0"), None, "a1"),
+            (Some("a1"), None, "a2"),
+            (Some("a0"), Some("a1"), "a0V"),
+            (Some("a1"), Some("a2"), "a1V"),
+            (Some("a0V"), Some("a1"), "a0l"),
+            (Some("Zz"), Some("a0"), "ZzV"),
+            (Some("Zz"), Some("a1"), "a0"),
+            (None, Some("Y00"), "Xzzz"),
+            (Some("bzz"), None, "c000"),
+            (Some("a0"), Some("a0V"), "a0G"),
+            (Some("a0"), Some("a0G"), "a08"),
+            (Some("b125"), Some("b129"), "b127"),
+            (Some("a0"), Some("a1V"), "a1"),
+            (Some("Zz"), Some("a01"), "a0"),
+            (None, Some("a0V"), "a0"),
+            (None, Some("b999"), "b99"),
+            (
+                None,
+                Some("A000000000000000000000000001"),
+                "A000000000000000000000000000V",
+            ),
+            (
+                Some("zzzzzzzzzzzzzzzzzzzzzzzzzzy"),
+                None,
+                "zzzzzzzzzzzzzzzzzzzzzzzzzzz",
+            ),
+            (
+                Some("zzzzzzzzzzzzzzzzzzzzzzzzzzz"),
+                None,
+                "zzzzzzzzzzzzzzzzzzzzzzzzzzzV",
+            ),
+        ];
+        for &(a, b, expected) in cases {
+            assert_eq!(
+                generate_key_between(a, b).unwrap(),
+                expected,
+                "{:?} {:?}",
+                a,
+                b
+            );
+        }
+
+        assert!(generate_key_between(Some("a1"), Some("a0")).is_err());
+        assert!(generate_key_between(Some("a00"), None).is_err());
+        assert!(generate_key_between(None, Some(SMALLEST_INTEGER)).is_err());
+    }
+
+    #[test]
+    fn generates_n_sorted_keys() {
+        assert_eq!(
+            generate_n_keys_between(None, None, 3).unwrap(),
+            ["a0", "a1", "a2"]
+        );
+        assert_eq!(
+            generate_n_keys_between(None, Some("a0"), 5).unwrap(),
+            ["Zv", "Zw", "Zx", "Zy", "Zz"]
+        );
+        assert_eq!(
+            generate_n_keys_between(Some("a4"), None, 10).unwrap(),
+            ["a5", "a6", "a7", "a8", "a9", "aA", "aB", "aC", "aD", "aE"]
+        );
+        assert_eq!(
+            generate_n_keys_between(Some("a0"), Some("a2"), 20)
+                .unwrap()
+                .join(" "),
+            "a04 a08 a0G a0K a0O a0V a0Z a0d a0l a0t a1 a14 a18 a1G a1O a1V a1Z a1d a1l a1t"
+        );
+    }
+
+    #[test]
+    fn validates_keys() {
+        for key in ["a0", "a1V", "Zz", "b10", "zzzzzzzzzzzzzzzzzzzzzzzzzzz"] {
+            assert!(is_valid_key(key), "{}", key);
+        }
+        for key in [
+            "",
+            "a",
+            "a00",
+            "a0-",
+            "b1",
+            "A00000000000000000000000000",
+            "a0 ",
+        ] {
+            assert!(!is_valid_key(key), "{}", key);
+        }
+    }
+
+    #[test]
+    fn reports_regenerated_indices() {
+        let mut elements: Vec<Element> = [
+            ("A", Some("a0")),
+            ("B", Some("a00")),
+            ("C", Some("a2")),
+            ("D", Some("a2")),
+            ("E", None),
+            ("F", Some("a1")),
+        ]
+        .iter()
+        .map(|(id, index)| rectangle(id, 1, 1, *index))
+        .collect();
+
+        let fixes = sync_invalid_indices(&mut elements).unwrap();
+        let problems: Vec<(&str, IndexProblem)> = fixes
+            .iter()
+            .map(|fix| (fix.id.as_str(), fix.problem))
+            .collect();
+        assert_eq!(
+            problems,
+            [
+                ("B", IndexProblem::Malformed),
+                ("D", IndexProblem::Duplicate),
+                ("E", IndexProblem::Missing),
+                ("F", IndexProblem::OutOfOrder),
+            ]
+        );
+        assert_eq!(fixes[0].previous.as_deref(), Some("a00"));
+        assert!(elements.iter().all(|element| {
+            let base = element.base();
+            let fixed = fixes.iter().any(|fix| fix.id == base.id);
+            base.version == if fixed { 2 } else { 1 }
+        }));
+        let indices: Vec<&str> = elements
+            .iter()
+            .map(|element| element.base().index.as_deref().unwrap())
+            .collect();
+        assert!(indices.windows(2).all(|pair| pair[0] < pair[1]));
+
+        assert!(sync_invalid_indices(&mut elements).unwrap().is_empty());
+    }
+
+    #[test]
+    fn finds_out_of_order_groups() {
+        let indices = [Some("a1"), None, Some("a0"), Some("a3")];
+        assert_eq!(invalid_index_groups(&indices), [vec![0, 1, 2, 3]]);
+
+        let indices = [Some("a0"), Some("a1"), Some("a2")];
+        assert!(invalid_index_groups(&indices).is_empty());
+
+        // bounds past either end of the array mean unbounded
+        let indices = [None, None];
+        assert_eq!(invalid_index_groups(&indices), [vec![-1, 0, 1, 3]]);
+    }
+}
@@ -0,0 +1,315 @@
+//! Port of `packages/excalidraw/data/image.ts`: scenes embedded in exported
+//! PNGs, as a `tEXt` chunk, and SVGs, as a comment delimited payload.
+
+use base64::{engine::general_purpose::STANDARD, Engine};
+use flate2::Crc;
+use serde_json::Value;
+
+use crate::error::{Error, Result};
+
+use super::{
+    encode::{decode_text, encode_text},
+    SCENE_TYPE,
+};
+
+/// `MIME_TYPES.excalidraw`, the keyword of the `tEXt` chunk and the SVG
+/// payload type.
+const MIME_TYPE: &str = "application/vnd.excalidraw+json";
+
+const PNG_SIGNATURE: [u8; 8] = [0x89, b'P', b'N', b'G', b'\r', b'\n', 0x1a, b'\n'];
+const TEXT_CHUNK: [u8; 4] = *b"tEXt";
+const END_CHUNK: [u8; 4] = *b"IEND";
+
+/// `SVG_EXPORT_TAG`
+const SVG_EXPORT_TAG: &str = "<!-- svg-source:excalidraw -->";
+const SVG_PAYLOAD_TYPE: &str = "<!-- payload-type:";
+const SVG_PAYLOAD_VERSION: &str = "<!-- payload-version:";
+const SVG_PAYLOAD_START: &str = "<!-- payload-start -->";
+const SVG_PAYLOAD_END: &str = "<!-- payload-end -->";
+
+fn not_embedded() -> Error {
+    Error::Other("the image has no embedded scene".into())
+}
+
+fn corrupted() -> Error {
+    Error::Other("the embedded scene is corrupted".into())
+}
+
+struct Chunk<'a> {
+    name: [u8; 4],
+    data: &'a [u8],
+}
+
+/// `png-chunks-extract`
+fn decode_chunks(png: &[u8]) -> Result<Vec<Chunk<'_>>> {
+    let invalid = || Error::Other("not a valid PNG".into());
+    let mut rest = png.strip_prefix(&PNG_SIGNATURE[..]).ok_or_else(invalid)?;
+    let mut chunks = Vec::new();
+    while !rest.is_empty() {
+        if rest.len() < 12 {
+            return Err(invalid());
+        }
+        let length = u32::from_be_bytes([rest[0], rest[1], rest[2], rest[3]]) as usize;
+        let end = 8usize.checked_add(length).ok_or_else(invalid)?;
+        if rest.len() < end + 4 {
+            return Err(invalid());
+        }
+        let crc = u32::from_be_bytes([rest[end], rest[end + 1], rest[end + 2], rest[end + 3]]);
+        if crc != chunk_crc(&rest[4..end]) {
+            return Err(invalid());
+        }
+        let chunk = Chunk {
+            name: [rest[4], rest[5], rest[6], rest[7]],
+            data: &rest[8..end],
+        };
+        rest = &rest[end + 4..];
+        let last = chunk.name == END_CHUNK;
+        chunks.push(chunk);
+        if last {
+            break;
+        }
+    }
+    Ok(chunks)
+}
+
+/// `png-chunks-encode`
+fn encode_chunks(chunks: &[Chunk]) -> Vec<u8> {
+    let mut png = PNG_SIGNATURE.to_vec();
+    for chunk in chunks {
+        png.extend_from_slice(&(chunk.data.len() as u32).to_be_bytes());
+        let start = png.len();
+        png.extend_from_slice(&chunk.name);
+        png.extend_from_slice(chunk.data);
+        let crc = chunk_crc(&png[start..]);
+        png.extend_from_slice(&crc.to_be_bytes());
+    }
+    png
+}
+
+/// CRC of a chunk's name and data.
+fn chunk_crc(bytes: &[u8]) -> u32 {
+    let mut crc = Crc::new();
+    crc.update(bytes);
+    crc.sum()
+}
+
+/// Keyword and text of a `tEXt` chunk, both latin-1.
+fn decode_text_chunk(data: &[u8]) -> (String, String) {
+    let latin1 = |bytes: &[u8]| bytes.iter().map(|&byte| char::from(byte)).collect();
+    match data.iter().position(|&byte| byte == 0) {
+        Some(nul) => (latin1(&data[..nul]), latin1(&data[nul + 1..])),
+        None => (latin1(data), String::new()),
+    }
+}
+
+/// Decodes what `encode` produced, or a scene embedded by versions that
+/// stored it as plain JSON.
+fn decode_embedded(json: &str) -> Result<String> {
+    let data: Value = serde_json::from_str(json).map_err(|_| corrupted())?;
+    if data.get("encoded").is_none() {
+        return if data["type"] == SCENE_TYPE {
+            Ok(json.into())
+        } else {
+            Err(corrupted())
+        };
+    }
+    serde_json::from_value(data)
+        .map_err(|_| corrupted())
+        .and_then(|data| decode_text(&data))
+}
+
+/// `decodePngMetadata`: the scene JSON embedded in `png`.
+pub fn decode_png_metadata(png: &[u8]) -> Result<String> {
+    let text = decode_chunks(png)?
+        .iter()
+        .filter(|chunk| chunk.name == TEXT_CHUNK)
+        .map(|chunk| decode_text_chunk(chunk.data))
+        .find(|(keyword, _)| keyword == MIME_TYPE)
+        .map(|(_, text)| text)
+        .ok_or_else(not_embedded)?;
+    decode_embedded(&text)
+}
+
+/// `encodePngMetadata`, replacing a scene `png` already embeds.
+pub fn encode_png_metadata(png: &[u8], scene: &str) -> Result<Vec<u8>> {
+    let encoded = serde_json::to_string(&encode_text(scene)?)?;
+    let mut text_chunk: Vec<u8> = MIME_TYPE.bytes().collect();
+    text_chunk.push(0);
+    // the byte string only has chars up to U+00FF, which latin-1 maps 1:1
+    text_chunk.extend(encoded.chars().map(|c| c as u8));
+
+    let mut chunks: Vec<Chunk> = decode_chunks(png)?
+        .into_iter()
+        .filter(|chunk| chunk.name != TEXT_CHUNK || decode_text_chunk(chunk.data).0 != MIME_TYPE)
+        .collect();
+    // before IEND, the last chunk
+    let end = chunks
+        .iter()
+        .position(|chunk| chunk.name == END_CHUNK)
+        .unwrap_or(chunks.len());
+    chunks.insert(
+        end,
+        Chunk {
+            name: TEXT_CHUNK,
+            data: &text_chunk,
+        },
+    );
+    Ok(encode_chunks(&chunks))
+}
+
+/// `encodeSvgMetadata`
+fn svg_payload(scene: &str) -> Result<String> {
+    let encoded = serde_json::to_string(&encode_text(scene)?)?;
+    let bytes: Vec<u8> = encoded.chars().map(|c| c as u8).collect();
+    Ok(format!(
+        "{}{} -->{}2 -->{}{}{}",
+        SVG_PAYLOAD_TYPE,
+        MIME_TYPE,
+        SVG_PAYLOAD_VERSION,
+        SVG_PAYLOAD_START,
+        STANDARD.encode(bytes),
+        SVG_PAYLOAD_END
+    ))
+}
+
+/// `decodeSvgMetadata`: the scene JSON embedded in `svg`.
+pub fn decode_svg_metadata(svg: &str) -> Result<String> {
+    if !svg.contains(&format!("payload-type:{}", MIME_TYPE)) {
+        return Err(not_embedded());
+    }
+    let start = svg.find(SVG_PAYLOAD_START).ok_or_else(not_embedded)? + SVG_PAYLOAD_START.len();
+    let end = start
+        + svg[start..]
+            .find(SVG_PAYLOAD_END)
+            .ok_or_else(not_embedded)?;
+    let base64: String = svg[start..end]
+        .chars()
+        .filter(|c| !c.is_ascii_whitespace())
+        .collect();
+    let bytes = STANDARD.decode(base64).map_err(|_| corrupted())?;
+
+    // version 1 base64 encoded the UTF-8 of the JSON, later versions the
+    // byte string `encode` returns
+    let version = svg
+        .find(SVG_PAYLOAD_VERSION)
+        .map(|i| &svg[i + SVG_PAYLOAD_VERSION.len()..])
+        .and_then(|rest| rest.split(' ').next())
+        .unwrap_or("1");
+    let json = if version == "1" {
+        String::from_utf8(bytes).map_err(|_| corrupted())?
+    } else {
+        bytes.iter().map(|&byte| char::from(byte)).collect()
+    };
+    decode_embedded(&json)
+}
+
+/// Embeds `scene` in `svg`, in place of the scene it already embeds, if any.
+pub fn encode_svg_metadata(svg: &str, scene: &str) -> Result<String> {
+    let payload = svg_payload(scene)?;
+    if let (Some(start), Some(end)) = (svg.find(SVG_PAYLOAD_TYPE), svg.find(SVG_PAYLOAD_END)) {
+        if start < end {
+            let end = end + SVG_PAYLOAD_END.len();
+            return Ok(format!("{}{}{}", &svg[..start], payload, &svg[end..]));
+        }
+    }
+
+    // where exportToSvg puts it, right after the export tag
+    let insert_at = match svg.find(SVG_EXPORT_TAG) {
+        Some(tag) => tag + SVG_EXPORT_TAG.len(),
+        None => {
+            let root = svg
+                .find("<svg")
+                .ok_or_else(|| Error::Other("not a valid SVG".into()))?;
+            root + svg[root..]
+                .find('>')
+                .ok_or_else(|| Error::Other("not a valid SVG".into()))?
+                + 1
+        }
+    };
+    Ok(format!(
+        "{}{}{}",
+        &svg[..insert_at],
+        payload,
+        &svg[insert_at..]
+    ))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::scene::Scene;
+
+    // the fixtures `export.test.tsx` imports
+    const PNG_V1: &[u8] =
+        include_bytes!("../../../packages/excalidraw/tests/fixtures/test_embedded_v1.png");
+    const PNG_V2: &[u8] =
+        include_bytes!("../../../packages/excalidraw/tests/fixtures/smiley_embedded_v2.png");
+    const SVG_V1: &str =
+        include_str!("../../../packages/excalidraw/tests/fixtures/test_embedded_v1.svg");
+    const SVG_V2: &str =
+        include_str!("../../../packages/excalidraw/tests/fixtures/smiley_embedded_v2.svg");
+    const PLAIN_PNG: &[u8] =
+        include_bytes!("../../../packages/excalidraw/tests/fixtures/smiley.png");
+
+    fn only_text(scene: &str) -> String {
+        let scene: Value = serde_json::from_str(scene).unwrap();
+        let elements = scene["elements"].as_array().unwrap();
+        assert_eq!(elements.len(), 1);
+        assert_eq!(elements[0]["type"], "text");
+        elements[0]["text"].as_str().unwrap().into()
+    }
+
+    #[test]
+    fn decodes_ts_fixtures() {
+        assert_eq!(only_text(&decode_png_metadata(PNG_V1).unwrap()), "test");
+        assert_eq!(only_text(&decode_png_metadata(PNG_V2).unwrap()), "😀");
+        assert_eq!(only_text(&decode_svg_metadata(SVG_V1).unwrap()), "test");
+        assert_eq!(only_text(&decode_svg_metadata(SVG_V2).unwrap()), "😀");
+    }
+
+    #[test]
+    fn replaces_embedded_scenes() {
+        let scene = include_str!("../../fixtures/scene.excalidraw");
+
+        let png = encode_png_metadata(PNG_V2, scene).unwrap();
+        assert_eq!(decode_png_metadata(&png).unwrap(), scene);
+        let text_chunks = decode_chunks(&png)
+            .unwrap()
+            .iter()
+            .filter(|chunk| chunk.name == TEXT_CHUNK)
+            .count();
+        assert_eq!(text_chunks, 1);
+        assert_eq!(decode_chunks(&png).unwrap().last().unwrap().name, END_CHUNK);
+
+        let svg = encode_svg_metadata(SVG_V1, scene).unwrap();
+        assert_eq!(decode_svg_metadata(&svg).unwrap(), scene);
+        assert_eq!(svg.matches(SVG_PAYLOAD_START).count(), 1);
+        assert!(
+            svg.ends_with(&SVG_V1[SVG_V1.find(SVG_PAYLOAD_END).unwrap() + SVG_PAYLOAD_END.len()..])
+        );
+        Scene::parse(&decode_svg_metadata(&svg).unwrap()).unwrap();
+    }
+
+    #[test]
+    fn embeds_into_plain_images() {
+        let scene = include_str!("../../fixtures/scene.excalidraw");
+        assert!(decode_png_metadata(PLAIN_PNG).is_err());
+        let png = encode_png_metadata(PLAIN_PNG, scene).unwrap();
+        assert_eq!(decode_png_metadata(&png).unwrap(), scene);
+
+        let plain_svg = r#"<svg xmlns="http://www.w3.org/2000/svg"><rect/></svg>"#;
+        assert!(decode_svg_metadata(plain_svg).is_err());
+        let svg = encode_svg_metadata(plain_svg, scene).unwrap();
+        assert!(svg.starts_with(r#"<svg xmlns="http://www.w3.org/2000/svg"><!-- payload-type"#));
+        assert_eq!(decode_svg_metadata(&svg).unwrap(), scene);
+    }
+
+    #[test]
+    fn rejects_corrupted_pngs() {
+        let mut png = PNG_V2.to_vec();
+        let last = png.len() - 1;
+        png[last] ^= 1;
+        assert!(decode_png_metadata(&png).is_err());
+        assert!(decode_png_metadata(b"not a png").is_err());
+    }
+}
@@ -0,0 +1,525 @@
+//! Serde model of the `.excalidraw` file format, mirroring
+//! `ExportedDataState` in `packages/excalidraw/data/types.ts`.
+//!
+//! Fields this model doesn't know about are kept in `extra` maps, enum
+//! values it doesn't know in `Unknown` variants and elements of unknown types
+//! as [`Element::Other`], so reading and writing a scene doesn't drop data
+//! added by newer app versions. Legacy scenes missing required fields have
+//! to go through [`restore`] first.
+
+pub mod encode;
+pub mod fractional_index;
+pub mod image;
+pub mod reconcile;
+pub mod restore;
+#[cfg(test)]
+pub mod test_util;
+
+use std::{
+    collections::BTreeMap,
+    time::{SystemTime, UNIX_EPOCH},
+};
+
+use rand::{distributions::Alphanumeric, Rng};
+use serde::{Deserialize, Serialize};
+use serde_json::{Map, Number, Value};
+
+use crate::error::Result;
+
+/// `EXPORT_DATA_TYPES.excalidraw`
+pub const SCENE_TYPE: &str = "excalidraw";
+/// `VERSIONS.excalidraw`
+pub const SCENE_VERSION: u32 = 2;
+/// Written as `source` when a scene doesn't say where it came from.
+pub const EXPORT_SOURCE: &str = "https://excalidraw.com";
+
+pub type Point = [f64; 2];
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct Scene {
+    #[serde(rename = "type")]
+    pub kind: String,
+    pub version: u32,
+    pub source: String,
+    pub elements: Vec<Element>,
+    pub app_state: AppState,
+    /// Absent in scenes stored on the collab server.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub files: Option<BTreeMap<String, BinaryFileData>>,
+    #[serde(flatten)]
+    pub extra: Map<String, Value>,
+}
+
+impl Scene {
+    pub fn parse(json: &str) -> Result<Self> {
+        Ok(serde_json::from_str(json)?)
+    }
+
+    /// Serializes the way `serializeAsJSON` does: two-space indent, and
+    /// integral numbers without a fractional part.
+    pub fn to_json(&self) -> Result<String> {
+        Ok(serde_json::to_string_pretty(&to_js_value(self)?)?)
+    }
+}
+
+/// Converts `value` to JSON, writing whole floats as integers the way
+/// `JSON.stringify` does, so files written here diff cleanly against files
+/// written by the app.
+pub fn to_js_value<T: Serialize>(value: &T) -> Result<Value> {
+    let mut value = serde_json::to_value(value)?;
+    normalize_numbers(&mut value);
+    Ok(value)
+}
+
+fn normalize_numbers(value: &mut Value) {
+    match value {
+        Value::Number(number) => {
+            let float = number.as_f64().unwrap_or_default();
+            // within ±2^53 every integral f64 maps exactly onto an i64
+            if number.is_f64() && float.fract() == 0.0 && float.abs() < 9_007_199_254_740_992.0 {
+                *number = Number::from(float as i64);
+            }
+        }
+        Value::Array(items) => items.iter_mut().for_each(normalize_numbers),
+        Value::Object(map) => map.values_mut().for_each(normalize_numbers),
+        _ => {}
+    }
+}
+
+/// The subset of app state kept by `cleanAppStateForExport`.
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct AppState {
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub grid_size: Option<f64>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub grid_step: Option<f64>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub grid_mode_enabled: Option<bool>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub view_background_color: Option<String>,
+    #[serde(flatten)]
+    pub extra: Map<String, Value>,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct BinaryFileData {
+    pub mime_type: String,
+    pub id: String,
+    #[serde(rename = "dataURL")]
+    pub data_url: String,
+    /// Epoch milliseconds.
+    pub created: i64,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub last_retrieved: Option<i64>,
+    #[serde(flatten)]
+    pub extra: Map<String, Value>,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(tag = "type", rename_all = "lowercase")]
+pub enum Element {
+    Selection(ElementBase),
+    Rectangle(ElementBase),
+    Diamond(ElementBase),
+    Ellipse(ElementBase),
+    Embeddable(ElementBase),
+    Iframe(ElementBase),
+    Text(TextElement),
+    Line(LinearElement),
+    Arrow(ArrowElement),
+    Freedraw(FreeDrawElement),
+    Image(ImageElement),
+    Frame(FrameElement),
+    Magicframe(FrameElement),
+    /// An element of a type this model doesn't know, or whose type specific
+    /// fields it can't read, kept so it's written back as is.
+    #[serde(untagged)]
+    Other(OtherElement),
+}
+
+impl Element {
+    pub fn base(&self) -> &ElementBase {
+        match self {
+            Element::Selection(base)
+            | Element::Rectangle(base)
+            | Element::Diamond(base)
+            | Element::Ellipse(base)
+            | Element::Embeddable(base)
+            | Element::Iframe(base) => base,
+            Element::Text(element) => &element.base,
+            Element::Line(element) => &element.base,
+            Element::Arrow(element) => &element.linear.base,
+            Element::Freedraw(element) => &element.base,
+            Element::Image(element) => &element.base,
+            Element::Frame(element) | Element::Magicframe(element) => &element.base,
+            Element::Other(element) => &element.base,
+        }
+    }
+
+    pub fn base_mut(&mut self) -> &mut ElementBase {
+        match self {
+            Element::Selection(base)
+            | Element::Rectangle(base)
+            | Element::Diamond(base)
+            | Element::Ellipse(base)
+            | Element::Embeddable(base)
+            | Element::Iframe(base) => base,
+            Element::Text(element) => &mut element.base,
+            Element::Line(element) => &mut element.base,
+            Element::Arrow(element) => &mut element.linear.base,
+            Element::Freedraw(element) => &mut element.base,
+            Element::Image(element) => &mut element.base,
+            Element::Frame(element) | Element::Magicframe(element) => &mut element.base,
+            Element::Other(element) => &mut element.base,
+        }
+    }
+}
+
+/// Properties shared by all elements, `_ExcalidrawElementBase` in TS.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ElementBase {
+    pub id: String,
+    pub x: f64,
+    pub y: f64,
+    pub stroke_color: String,
+    pub background_color: String,
+    pub fill_style: FillStyle,
+    pub stroke_width: f64,
+    pub stroke_style: StrokeStyle,
+    pub roundness: Option<Roundness>,
+    pub roughness: f64,
+    pub opacity: f64,
+    pub width: f64,
+    pub height: f64,
+    pub angle: f64,
+    pub seed: i64,
+    pub version: i64,
+    pub version_nonce: i64,
+    /// Fractional index; `null` for elements not yet added to a scene.
+    pub index: Option<String>,
+    pub is_deleted: bool,
+    /// Deepest group first.
+    pub group_ids: Vec<String>,
+    pub frame_id: Option<String>,
+    pub bound_elements: Option<Vec<BoundElement>>,
+    /// Epoch milliseconds of the last update.
+    pub updated: i64,
+    pub link: Option<String>,
+    pub locked: bool,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub custom_data: Option<Map<String, Value>>,
+    #[serde(flatten)]
+    pub extra: Map<String, Value>,
+}
+
+impl ElementBase {
+    /// Marks the element as changed, like `bumpVersion`, continuing from
+    /// `version` if given.
+    pub fn bump_version(&mut self, version: Option<i64>) {
+        self.version = version.unwrap_or(self.version) + 1;
+        self.version_nonce = random_integer();
+        self.updated = now_millis();
+    }
+}
+
+/// A random 21 character element id, like the app's `nanoid` ids.
+pub fn random_id() -> String {
+    rand::thread_rng()
+        .sample_iter(&Alphanumeric)
+        .take(21)
+        .map(char::from)
+        .collect()
+}
+
+/// A random integer in `[0, 2^31)`, as used for seeds and version nonces.
+pub fn random_integer() -> i64 {
+    rand::thread_rng().gen_range(0..1 << 31)
+}
+
+/// Milliseconds since the unix epoch.
+pub fn now_millis() -> i64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|elapsed| elapsed.as_millis() as i64)
+        .unwrap_or_default()
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum FillStyle {
+    Hachure,
+    CrossHatch,
+    Solid,
+    Zigzag,
+    /// From a newer version of the app, kept so it's written back as is.
+    #[serde(untagged)]
+    Unknown(String),
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum StrokeStyle {
+    Solid,
+    Dashed,
+    Dotted,
+    /// From a newer version of the app, kept so it's written back as is.
+    #[serde(untagged)]
+    Unknown(String),
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Roundness {
+    /// One of `ROUNDNESS`: 1 legacy, 2 proportional, 3 adaptive radius.
+    #[serde(rename = "type")]
+    pub kind: u8,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub value: Option<f64>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct BoundElement {
+    pub id: String,
+    #[serde(rename = "type")]
+    pub kind: BoundElementType,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum BoundElementType {
+    Arrow,
+    Text,
+    /// From a newer version of the app, kept so it's written back as is.
+    #[serde(untagged)]
+    Unknown(String),
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct TextElement {
+    #[serde(flatten)]
+    pub base: ElementBase,
+    pub font_size: f64,
+    /// One of `FONT_FAMILY`.
+    pub font_family: u32,
+    pub text: String,
+    pub text_align: TextAlign,
+    pub vertical_align: VerticalAlign,
+    pub container_id: Option<String>,
+    pub original_text: String,
+    pub auto_resize: bool,
+    /// Unitless, multiply with `font_size` for pixels.
+    pub line_height: f64,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum TextAlign {
+    Left,
+    Center,
+    Right,
+    /// From a newer version of the app, kept so it's written back as is.
+    #[serde(untagged)]
+    Unknown(String),
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum VerticalAlign {
+    Top,
+    Middle,
+    Bottom,
+    /// From a newer version of the app, kept so it's written back as is.
+    #[serde(untagged)]
+    Unknown(String),
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct LinearElement {
+    #[serde(flatten)]
+    pub base: ElementBase,
+    /// Relative to `x`/`y`, starting at `[0, 0]`.
+    pub points: Vec<Point>,
+    pub last_committed_point: Option<Point>,
+    pub start_binding: Option<PointBinding>,
+    pub end_binding: Option<PointBinding>,
+    pub start_arrowhead: Option<Arrowhead>,
+    pub end_arrowhead: Option<Arrowhead>,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ArrowElement {
+    #[serde(flatten)]
+    pub linear: LinearElement,
+    pub elbowed: bool,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct PointBinding {
+    pub element_id: String,
+    pub focus: f64,
+    pub gap: f64,
+    /// Only set on elbow arrows.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub fixed_point: Option<Point>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Arrowhead {
+    Arrow,
+    Bar,
+    /// Legacy, new elements use `Circle`.
+    Dot,
+    Circle,
+    CircleOutline,
+    Triangle,
+    TriangleOutline,
+    Diamond,
+    DiamondOutline,
+    /// From a newer version of the app, kept so it's written back as is.
+    #[serde(untagged)]
+    Unknown(String),
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct FreeDrawElement {
+    #[serde(flatten)]
+    pub base: ElementBase,
+    pub points: Vec<Point>,
+    pub pressures: Vec<f64>,
+    pub simulate_pressure: bool,
+    pub last_committed_point: Option<Point>,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ImageElement {
+    #[serde(flatten)]
+    pub base: ElementBase,
+    /// Key into [`Scene::files`].
+    pub file_id: Option<String>,
+    pub status: ImageStatus,
+    /// Flip factors along x and y, `1` or `-1`.
+    pub scale: [f64; 2],
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum ImageStatus {
+    Pending,
+    Saved,
+    Error,
+    /// From a newer version of the app, kept so it's written back as is.
+    #[serde(untagged)]
+    Unknown(String),
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct FrameElement {
+    #[serde(flatten)]
+    pub base: ElementBase,
+    pub name: Option<String>,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct OtherElement {
+    #[serde(rename = "type")]
+    pub kind: String,
+    /// Anything beyond the common properties ends up in `base.extra`.
+    #[serde(flatten)]
+    pub base: ElementBase,
+}
+
+#[cfg(test)]
+mod tests {
+    use serde_json::json;
+
+    use super::*;
+
+    const FIXTURE: &str = include_str!("../../fixtures/scene.excalidraw");
+
+    #[test]
+    fn round_trips_fixture() {
+        let scene = Scene::parse(FIXTURE).unwrap();
+        assert!(matches!(
+            scene.elements.as_slice(),
+            [
+                Element::Rectangle(_),
+                Element::Text(_),
+                Element::Arrow(_),
+                Element::Freedraw(_),
+                Element::Image(_),
+                Element::Frame(_),
+                Element::Ellipse(_),
+            ]
+        ));
+
+        let original: Value = serde_json::from_str(FIXTURE).unwrap();
+        let written: Value = serde_json::from_str(&scene.to_json().unwrap()).unwrap();
+        assert_eq!(written, original);
+    }
+
+    #[test]
+    fn keeps_unknown_fields() {
+        let scene = Scene::parse(FIXTURE).unwrap();
+        match &scene.elements[6] {
+            Element::Ellipse(base) => assert_eq!(base.extra["futureProp"], "kept"),
+            other => panic!("unexpected element {:?}", other),
+        }
+        assert_eq!(scene.app_state.extra["name"], "Fixture");
+        assert_eq!(scene.extra["futureTopLevel"], true);
+    }
+
+    #[test]
+    fn keeps_unknown_values_and_element_types() {
+        let mut original: Value = serde_json::from_str(FIXTURE).unwrap();
+        let elements = original["elements"].as_array_mut().unwrap();
+        elements[0]["fillStyle"] = json!("wavy");
+        elements[0]["boundElements"] = json!([{ "id": "sticker", "type": "sticker" }]);
+        elements[1]["textAlign"] = json!("justify");
+        let mut sticker = elements[0].clone();
+        sticker["type"] = json!("sticker");
+        sticker["id"] = json!("sticker");
+        sticker["emoji"] = json!("🎉");
+        elements.push(sticker);
+
+        let scene: Scene = serde_json::from_value(original.clone()).unwrap();
+        match &scene.elements[0] {
+            Element::Rectangle(base) => {
+                assert_eq!(base.fill_style, FillStyle::Unknown("wavy".into()));
+                assert_eq!(
+                    base.bound_elements.as_ref().unwrap()[0].kind,
+                    BoundElementType::Unknown("sticker".into())
+                );
+            }
+            other => panic!("unexpected element {:?}", other),
+        }
+        match scene.elements.last().unwrap() {
+            Element::Other(other) => {
+                assert_eq!(other.kind, "sticker");
+                assert_eq!(other.base.id, "sticker");
+                assert_eq!(other.base.extra["emoji"], "🎉");
+            }
+            other => panic!("unexpected element {:?}", other),
+        }
+
+        let written: Value = serde_json::from_str(&scene.to_json().unwrap()).unwrap();
+        assert_eq!(written, original);
+    }
+
+    #[test]
+    fn writes_whole_floats_as_integers() {
+        let scene = Scene::parse(FIXTURE).unwrap();
+        let json = scene.to_json().unwrap();
+        assert!(json.contains("\"x\": 100,"));
+        assert!(json.contains("\"y\": 120.5,"));
+    }
+}
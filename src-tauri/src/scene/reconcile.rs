@@ -0,0 +1,325 @@
+//! Merging remote elements into the local scene, a port of
+//! `reconcileElements` in `packages/excalidraw/data/reconcile.ts`.
+//!
+//! Clients reconciling the same two scenes end up with the same elements in
+//! the same order, whichever side is local.
+
+use std::{
+    cmp::Ordering,
+    collections::{HashMap, HashSet},
+};
+
+use super::{fractional_index, Element};
+use crate::error::Result;
+
+/// Whether the local copy of an element wins: it's being edited locally,
+/// it's newer, or it has the same version and the lower nonce, which
+/// resolves conflicting edits the same way on every client.
+fn should_discard_remote(editing: &[&str], local: &Element, remote: &Element) -> bool {
+    let (local, remote) = (local.base(), remote.base());
+    editing.contains(&local.id.as_str())
+        || local.version > remote.version
+        || (local.version == remote.version && local.version_nonce < remote.version_nonce)
+}
+
+fn ordered_index(element: &Element) -> Option<&str> {
+    element
+        .base()
+        .index
+        .as_deref()
+        .filter(|index| !index.is_empty())
+}
+
+/// `orderByFractionalIndex`: sorts by index, breaking ties by id.
+///
+/// Elements without an index go last, keeping their order. The TS
+/// comparator leaves their position to the JS engine's sort, but scenes
+/// only lack indices when they come from clients predating them, and
+/// `syncInvalidIndices` indexes them right after anyway.
+pub fn order_by_fractional_index(elements: &mut [Element]) {
+    elements.sort_by(|a, b| match (ordered_index(a), ordered_index(b)) {
+        (Some(a_index), Some(b_index)) => a_index
+            .cmp(b_index)
+            .then_with(|| a.base().id.cmp(&b.base().id)),
+        (Some(_), None) => Ordering::Less,
+        (None, Some(_)) => Ordering::Greater,
+        (None, None) => Ordering::Equal,
+    });
+}
+
+/// `reconcileElements`: keeps the winning copy of each element, remote
+/// ones first, orders them by fractional index and re-indexes any that
+/// collide. `editing` are the ids of elements being edited, resized or
+/// created locally, which keep their local copy.
+pub fn reconcile_elements(
+    local: &[Element],
+    remote: &[Element],
+    editing: &[&str],
+) -> Result<Vec<Element>> {
+    let local_by_id: HashMap<&str, &Element> = local
+        .iter()
+        .map(|element| (element.base().id.as_str(), element))
+        .collect();
+    let mut reconciled = Vec::with_capacity(local.len().max(remote.len()));
+    let mut added = HashSet::new();
+
+    for remote_element in remote {
+        let id = remote_element.base().id.as_str();
+        if !added.insert(id) {
+            continue;
+        }
+        match local_by_id.get(id) {
+            Some(&local_element)
+                if should_discard_remote(editing, local_element, remote_element) =>
+            {
+                reconciled.push(local_element.clone())
+            }
+            _ => reconciled.push(remote_element.clone()),
+        }
+    }
+    for local_element in local {
+        if added.insert(local_element.base().id.as_str()) {
+            reconciled.push(local_element.clone());
+        }
+    }
+
+    order_by_fractional_index(&mut reconciled);
+    // de-duplicate indices
+    let fixes = fractional_index::sync_invalid_indices(&mut reconciled)?;
+    if !fixes.is_empty() {
+        log::debug!("reconcile regenerated {} fractional indices", fixes.len());
+    }
+    Ok(reconciled)
+}
+
+#[cfg(test)]
+mod tests {
+    use proptest::prelude::*;
+    use serde::Deserialize;
+
+    use super::*;
+    use crate::scene::{random_integer, test_util::rectangle};
+
+    /// The cases `reconcile.test.ts` runs.
+    const FIXTURES: &str =
+        include_str!("../../../packages/excalidraw/tests/fixtures/reconcile.json");
+
+    #[derive(Deserialize)]
+    struct Fixtures {
+        reconcile: Vec<Group>,
+        identical: Vec<IdenticalCase>,
+    }
+
+    #[derive(Deserialize)]
+    struct Group {
+        description: String,
+        cases: Vec<Case>,
+    }
+
+    #[derive(Deserialize)]
+    struct Case {
+        local: Vec<String>,
+        remote: Vec<String>,
+        expected: Vec<String>,
+    }
+
+    #[derive(Deserialize)]
+    #[serde(rename_all = "camelCase")]
+    struct ElementLike {
+        id: String,
+        version: i64,
+        version_nonce: i64,
+        index: Option<String>,
+    }
+
+    #[derive(Deserialize)]
+    struct IdenticalCase {
+        local: Vec<ElementLike>,
+        remote: Vec<ElementLike>,
+        expected: Vec<String>,
+    }
+
+    fn ids(elements: &[Element]) -> Vec<&str> {
+        elements
+            .iter()
+            .map(|element| element.base().id.as_str())
+            .collect()
+    }
+
+    /// `idsToElements`: `A:2` is element `A` at version 2. Elements with the
+    /// same uid are the same object in the TS test, sharing the index the
+    /// first sync gave them, which `cache` stands in for.
+    fn to_elements(uids: &[String], cache: &mut HashMap<String, Element>) -> Vec<Element> {
+        let mut elements: Vec<Element> = uids
+            .iter()
+            .map(|uid| {
+                cache.get(uid).cloned().unwrap_or_else(|| {
+                    let (id, version) = match uid.split_once(':') {
+                        Some((id, version)) => (id, version.parse().unwrap()),
+                        None => (uid.as_str(), 0),
+                    };
+                    rectangle(id, version, random_integer(), None)
+                })
+            })
+            .collect();
+        fractional_index::sync_invalid_indices(&mut elements).unwrap();
+        for (uid, element) in uids.iter().zip(&elements) {
+            cache.insert(uid.clone(), element.clone());
+        }
+        elements
+    }
+
+    /// The checks of the TS `test` helper: the expected ids, unique indices,
+    /// and the same result when the other side reconciles, both with the
+    /// original scenes and with the result.
+    fn check(local: &[Element], remote: &[Element], expected: &[&str], case: &str) {
+        let reconciled = reconcile_elements(local, remote, &[]).unwrap();
+        assert_eq!(
+            ids(&reconciled),
+            expected,
+            "remote reconciliation of {}",
+            case
+        );
+        let indices: HashSet<_> = reconciled.iter().map(ordered_index).collect();
+        assert_eq!(
+            indices.len(),
+            reconciled.len(),
+            "duplicate indices in {}",
+            case
+        );
+
+        let convergent = reconcile_elements(remote, local, &[]).unwrap();
+        assert_eq!(
+            ids(&convergent),
+            expected,
+            "convergent reconciliation of {}",
+            case
+        );
+        let again = reconcile_elements(remote, &reconciled, &[]).unwrap();
+        assert_eq!(ids(&again), expected, "local re-reconciliation of {}", case);
+    }
+
+    #[test]
+    fn reconciles_the_shared_fixtures() {
+        let fixtures: Fixtures = serde_json::from_str(FIXTURES).unwrap();
+        for group in &fixtures.reconcile {
+            for case in &group.cases {
+                let mut cache = HashMap::new();
+                let local = to_elements(&case.local, &mut cache);
+                let remote = to_elements(&case.remote, &mut cache);
+                let expected: Vec<&str> = case
+                    .expected
+                    .iter()
+                    .map(|uid| uid.split(':').next().unwrap())
+                    .collect();
+                let name = format!("{:?} {:?} ({})", case.local, case.remote, group.description);
+                check(&local, &remote, &expected, &name);
+            }
+        }
+
+        for case in &fixtures.identical {
+            let to_elements = |elements: &[ElementLike]| -> Vec<Element> {
+                elements
+                    .iter()
+                    .map(|e| rectangle(&e.id, e.version, e.version_nonce, e.index.as_deref()))
+                    .collect()
+            };
+            let reconciled =
+                reconcile_elements(&to_elements(&case.local), &to_elements(&case.remote), &[])
+                    .unwrap();
+            assert_eq!(ids(&reconciled), case.expected);
+        }
+    }
+
+    #[test]
+    fn keeps_elements_being_edited() {
+        let local = [rectangle("A", 1, 5, Some("a0"))];
+        let remote = [rectangle("A", 2, 5, Some("a0"))];
+        let reconciled = reconcile_elements(&local, &remote, &["A"]).unwrap();
+        assert_eq!(reconciled[0].base().version, 1);
+        let reconciled = reconcile_elements(&local, &remote, &[]).unwrap();
+        assert_eq!(reconciled[0].base().version, 2);
+    }
+
+    #[test]
+    fn orders_by_index_then_id() {
+        let mut elements = vec![
+            rectangle("C", 1, 1, None),
+            rectangle("B", 1, 1, Some("a1")),
+            rectangle("D", 1, 1, None),
+            rectangle("A", 1, 1, Some("a1")),
+            rectangle("E", 1, 1, Some("a0")),
+        ];
+        order_by_fractional_index(&mut elements);
+        assert_eq!(ids(&elements), ["E", "A", "B", "C", "D"]);
+    }
+
+    /// Scenes over a small pool of ids, so both sides share elements. Like
+    /// in the fixtures, an id at a given version is the same element on
+    /// both sides until syncing the indices of a side bumps it.
+    fn scenes() -> impl Strategy<Value = (Vec<Element>, Vec<Element>)> {
+        let side = prop::collection::vec((0..8u8, 0..4i64), 0..10);
+        (side.clone(), side).prop_map(|(local, remote)| {
+            let build = |entries: Vec<(u8, i64)>| -> Vec<Element> {
+                let mut seen = HashSet::new();
+                let mut elements: Vec<Element> = entries
+                    .into_iter()
+                    .filter(|(id, _)| seen.insert(*id))
+                    .map(|(id, version)| {
+                        let nonce = version * 100 + id as i64;
+                        // some predate fractional indices
+                        let index = format!("a{}", (b'A' + id) as char);
+                        let index = if (id as i64 + version) % 3 == 0 {
+                            None
+                        } else {
+                            Some(index.as_str())
+                        };
+                        rectangle(&((b'A' + id) as char).to_string(), version, nonce, index)
+                    })
+                    .collect();
+                fractional_index::sync_invalid_indices(&mut elements).unwrap();
+                elements
+            };
+            (build(local), build(remote))
+        })
+    }
+
+    proptest! {
+        #[test]
+        fn converges_on_both_sides((local, remote) in scenes()) {
+            let reconciled = reconcile_elements(&local, &remote, &[]).unwrap();
+            let convergent = reconcile_elements(&remote, &local, &[]).unwrap();
+            prop_assert_eq!(ids(&reconciled), ids(&convergent));
+
+            // every element once, its winning copy, in index order
+            let all: HashSet<&str> = ids(&local).into_iter().chain(ids(&remote)).collect();
+            prop_assert_eq!(reconciled.len(), all.len());
+            for element in &reconciled {
+                let id = element.base().id.as_str();
+                let newest = local
+                    .iter()
+                    .chain(&remote)
+                    .filter(|candidate| candidate.base().id == id)
+                    .map(|candidate| {
+                        let base = candidate.base();
+                        (base.version, std::cmp::Reverse(base.version_nonce))
+                    })
+                    .max()
+                    .unwrap();
+                prop_assert!(element.base().version >= newest.0);
+            }
+            let indices: Vec<&str> = reconciled.iter().filter_map(ordered_index).collect();
+            prop_assert_eq!(indices.len(), reconciled.len());
+            prop_assert!(indices.windows(2).all(|pair| pair[0] < pair[1]));
+        }
+
+        #[test]
+        fn settles_after_reconciling_once((local, remote) in scenes()) {
+            let reconciled = reconcile_elements(&local, &remote, &[]).unwrap();
+            let again = reconcile_elements(&remote, &reconciled, &[]).unwrap();
+            prop_assert_eq!(ids(&again), ids(&reconciled));
+            let idempotent = reconcile_elements(&reconciled, &reconciled, &[]).unwrap();
+            prop_assert_eq!(idempotent, reconciled);
+        }
+    }
+}
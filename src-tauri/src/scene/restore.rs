@@ -0,0 +1,1237 @@
+//! Port of `restore` and `restoreElements` from
+//! `packages/excalidraw/data/restore.ts`, migrating scenes written by older
+//! versions (or by hand) to the current format before they reach the
+//! webview.
+//!
+//! Like the TS version, properties an element type doesn't define are
+//! dropped, while values the typed model doesn't know are kept. Unlike it,
+//! elements of unknown types are kept as they are, so a scene from a newer
+//! app version survives being opened and saved here.
+
+use std::collections::{BTreeMap, HashMap, HashSet};
+
+use serde_json::{json, Map, Value};
+
+use crate::error::{Error, Result};
+
+use super::{
+    fractional_index, now_millis, random_id, AppState, BinaryFileData, BoundElement,
+    BoundElementType, Element, Point, Scene, EXPORT_SOURCE, SCENE_TYPE, SCENE_VERSION,
+};
+
+/// `ROUNDNESS.LEGACY`
+const ROUNDNESS_LEGACY: u8 = 1;
+/// `ROUNDNESS.PROPORTIONAL_RADIUS`
+const ROUNDNESS_PROPORTIONAL_RADIUS: u8 = 2;
+
+/// Excalifont
+const DEFAULT_FONT_FAMILY: u32 = 5;
+/// `FONT_FAMILY` ids by name, with the line height from `FONT_METADATA`.
+const FONT_FAMILIES: &[(&str, u32, f64)] = &[
+    ("Virgil", 1, 1.25),
+    ("Helvetica", 2, 1.15),
+    ("Cascadia", 3, 1.2),
+    ("Excalifont", 5, 1.25),
+    ("Nunito", 6, 1.35),
+    ("Lilita One", 7, 1.15),
+    ("Comic Shanns", 8, 1.25),
+    ("Liberation Sans", 9, 1.15),
+];
+
+const DEFAULT_GRID_SIZE: f64 = 20.0;
+const DEFAULT_GRID_STEP: f64 = 5.0;
+const DEFAULT_VIEW_BACKGROUND_COLOR: &str = "#ffffff";
+const MIN_ZOOM: f64 = 0.1;
+const MAX_ZOOM: f64 = 30.0;
+
+/// Migrates a scene file's contents, failing if it isn't an Excalidraw scene
+/// at all. Returns the restored scene serialized as JSON.
+pub fn restore_json(json: &str) -> Result<String> {
+    let data: Value = serde_json::from_str(json)?;
+    if !is_valid_scene_data(&data) {
+        return Err(Error::Other("not an Excalidraw scene".into()));
+    }
+    restore(&data, None, true)?.to_json()
+}
+
+/// `isValidExcalidrawData`
+fn is_valid_scene_data(data: &Value) -> bool {
+    data["type"] == SCENE_TYPE
+        && match &data["elements"] {
+            Value::Null => true,
+            Value::Array(_) => data["appState"].is_null() || data["appState"].is_object(),
+            elements => !is_truthy(elements),
+        }
+}
+
+/// Restores a scene, given as `ImportedDataState` JSON. Elements newer in
+/// `local_elements` get their version bumped past the local one, so the
+/// restored copy wins when syncing.
+pub fn restore(
+    data: &Value,
+    local_elements: Option<&[Element]>,
+    repair_bindings: bool,
+) -> Result<Scene> {
+    let elements = match &data["elements"] {
+        Value::Array(elements) => restore_elements(elements, local_elements, repair_bindings)?,
+        _ => Vec::new(),
+    };
+    Ok(Scene {
+        kind: SCENE_TYPE.into(),
+        version: SCENE_VERSION,
+        source: data["source"].as_str().unwrap_or(EXPORT_SOURCE).into(),
+        elements,
+        app_state: restore_app_state(&data["appState"]),
+        files: Some(restore_files(&data["files"])),
+        extra: Map::new(),
+    })
+}
+
+/// Restores each element, dropping selections and invisibly small elements,
+/// then syncs fractional indices with the array order. Fails on elements
+/// whose common properties can't be read, rather than losing them.
+///
+/// With `repair_bindings`, references to missing frames, containers and
+/// bound elements are fixed up as well. Unlike the TS version, text
+/// dimensions are never refreshed, as that needs the fonts' metrics.
+pub fn restore_elements(
+    elements: &[Value],
+    local_elements: Option<&[Element]>,
+    repair_bindings: bool,
+) -> Result<Vec<Element>> {
+    let local_versions: HashMap<&str, i64> = local_elements
+        .unwrap_or_default()
+        .iter()
+        .map(|element| (element.base().id.as_str(), element.base().version))
+        .collect();
+
+    let mut ids = HashSet::new();
+    let mut restored = Vec::new();
+    for element in elements {
+        let element = match element.as_object() {
+            Some(element) => element,
+            None => continue,
+        };
+        if element.get("type").and_then(Value::as_str) == Some("selection")
+            || is_invisibly_small(element)
+        {
+            continue;
+        }
+        let mut migrated = match restore_element(element)? {
+            Some(migrated) => migrated,
+            None => continue,
+        };
+
+        let base = migrated.base_mut();
+        if let Some(&local_version) = local_versions.get(base.id.as_str()) {
+            if local_version > base.version {
+                base.bump_version(Some(local_version));
+            }
+        }
+        if ids.contains(&base.id) {
+            base.id = random_id();
+        }
+        ids.insert(base.id.clone());
+        restored.push(migrated);
+    }
+
+    let fixes = fractional_index::sync_invalid_indices(&mut restored)?;
+    if !fixes.is_empty() {
+        log::debug!("restore regenerated {} fractional indices", fixes.len());
+    }
+    if repair_bindings {
+        repair(&mut restored);
+    }
+    Ok(restored)
+}
+
+/// JS truthiness, for porting `a || b`.
+fn is_truthy(value: &Value) -> bool {
+    match value {
+        Value::Null => false,
+        Value::Bool(value) => *value,
+        Value::Number(value) => value.as_f64().map_or(false, |value| value != 0.0),
+        Value::String(value) => !value.is_empty(),
+        Value::Array(_) | Value::Object(_) => true,
+    }
+}
+
+/// `element[key] || default`
+fn or(element: &Map<String, Value>, key: &str, default: Value) -> Value {
+    element
+        .get(key)
+        .filter(|value| is_truthy(value))
+        .cloned()
+        .unwrap_or(default)
+}
+
+/// `element[key] ?? default`
+fn coalesce(element: &Map<String, Value>, key: &str, default: Value) -> Value {
+    element
+        .get(key)
+        .filter(|value| !value.is_null())
+        .cloned()
+        .unwrap_or(default)
+}
+
+/// `element[key]`, with missing keys as `null`.
+fn get<'a>(element: &'a Map<String, Value>, key: &str) -> &'a Value {
+    element.get(key).unwrap_or(&Value::Null)
+}
+
+fn number(element: &Map<String, Value>, key: &str) -> Option<f64> {
+    element.get(key).and_then(Value::as_f64)
+}
+
+/// `isInvisiblySmallElement`
+fn is_invisibly_small(element: &Map<String, Value>) -> bool {
+    match element.get("type").and_then(Value::as_str) {
+        Some("arrow" | "line" | "freedraw") => element
+            .get("points")
+            .and_then(Value::as_array)
+            .map_or(false, |points| points.len() < 2),
+        _ => number(element, "width") == Some(0.0) && number(element, "height") == Some(0.0),
+    }
+}
+
+/// `isUsingAdaptiveRadius`
+fn uses_adaptive_radius(kind: &str) -> bool {
+    matches!(kind, "rectangle" | "embeddable" | "iframe" | "image")
+}
+
+/// `restoreElementWithProperties`: fills in the properties every element
+/// has, then applies the type specific `extra` ones.
+fn restore_with(
+    element: &Map<String, Value>,
+    kind: &str,
+    extra: Map<String, Value>,
+) -> Map<String, Value> {
+    let original_type = element.get("type").and_then(Value::as_str).unwrap_or(kind);
+    let roundness = if is_truthy(get(element, "roundness")) {
+        get(element, "roundness").clone()
+    } else if element.get("strokeSharpness").and_then(Value::as_str) == Some("round") {
+        // old elements that would now use the adaptive radius keep the
+        // legacy algorithm
+        let kind = if uses_adaptive_radius(original_type) {
+            ROUNDNESS_LEGACY
+        } else {
+            ROUNDNESS_PROPORTIONAL_RADIUS
+        };
+        json!({ "type": kind })
+    } else {
+        Value::Null
+    };
+    let bound_elements = match element.get("boundElementIds") {
+        Some(Value::Array(ids)) => ids
+            .iter()
+            .map(|id| json!({ "type": "arrow", "id": id }))
+            .collect(),
+        _ => coalesce(element, "boundElements", json!([])),
+    };
+    let link = match element.get("link").and_then(Value::as_str) {
+        Some(link) if !link.is_empty() => json!(normalize_link(link)),
+        _ => Value::Null,
+    };
+
+    let mut base = Map::new();
+    base.insert("type".into(), json!(kind));
+    // every element needs a version > 0 for the scene version to pick it up
+    base.insert("version".into(), or(element, "version", json!(1)));
+    base.insert(
+        "versionNonce".into(),
+        coalesce(element, "versionNonce", json!(0)),
+    );
+    base.insert("index".into(), coalesce(element, "index", Value::Null));
+    base.insert(
+        "isDeleted".into(),
+        coalesce(element, "isDeleted", json!(false)),
+    );
+    base.insert("id".into(), or(element, "id", json!(random_id())));
+    base.insert("fillStyle".into(), or(element, "fillStyle", json!("solid")));
+    base.insert("strokeWidth".into(), or(element, "strokeWidth", json!(2)));
+    base.insert(
+        "strokeStyle".into(),
+        coalesce(element, "strokeStyle", json!("solid")),
+    );
+    base.insert("roughness".into(), coalesce(element, "roughness", json!(1)));
+    base.insert("opacity".into(), coalesce(element, "opacity", json!(100)));
+    base.insert("angle".into(), or(element, "angle", json!(0)));
+    base.insert(
+        "x".into(),
+        coalesce(&extra, "x", coalesce(element, "x", json!(0))),
+    );
+    base.insert(
+        "y".into(),
+        coalesce(&extra, "y", coalesce(element, "y", json!(0))),
+    );
+    base.insert(
+        "strokeColor".into(),
+        or(element, "strokeColor", json!("#1e1e1e")),
+    );
+    base.insert(
+        "backgroundColor".into(),
+        or(element, "backgroundColor", json!("transparent")),
+    );
+    base.insert("width".into(), or(element, "width", json!(0)));
+    base.insert("height".into(), or(element, "height", json!(0)));
+    base.insert("seed".into(), coalesce(element, "seed", json!(1)));
+    base.insert("groupIds".into(), coalesce(element, "groupIds", json!([])));
+    base.insert("frameId".into(), coalesce(element, "frameId", Value::Null));
+    base.insert("roundness".into(), roundness);
+    base.insert("boundElements".into(), bound_elements);
+    base.insert(
+        "updated".into(),
+        coalesce(element, "updated", json!(now_millis())),
+    );
+    base.insert("link".into(), link);
+    base.insert("locked".into(), coalesce(element, "locked", json!(false)));
+    if let Some(custom_data) = extra
+        .get("customData")
+        .or_else(|| element.get("customData"))
+    {
+        base.insert("customData".into(), custom_data.clone());
+    }
+
+    // getNormalizedDimensions: negative sizes grow the other way
+    for (size, position) in [("width", "x"), ("height", "y")] {
+        if let (Some(length), Some(start)) = (number(&base, size), number(&base, position)) {
+            if length < 0.0 {
+                base.insert(size.into(), json!(length.abs()));
+                base.insert(position.into(), json!(start - length.abs()));
+            }
+        }
+    }
+
+    base.extend(extra);
+    base
+}
+
+/// `restoreElement`, `None` for elements without a type.
+fn restore_element(element: &Map<String, Value>) -> Result<Option<Element>> {
+    let kind = match element.get("type").and_then(Value::as_str) {
+        Some(kind) => kind,
+        None => return Ok(None),
+    };
+    let mut extra = Map::new();
+    let restored = match kind {
+        "text" => {
+            let mut font_size = element.get("fontSize").cloned().unwrap_or_default();
+            let mut font_family = element.get("fontFamily").cloned().unwrap_or_default();
+            if let Some(font) = element.get("font") {
+                // legacy CSS shorthand, e.g. "20px Virgil"
+                let mut parts = font.as_str().unwrap_or_default().split(' ');
+                font_size = json!(parse_float(parts.next().unwrap_or_default()));
+                font_family = json!(font_family_by_name(parts.next().unwrap_or_default()));
+            }
+            let text = element
+                .get("text")
+                .and_then(Value::as_str)
+                .unwrap_or_default();
+
+            // old scenes have no line height; derive it from the element's
+            // height so they keep their layout
+            let line_height = if is_truthy(get(element, "lineHeight")) {
+                get(element, "lineHeight").clone()
+            } else if is_truthy(get(element, "height")) {
+                let lines = text
+                    .replace("\r\n", "\n")
+                    .replace('\r', "\n")
+                    .split('\n')
+                    .count();
+                let derived = number(element, "height").unwrap_or(f64::NAN)
+                    / lines as f64
+                    / font_size.as_f64().unwrap_or(f64::NAN);
+                if derived.is_finite() {
+                    json!(derived)
+                } else {
+                    json!(line_height(font_family.as_u64()))
+                }
+            } else {
+                json!(line_height(font_family.as_u64()))
+            };
+
+            extra.insert("fontSize".into(), font_size);
+            extra.insert("fontFamily".into(), font_family);
+            extra.insert("text".into(), json!(text));
+            extra.insert("textAlign".into(), or(element, "textAlign", json!("left")));
+            extra.insert(
+                "verticalAlign".into(),
+                or(element, "verticalAlign", json!("top")),
+            );
+            extra.insert(
+                "containerId".into(),
+                coalesce(element, "containerId", Value::Null),
+            );
+            extra.insert(
+                "originalText".into(),
+                or(element, "originalText", json!(text)),
+            );
+            extra.insert(
+                "autoResize".into(),
+                coalesce(element, "autoResize", json!(true)),
+            );
+            extra.insert("lineHeight".into(), line_height);
+            restore_with(element, kind, extra)
+        }
+        "freedraw" => {
+            for key in ["points", "simulatePressure", "pressures"] {
+                if let Some(value) = element.get(key) {
+                    extra.insert(key.into(), value.clone());
+                }
+            }
+            extra.insert("lastCommittedPoint".into(), Value::Null);
+            restore_with(element, kind, extra)
+        }
+        "image" => {
+            extra.insert("status".into(), or(element, "status", json!("pending")));
+            extra.insert("fileId".into(), coalesce(element, "fileId", Value::Null));
+            extra.insert("scale".into(), or(element, "scale", json!([1, 1])));
+            restore_with(element, kind, extra)
+        }
+        // "draw" is the legacy name of "line"
+        "line" | "draw" | "arrow" => {
+            let is_arrow = kind == "arrow";
+            let is_elbow = is_arrow && is_truthy(get(element, "elbowed"));
+            let default_end_arrowhead = if is_arrow {
+                json!("arrow")
+            } else {
+                Value::Null
+            };
+
+            // migrate the old arrow model without points to the new one
+            let mut points: Vec<Point> =
+                match serde_json::from_value(get(element, "points").clone()) {
+                    Ok(points) if Vec::len(&points) >= 2 => points,
+                    _ => vec![
+                        [0.0, 0.0],
+                        [
+                            number(element, "width").unwrap_or_default(),
+                            number(element, "height").unwrap_or_default(),
+                        ],
+                    ],
+                };
+            let [offset_x, offset_y] = points[0];
+            if offset_x != 0.0 || offset_y != 0.0 {
+                for point in &mut points {
+                    point[0] -= offset_x;
+                    point[1] -= offset_y;
+                }
+                extra.insert(
+                    "x".into(),
+                    json!(number(element, "x").unwrap_or_default() + offset_x),
+                );
+                extra.insert(
+                    "y".into(),
+                    json!(number(element, "y").unwrap_or_default() + offset_y),
+                );
+            }
+            let (width, height) = size_from_points(&points);
+
+            extra.insert(
+                "startBinding".into(),
+                repair_binding(get(element, "startBinding"), is_elbow),
+            );
+            extra.insert(
+                "endBinding".into(),
+                repair_binding(get(element, "endBinding"), is_elbow),
+            );
+            extra.insert("lastCommittedPoint".into(), Value::Null);
+            extra.insert(
+                "startArrowhead".into(),
+                element.get("startArrowhead").cloned().unwrap_or_default(),
+            );
+            extra.insert(
+                "endArrowhead".into(),
+                element
+                    .get("endArrowhead")
+                    .cloned()
+                    .unwrap_or(default_end_arrowhead),
+            );
+            extra.insert("points".into(), json!(points));
+            extra.insert("width".into(), json!(width));
+            extra.insert("height".into(), json!(height));
+            if is_arrow {
+                extra.insert("elbowed".into(), coalesce(element, "elbowed", json!(false)));
+            }
+            restore_with(element, if is_arrow { "arrow" } else { "line" }, extra)
+        }
+        "ellipse" | "rectangle" | "diamond" | "iframe" | "embeddable" => {
+            restore_with(element, kind, extra)
+        }
+        "frame" | "magicframe" => {
+            extra.insert("name".into(), coalesce(element, "name", Value::Null));
+            restore_with(element, kind, extra)
+        }
+        // a type from a newer version, kept as it is when it's complete
+        _ => {
+            if let Ok(other) = serde_json::from_value(Value::Object(element.clone())) {
+                return Ok(Some(other));
+            }
+            let mut restored = restore_with(element, kind, Map::new());
+            for (key, value) in element {
+                restored.entry(key.clone()).or_insert_with(|| value.clone());
+            }
+            restored
+        }
+    };
+
+    // what the typed model can't read ends up as `Element::Other`, so this
+    // only fails on broken common properties
+    let mut restored: Element = serde_json::from_value(Value::Object(restored)).map_err(|err| {
+        Error::Other(format!(
+            "failed to restore {} element {}: {}",
+            kind,
+            element.get("id").unwrap_or(&Value::Null),
+            err
+        ))
+    })?;
+
+    // empty text is kept, deleted, for the sake of collaborators
+    if let Element::Text(text) = &mut restored {
+        if text.text.is_empty() && !text.base.is_deleted {
+            text.base.is_deleted = true;
+            text.original_text = String::new();
+            text.base.bump_version(None);
+        }
+    }
+    Ok(Some(restored))
+}
+
+/// `parseFloat`, which reads the longest numeric prefix.
+fn parse_float(value: &str) -> f64 {
+    let value = value.trim_start();
+    (1..=value.len())
+        .rev()
+        .filter(|&end| value.is_char_boundary(end))
+        .find_map(|end| value[..end].parse::<f64>().ok())
+        .unwrap_or(f64::NAN)
+}
+
+fn font_family_by_name(name: &str) -> u32 {
+    FONT_FAMILIES
+        .iter()
+        .find(|(family, ..)| *family == name)
+        .map_or(DEFAULT_FONT_FAMILY, |&(_, id, _)| id)
+}
+
+/// `getLineHeight`, falling back to Excalifont's.
+fn line_height(font_family: Option<u64>) -> f64 {
+    FONT_FAMILIES
+        .iter()
+        .find(|&&(_, id, _)| Some(id as u64) == font_family)
+        .or_else(|| {
+            FONT_FAMILIES
+                .iter()
+                .find(|&&(_, id, _)| id == DEFAULT_FONT_FAMILY)
+        })
+        .map_or(1.25, |&(.., line_height)| line_height)
+}
+
+fn size_from_points(points: &[Point]) -> (f64, f64) {
+    let extent = |axis: usize| {
+        let values = points.iter().map(|point| point[axis]);
+        values.clone().fold(f64::NEG_INFINITY, f64::max) - values.fold(f64::INFINITY, f64::min)
+    };
+    (extent(0), extent(1))
+}
+
+/// `repairBinding`
+fn repair_binding(binding: &Value, is_elbow: bool) -> Value {
+    let mut binding = match binding {
+        Value::Object(binding) => binding.clone(),
+        _ => return Value::Null,
+    };
+    let focus = or(&binding, "focus", json!(0));
+    binding.insert("focus".into(), focus);
+    if is_elbow {
+        if let Some(Value::Array(fixed_point)) = binding.get_mut("fixedPoint") {
+            // an exact 0.5 makes the arrow heading jump due to rounding
+            for ratio in fixed_point {
+                if ratio.as_f64() == Some(0.5) {
+                    *ratio = json!(0.5001);
+                }
+            }
+        }
+    }
+    Value::Object(binding)
+}
+
+/// `normalizeLink`: trims the link and neutralizes scripting URLs, as
+/// `sanitizeUrl` does.
+fn normalize_link(link: &str) -> String {
+    let link = link.trim();
+    if link.is_empty() {
+        return link.into();
+    }
+    let link = link.replace('"', "&quot;");
+    let scheme: String = link
+        .chars()
+        .filter(|c| !c.is_whitespace() && !c.is_control())
+        .take_while(|&c| c != ':')
+        .collect::<String>()
+        .to_lowercase();
+    if link.contains(':') && matches!(scheme.as_str(), "javascript" | "data" | "vbscript") {
+        "about:blank".into()
+    } else {
+        link
+    }
+}
+
+/// Fixes references between restored elements, mutating them like the
+/// binding repair in `restoreElements`.
+fn repair(elements: &mut [Element]) {
+    let positions: HashMap<String, usize> = elements
+        .iter()
+        .enumerate()
+        .map(|(i, element)| (element.base().id.clone(), i))
+        .collect();
+
+    for i in 0..elements.len() {
+        // repairFrameMembership
+        if let Some(frame_id) = &elements[i].base().frame_id {
+            if !positions.contains_key(frame_id) {
+                elements[i].base_mut().frame_id = None;
+            }
+        }
+
+        let container_id = match &elements[i] {
+            Element::Text(text) => text.container_id.clone().filter(|id| !id.is_empty()),
+            _ => None,
+        };
+        if let Some(container_id) = container_id {
+            repair_bound_text(elements, &positions, i, &container_id);
+        } else if elements[i].base().bound_elements.is_some() {
+            repair_container(elements, &positions, i);
+        }
+
+        match &mut elements[i] {
+            Element::Arrow(arrow) => {
+                let linear = &mut arrow.linear;
+                for binding in [&mut linear.start_binding, &mut linear.end_binding] {
+                    if binding.as_ref().map_or(false, |binding| {
+                        !positions.contains_key(&binding.element_id)
+                    }) {
+                        *binding = None;
+                    }
+                }
+            }
+            // only arrows can bind
+            Element::Line(line) => {
+                line.start_binding = None;
+                line.end_binding = None;
+            }
+            _ => {}
+        }
+    }
+}
+
+/// `repairBoundElement`: drops the container id if the container is gone,
+/// otherwise makes sure the container lists the text.
+fn repair_bound_text(
+    elements: &mut [Element],
+    positions: &HashMap<String, usize>,
+    i: usize,
+    container_id: &str,
+) {
+    let container = match positions.get(container_id) {
+        Some(&container) => container,
+        None => {
+            if let Element::Text(text) = &mut elements[i] {
+                text.container_id = None;
+            }
+            return;
+        }
+    };
+    if elements[i].base().is_deleted {
+        return;
+    }
+    let text_id = elements[i].base().id.clone();
+    if let Some(bound_elements) = &mut elements[container].base_mut().bound_elements {
+        if !bound_elements.iter().any(|bound| bound.id == text_id) {
+            bound_elements.push(BoundElement {
+                id: text_id,
+                kind: BoundElementType::Text,
+            });
+        }
+    }
+}
+
+/// `repairContainerElement`: dedupes the container's bindings, drops those
+/// to missing or deleted elements and points bound text back at it.
+fn repair_container(elements: &mut [Element], positions: &HashMap<String, usize>, i: usize) {
+    let container_id = elements[i].base().id.clone();
+    let bindings = elements[i]
+        .base()
+        .bound_elements
+        .clone()
+        .unwrap_or_default();
+
+    let mut seen = HashSet::new();
+    let mut kept = Vec::new();
+    for binding in bindings {
+        let bound = match positions.get(&binding.id) {
+            Some(&bound) => bound,
+            None => continue,
+        };
+        if !seen.insert(binding.id.clone()) || elements[bound].base().is_deleted {
+            continue;
+        }
+        kept.push(binding);
+        if let Element::Text(text) = &mut elements[bound] {
+            if text.container_id.as_deref().map_or(true, str::is_empty) {
+                text.container_id = Some(container_id.clone());
+            }
+        }
+    }
+    elements[i].base_mut().bound_elements = Some(kept);
+}
+
+/// `Math.round`, which rounds halves up.
+fn js_round(value: f64) -> f64 {
+    (value + 0.5).floor()
+}
+
+/// Restores the exported subset of the app state: normalizes the grid,
+/// fills in defaults and migrates legacy keys other properties are kept as
+/// they are.
+fn restore_app_state(app_state: &Value) -> AppState {
+    let mut state = app_state.as_object().cloned().unwrap_or_default();
+
+    if let Some(docked) = state.remove("isSidebarDocked") {
+        if !state.contains_key("defaultSidebarDockedPreference") {
+            let docked = if docked.is_null() {
+                json!(false)
+            } else {
+                docked
+            };
+            state.insert("defaultSidebarDockedPreference".into(), docked);
+        }
+    }
+    if let Some(zoom) = state.get("zoom") {
+        // zoom used to be a plain number
+        let zoom = zoom
+            .as_f64()
+            .or_else(|| zoom["value"].as_f64())
+            .unwrap_or(1.0);
+        let zoom = (js_round(zoom * 1e6) / 1e6).clamp(MIN_ZOOM, MAX_ZOOM);
+        state.insert("zoom".into(), json!({ "value": zoom }));
+    }
+    if state.get("openSidebar").map_or(false, Value::is_string) {
+        state.insert("openSidebar".into(), json!({ "name": "default" }));
+    }
+
+    let grid = |value: Option<Value>, default: f64| {
+        let value = value
+            .and_then(|value| value.as_f64())
+            .filter(|value| value.is_finite())
+            .unwrap_or(default);
+        Some(js_round(value).clamp(1.0, 100.0))
+    };
+    AppState {
+        grid_size: grid(state.remove("gridSize"), DEFAULT_GRID_SIZE),
+        grid_step: grid(state.remove("gridStep"), DEFAULT_GRID_STEP),
+        grid_mode_enabled: Some(
+            state
+                .remove("gridModeEnabled")
+                .and_then(|value| value.as_bool())
+                .unwrap_or(false),
+        ),
+        view_background_color: Some(
+            state
+                .remove("viewBackgroundColor")
+                .and_then(|value| value.as_str().map(String::from))
+                .unwrap_or_else(|| DEFAULT_VIEW_BACKGROUND_COLOR.into()),
+        ),
+        extra: state,
+    }
+}
+
+fn restore_files(files: &Value) -> BTreeMap<String, BinaryFileData> {
+    let files = match files.as_object() {
+        Some(files) => files,
+        None => return BTreeMap::new(),
+    };
+    files
+        .iter()
+        .filter_map(|(id, file)| match serde_json::from_value(file.clone()) {
+            Ok(file) => Some((id.clone(), file)),
+            Err(err) => {
+                log::warn!("dropping file {}: {}", id, err);
+                None
+            }
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::BTreeSet;
+
+    use super::*;
+    use crate::scene::to_js_value;
+
+    /// The vitest snapshots `packages/excalidraw/tests/data/restore.test.ts`
+    /// checks its results against.
+    const SNAPSHOTS: &str =
+        include_str!("../../../packages/excalidraw/tests/data/__snapshots__/restore.test.ts.snap");
+
+    /// Parses the snapshot file into JSON. `Any<Number>` matchers become
+    /// `"<any>"`, and `undefined` properties are dropped.
+    fn snapshots() -> HashMap<String, Value> {
+        let mut snapshots = HashMap::new();
+        for block in SNAPSHOTS.split("exports[`").skip(1) {
+            let (name, rest) = block.split_once("`] = `").unwrap();
+            let body = &rest[..rest.find("\n`;").unwrap()];
+            let json: Vec<String> = body
+                .lines()
+                .filter(|line| !line.ends_with(": undefined,"))
+                .map(|line| line.replace("Any<Number>", "\"<any>\""))
+                .collect();
+            let json = strip_trailing_commas(&json.join("\n"));
+            snapshots.insert(name.to_string(), serde_json::from_str(&json).unwrap());
+        }
+        snapshots
+    }
+
+    fn strip_trailing_commas(json: &str) -> String {
+        let mut stripped = String::new();
+        let mut chars = json.chars().peekable();
+        while let Some(c) = chars.next() {
+            if c == ',' {
+                let rest: String = chars.clone().collect();
+                if rest.trim_start().starts_with(['}', ']']) {
+                    continue;
+                }
+            }
+            stripped.push(c);
+        }
+        stripped
+    }
+
+    /// Compares like the snapshot serializer prints: non-integers as
+    /// strings with five decimals. `updated` is skipped, the TS tests pin it
+    /// to 1.
+    fn matches(expected: &Value, actual: &Value) -> bool {
+        match (expected, actual) {
+            (Value::String(expected), _) if expected == "<any>" => actual.is_number(),
+            (Value::String(expected), Value::Number(actual)) => {
+                format!("{:.5}", actual.as_f64().unwrap()) == *expected
+            }
+            (Value::Number(expected), Value::Number(actual)) => {
+                expected.as_f64() == actual.as_f64()
+            }
+            (Value::Array(expected), Value::Array(actual)) => {
+                expected.len() == actual.len()
+                    && expected.iter().zip(actual).all(|(e, a)| matches(e, a))
+            }
+            (Value::Object(expected), Value::Object(actual)) => {
+                let keys = |map: &Map<String, Value>| -> BTreeSet<String> {
+                    map.keys()
+                        .filter(|key| *key != "updated")
+                        .cloned()
+                        .collect()
+                };
+                keys(expected) == keys(actual)
+                    && keys(expected)
+                        .iter()
+                        .all(|key| matches(&expected[key], &actual[key]))
+            }
+            _ => expected == actual,
+        }
+    }
+
+    fn assert_snapshot(snapshots: &HashMap<String, Value>, name: &str, element: &Element) {
+        let actual = to_js_value(element).unwrap();
+        assert!(
+            matches(&snapshots[name], &actual),
+            "{}\nexpected {:#}\nactual {:#}",
+            name,
+            snapshots[name],
+            actual
+        );
+    }
+
+    /// What the TS tests' `API.createElement` returns with default app state.
+    fn created(kind: &str, overrides: Value) -> Value {
+        let mut element = json!({
+            "id": "id0",
+            "type": kind,
+            "x": 0,
+            "y": 0,
+            "width": 100,
+            "height": 100,
+            "angle": 0,
+            "strokeColor": "#1e1e1e",
+            "backgroundColor": "transparent",
+            "fillStyle": "solid",
+            "strokeWidth": 2,
+            "strokeStyle": "solid",
+            "roughness": 1,
+            "opacity": 100,
+            "groupIds": [],
+            "frameId": null,
+            "index": null,
+            "roundness": { "type": 3 },
+            "seed": 1,
+            "version": 1,
+            "versionNonce": 0,
+            "isDeleted": false,
+            "boundElements": null,
+            "updated": 1,
+            "link": null,
+            "locked": false,
+        });
+        let extra = match kind {
+            "text" => json!({
+                "fontSize": 20,
+                "fontFamily": 5,
+                "text": "test",
+                "originalText": "test",
+                "textAlign": "left",
+                "verticalAlign": "top",
+                "containerId": null,
+                "autoResize": true,
+                "lineHeight": 1.25,
+            }),
+            "line" | "arrow" => json!({
+                "roundness": { "type": 2 },
+                "points": [[0, 0], [100, 100]],
+                "lastCommittedPoint": null,
+                "startBinding": null,
+                "endBinding": null,
+                "startArrowhead": null,
+                "endArrowhead": null,
+                "elbowed": false,
+            }),
+            "freedraw" => json!({
+                "width": 0,
+                "height": 0,
+                "points": [],
+                "pressures": [],
+                "simulatePressure": true,
+                "lastCommittedPoint": null,
+            }),
+            _ => json!({}),
+        };
+        for overrides in [extra, overrides] {
+            for (key, value) in overrides.as_object().unwrap() {
+                element[key] = value.clone();
+            }
+        }
+        if kind == "line" {
+            element.as_object_mut().unwrap().remove("elbowed");
+        }
+        element
+    }
+
+    /// The TS snapshot tests mock `isInvisiblySmallElement` away.
+    fn restore_unfiltered(elements: &[Value]) -> Vec<Element> {
+        let mut restored: Vec<Element> = elements
+            .iter()
+            .filter_map(|element| restore_element(element.as_object().unwrap()).unwrap())
+            .collect();
+        fractional_index::sync_invalid_indices(&mut restored).unwrap();
+        restored
+    }
+
+    #[test]
+    fn matches_ts_snapshots() {
+        let snapshots = snapshots();
+        let prefix = "restoreElements > ";
+
+        let text = created(
+            "text",
+            json!({
+                "id": "id-text01",
+                "fontSize": 14,
+                "fontFamily": 1,
+                "text": "text",
+                "originalText": "text",
+                "textAlign": "center",
+                "verticalAlign": "middle",
+                "x": -20,
+                "y": -8.75,
+            }),
+        );
+        assert_snapshot(
+            &snapshots,
+            &format!(
+                "{}should restore text element correctly passing value for each attribute 1",
+                prefix
+            ),
+            &restore_unfiltered(&[text])[0],
+        );
+
+        let text = created(
+            "text",
+            json!({ "id": "id-text01", "text": null, "font": "10 unknown" }),
+        );
+        assert_snapshot(
+            &snapshots,
+            &format!("{}should restore text element correctly with unknown font family, null text and undefined alignment 1", prefix),
+            &restore_unfiltered(&[text])[0],
+        );
+
+        let freedraw = created("freedraw", json!({ "id": "id-freedraw01" }));
+        assert_snapshot(
+            &snapshots,
+            &format!("{}should restore freedraw element correctly 1", prefix),
+            &restore_unfiltered(&[freedraw])[0],
+        );
+
+        let line = created("line", json!({ "id": "id-line01" }));
+        let draw = created("line", json!({ "id": "id-draw01", "type": "draw" }));
+        let restored = restore_unfiltered(&[line, draw]);
+        assert_snapshot(
+            &snapshots,
+            &format!(
+                "{}should restore line and draw elements correctly 1",
+                prefix
+            ),
+            &restored[0],
+        );
+        assert_snapshot(
+            &snapshots,
+            &format!(
+                "{}should restore line and draw elements correctly 2",
+                prefix
+            ),
+            &restored[1],
+        );
+
+        let arrow = created("arrow", json!({ "id": "id-arrow01" }));
+        assert_snapshot(
+            &snapshots,
+            &format!("{}should restore arrow element correctly 1", prefix),
+            &restore_unfiltered(&[arrow])[0],
+        );
+
+        let shapes: Vec<Value> = ["rectangle", "ellipse", "diamond"]
+            .iter()
+            .enumerate()
+            .map(|(i, kind)| {
+                created(
+                    kind,
+                    json!({
+                        "id": (i + 1).to_string(),
+                        "fillStyle": "cross-hatch",
+                        "strokeWidth": 2,
+                        "strokeStyle": "dashed",
+                        "roughness": 2,
+                        "opacity": 10,
+                        "x": 10,
+                        "y": 20,
+                        "strokeColor": "red",
+                        "backgroundColor": "blue",
+                        "width": 100,
+                        "height": 200,
+                        "groupIds": ["1", "2", "3"],
+                    }),
+                )
+            })
+            .collect();
+        for (i, shape) in restore_unfiltered(&shapes).iter().enumerate() {
+            assert_snapshot(
+                &snapshots,
+                &format!(
+                    "{}should restore correctly with rectangle, ellipse and diamond elements {}",
+                    prefix,
+                    i + 1
+                ),
+                shape,
+            );
+        }
+    }
+
+    #[test]
+    fn drops_selection_and_small_elements() {
+        let elements = [
+            json!({ "type": "selection" }),
+            created("rectangle", json!({ "width": 0, "height": 0 })),
+            created("line", json!({ "points": [[0, 0]] })),
+        ];
+        assert!(restore_elements(&elements, None, false).unwrap().is_empty());
+    }
+
+    #[test]
+    fn keeps_elements_of_unknown_types() {
+        let elements = [
+            created("sticker", json!({ "id": "sticker", "emoji": "🎉" })),
+            json!({ "type": "sticker", "id": "bare", "emoji": "🎈" }),
+        ];
+        match restore_elements(&elements, None, false).unwrap().as_slice() {
+            [Element::Other(full), Element::Other(bare)] => {
+                assert_eq!(full.kind, "sticker");
+                assert_eq!(full.base.extra["emoji"], "🎉");
+                // filled in like any other element
+                assert_eq!(bare.base.id, "bare");
+                assert!(bare.base.index.is_some());
+                assert_eq!(bare.base.extra["emoji"], "🎈");
+            }
+            other => panic!("unexpected elements {:?}", other),
+        }
+
+        // losing an element silently is worse than not opening the scene
+        let broken = created("rectangle", json!({ "x": "left" }));
+        assert!(restore_elements(&[broken], None, false).is_err());
+    }
+
+    #[test]
+    fn bumps_versions_of_newer_local_elements() {
+        let rectangle = created("rectangle", json!({ "id": "rect" }));
+        let ellipse = created("ellipse", json!({ "id": "ellipse" }));
+        let mut local = restore_elements(std::slice::from_ref(&rectangle), None, false).unwrap();
+        local[0].base_mut().bump_version(None);
+        let local_version = local[0].base().version;
+
+        let restored = restore_elements(&[rectangle, ellipse], Some(&local), false).unwrap();
+        // one bump past the local version, another for the new index
+        assert_eq!(restored[0].base().version, local_version + 2);
+        assert_eq!(restored[1].base().version, 2);
+    }
+
+    #[test]
+    fn normalizes_linear_points() {
+        let line = created(
+            "line",
+            json!({ "points": "not an array", "width": 100, "height": 200 }),
+        );
+        let shifted = created(
+            "line",
+            json!({ "id": "shifted", "x": 30, "y": 40, "points": [[3, 4], [5, 6]] }),
+        );
+        let restored = restore_elements(&[line, shifted], None, false).unwrap();
+
+        match &restored[..] {
+            [Element::Line(line), Element::Line(shifted)] => {
+                assert_eq!(line.points, [[0.0, 0.0], [100.0, 200.0]]);
+                assert_eq!(shifted.points, [[0.0, 0.0], [2.0, 2.0]]);
+                assert_eq!((shifted.base.x, shifted.base.y), (33.0, 44.0));
+                assert_eq!((shifted.base.width, shifted.base.height), (2.0, 2.0));
+            }
+            other => panic!("unexpected elements {:?}", other),
+        }
+    }
+
+    #[test]
+    fn migrates_legacy_properties() {
+        let mut rectangle = created(
+            "rectangle",
+            json!({ "strokeSharpness": "round", "boundElementIds": ["arrow"], "width": -50 }),
+        );
+        let object = rectangle.as_object_mut().unwrap();
+        for key in [
+            "roundness",
+            "boundElements",
+            "frameId",
+            "index",
+            "link",
+            "locked",
+        ] {
+            object.remove(key);
+        }
+        // only the CSS shorthand, from before fontSize and fontFamily
+        let mut text = created(
+            "text",
+            json!({ "id": "legacy", "font": "20px Virgil", "text": "one\ntwo", "height": 60 }),
+        );
+        let object = text.as_object_mut().unwrap();
+        for key in ["fontSize", "fontFamily", "lineHeight", "originalText"] {
+            object.remove(key);
+        }
+        let restored = restore_elements(&[rectangle, text], None, false).unwrap();
+        let base = restored[0].base();
+        assert_eq!(
+            base.roundness.as_ref().map(|r| r.kind),
+            Some(ROUNDNESS_LEGACY)
+        );
+        assert_eq!(base.bound_elements.as_ref().unwrap()[0].id, "arrow");
+        assert_eq!((base.x, base.width), (-50.0, 50.0));
+        assert_eq!(base.index.as_deref(), Some("a0"));
+        assert!(!base.extra.contains_key("strokeSharpness"));
+        match &restored[1] {
+            Element::Text(text) => {
+                assert_eq!((text.font_size, text.font_family), (20.0, 1));
+                assert_eq!(text.line_height, 1.5);
+            }
+            other => panic!("unexpected element {:?}", other),
+        }
+    }
+
+    #[test]
+    fn repairs_bindings() {
+        let container = created(
+            "rectangle",
+            json!({
+                "id": "box",
+                "frameId": "missing-frame",
+                "boundElements": [
+                    { "type": "text", "id": "label" },
+                    { "type": "text", "id": "label" },
+                    { "type": "arrow", "id": "missing" },
+                ],
+            }),
+        );
+        let label = created("text", json!({ "id": "label" }));
+        let arrow = created(
+            "arrow",
+            json!({
+                "id": "arrow",
+                "startBinding": { "elementId": "box", "focus": null, "gap": 1 },
+                "endBinding": { "elementId": "missing", "focus": 0, "gap": 1 },
+            }),
+        );
+        let restored = restore_elements(&[container, label, arrow], None, true).unwrap();
+
+        assert_eq!(restored[0].base().frame_id, None);
+        assert_eq!(
+            restored[0].base().bound_elements.as_deref(),
+            Some(
+                &[BoundElement {
+                    id: "label".into(),
+                    kind: BoundElementType::Text
+                }][..]
+            )
+        );
+        match &restored[1] {
+            Element::Text(text) => assert_eq!(text.container_id.as_deref(), Some("box")),
+            other => panic!("unexpected element {:?}", other),
+        }
+        match &restored[2] {
+            Element::Arrow(arrow) => {
+                assert_eq!(arrow.linear.start_binding.as_ref().unwrap().focus, 0.0);
+                assert!(arrow.linear.end_binding.is_none());
+            }
+            other => panic!("unexpected element {:?}", other),
+        }
+    }
+
+    #[test]
+    fn restores_app_state() {
+        let app_state = restore_app_state(&json!({
+            "gridSize": 1000,
+            "zoom": 2,
+            "isSidebarDocked": true,
+            "name": "kept",
+        }));
+        assert_eq!(app_state.grid_size, Some(100.0));
+        assert_eq!(app_state.grid_step, Some(DEFAULT_GRID_STEP));
+        assert_eq!(app_state.view_background_color.as_deref(), Some("#ffffff"));
+        assert_eq!(app_state.extra["zoom"], json!({ "value": 2.0 }));
+        assert_eq!(app_state.extra["defaultSidebarDockedPreference"], true);
+        assert_eq!(app_state.extra["name"], "kept");
+    }
+
+    #[test]
+    fn rejects_non_scenes() {
+        assert!(restore_json(r#"{"type":"excalidrawlib","libraryItems":[]}"#).is_err());
+        let scene = restore_json(r#"{"type":"excalidraw","elements":[]}"#).unwrap();
+        assert!(Scene::parse(&scene).unwrap().elements.is_empty());
+    }
+
+    #[test]
+    fn neutralizes_script_links() {
+        assert_eq!(normalize_link(" javascript:alert(1) "), "about:blank");
+        assert_eq!(normalize_link("https://example.com"), "https://example.com");
+    }
+}
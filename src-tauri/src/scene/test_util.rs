@@ -0,0 +1,33 @@
+//! Elements and scratch directories for tests across the crate.
+
+use std::{fs, path::PathBuf};
+
+use serde_json::{json, Value};
+
+use super::Element;
+
+/// A plain rectangle as the editor saves it.
+pub fn rectangle_json(id: &str, version: i64, version_nonce: i64, index: Option<&str>) -> Value {
+    json!({
+        "type": "rectangle", "id": id, "x": 0, "y": 0, "width": 10, "height": 10,
+        "angle": 0, "strokeColor": "#1e1e1e", "backgroundColor": "transparent",
+        "fillStyle": "solid", "strokeWidth": 2, "strokeStyle": "solid", "roughness": 1,
+        "opacity": 100, "groupIds": [], "frameId": null, "roundness": null, "seed": 1,
+        "version": version, "versionNonce": version_nonce, "index": index,
+        "isDeleted": false, "boundElements": null, "updated": 1, "link": null,
+        "locked": false,
+    })
+}
+
+pub fn rectangle(id: &str, version: i64, version_nonce: i64, index: Option<&str>) -> Element {
+    serde_json::from_value(rectangle_json(id, version, version_nonce, index)).unwrap()
+}
+
+/// An empty directory of its own for the test `name`, emptied if an earlier
+/// run left it behind.
+pub fn temp_dir(name: &str) -> PathBuf {
+    let dir = std::env::temp_dir().join(format!("excalidraw-{}-{}", name, std::process::id()));
+    let _ = fs::remove_dir_all(&dir);
+    fs::create_dir_all(&dir).unwrap();
+    dir
+}
@@ -0,0 +1,413 @@
+//! Full-text search over the drawings on disk: the text of text elements,
+//! frame names and element links of every scene in the workspace and the
+//! recent files. An inverted index from words to the elements holding them
+//! is kept in the app data dir, and scenes are only read again once they
+//! change.
+
+use std::{
+    collections::{BTreeMap, BTreeSet},
+    fs,
+    ops::Bound,
+    path::{Path, PathBuf},
+    sync::Mutex,
+};
+
+use serde::{Deserialize, Serialize};
+use tauri::{AppHandle, Runtime, State};
+
+use crate::{
+    error::{Error, Result},
+    files,
+    scene::{restore, Element, Scene},
+    settings::SettingsState,
+    workspace::{self, Workspace},
+};
+
+pub const INDEX_FILE: &str = "search-index.json";
+/// Enough to pick from; a query matching more is too vague anyway.
+const MAX_HITS: usize = 200;
+
+/// Where in the element the text was found.
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub enum Field {
+    Text,
+    Frame,
+    Link,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct IndexedText {
+    element_id: String,
+    field: Field,
+    text: String,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct IndexedFile {
+    /// Epoch milliseconds, to tell when the scene needs indexing again.
+    modified: i64,
+    texts: Vec<IndexedText>,
+}
+
+/// An element whose text matched, to jump and zoom to.
+#[derive(Debug, Clone, PartialEq, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct SearchHit {
+    pub path: PathBuf,
+    pub element_id: String,
+    pub field: Field,
+    pub text: String,
+}
+
+#[derive(Debug, Default, Serialize, Deserialize)]
+pub struct SearchIndex {
+    files: BTreeMap<PathBuf, IndexedFile>,
+    /// Words to the scenes they appear in, to the positions in
+    /// [`IndexedFile::texts`] holding them.
+    terms: BTreeMap<String, BTreeMap<PathBuf, BTreeSet<usize>>>,
+}
+
+/// Lowercased words, split on anything but letters and digits.
+fn tokenize(text: &str) -> impl Iterator<Item = String> + '_ {
+    text.split(|c: char| !c.is_alphanumeric())
+        .filter(|word| !word.is_empty())
+        .map(str::to_lowercase)
+}
+
+/// The searchable text of the elements in `scene`.
+fn scene_texts(scene: &Scene) -> Vec<IndexedText> {
+    let mut texts = Vec::new();
+    for element in &scene.elements {
+        let base = element.base();
+        if base.is_deleted {
+            continue;
+        }
+        let mut push = |field, text: &str| {
+            if !text.trim().is_empty() {
+                texts.push(IndexedText {
+                    element_id: base.id.clone(),
+                    field,
+                    text: text.to_string(),
+                });
+            }
+        };
+        match element {
+            // unwrapped, so words aren't split at line breaks
+            Element::Text(text) if !text.original_text.is_empty() => {
+                push(Field::Text, &text.original_text)
+            }
+            Element::Text(text) => push(Field::Text, &text.text),
+            Element::Frame(frame) | Element::Magicframe(frame) => {
+                push(Field::Frame, frame.name.as_deref().unwrap_or_default())
+            }
+            _ => {}
+        }
+        if let Some(link) = &base.link {
+            push(Field::Link, link);
+        }
+    }
+    texts
+}
+
+impl SearchIndex {
+    fn insert(&mut self, path: &Path, file: IndexedFile) {
+        for (position, text) in file.texts.iter().enumerate() {
+            for term in tokenize(&text.text) {
+                self.terms
+                    .entry(term)
+                    .or_default()
+                    .entry(path.to_path_buf())
+                    .or_default()
+                    .insert(position);
+            }
+        }
+        self.files.insert(path.to_path_buf(), file);
+    }
+
+    fn remove(&mut self, path: &Path) -> bool {
+        if self.files.remove(path).is_none() {
+            return false;
+        }
+        self.terms.retain(|_, postings| {
+            postings.remove(path);
+            !postings.is_empty()
+        });
+        true
+    }
+
+    /// Indexes `paths`, reading only scenes changed since they were last
+    /// indexed, and drops any other scene. Returns whether anything changed.
+    ///
+    /// Paths that aren't valid UTF-8 are skipped: neither the index on disk
+    /// nor the hits sent to the webview, both JSON, could hold them.
+    pub fn sync(&mut self, paths: &BTreeSet<PathBuf>) -> bool {
+        let stale: Vec<PathBuf> = self
+            .files
+            .keys()
+            .filter(|path| !paths.contains(*path))
+            .cloned()
+            .collect();
+        let mut changed = !stale.is_empty();
+        for path in stale {
+            self.remove(&path);
+        }
+
+        for path in paths {
+            if path.to_str().is_none() {
+                log::debug!("not indexing {}: not UTF-8", path.display());
+                continue;
+            }
+            let modified = match workspace::modified_millis(path) {
+                Ok(modified) => modified,
+                Err(_) => {
+                    changed |= self.remove(path);
+                    continue;
+                }
+            };
+            if self
+                .files
+                .get(path)
+                .map_or(false, |file| file.modified == modified)
+            {
+                continue;
+            }
+            self.remove(path);
+            // a broken scene is kept without text, so it isn't read again
+            // until it changes
+            let texts = match files::read_scene_json(path)
+                .and_then(|json| restore::restore_json(&json))
+                .and_then(|json| Scene::parse(&json))
+            {
+                Ok(scene) => scene_texts(&scene),
+                Err(err) => {
+                    log::warn!("not indexing {}: {}", path.display(), err);
+                    Vec::new()
+                }
+            };
+            self.insert(path, IndexedFile { modified, texts });
+            changed = true;
+        }
+        changed
+    }
+
+    /// Elements with text matching every word of `query`, the last word
+    /// possibly cut short as it's being typed. Whole-word matches rank
+    /// first.
+    pub fn query(&self, query: &str) -> Vec<SearchHit> {
+        let words: BTreeSet<String> = tokenize(query).collect();
+        // (path, position) to the words matched in full
+        let mut matches: Option<BTreeMap<(&Path, usize), usize>> = None;
+        for word in &words {
+            let mut found: BTreeMap<(&Path, usize), usize> = BTreeMap::new();
+            let terms = self
+                .terms
+                .range::<str, _>((Bound::Included(word.as_str()), Bound::Unbounded))
+                .take_while(|(term, _)| term.starts_with(word.as_str()));
+            for (term, postings) in terms {
+                for (path, positions) in postings {
+                    for position in positions {
+                        let exact = found.entry((path.as_path(), *position)).or_default();
+                        *exact = (*exact).max(usize::from(term == word));
+                    }
+                }
+            }
+            matches = Some(match matches {
+                None => found,
+                Some(matches) => matches
+                    .into_iter()
+                    .filter_map(|(key, exact)| found.get(&key).map(|found| (key, exact + found)))
+                    .collect(),
+            });
+        }
+
+        let mut matches: Vec<_> = matches.unwrap_or_default().into_iter().collect();
+        matches.sort_by(|(a, a_exact), (b, b_exact)| b_exact.cmp(a_exact).then(a.cmp(b)));
+        matches
+            .into_iter()
+            .take(MAX_HITS)
+            .map(|((path, position), _)| {
+                let text = &self.files[path].texts[position];
+                SearchHit {
+                    path: path.to_path_buf(),
+                    element_id: text.element_id.clone(),
+                    field: text.field,
+                    text: text.text.clone(),
+                }
+            })
+            .collect()
+    }
+}
+
+/// The search index, kept in sync with the scenes on disk on every search.
+pub struct Search {
+    path: PathBuf,
+    index: Mutex<SearchIndex>,
+}
+
+impl Search {
+    /// Loads the index at `path`, starting over when it's missing or
+    /// unreadable.
+    pub fn load(path: PathBuf) -> Self {
+        let index = fs::read(&path)
+            .map_err(Error::from)
+            .and_then(|json| Ok(serde_json::from_slice(&json)?))
+            .unwrap_or_else(|err| {
+                if path.exists() {
+                    log::warn!("rebuilding the search index: {}", err);
+                }
+                SearchIndex::default()
+            });
+        Self {
+            path,
+            index: Mutex::new(index),
+        }
+    }
+
+    /// Searches `paths`, indexing those that changed first.
+    pub fn search(&self, paths: &BTreeSet<PathBuf>, query: &str) -> Result<Vec<SearchHit>> {
+        let mut index = self.index.lock().unwrap();
+        if index.sync(paths) {
+            if let Some(dir) = self.path.parent() {
+                fs::create_dir_all(dir)?;
+            }
+            files::write_atomic(&self.path, &serde_json::to_vec(&*index)?)?;
+        }
+        Ok(index.query(query))
+    }
+}
+
+pub fn index_path<R: Runtime>(app: &AppHandle<R>) -> Result<PathBuf> {
+    app.path_resolver()
+        .app_data_dir()
+        .map(|dir| dir.join(INDEX_FILE))
+        .ok_or_else(|| Error::Other("could not resolve the app data dir".into()))
+}
+
+/// Searches the text in the scenes of the workspace and the recent files.
+#[tauri::command]
+pub async fn search(
+    search: State<'_, Search>,
+    workspace: State<'_, Workspace>,
+    settings: State<'_, SettingsState>,
+    query: String,
+) -> Result<Vec<SearchHit>> {
+    let mut paths: BTreeSet<PathBuf> = workspace.paths().into_iter().collect();
+    paths.extend(settings.0.lock().unwrap().recent_files.iter().cloned());
+    search.search(&paths, &query)
+}
+
+#[cfg(test)]
+mod tests {
+    use serde_json::json;
+
+    use super::*;
+    use crate::scene::test_util::temp_dir;
+
+    fn write_scene(path: &Path, elements: serde_json::Value) {
+        let scene = json!({ "type": "excalidraw", "version": 2, "elements": elements });
+        fs::write(path, scene.to_string()).unwrap();
+    }
+
+    fn hits(index: &SearchIndex, query: &str) -> Vec<(String, Field)> {
+        index
+            .query(query)
+            .into_iter()
+            .map(|hit| (hit.element_id, hit.field))
+            .collect()
+    }
+
+    #[test]
+    fn indexes_text_frames_and_links() {
+        let dir = temp_dir("search");
+        let first = dir.join("first.excalidraw");
+        let second = dir.join("second.excalidraw");
+        write_scene(
+            &first,
+            json!([
+                { "type": "text", "id": "title", "x": 0, "y": 0, "width": 10, "height": 10,
+                  "fontSize": 20, "fontFamily": 1,
+                  "text": "Payment\nservice", "originalText": "Payment service" },
+                { "type": "frame", "id": "frame", "name": "Checkout flow",
+                  "x": 0, "y": 0, "width": 100, "height": 100 },
+                { "type": "rectangle", "id": "box", "link": "https://example.com/payments",
+                  "x": 0, "y": 0, "width": 10, "height": 10 },
+                { "type": "text", "id": "gone", "isDeleted": true, "text": "payment",
+                  "x": 0, "y": 0, "width": 10, "height": 10, "fontSize": 20, "fontFamily": 1 },
+            ]),
+        );
+        write_scene(
+            &second,
+            json!([
+                { "type": "text", "id": "note", "x": 0, "y": 0, "width": 10, "height": 10,
+                  "fontSize": 20, "fontFamily": 1,
+                  "text": "Retry the payment", "originalText": "Retry the payment" },
+            ]),
+        );
+        let mut paths: BTreeSet<PathBuf> = [first.clone(), second.clone()].into_iter().collect();
+
+        let mut index = SearchIndex::default();
+        assert!(index.sync(&paths));
+        assert!(!index.sync(&paths));
+        // whole words first, then prefixes
+        assert_eq!(
+            hits(&index, "PAYMENT"),
+            [
+                ("title".to_string(), Field::Text),
+                ("note".to_string(), Field::Text),
+                ("box".to_string(), Field::Link),
+            ]
+        );
+        assert_eq!(
+            hits(&index, "checkout fl"),
+            [("frame".to_string(), Field::Frame)]
+        );
+        assert_eq!(
+            hits(&index, "payment retry"),
+            [("note".to_string(), Field::Text)]
+        );
+        assert!(hits(&index, "refund").is_empty());
+        assert!(hits(&index, "").is_empty());
+        let hit = &index.query("service")[0];
+        assert_eq!(
+            (hit.path.as_path(), hit.text.as_str()),
+            (first.as_path(), "Payment service")
+        );
+
+        // survives a round trip through the app data dir
+        let mut index: SearchIndex =
+            serde_json::from_slice(&serde_json::to_vec(&index).unwrap()).unwrap();
+        assert!(!index.sync(&paths));
+        paths.remove(&second);
+        assert!(index.sync(&paths));
+        assert!(hits(&index, "retry").is_empty());
+        assert!(!index.terms.contains_key("retry"));
+
+        fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn skips_paths_json_cant_hold() {
+        use std::{ffi::OsStr, os::unix::ffi::OsStrExt};
+
+        let dir = temp_dir("search-utf8");
+        let path = dir.join(OsStr::from_bytes(b"caf\xe9.excalidraw"));
+        write_scene(
+            &path,
+            json!([
+                { "type": "text", "id": "note", "x": 0, "y": 0, "width": 10, "height": 10,
+                  "fontSize": 20, "fontFamily": 1, "text": "menu", "originalText": "menu" },
+            ]),
+        );
+
+        let mut index = SearchIndex::default();
+        assert!(!index.sync(&[path].into_iter().collect()));
+        assert!(hits(&index, "menu").is_empty());
+        serde_json::to_vec(&index).unwrap();
+
+        fs::remove_dir_all(dir).unwrap();
+    }
+}
@@ -0,0 +1,72 @@
+use std::{fs, path::PathBuf, sync::Mutex};
+
+use log::LevelFilter;
+use serde::{Deserialize, Serialize};
+use tauri::{AppHandle, Runtime};
+
+use crate::error::{Error, Result};
+
+const SETTINGS_FILE: &str = "settings.json";
+
+/// User preferences persisted as JSON in the app config dir.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default, rename_all = "camelCase")]
+pub struct Settings {
+    /// Accelerator that toggles the overlay between drawing and pass-through.
+    pub toggle_shortcut: String,
+    /// Whether focus changes move the overlay between drawing and
+    /// pass-through, on top of the explicit commands.
+    pub focus_driven: bool,
+    /// Most verbose level written to the log file.
+    pub log_level: LevelFilter,
+    /// Most recently opened scene files, newest first.
+    pub recent_files: Vec<PathBuf>,
+    /// Folder of `.excalidrawlib` files the library is kept in, when not
+    /// the one in the app data dir.
+    pub library_dir: Option<PathBuf>,
+    /// Folder open as the workspace, reopened on launch.
+    pub workspace_dir: Option<PathBuf>,
+}
+
+impl Default for Settings {
+    fn default() -> Self {
+        Self {
+            toggle_shortcut: "CmdOrCtrl+Shift+D".into(),
+            focus_driven: false,
+            log_level: if cfg!(debug_assertions) {
+                LevelFilter::Debug
+            } else {
+                LevelFilter::Info
+            },
+            recent_files: Vec::new(),
+            library_dir: None,
+            workspace_dir: None,
+        }
+    }
+}
+
+pub struct SettingsState(pub Mutex<Settings>);
+
+fn settings_path<R: Runtime>(app: &AppHandle<R>) -> Result<PathBuf> {
+    app.path_resolver()
+        .app_config_dir()
+        .map(|dir| dir.join(SETTINGS_FILE))
+        .ok_or_else(|| Error::Other("could not resolve the app config dir".into()))
+}
+
+/// Loads the persisted settings, falling back to defaults when the file is
+/// missing or unreadable.
+pub fn load<R: Runtime>(app: &AppHandle<R>) -> Settings {
+    settings_path(app)
+        .and_then(|path| Ok(serde_json::from_slice(&fs::read(path)?)?))
+        .unwrap_or_default()
+}
+
+pub fn save<R: Runtime>(app: &AppHandle<R>, settings: &Settings) -> Result<()> {
+    let path = settings_path(app)?;
+    if let Some(dir) = path.parent() {
+        fs::create_dir_all(dir)?;
+    }
+    fs::write(path, serde_json::to_vec_pretty(settings)?)?;
+    Ok(())
+}
@@ -0,0 +1,124 @@
+//! Share-link payloads and encrypted backups, encoded natively so large
+//! scenes don't have to round-trip through the webview's crypto APIs, and
+//! the backend that stores share links on the local network.
+
+mod server;
+
+use std::{
+    net::{IpAddr, Ipv4Addr, SocketAddr},
+    path::PathBuf,
+    sync::Mutex,
+};
+
+use serde::Serialize;
+use serde_json::Value;
+use tauri::{AppHandle, Runtime, State};
+
+use crate::{
+    collab,
+    error::{Error, Result},
+    scene::encode::{self, Decompressed},
+};
+pub use server::ShareServer;
+
+/// The port the share server listens on unless told otherwise, next to the
+/// collab relay's.
+pub const DEFAULT_PORT: u16 = 3003;
+
+const SHARE_DIR: &str = "share-links";
+
+/// A new key for [`compress_data`], in the format share links carry.
+#[tauri::command]
+pub fn generate_encryption_key() -> String {
+    encode::generate_encryption_key()
+}
+
+/// `compressData`: compresses and encrypts `data`, storing `metadata` (or
+/// `null`) alongside it.
+#[tauri::command]
+pub fn compress_data(
+    data: Vec<u8>,
+    metadata: Option<Value>,
+    encryption_key: String,
+) -> Result<Vec<u8>> {
+    encode::compress_data(&data, &metadata.unwrap_or_default(), &encryption_key)
+}
+
+/// `decompressData`: fails if the key is wrong or the payload corrupted.
+#[tauri::command]
+pub fn decompress_data(payload: Vec<u8>, decryption_key: String) -> Result<Decompressed> {
+    encode::decompress_data(&payload, &decryption_key)
+}
+
+/// The share server this app hosts, if any.
+#[derive(Default)]
+pub struct ShareHost(Mutex<Option<ShareServer>>);
+
+impl ShareHost {
+    pub fn stop(&self) {
+        if let Some(server) = self.0.lock().unwrap().take() {
+            server.stop();
+        }
+    }
+}
+
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ShareServerInfo {
+    pub port: u16,
+    /// `BACKEND_V2_GET_URL` for clients of this server, on the LAN address
+    /// when there is one.
+    pub get_url: String,
+    /// `BACKEND_V2_POST_URL`
+    pub post_url: String,
+}
+
+impl ShareServerInfo {
+    pub fn new(server: &ShareServer) -> Self {
+        let port = server.local_addr().port();
+        let ip = collab::lan_address().unwrap_or(IpAddr::V4(Ipv4Addr::LOCALHOST));
+        let base = format!("http://{}", SocketAddr::new(ip, port));
+        Self {
+            port,
+            get_url: format!("{}{}", base, server::GET_PATH),
+            post_url: format!("{}{}", base, server::POST_PATH),
+        }
+    }
+}
+
+fn share_dir<R: Runtime>(app: &AppHandle<R>) -> Result<PathBuf> {
+    app.path_resolver()
+        .app_data_dir()
+        .map(|dir| dir.join(SHARE_DIR))
+        .ok_or_else(|| Error::Other("could not resolve the app data dir".into()))
+}
+
+/// Starts serving share links from the app data dir on all interfaces, on
+/// `port` or [`DEFAULT_PORT`].
+#[tauri::command]
+pub fn start_share_server<R: Runtime>(
+    app: AppHandle<R>,
+    host: State<ShareHost>,
+    port: Option<u16>,
+) -> Result<ShareServerInfo> {
+    let mut server = host.0.lock().unwrap();
+    if server.is_some() {
+        return Err(Error::Other("already serving share links".into()));
+    }
+    let addr = SocketAddr::from((Ipv4Addr::UNSPECIFIED, port.unwrap_or(DEFAULT_PORT)));
+    let started = ShareServer::start(addr, share_dir(&app)?)?;
+    log::info!("serving share links on {}", started.local_addr());
+    let info = ShareServerInfo::new(&started);
+    *server = Some(started);
+    Ok(info)
+}
+
+#[tauri::command]
+pub fn stop_share_server(host: State<ShareHost>) {
+    host.stop();
+}
+
+#[tauri::command]
+pub fn get_share_server(host: State<ShareHost>) -> Option<ShareServerInfo> {
+    host.0.lock().unwrap().as_ref().map(ShareServerInfo::new)
+}
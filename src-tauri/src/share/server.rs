@@ -0,0 +1,308 @@
+//! A self-hosted stand-in for the json backend `exportToBackend` and
+//! `importFromBackend` talk to, so `#json=<id>,<key>` links work without
+//! reaching json.excalidraw.com. Point `VITE_APP_BACKEND_V2_GET_URL` at
+//! `http://<host>:<port>/api/v2/` and `VITE_APP_BACKEND_V2_POST_URL` at
+//! `http://<host>:<port>/api/v2/post/`.
+//!
+//! Payloads are encrypted before upload and the key stays in the link's
+//! hash, so the server only ever stores opaque blobs, one file per id.
+
+use std::{
+    fs::{self, OpenOptions},
+    io::{self, ErrorKind, Read, Write},
+    net::SocketAddr,
+    path::{Path, PathBuf},
+    sync::Arc,
+    thread::{self, JoinHandle},
+};
+
+use rand::Rng;
+use serde_json::json;
+use tiny_http::{Header, Method, Request, Response, Server};
+
+use crate::error::{Error, Result};
+
+/// The path `BACKEND_V2_GET` ends in; scenes are fetched from `<it><id>`.
+pub const GET_PATH: &str = "/api/v2/";
+/// The path `BACKEND_V2_POST` ends in.
+pub const POST_PATH: &str = "/api/v2/post/";
+/// Large scenes with embedded images still fit, anything bigger gets the
+/// `RequestTooLargeError` the hosted backend answers with.
+const MAX_PAYLOAD_BYTES: usize = 20 * 1024 * 1024;
+/// Requests answered at once; more wait in the queue, so a burst of
+/// uploads can't exhaust threads or memory.
+const WORKERS: usize = 4;
+
+pub struct ShareServer {
+    addr: SocketAddr,
+    server: Arc<Server>,
+    workers: Vec<JoinHandle<()>>,
+}
+
+impl ShareServer {
+    /// Serves the payloads in `dir` on `addr`, creating the dir if needed.
+    pub fn start(addr: SocketAddr, dir: PathBuf) -> Result<Self> {
+        fs::create_dir_all(&dir)?;
+        let server = Server::http(addr).map_err(|err| {
+            Error::Other(format!(
+                "failed to start the share server on {}: {}",
+                addr, err
+            ))
+        })?;
+        let addr = server.server_addr().to_ip().unwrap_or(addr);
+        let server = Arc::new(server);
+
+        let workers = (0..WORKERS)
+            .map(|_| {
+                let server = Arc::clone(&server);
+                let dir = dir.clone();
+                thread::spawn(move || {
+                    for request in server.incoming_requests() {
+                        let description = format!("{} {}", request.method(), request.url());
+                        if let Err(err) = handle(request, &dir) {
+                            log::debug!("share request {} failed: {}", description, err);
+                        }
+                    }
+                })
+            })
+            .collect();
+
+        Ok(Self {
+            addr,
+            server,
+            workers,
+        })
+    }
+
+    pub fn local_addr(&self) -> SocketAddr {
+        self.addr
+    }
+
+    /// Blocks until the server stops, for the headless `share-server`
+    /// subcommand.
+    pub fn wait(self) {
+        for worker in self.workers {
+            let _ = worker.join();
+        }
+    }
+
+    /// Stops accepting requests. Ones already being answered finish.
+    pub fn stop(self) {
+        // each unblock releases one waiting worker
+        for _ in &self.workers {
+            self.server.unblock();
+        }
+        self.wait();
+    }
+}
+
+fn handle(mut request: Request, dir: &Path) -> io::Result<()> {
+    let url = request
+        .url()
+        .split('?')
+        .next()
+        .unwrap_or_default()
+        .to_string();
+    let response = match (request.method(), url.as_str()) {
+        // preflight of a page posting from another origin
+        (Method::Options, _) => Response::from_data(Vec::new())
+            .with_status_code(204)
+            .with_header(header("Access-Control-Allow-Methods", "GET, POST, OPTIONS"))
+            .with_header(header("Access-Control-Allow-Headers", "Content-Type")),
+        (Method::Post, POST_PATH) => match read_payload(&mut request)? {
+            Some(payload) => match store(dir, &payload) {
+                Ok(id) => json_response(200, &json!({ "id": id })),
+                Err(err) => {
+                    log::error!("failed to store shared scene: {}", err);
+                    json_response(500, &json!({ "error_class": "StorageError" }))
+                }
+            },
+            None => json_response(413, &json!({ "error_class": "RequestTooLargeError" })),
+        },
+        (Method::Get, path) if path.starts_with(GET_PATH) => {
+            match load(dir, &path[GET_PATH.len()..]) {
+                Some(payload) => Response::from_data(payload)
+                    .with_header(header("Content-Type", "application/octet-stream")),
+                None => Response::from_string("not found").with_status_code(404),
+            }
+        }
+        _ => Response::from_string("not found").with_status_code(404),
+    };
+    request.respond(response.with_header(header("Access-Control-Allow-Origin", "*")))
+}
+
+/// The request body, `None` if it's over [`MAX_PAYLOAD_BYTES`].
+fn read_payload(request: &mut Request) -> io::Result<Option<Vec<u8>>> {
+    if request
+        .body_length()
+        .map_or(false, |length| length > MAX_PAYLOAD_BYTES)
+    {
+        return Ok(None);
+    }
+    let mut payload = Vec::new();
+    // chunked bodies don't announce their length
+    request
+        .as_reader()
+        .take(MAX_PAYLOAD_BYTES as u64 + 1)
+        .read_to_end(&mut payload)?;
+    Ok((payload.len() <= MAX_PAYLOAD_BYTES).then(|| payload))
+}
+
+/// Writes `payload` under a new numeric id, like the ones the hosted
+/// backend hands out. The file is created exclusively, so two uploads can't
+/// claim the same id; nobody knows the id before it's returned, so it can't
+/// be read half written.
+fn store(dir: &Path, payload: &[u8]) -> Result<String> {
+    loop {
+        let id = rand::thread_rng()
+            .gen_range(1_000_000_000_000_000u64..10_000_000_000_000_000)
+            .to_string();
+        let path = dir.join(&id);
+        let mut file = match OpenOptions::new().write(true).create_new(true).open(&path) {
+            Ok(file) => file,
+            Err(err) if err.kind() == ErrorKind::AlreadyExists => continue,
+            Err(err) => return Err(err.into()),
+        };
+        if let Err(err) = file.write_all(payload).and_then(|_| file.sync_all()) {
+            let _ = fs::remove_file(&path);
+            return Err(err.into());
+        }
+        return Ok(id);
+    }
+}
+
+fn load(dir: &Path, id: &str) -> Option<Vec<u8>> {
+    if id.is_empty() || !id.chars().all(|c| c.is_ascii_digit()) {
+        return None;
+    }
+    match fs::read(dir.join(id)) {
+        Ok(payload) => Some(payload),
+        Err(err) if err.kind() == ErrorKind::NotFound => None,
+        Err(err) => {
+            log::error!("failed to read shared scene {}: {}", id, err);
+            None
+        }
+    }
+}
+
+fn json_response(status: u16, body: &serde_json::Value) -> Response<io::Cursor<Vec<u8>>> {
+    Response::from_data(body.to_string())
+        .with_status_code(status)
+        .with_header(header("Content-Type", "application/json"))
+}
+
+fn header(name: &str, value: &str) -> Header {
+    Header::from_bytes(name, value).expect("static header is valid")
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{
+        io::{BufRead, BufReader, Write},
+        net::{Ipv4Addr, TcpStream},
+    };
+
+    use super::*;
+    use crate::scene::test_util::temp_dir;
+
+    /// A bare HTTP/1.1 client, returning the status and body.
+    fn send(addr: SocketAddr, method: &str, path: &str, body: &[u8]) -> (u16, Vec<u8>) {
+        let mut stream = TcpStream::connect(addr).unwrap();
+        write!(
+            stream,
+            "{} {} HTTP/1.1\r\nHost: localhost\r\nContent-Length: {}\r\nConnection: close\r\n\r\n",
+            method,
+            path,
+            body.len()
+        )
+        .unwrap();
+        stream.write_all(body).unwrap();
+
+        let mut reader = BufReader::new(stream);
+        let mut status = String::new();
+        reader.read_line(&mut status).unwrap();
+        let status = status.split(' ').nth(1).unwrap().parse().unwrap();
+        let mut line = String::new();
+        while reader.read_line(&mut line).unwrap() > 2 {
+            line.clear();
+        }
+        let mut body = Vec::new();
+        reader.read_to_end(&mut body).unwrap();
+        (status, body)
+    }
+
+    #[test]
+    fn stores_and_serves_payloads() {
+        let dir = temp_dir("share");
+        let server =
+            ShareServer::start(SocketAddr::from((Ipv4Addr::LOCALHOST, 0)), dir.clone()).unwrap();
+        let addr = server.local_addr();
+
+        let payload = [0u8, 1, 2, 255];
+        let (status, body) = send(addr, "POST", POST_PATH, &payload);
+        assert_eq!(status, 200);
+        let body: serde_json::Value = serde_json::from_slice(&body).unwrap();
+        let id = body["id"].as_str().unwrap();
+        assert!(id.chars().all(|c| c.is_ascii_digit()));
+
+        let (status, body) = send(addr, "GET", &format!("{}{}", GET_PATH, id), &[]);
+        assert_eq!((status, body), (200, payload.to_vec()));
+        assert_eq!(send(addr, "GET", "/api/v2/123", &[]).0, 404);
+        assert_eq!(send(addr, "GET", "/api/v2/..%2Fsecret", &[]).0, 404);
+        assert_eq!(send(addr, "OPTIONS", POST_PATH, &[]).0, 204);
+
+        server.stop();
+        fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn answers_concurrent_uploads() {
+        let dir = temp_dir("share-concurrent");
+        let server =
+            ShareServer::start(SocketAddr::from((Ipv4Addr::LOCALHOST, 0)), dir.clone()).unwrap();
+        let addr = server.local_addr();
+
+        let uploads: Vec<_> = (0..WORKERS as u8 * 4)
+            .map(|i| {
+                thread::spawn(move || {
+                    let (status, body) = send(addr, "POST", POST_PATH, &[i]);
+                    assert_eq!(status, 200);
+                    let body: serde_json::Value = serde_json::from_slice(&body).unwrap();
+                    (body["id"].as_str().unwrap().to_string(), i)
+                })
+            })
+            .collect();
+        for upload in uploads {
+            let (id, payload) = upload.join().unwrap();
+            let (status, body) = send(addr, "GET", &format!("{}{}", GET_PATH, id), &[]);
+            assert_eq!((status, body), (200, vec![payload]));
+        }
+
+        server.stop();
+        fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn rejects_oversized_payloads() {
+        let dir = temp_dir("share-big");
+        let server =
+            ShareServer::start(SocketAddr::from((Ipv4Addr::LOCALHOST, 0)), dir.clone()).unwrap();
+        let mut stream = TcpStream::connect(server.local_addr()).unwrap();
+        write!(
+            stream,
+            "POST {} HTTP/1.1\r\nHost: localhost\r\nContent-Length: {}\r\nConnection: close\r\n\r\n",
+            POST_PATH,
+            MAX_PAYLOAD_BYTES + 1
+        )
+        .unwrap();
+        let mut response = String::new();
+        BufReader::new(stream)
+            .read_to_string(&mut response)
+            .unwrap();
+        assert!(response.starts_with("HTTP/1.1 413"));
+        assert!(response.ends_with(r#"{"error_class":"RequestTooLargeError"}"#));
+
+        server.stop();
+        fs::remove_dir_all(dir).unwrap();
+    }
+}
@@ -0,0 +1,75 @@
+use tauri::{AppHandle, GlobalShortcutManager, Runtime, State};
+
+use crate::{
+    error::Result,
+    overlay::{self, OverlayCommand},
+    settings::{self, SettingsState},
+};
+
+/// Registers the system-wide accelerator that toggles draw mode. It has to be
+/// global: a window in pass-through never receives the input that would
+/// otherwise bring it back.
+pub fn register<R: Runtime>(app: &AppHandle<R>, accelerator: &str) -> Result<()> {
+    let handle = app.clone();
+    app.global_shortcut_manager()
+        .register(accelerator, move || {
+            let _ = overlay::dispatch(&handle, OverlayCommand::ToggleDrawing);
+        })
+        .map_err(tauri::Error::from)?;
+    Ok(())
+}
+
+fn unregister<R: Runtime>(app: &AppHandle<R>, accelerator: &str) -> Result<()> {
+    let mut manager = app.global_shortcut_manager();
+    if manager
+        .is_registered(accelerator)
+        .map_err(tauri::Error::from)?
+    {
+        manager
+            .unregister(accelerator)
+            .map_err(tauri::Error::from)?;
+    }
+    Ok(())
+}
+
+#[tauri::command]
+pub fn get_toggle_shortcut(settings: State<SettingsState>) -> String {
+    settings.0.lock().unwrap().toggle_shortcut.clone()
+}
+
+/// Rebinds the toggle shortcut and persists it. The new accelerator is
+/// registered before the old one is released, so a rejected binding leaves
+/// the current one working, and any later failure switches back to it.
+#[tauri::command]
+pub fn set_toggle_shortcut<R: Runtime>(
+    app: AppHandle<R>,
+    settings: State<SettingsState>,
+    accelerator: String,
+) -> Result<()> {
+    let mut settings = settings.0.lock().unwrap();
+    if settings.toggle_shortcut == accelerator {
+        return Ok(());
+    }
+
+    register(&app, &accelerator)?;
+    let previous = settings.toggle_shortcut.clone();
+    let switched = unregister(&app, &previous).and_then(|_| {
+        let mut updated = settings.clone();
+        updated.toggle_shortcut = accelerator.clone();
+        settings::save(&app, &updated).map_err(|err| {
+            if let Err(err) = register(&app, &previous) {
+                log::error!("failed to restore shortcut {}: {}", previous, err);
+            }
+            err
+        })
+    });
+    if let Err(err) = switched {
+        if let Err(err) = unregister(&app, &accelerator) {
+            log::error!("failed to release shortcut {}: {}", accelerator, err);
+        }
+        return Err(err);
+    }
+
+    settings.toggle_shortcut = accelerator;
+    Ok(())
+}
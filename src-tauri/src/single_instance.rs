@@ -0,0 +1,374 @@
+use std::{
+    fs::{self, OpenOptions},
+    io::{self, BufRead, BufReader, ErrorKind, Write},
+    net::{Ipv4Addr, SocketAddr, TcpListener, TcpStream},
+    path::{Path, PathBuf},
+    sync::mpsc::{self, Receiver},
+    thread,
+    time::{Duration, Instant, SystemTime},
+};
+
+use serde::{Deserialize, Serialize};
+use uuid::Uuid;
+
+use crate::launch::LaunchArgs;
+
+pub const LOCK_FILE: &str = "instance.lock";
+
+const CONNECT_TIMEOUT: Duration = Duration::from_millis(500);
+const ACK: &str = "ok";
+/// A lock file still empty, or a takeover still in progress, after this long
+/// was left behind by a launch that crashed.
+const ABANDONED_AFTER: Duration = Duration::from_secs(5);
+const RETRY_INTERVAL: Duration = Duration::from_millis(50);
+/// Gives up on a lock that keeps changing hands.
+const ACQUIRE_TIMEOUT: Duration = Duration::from_secs(15);
+
+/// Contents of the lock file: where the running instance listens, and the
+/// secret a forwarded invocation has to present. The file lives in the
+/// user's app data dir, so other users can't read the token.
+#[derive(Serialize, Deserialize)]
+struct InstanceLock {
+    port: u16,
+    token: String,
+}
+
+#[derive(Serialize, Deserialize)]
+struct Handoff {
+    token: String,
+    args: LaunchArgs,
+}
+
+pub enum Instance {
+    /// No other instance is running; this process owns the lock.
+    Primary(InstanceServer),
+    /// The arguments were handed to the running instance; exit now.
+    Forwarded,
+}
+
+/// Either forwards `args` to the running instance or claims the lock at
+/// `lock_path`. A lock whose owner doesn't answer is considered stale and
+/// taken over.
+///
+/// The lock file is created exclusively, so of several launches racing for
+/// it, say when a file manager opens many files at once, exactly one becomes
+/// the primary and the others forward to it.
+///
+/// Once claimed, forwarded invocations are accepted right away and queued
+/// until [`InstanceServer::serve`] is called, so a launch arriving while the
+/// app is still starting up doesn't mistake it for a stale lock.
+pub fn acquire(lock_path: &Path, args: &LaunchArgs) -> io::Result<Instance> {
+    if let Some(dir) = lock_path.parent() {
+        fs::create_dir_all(dir)?;
+    }
+    // listening before the lock exists, so its owner answers as soon as
+    // anyone can read it
+    let listener = TcpListener::bind((Ipv4Addr::LOCALHOST, 0))?;
+    let lock = InstanceLock {
+        port: listener.local_addr()?.port(),
+        token: Uuid::new_v4().to_string(),
+    };
+
+    let started = Instant::now();
+    loop {
+        match create_lock(lock_path, &lock) {
+            Ok(()) => break,
+            Err(err) if err.kind() == ErrorKind::AlreadyExists => {}
+            Err(err) => return Err(err),
+        }
+        match read_lock(lock_path) {
+            Some(running) => match forward(&running, args) {
+                Ok(()) => return Ok(Instance::Forwarded),
+                Err(err) => {
+                    log::debug!("instance lock looks stale: {}", err);
+                    remove_stale(lock_path, Some(&running.token))?;
+                }
+            },
+            // still being written by a racing launch, unless abandoned
+            None if is_abandoned(lock_path) => remove_stale(lock_path, None)?,
+            None => thread::sleep(RETRY_INTERVAL),
+        }
+        if started.elapsed() > ACQUIRE_TIMEOUT {
+            return Err(io::Error::new(
+                ErrorKind::TimedOut,
+                "timed out waiting for the instance lock",
+            ));
+        }
+    }
+
+    let (sender, receiver) = mpsc::channel();
+    let token = lock.token.clone();
+    thread::spawn(move || {
+        for stream in listener.incoming() {
+            match stream.and_then(|stream| receive(stream, &token)) {
+                Ok(args) => {
+                    if sender.send(args).is_err() {
+                        break;
+                    }
+                }
+                Err(err) => log::warn!("rejected forwarded launch: {}", err),
+            }
+        }
+    });
+
+    Ok(Instance::Primary(InstanceServer {
+        receiver,
+        guard: InstanceGuard {
+            token: lock.token,
+            lock_path: lock_path.to_path_buf(),
+        },
+    }))
+}
+
+/// Claims `path`, failing with `AlreadyExists` if another instance has.
+fn create_lock(path: &Path, lock: &InstanceLock) -> io::Result<()> {
+    let mut file = OpenOptions::new().write(true).create_new(true).open(path)?;
+    let written = serde_json::to_writer(&mut file, lock)
+        .map_err(io::Error::from)
+        .and_then(|_| file.sync_all());
+    if written.is_err() {
+        let _ = fs::remove_file(path);
+    }
+    written
+}
+
+/// Whether `path` was last touched longer than [`ABANDONED_AFTER`] ago.
+fn is_abandoned(path: &Path) -> bool {
+    fs::metadata(path)
+        .and_then(|metadata| metadata.modified())
+        .ok()
+        .and_then(|modified| SystemTime::now().duration_since(modified).ok())
+        .map_or(false, |age| age > ABANDONED_AFTER)
+}
+
+/// Removes the lock at `path` if it's still the stale one: the lock with
+/// `token`, or with `None` an abandoned unreadable one. Launches taking
+/// over serialize on a second file, so a fresh lock claimed by one of them
+/// is never mistaken for the stale one by another.
+fn remove_stale(path: &Path, token: Option<&str>) -> io::Result<()> {
+    let mut takeover = path.as_os_str().to_owned();
+    takeover.push(".takeover");
+    let takeover = PathBuf::from(takeover);
+    match OpenOptions::new()
+        .write(true)
+        .create_new(true)
+        .open(&takeover)
+    {
+        Ok(_) => {}
+        Err(err) if err.kind() == ErrorKind::AlreadyExists => {
+            if is_abandoned(&takeover) {
+                let _ = fs::remove_file(&takeover);
+            } else {
+                thread::sleep(RETRY_INTERVAL);
+            }
+            return Ok(());
+        }
+        Err(err) => return Err(err),
+    }
+
+    let current = read_lock(path).map(|lock| lock.token);
+    let stale = match token {
+        Some(token) => current.as_deref() == Some(token),
+        None => current.is_none() && is_abandoned(path),
+    };
+    let removed = match stale.then(|| fs::remove_file(path)) {
+        Some(Err(err)) if err.kind() != ErrorKind::NotFound => Err(err),
+        _ => Ok(()),
+    };
+    let _ = fs::remove_file(&takeover);
+    removed
+}
+
+fn read_lock(path: &Path) -> Option<InstanceLock> {
+    serde_json::from_slice(&fs::read(path).ok()?).ok()
+}
+
+fn forward(lock: &InstanceLock, args: &LaunchArgs) -> io::Result<()> {
+    let addr = SocketAddr::from((Ipv4Addr::LOCALHOST, lock.port));
+    let mut stream = TcpStream::connect_timeout(&addr, CONNECT_TIMEOUT)?;
+    stream.set_read_timeout(Some(CONNECT_TIMEOUT))?;
+
+    let handoff = Handoff {
+        token: lock.token.clone(),
+        args: args.clone(),
+    };
+    serde_json::to_writer(&mut stream, &handoff)?;
+    stream.write_all(b"\n")?;
+
+    // anything else listening on a reused port won't acknowledge
+    let mut ack = String::new();
+    BufReader::new(stream).read_line(&mut ack)?;
+    if ack.trim_end() == ACK {
+        Ok(())
+    } else {
+        Err(io::Error::new(
+            io::ErrorKind::InvalidData,
+            "unexpected handoff response",
+        ))
+    }
+}
+
+/// Invocations forwarded by later launches of the app.
+pub struct InstanceServer {
+    receiver: Receiver<LaunchArgs>,
+    guard: InstanceGuard,
+}
+
+impl InstanceServer {
+    /// Calls `on_launch` on a background thread for each forwarded
+    /// invocation, including any queued so far, and returns a guard that
+    /// releases the lock.
+    pub fn serve<F>(self, on_launch: F) -> InstanceGuard
+    where
+        F: Fn(LaunchArgs) + Send + 'static,
+    {
+        let receiver = self.receiver;
+        thread::spawn(move || {
+            for args in receiver {
+                on_launch(args);
+            }
+        });
+        self.guard
+    }
+}
+
+fn receive(stream: TcpStream, token: &str) -> io::Result<LaunchArgs> {
+    stream.set_read_timeout(Some(CONNECT_TIMEOUT))?;
+    let mut line = String::new();
+    BufReader::new(&stream).read_line(&mut line)?;
+    let handoff: Handoff = serde_json::from_str(&line)?;
+    if handoff.token != token {
+        return Err(io::Error::new(
+            io::ErrorKind::PermissionDenied,
+            "invalid instance token",
+        ));
+    }
+    (&stream).write_all(format!("{}\n", ACK).as_bytes())?;
+    Ok(handoff.args)
+}
+
+/// Removes the lock file on release, unless another instance has taken it
+/// over in the meantime.
+pub struct InstanceGuard {
+    token: String,
+    lock_path: PathBuf,
+}
+
+impl InstanceGuard {
+    pub fn release(&self) {
+        if read_lock(&self.lock_path).map_or(false, |lock| lock.token == self.token) {
+            let _ = fs::remove_file(&self.lock_path);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::{Arc, Barrier};
+
+    use super::*;
+    use crate::scene::test_util::temp_dir;
+
+    fn lock_path(name: &str) -> PathBuf {
+        temp_dir(name).join(LOCK_FILE)
+    }
+
+    fn args(file: &str) -> LaunchArgs {
+        LaunchArgs {
+            files: vec![PathBuf::from(file)],
+            ..LaunchArgs::default()
+        }
+    }
+
+    #[test]
+    fn forwards_to_the_running_instance() {
+        let path = lock_path("instance-forward");
+        let server = match acquire(&path, &args("first.excalidraw")).unwrap() {
+            Instance::Primary(server) => server,
+            Instance::Forwarded => panic!("no instance was running"),
+        };
+        assert!(matches!(
+            acquire(&path, &args("second.excalidraw")).unwrap(),
+            Instance::Forwarded
+        ));
+
+        let (sender, receiver) = mpsc::channel();
+        let guard = server.serve(move |args| sender.send(args).unwrap());
+        assert_eq!(
+            receiver.recv_timeout(Duration::from_secs(5)).unwrap(),
+            args("second.excalidraw")
+        );
+        guard.release();
+        assert!(!path.exists());
+
+        fs::remove_dir_all(path.parent().unwrap()).unwrap();
+    }
+
+    #[test]
+    fn takes_over_a_stale_lock() {
+        let path = lock_path("instance-stale");
+        fs::create_dir_all(path.parent().unwrap()).unwrap();
+        // a crashed instance's port, nothing listens on it anymore
+        let port = TcpListener::bind((Ipv4Addr::LOCALHOST, 0))
+            .unwrap()
+            .local_addr()
+            .unwrap()
+            .port();
+        let stale = InstanceLock {
+            port,
+            token: "crashed".into(),
+        };
+        fs::write(&path, serde_json::to_vec(&stale).unwrap()).unwrap();
+
+        let server = match acquire(&path, &LaunchArgs::default()).unwrap() {
+            Instance::Primary(server) => server,
+            Instance::Forwarded => panic!("forwarded to a stale lock"),
+        };
+        assert_ne!(read_lock(&path).unwrap().token, stale.token);
+        assert!(matches!(
+            acquire(&path, &LaunchArgs::default()).unwrap(),
+            Instance::Forwarded
+        ));
+        drop(server);
+
+        fs::remove_dir_all(path.parent().unwrap()).unwrap();
+    }
+
+    #[test]
+    fn one_of_concurrent_launches_wins() {
+        let path = lock_path("instance-race");
+        let launches = 8;
+        let barrier = Arc::new(Barrier::new(launches));
+        let threads: Vec<_> = (0..launches)
+            .map(|i| {
+                let path = path.clone();
+                let barrier = barrier.clone();
+                thread::spawn(move || {
+                    barrier.wait();
+                    acquire(&path, &args(&format!("{}.excalidraw", i))).unwrap()
+                })
+            })
+            .collect();
+        // the servers are kept until every launch is done, so none of them
+        // looks stale to the others
+        let instances: Vec<_> = threads
+            .into_iter()
+            .map(|thread| thread.join().unwrap())
+            .collect();
+        let mut servers = instances.into_iter().filter_map(|instance| match instance {
+            Instance::Primary(server) => Some(server),
+            Instance::Forwarded => None,
+        });
+        let server = servers.next().unwrap();
+        assert!(servers.next().is_none());
+
+        for _ in 1..launches {
+            server
+                .receiver
+                .recv_timeout(Duration::from_secs(5))
+                .unwrap();
+        }
+
+        fs::remove_dir_all(path.parent().unwrap()).unwrap();
+    }
+}
@@ -0,0 +1,386 @@
+//! Collaboration rooms persisted in the app data dir, standing in for the
+//! Firestore scenes and Cloud Storage files of `excalidraw-app/data/firebase.ts`
+//! so rooms and their images survive without Google services.
+//!
+//! Nothing is stored in the clear. Scenes are encrypted with the room key
+//! into the same `{ sceneVersion, iv, ciphertext }` documents Firestore
+//! holds, and files are the `compressData` payloads the webview uploads,
+//! kept as is:
+//!
+//! ```text
+//! storage/
+//!   scenes/<room id>.json
+//!   files/rooms/<room id>/<file id>
+//! ```
+
+use std::{fs, io::ErrorKind, path::PathBuf, sync::Mutex};
+
+use base64::{engine::general_purpose::STANDARD, Engine};
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use tauri::{AppHandle, Runtime, State};
+
+use crate::{
+    error::{Error, Result},
+    files,
+    scene::{self, encode, reconcile, restore, BinaryFileData, Element},
+};
+
+const STORAGE_DIR: &str = "storage";
+const SCENES_DIR: &str = "scenes";
+const SCENE_EXTENSION: &str = "json";
+/// `DELETED_ELEMENT_TIMEOUT`
+const DELETED_ELEMENT_TIMEOUT_MS: i64 = 24 * 60 * 60 * 1000;
+/// `MIME_TYPES.binary`
+const BINARY_MIME_TYPE: &str = "application/octet-stream";
+
+/// `FirebaseStoredScene`, with the blobs in base64.
+#[derive(Debug, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct StoredScene {
+    scene_version: i64,
+    iv: String,
+    ciphertext: String,
+}
+
+/// A file to store, `{ id, buffer }` in `saveFilesToFirebase`.
+#[derive(Debug, Clone, Deserialize)]
+pub struct RoomFile {
+    pub id: String,
+    pub buffer: Vec<u8>,
+}
+
+#[derive(Debug, Default, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct SavedFiles {
+    pub saved_files: Vec<String>,
+    pub errored_files: Vec<String>,
+}
+
+#[derive(Debug, Default, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct LoadedFiles {
+    pub loaded_files: Vec<BinaryFileData>,
+    pub errored_files: Vec<String>,
+}
+
+pub struct RoomStorage {
+    dir: PathBuf,
+    /// Held while a scene is read, reconciled and written back, which the
+    /// Firestore transaction did.
+    scenes: Mutex<()>,
+}
+
+impl RoomStorage {
+    pub fn new(dir: PathBuf) -> Self {
+        Self {
+            dir,
+            scenes: Mutex::new(()),
+        }
+    }
+
+    /// `loadFromFirebase`: the syncable elements of the room, `None` if
+    /// nothing was saved yet.
+    pub fn load_scene(&self, room_id: &str, room_key: &str) -> Result<Option<Vec<Element>>> {
+        let _scenes = self.scenes.lock().unwrap();
+        self.read_scene(room_id, room_key)
+    }
+
+    /// `saveToFirebase`: merges `elements` into the stored scene and returns
+    /// what was stored, as loading the room would. `editing` are the ids of
+    /// elements being edited locally, which keep their local copy.
+    pub fn save_scene(
+        &self,
+        room_id: &str,
+        room_key: &str,
+        elements: &[Element],
+        editing: &[&str],
+    ) -> Result<Vec<Element>> {
+        let _scenes = self.scenes.lock().unwrap();
+        let elements = match self.read_scene(room_id, room_key)? {
+            Some(stored) => syncable(reconcile::reconcile_elements(elements, &stored, editing)?),
+            None => elements.to_vec(),
+        };
+
+        let json = serde_json::to_vec(&scene::to_js_value(&elements)?)?;
+        let iv = encode::generate_iv();
+        let ciphertext = encode::encrypt_data(room_key, &iv, &json)?;
+        let stored = StoredScene {
+            scene_version: elements.iter().map(|element| element.base().version).sum(),
+            iv: STANDARD.encode(iv),
+            ciphertext: STANDARD.encode(ciphertext),
+        };
+        let path = self.scene_path(room_id)?;
+        fs::create_dir_all(self.dir.join(SCENES_DIR))?;
+        files::write_atomic(&path, &serde_json::to_vec(&stored)?)?;
+
+        restore_stored(&json)
+    }
+
+    fn read_scene(&self, room_id: &str, room_key: &str) -> Result<Option<Vec<Element>>> {
+        let contents = match fs::read(self.scene_path(room_id)?) {
+            Ok(contents) => contents,
+            Err(err) if err.kind() == ErrorKind::NotFound => return Ok(None),
+            Err(err) => return Err(err.into()),
+        };
+        let stored: StoredScene = serde_json::from_slice(&contents)?;
+        let iv = decode_base64(&stored.iv)?;
+        let ciphertext = decode_base64(&stored.ciphertext)?;
+        let json = encode::decrypt_data(&iv, &ciphertext, room_key)?;
+        restore_stored(&json).map(Some)
+    }
+
+    fn scene_path(&self, room_id: &str) -> Result<PathBuf> {
+        let name = safe_segment(room_id)?;
+        Ok(self
+            .dir
+            .join(SCENES_DIR)
+            .join(format!("{}.{}", name, SCENE_EXTENSION)))
+    }
+
+    /// `saveFilesToFirebase`: stores each file under `prefix`, reporting the
+    /// ones that failed rather than failing them all.
+    pub fn save_files(&self, prefix: &str, files: &[RoomFile]) -> SavedFiles {
+        let mut result = SavedFiles::default();
+        for file in files {
+            let saved = self.file_path(prefix, &file.id).and_then(|path| {
+                if let Some(dir) = path.parent() {
+                    fs::create_dir_all(dir)?;
+                }
+                files::write_atomic(&path, &file.buffer)
+            });
+            match saved {
+                Ok(()) => result.saved_files.push(file.id.clone()),
+                Err(err) => {
+                    log::warn!("failed to store file {}: {}", file.id, err);
+                    result.errored_files.push(file.id.clone());
+                }
+            }
+        }
+        result
+    }
+
+    /// `loadFilesFromFirebase`: decrypts the files stored under `prefix`.
+    pub fn load_files(&self, prefix: &str, decryption_key: &str, ids: &[String]) -> LoadedFiles {
+        let mut result = LoadedFiles::default();
+        let mut ids = ids.to_vec();
+        ids.sort();
+        ids.dedup();
+        for id in ids {
+            let loaded = self
+                .file_path(prefix, &id)
+                .and_then(|path| Ok(fs::read(path)?))
+                .and_then(|payload| load_file(&id, &payload, decryption_key));
+            match loaded {
+                Ok(file) => result.loaded_files.push(file),
+                Err(err) => {
+                    log::warn!("failed to load file {}: {}", id, err);
+                    result.errored_files.push(id);
+                }
+            }
+        }
+        result
+    }
+
+    fn file_path(&self, prefix: &str, id: &str) -> Result<PathBuf> {
+        let mut path = self.dir.clone();
+        for segment in prefix.split('/').filter(|segment| !segment.is_empty()) {
+            path.push(safe_segment(segment)?);
+        }
+        path.push(safe_segment(id)?);
+        Ok(path)
+    }
+}
+
+pub fn storage_dir<R: Runtime>(app: &AppHandle<R>) -> Result<PathBuf> {
+    app.path_resolver()
+        .app_data_dir()
+        .map(|dir| dir.join(STORAGE_DIR))
+        .ok_or_else(|| Error::Other("could not resolve the app data dir".into()))
+}
+
+/// Room, file and prefix names come from the webview, so only plain names
+/// that can't climb out of the storage dir are accepted.
+fn safe_segment(segment: &str) -> Result<&str> {
+    let valid = !segment.is_empty()
+        && segment
+            .chars()
+            .all(|c| c.is_ascii_alphanumeric() || c == '-' || c == '_');
+    if valid {
+        Ok(segment)
+    } else {
+        Err(Error::Other(format!("invalid storage name {:?}", segment)))
+    }
+}
+
+fn decode_base64(data: &str) -> Result<Vec<u8>> {
+    STANDARD
+        .decode(data)
+        .map_err(|err| Error::Other(format!("corrupted room scene: {}", err)))
+}
+
+/// `getSyncableElements(restoreElements(elements, null))`
+fn restore_stored(json: &[u8]) -> Result<Vec<Element>> {
+    let elements: Vec<Value> = serde_json::from_slice(json)?;
+    Ok(syncable(restore::restore_elements(&elements, None, false)?))
+}
+
+/// `getSyncableElements`: drops elements deleted over a day ago. Restoring
+/// has already dropped the invisibly small ones.
+fn syncable(mut elements: Vec<Element>) -> Vec<Element> {
+    let cutoff = scene::now_millis() - DELETED_ELEMENT_TIMEOUT_MS;
+    elements.retain(|element| {
+        let base = element.base();
+        !base.is_deleted || base.updated > cutoff
+    });
+    elements
+}
+
+fn load_file(id: &str, payload: &[u8], decryption_key: &str) -> Result<BinaryFileData> {
+    let decompressed = encode::decompress_data(payload, decryption_key)?;
+    let data_url = String::from_utf8(decompressed.data)
+        .map_err(|_| Error::Other("stored file is not a data URL".into()))?;
+    let metadata = &decompressed.metadata;
+    let created = metadata
+        .get("created")
+        .and_then(Value::as_i64)
+        .unwrap_or_else(scene::now_millis);
+    Ok(BinaryFileData {
+        mime_type: metadata
+            .get("mimeType")
+            .and_then(Value::as_str)
+            .filter(|mime_type| !mime_type.is_empty())
+            .unwrap_or(BINARY_MIME_TYPE)
+            .into(),
+        id: id.into(),
+        data_url,
+        created,
+        last_retrieved: Some(created),
+        extra: Default::default(),
+    })
+}
+
+#[tauri::command]
+pub async fn load_room_scene(
+    storage: State<'_, RoomStorage>,
+    room_id: String,
+    room_key: String,
+) -> Result<Option<Vec<Element>>> {
+    storage.load_scene(&room_id, &room_key)
+}
+
+#[tauri::command]
+pub async fn save_room_scene(
+    storage: State<'_, RoomStorage>,
+    room_id: String,
+    room_key: String,
+    elements: Vec<Element>,
+    editing: Vec<String>,
+) -> Result<Vec<Element>> {
+    let editing: Vec<&str> = editing.iter().map(String::as_str).collect();
+    storage.save_scene(&room_id, &room_key, &elements, &editing)
+}
+
+/// Stores encrypted files under `prefix`, e.g. `/files/rooms/<room id>`.
+#[tauri::command]
+pub async fn save_room_files(
+    storage: State<'_, RoomStorage>,
+    prefix: String,
+    files: Vec<RoomFile>,
+) -> Result<SavedFiles> {
+    Ok(storage.save_files(&prefix, &files))
+}
+
+#[tauri::command]
+pub async fn load_room_files(
+    storage: State<'_, RoomStorage>,
+    prefix: String,
+    decryption_key: String,
+    file_ids: Vec<String>,
+) -> Result<LoadedFiles> {
+    Ok(storage.load_files(&prefix, &decryption_key, &file_ids))
+}
+
+#[cfg(test)]
+mod tests {
+    use serde_json::json;
+
+    use super::*;
+    use crate::scene::test_util::{rectangle, temp_dir};
+
+    fn ids(elements: &[Element]) -> Vec<(&str, i64)> {
+        elements
+            .iter()
+            .map(|element| (element.base().id.as_str(), element.base().version))
+            .collect()
+    }
+
+    #[test]
+    fn reconciles_scenes_with_the_stored_one() {
+        let dir = temp_dir("storage-scenes");
+        let storage = RoomStorage::new(dir.clone());
+        let key = encode::generate_encryption_key();
+        assert_eq!(storage.load_scene("room1", &key).unwrap(), None);
+
+        let first = [
+            rectangle("A", 2, 1, Some("a0")),
+            rectangle("B", 1, 1, Some("a1")),
+        ];
+        storage.save_scene("room1", &key, &first, &[]).unwrap();
+        let second = [
+            rectangle("A", 1, 1, Some("a0")),
+            rectangle("C", 1, 1, Some("a2")),
+        ];
+        let stored = storage.save_scene("room1", &key, &second, &[]).unwrap();
+        assert_eq!(ids(&stored), [("A", 2), ("B", 1), ("C", 1)]);
+        assert_eq!(storage.load_scene("room1", &key).unwrap().unwrap(), stored);
+
+        // encrypted at rest, and useless without the key
+        let raw = fs::read_to_string(dir.join("scenes/room1.json")).unwrap();
+        assert!(!raw.contains("rectangle"));
+        assert!(raw.contains("\"sceneVersion\":4"));
+        let other_key = encode::generate_encryption_key();
+        assert!(storage.load_scene("room1", &other_key).is_err());
+
+        assert!(storage.load_scene("../room1", &key).is_err());
+        fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn stores_encrypted_files() {
+        let dir = temp_dir("storage-files");
+        let storage = RoomStorage::new(dir.clone());
+        let key = encode::generate_encryption_key();
+        let payload = encode::compress_data(
+            b"data:image/png;base64,AAAA",
+            &json!({ "mimeType": "image/png", "created": 42 }),
+            &key,
+        )
+        .unwrap();
+        let files = [
+            RoomFile {
+                id: "file1".into(),
+                buffer: payload,
+            },
+            RoomFile {
+                id: "../file2".into(),
+                buffer: Vec::new(),
+            },
+        ];
+
+        let saved = storage.save_files("/files/rooms/room1", &files);
+        assert_eq!(saved.saved_files, ["file1"]);
+        assert_eq!(saved.errored_files, ["../file2"]);
+        assert!(dir.join("files/rooms/room1/file1").is_file());
+
+        let ids = ["file1".to_string(), "file1".into(), "missing".into()];
+        let loaded = storage.load_files("/files/rooms/room1", &key, &ids);
+        assert_eq!(loaded.errored_files, ["missing"]);
+        assert_eq!(loaded.loaded_files.len(), 1);
+        let file = &loaded.loaded_files[0];
+        assert_eq!(file.mime_type, "image/png");
+        assert_eq!(file.data_url, "data:image/png;base64,AAAA");
+        assert_eq!(file.created, 42);
+        fs::remove_dir_all(dir).unwrap();
+    }
+}
@@ -0,0 +1,293 @@
+//! Small PNG previews of scene files, for galleries of the workspace and
+//! recent files. Thumbnails are cached in the app data dir by a hash of the
+//! scene, so an edited file gets a new one, and the webview loads them from
+//! the `thumb` URI scheme without parsing any scene itself.
+
+use std::{
+    collections::HashMap,
+    fs,
+    io::ErrorKind,
+    path::{Path, PathBuf},
+    sync::Mutex,
+    time::{Duration, SystemTime},
+};
+
+use serde::Serialize;
+use sha2::{Digest, Sha256};
+use tauri::{
+    http::{Request as HttpRequest, Response as HttpResponse, ResponseBuilder},
+    AppHandle, Manager, Runtime, State,
+};
+
+use crate::{
+    error::{Error, Result},
+    export::{png, svg, ExportOptions, DEFAULT_EXPORT_PADDING},
+    files,
+    scene::{restore, Scene},
+    settings::SettingsState,
+    workspace::{self, Workspace},
+};
+
+/// Thumbnails are at `thumb://localhost/<hash>`.
+pub const SCHEME: &str = "thumb";
+pub const THUMBNAIL_DIR: &str = "thumbnails";
+/// Longest side of a thumbnail, in pixels.
+const MAX_SIZE: f64 = 256.0;
+/// Thumbnails made longer ago are removed on startup. Which files they were
+/// for isn't kept across sessions, so those of edited or deleted files would
+/// pile up otherwise; one still in use is simply rendered again.
+const MAX_AGE: Duration = Duration::from_secs(30 * 24 * 60 * 60);
+
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct Thumbnail {
+    pub path: PathBuf,
+    pub hash: String,
+    /// Where the webview loads it from; Windows only serves custom schemes
+    /// over `https://<scheme>.localhost`.
+    pub url: String,
+}
+
+impl Thumbnail {
+    fn new(path: PathBuf, hash: String) -> Self {
+        let url = if cfg!(windows) {
+            format!("https://{}.localhost/{}", SCHEME, hash)
+        } else {
+            format!("{}://localhost/{}", SCHEME, hash)
+        };
+        Self { path, hash, url }
+    }
+}
+
+/// What the file was like when its thumbnail was made.
+struct Cached {
+    modified: i64,
+    len: u64,
+    hash: String,
+}
+
+pub struct Thumbnails {
+    dir: PathBuf,
+    cached: Mutex<HashMap<PathBuf, Cached>>,
+}
+
+impl Thumbnails {
+    /// Serves the thumbnails in `dir`, removing those older than
+    /// [`MAX_AGE`].
+    pub fn new(dir: PathBuf) -> Self {
+        if let Err(err) = prune(&dir, MAX_AGE) {
+            log::warn!("failed to prune thumbnails: {}", err);
+        }
+        Self {
+            dir,
+            cached: Mutex::default(),
+        }
+    }
+
+    fn file(&self, hash: &str) -> PathBuf {
+        self.dir.join(format!("{}.png", hash))
+    }
+
+    /// The hash of the thumbnail of the scene at `path`, rendering it
+    /// unless the file is unchanged or a scene like it was seen before.
+    pub fn thumbnail(&self, path: &Path) -> Result<String> {
+        let modified = workspace::modified_millis(path)?;
+        let len = path.metadata()?.len();
+        if let Some(cached) = self.cached.lock().unwrap().get(path) {
+            if (cached.modified, cached.len) == (modified, len) && self.file(&cached.hash).exists()
+            {
+                return Ok(cached.hash.clone());
+            }
+        }
+
+        let json = files::read_scene_json(path)?;
+        let hash = format!("{:x}", Sha256::digest(json.as_bytes()));
+        let file = self.file(&hash);
+        if !file.exists() {
+            let png = render(&json)?;
+            fs::create_dir_all(&self.dir)?;
+            files::write_atomic(&file, &png)?;
+        }
+
+        let mut cached = self.cached.lock().unwrap();
+        let previous = cached.insert(
+            path.to_path_buf(),
+            Cached {
+                modified,
+                len,
+                hash: hash.clone(),
+            },
+        );
+        // the file changed, its old thumbnail is of no use unless another
+        // file has the same scene
+        if let Some(previous) = previous.filter(|previous| previous.hash != hash) {
+            if !cached.values().any(|other| other.hash == previous.hash) {
+                let _ = fs::remove_file(self.file(&previous.hash));
+            }
+        }
+        Ok(hash)
+    }
+
+    /// The PNG of a thumbnail made before.
+    pub fn read(&self, hash: &str) -> Option<Vec<u8>> {
+        if hash.len() != 64 || !hash.chars().all(|c| c.is_ascii_hexdigit()) {
+            return None;
+        }
+        fs::read(self.file(hash)).ok()
+    }
+}
+
+/// Removes the thumbnails in `dir` made more than `max_age` ago.
+fn prune(dir: &Path, max_age: Duration) -> Result<()> {
+    let entries = match fs::read_dir(dir) {
+        Ok(entries) => entries,
+        Err(err) if err.kind() == ErrorKind::NotFound => return Ok(()),
+        Err(err) => return Err(err.into()),
+    };
+    let now = SystemTime::now();
+    for entry in entries {
+        let entry = entry?;
+        let path = entry.path();
+        if path.extension().map_or(true, |ext| ext != "png") {
+            continue;
+        }
+        let age = entry
+            .metadata()
+            .and_then(|metadata| metadata.modified())
+            .ok()
+            .and_then(|modified| now.duration_since(modified).ok());
+        if age.map_or(false, |age| age > max_age) {
+            fs::remove_file(&path)?;
+        }
+    }
+    Ok(())
+}
+
+/// Renders the scene `json` scaled down to fit [`MAX_SIZE`].
+fn render(json: &str) -> Result<Vec<u8>> {
+    let scene = Scene::parse(&restore::restore_json(json)?)?;
+    let size = svg::common_bounds(
+        scene
+            .elements
+            .iter()
+            .filter(|element| !element.base().is_deleted),
+    )
+    .map_or(0.0, |[min_x, min_y, max_x, max_y]| {
+        (max_x - min_x).max(max_y - min_y)
+    }) + 2.0 * DEFAULT_EXPORT_PADDING;
+    let options = ExportOptions {
+        scale: (MAX_SIZE / size).min(1.0),
+        ..ExportOptions::default()
+    };
+    png::render(&scene, &options)
+}
+
+pub fn thumbnail_dir<R: Runtime>(app: &AppHandle<R>) -> Result<PathBuf> {
+    app.path_resolver()
+        .app_data_dir()
+        .map(|dir| dir.join(THUMBNAIL_DIR))
+        .ok_or_else(|| Error::Other("could not resolve the app data dir".into()))
+}
+
+/// Serves `thumb://localhost/<hash>`. Thumbnails never change, so the
+/// webview may cache them for good.
+pub fn protocol<R: Runtime>(
+    app: &AppHandle<R>,
+    request: &HttpRequest,
+) -> std::result::Result<HttpResponse, Box<dyn std::error::Error>> {
+    let hash = request
+        .uri()
+        .split(['?', '#'])
+        .next()
+        .unwrap_or_default()
+        .trim_end_matches('/')
+        .rsplit('/')
+        .next()
+        .unwrap_or_default();
+    let png = app
+        .try_state::<Thumbnails>()
+        .and_then(|thumbnails| thumbnails.read(hash));
+    let response = ResponseBuilder::new().header("Access-Control-Allow-Origin", "*");
+    match png {
+        Some(png) => response
+            .mimetype("image/png")
+            .header("Cache-Control", "max-age=31536000, immutable")
+            .body(png),
+        None => response.status(404).body(Vec::new()),
+    }
+}
+
+/// Thumbnails of the scenes at `paths`, those in the workspace or the
+/// recent files. Scenes that fail to render are left out.
+#[tauri::command]
+pub async fn get_thumbnails(
+    thumbnails: State<'_, Thumbnails>,
+    workspace: State<'_, Workspace>,
+    settings: State<'_, SettingsState>,
+    paths: Vec<PathBuf>,
+) -> Result<Vec<Thumbnail>> {
+    let mut known = workspace.paths();
+    known.extend(settings.0.lock().unwrap().recent_files.iter().cloned());
+    let mut rendered = Vec::new();
+    for path in paths {
+        if !known.contains(&path) {
+            return Err(Error::Other(format!(
+                "{} is not in the workspace or the recent files",
+                path.display()
+            )));
+        }
+        match thumbnails.thumbnail(&path) {
+            Ok(hash) => rendered.push(Thumbnail::new(path, hash)),
+            Err(err) => log::warn!("no thumbnail for {}: {}", path.display(), err),
+        }
+    }
+    Ok(rendered)
+}
+
+#[cfg(test)]
+mod tests {
+    use resvg::tiny_skia::Pixmap;
+
+    use super::*;
+    use crate::scene::test_util::temp_dir;
+
+    const FIXTURE: &str = include_str!("../fixtures/scene.excalidraw");
+
+    #[test]
+    fn caches_thumbnails_by_content() {
+        let dir = temp_dir("thumbnails");
+        let path = dir.join("scene.excalidraw");
+        let copy = dir.join("copy.excalidraw");
+        fs::write(&path, FIXTURE).unwrap();
+        fs::write(&copy, FIXTURE).unwrap();
+        let thumbnails = Thumbnails::new(dir.join(THUMBNAIL_DIR));
+
+        let hash = thumbnails.thumbnail(&path).unwrap();
+        assert_eq!(thumbnails.thumbnail(&path).unwrap(), hash);
+        assert_eq!(thumbnails.thumbnail(&copy).unwrap(), hash);
+        let png = Pixmap::decode_png(&thumbnails.read(&hash).unwrap()).unwrap();
+        assert!(png.width().max(png.height()) <= MAX_SIZE as u32);
+        assert!(thumbnails.read("../scene.excalidraw").is_none());
+
+        // a changed file gets a new thumbnail, the old one stays while the
+        // copy still has that scene
+        let mut edited: serde_json::Value = serde_json::from_str(FIXTURE).unwrap();
+        edited["elements"][0]["x"] = 1000.into();
+        fs::write(&path, edited.to_string()).unwrap();
+        let edited_hash = thumbnails.thumbnail(&path).unwrap();
+        assert_ne!(edited_hash, hash);
+        assert!(thumbnails.read(&hash).is_some());
+        fs::write(&copy, edited.to_string()).unwrap();
+        assert_eq!(thumbnails.thumbnail(&copy).unwrap(), edited_hash);
+        assert!(thumbnails.read(&hash).is_none());
+
+        // recent ones survive a restart, old ones don't
+        let thumbnails = Thumbnails::new(dir.join(THUMBNAIL_DIR));
+        assert!(thumbnails.read(&edited_hash).is_some());
+        std::thread::sleep(Duration::from_millis(10));
+        prune(&dir.join(THUMBNAIL_DIR), Duration::from_millis(1)).unwrap();
+        assert!(thumbnails.read(&edited_hash).is_none());
+
+        fs::remove_dir_all(dir).unwrap();
+    }
+}
@@ -0,0 +1,132 @@
+use std::path::PathBuf;
+
+use tauri::{
+    AppHandle, CustomMenuItem, Icon, Manager, Runtime, SystemTray, SystemTrayEvent, SystemTrayMenu,
+    SystemTrayMenuItem, SystemTraySubmenu,
+};
+
+use crate::{
+    error::Result,
+    files,
+    overlay::{self, OverlayCommand, OverlayMode, OverlayState},
+};
+
+const TOGGLE_DRAWING: &str = "toggle-drawing";
+const TOGGLE_PRESENTING: &str = "toggle-presenting";
+const TOGGLE_VISIBILITY: &str = "toggle-visibility";
+const OPEN_FILE: &str = "open-file";
+const QUIT: &str = "quit";
+const RECENT_PREFIX: &str = "recent:";
+
+const DRAWING_ICON: &[u8] = include_bytes!("../icons/tray-drawing.png");
+const PASSTHROUGH_ICON: &[u8] = include_bytes!("../icons/tray-passthrough.png");
+
+/// The tray is the only visible handle on the overlay once it is in
+/// pass-through, since the window itself is transparent and undecorated.
+pub fn build() -> SystemTray {
+    SystemTray::new().with_menu(menu(&[]))
+}
+
+fn menu(recent_files: &[PathBuf]) -> SystemTrayMenu {
+    let mut recent = SystemTrayMenu::new();
+    if recent_files.is_empty() {
+        recent = recent.add_item(CustomMenuItem::new("recent-empty", "No recent files").disabled());
+    }
+    for (i, path) in recent_files.iter().enumerate() {
+        let title = path
+            .file_name()
+            .unwrap_or(path.as_os_str())
+            .to_string_lossy();
+        recent = recent.add_item(CustomMenuItem::new(
+            format!("{}{}", RECENT_PREFIX, i),
+            title,
+        ));
+    }
+
+    SystemTrayMenu::new()
+        .add_item(CustomMenuItem::new(TOGGLE_DRAWING, "Drawing").selected())
+        .add_item(CustomMenuItem::new(TOGGLE_PRESENTING, "Presenting"))
+        .add_item(CustomMenuItem::new(overlay::CLEAR_CANVAS, "Clear canvas"))
+        .add_item(CustomMenuItem::new(TOGGLE_VISIBILITY, "Hide overlay"))
+        .add_native_item(SystemTrayMenuItem::Separator)
+        .add_item(CustomMenuItem::new(OPEN_FILE, "Open file…"))
+        .add_submenu(SystemTraySubmenu::new("Recent files", recent))
+        .add_native_item(SystemTrayMenuItem::Separator)
+        .add_item(CustomMenuItem::new(QUIT, "Quit"))
+}
+
+/// Replaces the menu so the recent files submenu matches the settings.
+pub fn rebuild_menu<R: Runtime>(app: &AppHandle<R>) -> Result<()> {
+    app.tray_handle()
+        .set_menu(menu(&files::recent_files(app)))?;
+    refresh(app)
+}
+
+/// Syncs the tray icon and item states with the overlay mode.
+pub fn refresh<R: Runtime>(app: &AppHandle<R>) -> Result<()> {
+    let mode = app.state::<OverlayState>().mode();
+
+    let tray = app.tray_handle();
+    let (icon, tooltip) = match mode {
+        OverlayMode::Drawing => (DRAWING_ICON, "Excalidraw — drawing"),
+        OverlayMode::Presenting => (DRAWING_ICON, "Excalidraw — presenting"),
+        OverlayMode::PassThrough => (PASSTHROUGH_ICON, "Excalidraw — click-through"),
+        OverlayMode::Hidden => (PASSTHROUGH_ICON, "Excalidraw — hidden"),
+    };
+    tray.set_icon(Icon::Raw(icon.to_vec()))?;
+    tray.set_tooltip(tooltip)?;
+    tray.get_item(TOGGLE_DRAWING)
+        .set_selected(mode == OverlayMode::Drawing)?;
+    tray.get_item(TOGGLE_PRESENTING)
+        .set_selected(mode == OverlayMode::Presenting)?;
+    tray.get_item(TOGGLE_VISIBILITY)
+        .set_title(if mode.is_visible() {
+            "Hide overlay"
+        } else {
+            "Show overlay"
+        })?;
+    Ok(())
+}
+
+pub fn handle_event<R: Runtime>(app: &AppHandle<R>, event: SystemTrayEvent) {
+    if let SystemTrayEvent::MenuItemClick { id, .. } = event {
+        if let Err(err) = handle_menu_item(app, &id) {
+            log::error!("tray action {} failed: {}", id, err);
+        }
+    }
+}
+
+fn handle_menu_item<R: Runtime>(app: &AppHandle<R>, id: &str) -> Result<()> {
+    match id {
+        TOGGLE_DRAWING => {
+            overlay::dispatch(app, OverlayCommand::ToggleDrawing)?;
+        }
+        TOGGLE_PRESENTING => {
+            let mode = match app.state::<OverlayState>().mode() {
+                OverlayMode::Presenting => OverlayMode::Drawing,
+                _ => OverlayMode::Presenting,
+            };
+            overlay::dispatch(app, OverlayCommand::Enter(mode))?;
+        }
+        overlay::CLEAR_CANVAS => overlay::clear_canvas(app)?,
+        TOGGLE_VISIBILITY => {
+            overlay::dispatch(app, OverlayCommand::ToggleVisibility)?;
+        }
+        OPEN_FILE => files::pick_and_open(app),
+        QUIT => app.exit(0),
+        _ => {
+            if let Some(index) = id.strip_prefix(RECENT_PREFIX) {
+                let recent = files::recent_files(app);
+                if let Some(path) = index.parse::<usize>().ok().and_then(|i| recent.get(i)) {
+                    files::open_path(app, path.clone())?;
+                }
+            }
+        }
+    }
+    Ok(())
+}
+
+#[tauri::command]
+pub fn quit<R: Runtime>(app: AppHandle<R>) {
+    app.exit(0);
+}
@@ -0,0 +1,318 @@
+//! Watching the scene file open in the app, so edits made by another tool
+//! or pulled in with git show up without reopening it. External edits are
+//! merged into the scene in the app rather than replacing it, so neither
+//! side's changes are lost.
+
+use std::{
+    collections::HashSet,
+    io::ErrorKind,
+    path::{Path, PathBuf},
+    sync::{Arc, Mutex},
+    time::Duration,
+};
+
+use notify_debouncer_mini::{
+    new_debouncer,
+    notify::{RecommendedWatcher, RecursiveMode},
+    DebounceEventResult, Debouncer,
+};
+use tauri::{AppHandle, Manager, Runtime, State};
+
+use crate::{
+    error::{Error, Result},
+    files::{self, FileScope, SceneFile},
+    overlay::MAIN_WINDOW,
+    scene::{reconcile, restore, Element, Scene},
+};
+
+/// Emitted to the webview with a [`SceneFile`] holding the merged scene
+/// when the watched file changes on disk.
+pub const SCENE_CHANGED: &str = "scene-changed";
+
+/// Editors and git tend to write a file in several steps.
+const DEBOUNCE: Duration = Duration::from_millis(300);
+
+struct Watched {
+    path: PathBuf,
+    /// The scene in the app, which external edits are merged into.
+    local: Scene,
+    /// The elements on disk as last read or written, telling which
+    /// elements an external edit removed.
+    base: Vec<Element>,
+    _debouncer: Debouncer<RecommendedWatcher>,
+}
+
+#[derive(Default)]
+pub struct SceneWatcher(Arc<Mutex<Option<Watched>>>);
+
+impl SceneWatcher {
+    /// Watches `path`, with `local` as the scene the app has open.
+    pub fn watch<R: Runtime>(&self, app: &AppHandle<R>, path: PathBuf, local: Scene) -> Result<()> {
+        // editors save by renaming over the file, which drops a watch on the
+        // file itself, so watch the folder it's in
+        let dir = path
+            .parent()
+            .filter(|dir| !dir.as_os_str().is_empty())
+            .unwrap_or_else(|| Path::new("."))
+            .canonicalize()?;
+        let base = load(&path)?.elements;
+        // events name files by their path under the watched folder
+        let path = dir.join(path.file_name().unwrap_or_default());
+
+        let state = Arc::clone(&self.0);
+        let handle = app.clone();
+        let mut debouncer = new_debouncer(DEBOUNCE, move |result: DebounceEventResult| {
+            let events = match result {
+                Ok(events) => events,
+                Err(err) => {
+                    log::warn!("scene watcher error: {}", err);
+                    return;
+                }
+            };
+            let mut state = state.lock().unwrap();
+            let watched = match state.as_mut() {
+                Some(watched) => watched,
+                None => return,
+            };
+            if events.iter().any(|event| event.path == watched.path) {
+                if let Err(err) = reload(&handle, watched) {
+                    log::error!("failed to reload {}: {}", watched.path.display(), err);
+                }
+            }
+        })
+        .map_err(|err| Error::Other(err.to_string()))?;
+        debouncer
+            .watcher()
+            .watch(&dir, RecursiveMode::NonRecursive)
+            .map_err(|err| Error::Other(err.to_string()))?;
+
+        log::debug!("watching {}", path.display());
+        *self.0.lock().unwrap() = Some(Watched {
+            path,
+            local,
+            base,
+            _debouncer: debouncer,
+        });
+        Ok(())
+    }
+
+    pub fn unwatch(&self) {
+        self.0.lock().unwrap().take();
+    }
+
+    /// Records the scene the app now has open, if `path` is being watched.
+    /// After a save, `path` is on disk as well and its change isn't
+    /// reported back.
+    pub fn update(&self, path: Option<&Path>, contents: &str, saved: bool) -> Result<()> {
+        let mut state = self.0.lock().unwrap();
+        let watched = match state.as_mut() {
+            Some(watched) => watched,
+            None => return Ok(()),
+        };
+        if let Some(path) = path {
+            if path.canonicalize().ok().as_ref() != Some(&watched.path) {
+                return Ok(());
+            }
+        }
+        watched.local = Scene::parse(contents)?;
+        if saved {
+            watched.base = load(&watched.path)?.elements;
+        }
+        Ok(())
+    }
+}
+
+/// The scene at `path`, restored like a scene the user opens.
+fn load(path: &Path) -> Result<Scene> {
+    Scene::parse(&restore::restore_json(&files::read_scene_json(path)?)?)
+}
+
+fn reload<R: Runtime>(app: &AppHandle<R>, watched: &mut Watched) -> Result<()> {
+    let external = match load(&watched.path) {
+        Ok(external) => external,
+        // mid-rename, the next event has the new file
+        Err(Error::Io(err)) if err.kind() == ErrorKind::NotFound => return Ok(()),
+        Err(err) => return Err(err),
+    };
+    if external.elements == watched.base {
+        return Ok(());
+    }
+    let merged = merge_external(&watched.local, &watched.base, &external)?;
+    watched.base = external.elements;
+    let merged = match merged {
+        Some(merged) => merged,
+        None => return Ok(()),
+    };
+    log::info!("{} changed on disk", watched.path.display());
+
+    let change = SceneFile {
+        path: watched.path.clone(),
+        contents: merged.to_json()?,
+    };
+    watched.local = merged;
+    if let Some(window) = app.get_window(MAIN_WINDOW) {
+        window.emit(SCENE_CHANGED, change)?;
+    }
+    Ok(())
+}
+
+/// Merges the scene on disk into the one in the app, `None` if it brings
+/// nothing new.
+///
+/// Elements are reconciled like a collaborator's, so the higher version of
+/// an element edited on both sides wins. Elements gone from the file since
+/// `base`, the last version on disk, were deleted externally and are
+/// dropped unless they were edited in the app since. Files are combined and
+/// the app state stays the app's.
+pub fn merge_external(local: &Scene, base: &[Element], external: &Scene) -> Result<Option<Scene>> {
+    let external_ids: HashSet<&str> = external
+        .elements
+        .iter()
+        .map(|element| element.base().id.as_str())
+        .collect();
+    let removed: Vec<&Element> = base
+        .iter()
+        .filter(|element| !external_ids.contains(element.base().id.as_str()))
+        .collect();
+    let kept: Vec<Element> = local
+        .elements
+        .iter()
+        .filter(|element| {
+            let element = element.base();
+            !removed.iter().any(|removed| {
+                let removed = removed.base();
+                removed.id == element.id && removed.version >= element.version
+            })
+        })
+        .cloned()
+        .collect();
+
+    let elements = reconcile::reconcile_elements(&kept, &external.elements, &[])?;
+    let mut files = local.files.clone();
+    for (id, file) in external.files.iter().flatten() {
+        files
+            .get_or_insert_with(Default::default)
+            .entry(id.clone())
+            .or_insert_with(|| file.clone());
+    }
+    if elements == local.elements && files == local.files {
+        return Ok(None);
+    }
+    Ok(Some(Scene {
+        elements,
+        files,
+        ..local.clone()
+    }))
+}
+
+/// Starts watching `path`, a scene the user opened, for changes made
+/// outside the app. `contents` is the scene as the app has it.
+#[tauri::command]
+pub fn watch_scene<R: Runtime>(
+    app: AppHandle<R>,
+    scope: State<FileScope>,
+    watcher: State<SceneWatcher>,
+    path: PathBuf,
+    contents: String,
+) -> Result<()> {
+    scope.ensure_allowed(&path)?;
+    watcher.watch(&app, path, Scene::parse(&contents)?)
+}
+
+/// Keeps the scene external edits are merged into current. Call it as the
+/// scene changes in the app.
+#[tauri::command]
+pub fn update_watched_scene(watcher: State<SceneWatcher>, contents: String) -> Result<()> {
+    watcher.update(None, &contents, false)
+}
+
+#[tauri::command]
+pub fn unwatch_scene(watcher: State<SceneWatcher>) {
+    watcher.unwatch();
+}
+
+#[cfg(test)]
+mod tests {
+    use serde_json::{json, Value};
+
+    use super::*;
+    use crate::scene::test_util::{rectangle_json, temp_dir};
+
+    fn element(id: &str, version: i64, index: &str) -> Value {
+        rectangle_json(id, version, version, Some(index))
+    }
+
+    fn scene(elements: &[Value]) -> Scene {
+        serde_json::from_value(json!({
+            "type": "excalidraw",
+            "version": 2,
+            "source": "test",
+            "elements": elements,
+            "appState": { "viewBackgroundColor": "#ffffff" },
+            "files": {},
+        }))
+        .unwrap()
+    }
+
+    fn versions(scene: &Scene) -> Vec<(&str, i64)> {
+        scene
+            .elements
+            .iter()
+            .map(|element| (element.base().id.as_str(), element.base().version))
+            .collect()
+    }
+
+    #[test]
+    fn merges_non_conflicting_external_edits() {
+        let base = scene(&[
+            element("A", 1, "a0"),
+            element("B", 1, "a1"),
+            element("C", 1, "a2"),
+            element("D", 1, "a3"),
+        ]);
+        // the app edited A and D, another tool edited B, deleted C and D
+        // and added E
+        let local = scene(&[
+            element("A", 2, "a0"),
+            element("B", 1, "a1"),
+            element("C", 1, "a2"),
+            element("D", 2, "a3"),
+        ]);
+        let external = scene(&[
+            element("A", 1, "a0"),
+            element("B", 3, "a1"),
+            element("E", 1, "a4"),
+        ]);
+
+        let merged = merge_external(&local, &base.elements, &external)
+            .unwrap()
+            .unwrap();
+        assert_eq!(versions(&merged), [("A", 2), ("B", 3), ("D", 2), ("E", 1)]);
+        assert_eq!(merged.app_state, local.app_state);
+
+        // nothing new once merged
+        assert_eq!(
+            merge_external(&merged, &external.elements, &external).unwrap(),
+            None
+        );
+    }
+
+    #[test]
+    fn merges_edits_read_from_disk() {
+        let dir = temp_dir("watch");
+        let path = dir.join("scene.excalidraw");
+        let original = scene(&[element("A", 1, "a0")]);
+        std::fs::write(&path, original.to_json().unwrap()).unwrap();
+
+        let loaded = load(&path).unwrap();
+        assert_eq!(versions(&loaded), [("A", 1)]);
+        let edited = scene(&[element("A", 1, "a0"), element("B", 1, "a1")]);
+        std::fs::write(&path, edited.to_json().unwrap()).unwrap();
+        let merged = merge_external(&original, &loaded.elements, &load(&path).unwrap())
+            .unwrap()
+            .unwrap();
+        assert_eq!(versions(&merged), [("A", 1), ("B", 1)]);
+
+        std::fs::remove_dir_all(dir).unwrap();
+    }
+}
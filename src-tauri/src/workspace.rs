@@ -0,0 +1,494 @@
+//! Workspaces: a folder of drawings, e.g. the docs of a repo, indexed so
+//! the webview can browse and search them. The index follows the folder as
+//! files are added, edited, moved or removed, and is sent to the webview as
+//! [`WORKSPACE_CHANGED`] events.
+
+use std::{
+    collections::BTreeMap,
+    path::{Path, PathBuf},
+    sync::{Arc, Mutex},
+    time::{Duration, UNIX_EPOCH},
+};
+
+use notify_debouncer_mini::{
+    new_debouncer,
+    notify::{RecommendedWatcher, RecursiveMode},
+    DebounceEventResult, Debouncer,
+};
+use serde::Serialize;
+use tauri::{api::dialog::blocking::FileDialogBuilder, AppHandle, Manager, Runtime, State};
+use walkdir::WalkDir;
+
+use crate::{
+    error::{Error, Result},
+    files::{self, SceneFile},
+    overlay::MAIN_WINDOW,
+    scene::{restore, Element, Scene},
+    settings::{self, SettingsState},
+};
+
+/// Emitted to the webview with the [`WorkspaceListing`] whenever the
+/// folder changes.
+pub const WORKSPACE_CHANGED: &str = "workspace-changed";
+
+/// Scene files, and exports with the scene embedded.
+const SCENE_SUFFIXES: &[&str] = &[".excalidraw", ".excalidraw.png", ".excalidraw.svg"];
+/// Folders never worth descending into.
+const SKIPPED_DIRS: &[&str] = &["node_modules", "target"];
+const DEBOUNCE: Duration = Duration::from_millis(500);
+
+#[derive(Debug, Clone, PartialEq, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct WorkspaceEntry {
+    pub path: PathBuf,
+    /// Relative to the workspace folder, `/` separated.
+    pub relative_path: String,
+    /// The scene's name, or its file name.
+    pub title: String,
+    /// Elements that aren't deleted.
+    pub element_count: usize,
+    /// Epoch milliseconds.
+    pub modified: i64,
+    /// Titles of the frames in the scene, in scene order.
+    pub frames: Vec<String>,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct WorkspaceListing {
+    pub dir: PathBuf,
+    pub entries: Vec<WorkspaceEntry>,
+}
+
+fn scene_suffix(path: &Path) -> Option<&'static str> {
+    let name = path.file_name()?.to_str()?.to_ascii_lowercase();
+    SCENE_SUFFIXES
+        .iter()
+        .rev()
+        .find(|suffix| name.ends_with(*suffix) && name.len() > suffix.len())
+        .copied()
+}
+
+fn is_skipped_dir(name: &str) -> bool {
+    name.starts_with('.') || SKIPPED_DIRS.contains(&name)
+}
+
+/// `getFrameLikeTitle`
+fn frame_title(element: &Element) -> Option<String> {
+    match element {
+        Element::Frame(frame) => Some(frame.name.clone().unwrap_or_else(|| "Frame".into())),
+        Element::Magicframe(frame) => Some(frame.name.clone().unwrap_or_else(|| "AI Frame".into())),
+        _ => None,
+    }
+}
+
+/// When `path` was last modified, in epoch milliseconds.
+pub fn modified_millis(path: &Path) -> Result<i64> {
+    Ok(path
+        .metadata()?
+        .modified()?
+        .duration_since(UNIX_EPOCH)
+        .map_or(0, |since| since.as_millis() as i64))
+}
+
+/// Reads the scene at `path` into an entry of the workspace at `root`.
+pub fn index_file(root: &Path, path: &Path) -> Result<WorkspaceEntry> {
+    let suffix = scene_suffix(path)
+        .ok_or_else(|| Error::Other(format!("{} is not a scene", path.display())))?;
+    let modified = modified_millis(path)?;
+    let scene = Scene::parse(&restore::restore_json(&files::read_scene_json(path)?)?)?;
+    let elements: Vec<&Element> = scene
+        .elements
+        .iter()
+        .filter(|element| !element.base().is_deleted)
+        .collect();
+
+    let file_name = path
+        .file_name()
+        .unwrap_or_default()
+        .to_string_lossy()
+        .to_string();
+    let title = scene
+        .app_state
+        .extra
+        .get("name")
+        .and_then(|name| name.as_str())
+        .filter(|name| !name.trim().is_empty())
+        .map_or_else(
+            || file_name[..file_name.len() - suffix.len()].to_string(),
+            String::from,
+        );
+    let relative_path = path
+        .strip_prefix(root)
+        .unwrap_or(path)
+        .components()
+        .map(|component| component.as_os_str().to_string_lossy())
+        .collect::<Vec<_>>()
+        .join("/");
+
+    Ok(WorkspaceEntry {
+        path: path.to_path_buf(),
+        relative_path,
+        title,
+        element_count: elements.len(),
+        modified,
+        frames: elements.into_iter().filter_map(frame_title).collect(),
+    })
+}
+
+/// Indexes every scene under `dir`, skipping hidden folders and build
+/// output. Files that fail to parse are left out.
+pub fn scan(root: &Path, dir: &Path) -> BTreeMap<PathBuf, WorkspaceEntry> {
+    let walker = WalkDir::new(dir).into_iter().filter_entry(|entry| {
+        entry.depth() == 0
+            || !entry.file_type().is_dir()
+            || !is_skipped_dir(&entry.file_name().to_string_lossy())
+    });
+    let mut entries = BTreeMap::new();
+    for entry in walker.filter_map(|entry| entry.ok()) {
+        if !entry.file_type().is_file() || scene_suffix(entry.path()).is_none() {
+            continue;
+        }
+        match index_file(root, entry.path()) {
+            Ok(indexed) => {
+                entries.insert(entry.path().to_path_buf(), indexed);
+            }
+            Err(err) => log::warn!("not indexing {}: {}", entry.path().display(), err),
+        }
+    }
+    entries
+}
+
+/// Entries matching every word of `query` in their title, path or frame
+/// titles, those with it in the title first, then the most recent.
+pub fn search<'a>(
+    entries: impl IntoIterator<Item = &'a WorkspaceEntry>,
+    query: &str,
+) -> Vec<WorkspaceEntry> {
+    let query = query.to_lowercase();
+    let words: Vec<&str> = query.split_whitespace().collect();
+    let mut matches: Vec<(bool, &WorkspaceEntry)> = entries
+        .into_iter()
+        .filter_map(|entry| {
+            let title = entry.title.to_lowercase();
+            let haystack = format!(
+                "{}\n{}\n{}",
+                title,
+                entry.relative_path.to_lowercase(),
+                entry.frames.join("\n").to_lowercase()
+            );
+            words
+                .iter()
+                .all(|word| haystack.contains(word))
+                .then(|| (words.iter().all(|word| title.contains(word)), entry))
+        })
+        .collect();
+    matches.sort_by(|(a_title, a), (b_title, b)| {
+        b_title
+            .cmp(a_title)
+            .then_with(|| b.modified.cmp(&a.modified))
+    });
+    matches
+        .into_iter()
+        .map(|(_, entry)| entry.clone())
+        .collect()
+}
+
+struct Index {
+    root: PathBuf,
+    entries: BTreeMap<PathBuf, WorkspaceEntry>,
+    _debouncer: Debouncer<RecommendedWatcher>,
+}
+
+impl Index {
+    fn listing(&self) -> WorkspaceListing {
+        WorkspaceListing {
+            dir: self.root.clone(),
+            entries: self.entries.values().cloned().collect(),
+        }
+    }
+
+    /// Brings the entries at or under `path` up to date with the disk.
+    fn refresh(&mut self, path: &Path) -> bool {
+        let is_dir = path.is_dir();
+        let hidden = path.strip_prefix(&self.root).map_or(true, |relative| {
+            // every folder on the way, the file itself may start with a dot
+            let dirs = if is_dir {
+                Some(relative)
+            } else {
+                relative.parent()
+            };
+            dirs.into_iter()
+                .flat_map(Path::components)
+                .any(|component| is_skipped_dir(&component.as_os_str().to_string_lossy()))
+        });
+        if hidden {
+            return false;
+        }
+
+        let before = self.entries.len();
+        if is_dir {
+            // a folder moved in, or out and back
+            let scanned = scan(&self.root, path);
+            let changed = scanned
+                .iter()
+                .any(|(path, entry)| self.entries.get(path) != Some(entry));
+            self.entries.extend(scanned);
+            return changed;
+        }
+        if scene_suffix(path).is_some() && path.is_file() {
+            return match index_file(&self.root, path) {
+                Ok(entry) => self.entries.insert(path.to_path_buf(), entry.clone()) != Some(entry),
+                Err(err) => {
+                    log::debug!("not indexing {}: {}", path.display(), err);
+                    self.entries.remove(path).is_some()
+                }
+            };
+        }
+        // removed, a file or a whole folder
+        self.entries.retain(|indexed, _| !indexed.starts_with(path));
+        self.entries.len() != before
+    }
+}
+
+/// The workspace open in the app, if any.
+#[derive(Default)]
+pub struct Workspace(Arc<Mutex<Option<Index>>>);
+
+impl Workspace {
+    /// Indexes `dir` and watches it, replacing the open workspace.
+    pub fn open<R: Runtime>(&self, app: &AppHandle<R>, dir: &Path) -> Result<WorkspaceListing> {
+        // events name files by their path under the watched folder
+        let root = dir.canonicalize()?;
+        let state = Arc::clone(&self.0);
+        let handle = app.clone();
+        let mut debouncer = new_debouncer(DEBOUNCE, move |result: DebounceEventResult| {
+            let events = match result {
+                Ok(events) => events,
+                Err(err) => {
+                    log::warn!("workspace watcher error: {}", err);
+                    return;
+                }
+            };
+            let mut state = state.lock().unwrap();
+            let index = match state.as_mut() {
+                Some(index) => index,
+                None => return,
+            };
+            let mut changed = false;
+            for event in &events {
+                changed |= index.refresh(&event.path);
+            }
+            if let (true, Some(window)) = (changed, handle.get_window(MAIN_WINDOW)) {
+                if let Err(err) = window.emit(WORKSPACE_CHANGED, index.listing()) {
+                    log::error!("failed to send the workspace: {}", err);
+                }
+            }
+        })
+        .map_err(|err| Error::Other(err.to_string()))?;
+        debouncer
+            .watcher()
+            .watch(&root, RecursiveMode::Recursive)
+            .map_err(|err| Error::Other(err.to_string()))?;
+
+        let index = Index {
+            entries: scan(&root, &root),
+            root,
+            _debouncer: debouncer,
+        };
+        log::info!(
+            "opened workspace {} with {} scenes",
+            index.root.display(),
+            index.entries.len()
+        );
+        let listing = index.listing();
+        *self.0.lock().unwrap() = Some(index);
+        Ok(listing)
+    }
+
+    pub fn close(&self) {
+        self.0.lock().unwrap().take();
+    }
+
+    pub fn listing(&self) -> Option<WorkspaceListing> {
+        self.0.lock().unwrap().as_ref().map(Index::listing)
+    }
+
+    pub fn search(&self, query: &str) -> Vec<WorkspaceEntry> {
+        match &*self.0.lock().unwrap() {
+            Some(index) => search(index.entries.values(), query),
+            None => Vec::new(),
+        }
+    }
+
+    /// The scenes in the workspace.
+    pub fn paths(&self) -> Vec<PathBuf> {
+        self.0
+            .lock()
+            .unwrap()
+            .as_ref()
+            .map_or_else(Vec::new, |index| index.entries.keys().cloned().collect())
+    }
+
+    fn contains(&self, path: &Path) -> bool {
+        self.0
+            .lock()
+            .unwrap()
+            .as_ref()
+            .map_or(false, |index| index.entries.contains_key(path))
+    }
+}
+
+/// Reopens the workspace of the previous session.
+pub fn restore<R: Runtime>(app: &AppHandle<R>, dir: Option<&Path>) {
+    if let Some(dir) = dir {
+        if let Err(err) = app.state::<Workspace>().open(app, dir) {
+            log::warn!("failed to reopen workspace {}: {}", dir.display(), err);
+        }
+    }
+}
+
+/// Lets the user pick a folder to open as the workspace.
+#[tauri::command]
+pub async fn open_workspace<R: Runtime>(
+    app: AppHandle<R>,
+    workspace: State<'_, Workspace>,
+    settings: State<'_, SettingsState>,
+) -> Result<Option<WorkspaceListing>> {
+    let dir = match FileDialogBuilder::new().pick_folder() {
+        Some(dir) => dir,
+        None => return Ok(None),
+    };
+    let listing = workspace.open(&app, &dir)?;
+    let mut settings = settings.0.lock().unwrap();
+    settings.workspace_dir = Some(listing.dir.clone());
+    settings::save(&app, &settings)?;
+    Ok(Some(listing))
+}
+
+#[tauri::command]
+pub fn close_workspace<R: Runtime>(
+    app: AppHandle<R>,
+    workspace: State<Workspace>,
+    settings: State<SettingsState>,
+) -> Result<()> {
+    workspace.close();
+    let mut settings = settings.0.lock().unwrap();
+    settings.workspace_dir = None;
+    settings::save(&app, &settings)
+}
+
+#[tauri::command]
+pub fn list_workspace(workspace: State<Workspace>) -> Option<WorkspaceListing> {
+    workspace.listing()
+}
+
+/// Scenes whose title, path or frames match `query`.
+#[tauri::command]
+pub fn search_workspace(workspace: State<Workspace>, query: String) -> Vec<WorkspaceEntry> {
+    workspace.search(&query)
+}
+
+/// Loads a scene of the workspace like one the user opened.
+#[tauri::command]
+pub async fn open_workspace_file<R: Runtime>(
+    app: AppHandle<R>,
+    workspace: State<'_, Workspace>,
+    path: PathBuf,
+) -> Result<SceneFile> {
+    if !workspace.contains(&path) {
+        return Err(Error::Other(format!(
+            "{} is not in the workspace",
+            path.display()
+        )));
+    }
+    files::read_scene(&app, path)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::fs;
+
+    use serde_json::json;
+
+    use super::*;
+    use crate::scene::test_util::temp_dir;
+
+    fn scene(name: Option<&str>, frames: &[&str], deleted: usize) -> String {
+        let mut elements = Vec::new();
+        for (i, frame) in frames.iter().enumerate() {
+            elements.push(json!({
+                "type": "frame", "id": format!("frame{}", i), "name": frame,
+                "x": 0, "y": 0, "width": 100, "height": 100,
+            }));
+        }
+        for i in 0..deleted {
+            elements.push(json!({
+                "type": "rectangle", "id": format!("deleted{}", i), "isDeleted": true,
+                "x": 0, "y": 0, "width": 10, "height": 10,
+            }));
+        }
+        elements.push(json!({
+            "type": "rectangle", "id": "box", "x": 0, "y": 0, "width": 10, "height": 10,
+        }));
+        json!({
+            "type": "excalidraw",
+            "version": 2,
+            "elements": elements,
+            "appState": { "name": name },
+        })
+        .to_string()
+    }
+
+    #[test]
+    fn indexes_and_searches_a_folder() {
+        let root = temp_dir("workspace");
+        fs::create_dir_all(root.join("docs/arch")).unwrap();
+        fs::create_dir_all(root.join(".git")).unwrap();
+        fs::create_dir_all(root.join("node_modules/pkg")).unwrap();
+        fs::write(
+            root.join("docs/arch/overview.excalidraw"),
+            scene(Some("System overview"), &["Login flow", "Billing"], 2),
+        )
+        .unwrap();
+        fs::write(root.join("roadmap.excalidraw"), scene(None, &[], 0)).unwrap();
+        fs::write(root.join("notes.md"), "# notes").unwrap();
+        fs::write(root.join("broken.excalidraw"), "{").unwrap();
+        fs::write(root.join(".git/stash.excalidraw"), scene(None, &[], 0)).unwrap();
+        fs::write(
+            root.join("node_modules/pkg/demo.excalidraw"),
+            scene(None, &[], 0),
+        )
+        .unwrap();
+
+        let entries = scan(&root, &root);
+        let relative: Vec<&str> = entries
+            .values()
+            .map(|entry| entry.relative_path.as_str())
+            .collect();
+        assert_eq!(
+            relative,
+            ["docs/arch/overview.excalidraw", "roadmap.excalidraw"]
+        );
+
+        let overview = &entries[&root.join("docs/arch/overview.excalidraw")];
+        assert_eq!(overview.title, "System overview");
+        assert_eq!(overview.element_count, 3);
+        assert_eq!(overview.frames, ["Login flow", "Billing"]);
+        assert!(overview.modified > 0);
+        assert_eq!(entries[&root.join("roadmap.excalidraw")].title, "roadmap");
+
+        let titles = |query: &str| -> Vec<String> {
+            search(entries.values(), query)
+                .into_iter()
+                .map(|entry| entry.title)
+                .collect()
+        };
+        assert_eq!(titles("LOGIN"), ["System overview"]);
+        assert_eq!(titles("arch overview"), ["System overview"]);
+        assert_eq!(titles("road"), ["roadmap"]);
+        assert_eq!(titles("").len(), 2);
+        assert!(titles("missing").is_empty());
+
+        fs::remove_dir_all(&root).unwrap();
+    }
+}